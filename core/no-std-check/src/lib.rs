@@ -0,0 +1,183 @@
+//! Minimal runtime implementing the core traits without std, so that
+//! building this crate checks that `blockchain-core` stays usable under
+//! `no_std` with `alloc`. It is kept out of the workspace, so that the std
+//! feature is not enabled through other members, and is built from its own
+//! directory.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
+use blockchain_core::{
+	Block, BlockExecutor, BuildGenesis, ExtrinsicBuilder, ExtrinsicOutcome,
+	StorageExternalities,
+};
+
+/// Block of a chain without state commitments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckBlock {
+	pub id: u64,
+	pub parent: Option<u64>,
+	pub number: u64,
+	pub extrinsics: Vec<u64>,
+}
+
+impl Block for CheckBlock {
+	type Identifier = u64;
+
+	fn id(&self) -> u64 {
+		self.id
+	}
+
+	fn parent_id(&self) -> Option<u64> {
+		self.parent
+	}
+
+	fn number(&self) -> u64 {
+		self.number
+	}
+}
+
+/// Storage kept in a sorted map.
+#[derive(Clone, Debug, Default)]
+pub struct CheckState(pub BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl StorageExternalities<()> for CheckState {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, ()> {
+		Ok(self.0.get(key).cloned())
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.0.insert(key, value);
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		self.0.remove(key);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		for (key, value) in &self.0 {
+			f(key, value);
+		}
+	}
+}
+
+/// Error of the check executor.
+#[derive(Debug)]
+pub enum CheckError {
+	Overflow,
+}
+
+/// Executor summing the extrinsics of each block into a counter.
+#[derive(Clone, Debug, Default)]
+pub struct CheckExecutor;
+
+const COUNTER_KEY: &[u8] = b"counter";
+
+impl CheckExecutor {
+	fn add(&self, value: u64, state: &mut CheckState) -> Result<(), CheckError> {
+		let counter = state.0.get(COUNTER_KEY)
+			.map(|bytes| {
+				let mut array = [0u8; 8];
+				array.copy_from_slice(bytes);
+				u64::from_le_bytes(array)
+			})
+			.unwrap_or(0);
+		let counter = counter.checked_add(value).ok_or(CheckError::Overflow)?;
+		state.write_storage(COUNTER_KEY.to_vec(), counter.to_le_bytes().to_vec());
+		Ok(())
+	}
+}
+
+impl BlockExecutor for CheckExecutor {
+	type Error = CheckError;
+	type Block = CheckBlock;
+	type Externalities = CheckState;
+	type Event = ();
+
+	fn execute_block(
+		&self,
+		block: &CheckBlock,
+		state: &mut CheckState,
+	) -> Result<Vec<()>, CheckError> {
+		for extrinsic in &block.extrinsics {
+			self.add(*extrinsic, state)?;
+		}
+
+		Ok(Vec::new())
+	}
+}
+
+impl BuildGenesis for CheckExecutor {
+	fn build_genesis(&self, state: &mut CheckState) -> Result<CheckBlock, CheckError> {
+		self.add(0, state)?;
+
+		Ok(CheckBlock { id: 0, parent: None, number: 0, extrinsics: Vec::new() })
+	}
+}
+
+impl ExtrinsicBuilder for CheckExecutor {
+	type BuildBlock = CheckBlock;
+	type Inherent = ();
+	type Extrinsic = u64;
+
+	fn initialize_block(
+		&self,
+		parent_block: &CheckBlock,
+		_state: &mut CheckState,
+		_inherent: (),
+	) -> Result<CheckBlock, CheckError> {
+		Ok(CheckBlock {
+			id: parent_block.id + 1,
+			parent: Some(parent_block.id),
+			number: parent_block.number + 1,
+			extrinsics: Vec::new(),
+		})
+	}
+
+	fn apply_extrinsic(
+		&self,
+		block: &mut CheckBlock,
+		extrinsic: u64,
+		state: &mut CheckState,
+	) -> Result<ExtrinsicOutcome<()>, CheckError> {
+		self.add(extrinsic, state)?;
+		block.extrinsics.push(extrinsic);
+
+		Ok(ExtrinsicOutcome { weight: 0, events: Vec::new() })
+	}
+
+	fn finalize_block(
+		&self,
+		_block: &mut CheckBlock,
+		_state: &mut CheckState,
+	) -> Result<(), CheckError> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn builds_and_executes_block_without_std() {
+		let executor = CheckExecutor;
+		let mut genesis_state = CheckState::default();
+		let genesis = executor.build_genesis(&mut genesis_state).unwrap();
+
+		let mut state = genesis_state.clone();
+		let mut block = executor.initialize_block(&genesis, &mut state, ()).unwrap();
+		for extrinsic in [1, 2, 3].iter() {
+			executor.apply_extrinsic(&mut block, *extrinsic, &mut state).unwrap();
+		}
+		executor.finalize_block(&mut block, &mut state).unwrap();
+
+		let mut replayed = genesis_state;
+		executor.execute_block(&block, &mut replayed).unwrap();
+		assert_eq!(replayed.0, state.0);
+		assert_eq!(state.read_storage(COUNTER_KEY).unwrap(), Some(6u64.to_le_bytes().to_vec()));
+	}
+}
@@ -0,0 +1,20 @@
+use core::hash;
+use crate::Block;
+
+/// Wrapper comparing and hashing blocks by their id only.
+#[derive(Clone, Debug)]
+pub struct ById<B>(pub B);
+
+impl<B: Block> PartialEq for ById<B> {
+	fn eq(&self, other: &Self) -> bool {
+		self.0.id() == other.0.id()
+	}
+}
+
+impl<B: Block> Eq for ById<B> { }
+
+impl<B: Block> hash::Hash for ById<B> {
+	fn hash<H: hash::Hasher>(&self, state: &mut H) {
+		self.0.id().hash(state)
+	}
+}
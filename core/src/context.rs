@@ -0,0 +1,9 @@
+/// Context of a block being executed, known to the importer but not
+/// necessarily to the block itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockContext<Id> {
+	/// Id of the parent block.
+	pub parent_id: Id,
+	/// Number of the parent block.
+	pub parent_number: u64,
+}
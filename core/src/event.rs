@@ -0,0 +1,32 @@
+use alloc::vec::Vec;
+
+/// Extrinsic index of events emitted outside of any extrinsic, at the
+/// block level.
+pub const BLOCK_EVENT_INDEX: u32 = u32::MAX;
+
+/// Event tagged with the index of the extrinsic in the block that emitted
+/// it, or `BLOCK_EVENT_INDEX` for block level events.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventRecord<E> {
+	/// Index of the extrinsic emitting the event.
+	pub index: u32,
+	/// The event.
+	pub event: E,
+}
+
+impl<E> EventRecord<E> {
+	/// Whether the event was emitted at the block level.
+	pub fn is_block_event(&self) -> bool {
+		self.index == BLOCK_EVENT_INDEX
+	}
+}
+
+/// Outcome of applying an extrinsic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtrinsicOutcome<E> {
+	/// Weight actually consumed by the extrinsic, at most its estimated
+	/// weight. The difference is refunded to the block.
+	pub weight: u64,
+	/// Events emitted by the extrinsic.
+	pub events: Vec<E>,
+}
@@ -0,0 +1,163 @@
+#[cfg(feature = "std")]
+use std::error as stderror;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use core::future::Future;
+use core::pin::Pin;
+use crate::{Block, BlockExecutor, ExtrinsicBuilder, ExtrinsicOutcome};
+
+/// Boxed future returned by asynchronous executors.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output=T> + 'a>>;
+
+/// Asynchronous block executor
+pub trait AsyncBlockExecutor {
+	#[cfg(feature = "std")]
+	/// Error type
+	type Error: stderror::Error + 'static;
+	#[cfg(not(feature = "std"))]
+	/// Error type
+	type Error: 'static;
+	/// Block type
+	type Block: Block;
+	/// Externalities type
+	type Externalities: ?Sized;
+	/// Event type emitted during execution
+	type Event;
+
+	/// Execute the block via a block object and given state, returning
+	/// events emitted during the execution.
+	fn execute_block<'a>(
+		&'a self,
+		block: &'a Self::Block,
+		state: &'a mut Self::Externalities,
+	) -> BoxFuture<'a, Result<Vec<Self::Event>, Self::Error>>;
+}
+
+/// Asynchronous builder executor
+pub trait AsyncExtrinsicBuilder: AsyncBlockExecutor {
+	/// Build block type
+	type BuildBlock;
+	/// Extrinsic
+	type Extrinsic;
+
+	/// Apply extrinsic to a given block, returning the weight it consumed
+	/// and the events it emitted.
+	fn apply_extrinsic<'a>(
+		&'a self,
+		block: &'a mut Self::BuildBlock,
+		extrinsic: Self::Extrinsic,
+		state: &'a mut Self::Externalities,
+	) -> BoxFuture<'a, Result<ExtrinsicOutcome<Self::Event>, Self::Error>>;
+}
+
+/// Adapter turning a synchronous executor into an asynchronous one by
+/// running it inline. The wrapped executor runs to completion on the
+/// polling thread, when the returned future is first polled, so the future
+/// is ready on that first poll. It does not offload work from the polling
+/// thread, and blocks it for as long as execution takes; executors that are
+/// slow to run should be moved to a blocking thread by the caller instead.
+#[derive(Clone)]
+pub struct InlineExecutor<E>(pub E);
+
+impl<E: BlockExecutor> AsyncBlockExecutor for InlineExecutor<E> {
+	type Error = E::Error;
+	type Block = E::Block;
+	type Externalities = E::Externalities;
+	type Event = E::Event;
+
+	fn execute_block<'a>(
+		&'a self,
+		block: &'a Self::Block,
+		state: &'a mut Self::Externalities,
+	) -> BoxFuture<'a, Result<Vec<Self::Event>, Self::Error>> {
+		Box::pin(async move {
+			self.0.execute_block(block, state)
+		})
+	}
+}
+
+impl<E: ExtrinsicBuilder> AsyncExtrinsicBuilder for InlineExecutor<E> {
+	type BuildBlock = E::BuildBlock;
+	type Extrinsic = E::Extrinsic;
+
+	fn apply_extrinsic<'a>(
+		&'a self,
+		block: &'a mut Self::BuildBlock,
+		extrinsic: Self::Extrinsic,
+		state: &'a mut Self::Externalities,
+	) -> BoxFuture<'a, Result<ExtrinsicOutcome<Self::Event>, Self::Error>> {
+		Box::pin(async move {
+			self.0.apply_extrinsic(block, extrinsic, state)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::cell::Cell;
+	use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+	#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+	struct TestBlock;
+
+	impl Block for TestBlock {
+		type Identifier = ();
+
+		fn id(&self) { }
+		fn parent_id(&self) -> Option<()> { None }
+		fn number(&self) -> u64 { 0 }
+	}
+
+	#[derive(Debug)]
+	struct TestError;
+
+	impl core::fmt::Display for TestError {
+		fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+			write!(f, "{:?}", self)
+		}
+	}
+
+	impl stderror::Error for TestError { }
+
+	#[derive(Default)]
+	struct CountingExecutor {
+		executed: Cell<usize>,
+	}
+
+	impl BlockExecutor for CountingExecutor {
+		type Error = TestError;
+		type Block = TestBlock;
+		type Externalities = ();
+		type Event = usize;
+
+		fn execute_block(&self, _block: &TestBlock, _state: &mut ()) -> Result<Vec<usize>, TestError> {
+			self.executed.set(self.executed.get() + 1);
+			Ok(vec![self.executed.get()])
+		}
+	}
+
+	fn noop_waker() -> Waker {
+		fn clone(_: *const ()) -> RawWaker { RawWaker::new(core::ptr::null(), &VTABLE) }
+		fn noop(_: *const ()) { }
+		static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+		unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+	}
+
+	#[test]
+	fn runs_executor_inline_on_first_poll() {
+		let executor = InlineExecutor(CountingExecutor::default());
+		let mut state = ();
+		let mut future = executor.execute_block(&TestBlock, &mut state);
+		assert_eq!(executor.0.executed.get(), 0);
+
+		let waker = noop_waker();
+		let mut context = Context::from_waker(&waker);
+		match future.as_mut().poll(&mut context) {
+			Poll::Ready(Ok(events)) => assert_eq!(events, vec![1]),
+			_ => panic!("inline execution is ready on first poll"),
+		}
+		assert_eq!(executor.0.executed.get(), 1);
+	}
+}
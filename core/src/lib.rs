@@ -5,4 +5,12 @@
 extern crate alloc;
 
 mod traits;
+mod future;
+mod by_id;
+mod event;
+mod context;
 pub use crate::traits::*;
+pub use crate::future::*;
+pub use crate::by_id::ById;
+pub use crate::event::{EventRecord, ExtrinsicOutcome, BLOCK_EVENT_INDEX};
+pub use crate::context::BlockContext;
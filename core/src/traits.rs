@@ -1,17 +1,38 @@
 #[cfg(feature = "std")]
 use std::error as stderror;
 use alloc::vec::Vec;
-use core::hash;
+use alloc::collections::BTreeMap;
+use core::{fmt, hash};
+use core::any::{Any, TypeId};
+use crate::{BlockContext, ExtrinsicOutcome};
 
 /// A block contains a hash, and reference a parent block via parent hash.
 pub trait Block: Clone {
 	/// Hash type of the block.
-	type Identifier: Clone + Eq + hash::Hash;
+	type Identifier: Clone + Eq + hash::Hash + fmt::Debug;
 
 	/// Get the block hash.
 	fn id(&self) -> Self::Identifier;
 	/// Get the parent block hash. None if this block is genesis.
 	fn parent_id(&self) -> Option<Self::Identifier>;
+	/// Get the block number. Genesis is 0, and each child is its parent's
+	/// number plus one.
+	fn number(&self) -> u64;
+
+	/// Whether this block is genesis, i.e. has no parent.
+	fn is_genesis(&self) -> bool {
+		self.parent_id().is_none()
+	}
+}
+
+/// Block whose header can be taken apart from its body, so that the chain
+/// can be followed without extrinsics or state, such as by light clients.
+pub trait HeaderBlock: Block {
+	/// Header type, identified the same as the block.
+	type Header: Block<Identifier=Self::Identifier>;
+
+	/// Get the header of the block.
+	fn header(&self) -> Self::Header;
 }
 
 /// A value where the key is contained in.
@@ -56,10 +77,105 @@ impl AsExternalities<dyn NullExternalities> for () {
 pub trait StorageExternalities<Error> {
 	/// Read storage value.
 	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+	/// Visit a storage value without copying it. The default implementation
+	/// falls back to `read_storage`.
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), Error> {
+		let value = self.read_storage(key)?;
+		f(value.as_deref());
+		Ok(())
+	}
+	/// Check whether a storage value exists.
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		self.read_storage(key).map(|v| v.is_some()).unwrap_or(false)
+	}
 	/// Write storage value.
 	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>);
+	/// Write storage values at once. The default implementation writes them
+	/// one by one.
+	fn write_storage_batch(&mut self, entries: Vec<(Vec<u8>, Vec<u8>)>) {
+		for (key, value) in entries {
+			self.write_storage(key, value);
+		}
+	}
 	/// Remove storage value.
 	fn remove_storage(&mut self, key: &[u8]);
+	/// Visit all storage entries, in ascending order of keys.
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8]));
+	/// Remove all storage values whose key starts with the given prefix.
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		let mut keys = Vec::new();
+		self.for_each_storage(&mut |key, _| {
+			if key.starts_with(prefix) {
+				keys.push(key.to_vec());
+			}
+		});
+
+		for key in keys {
+			self.remove_storage(&key);
+		}
+	}
+}
+
+/// Read-only view of a key value based storage, for code that must not
+/// change state, such as queries. Every `StorageExternalities` can be
+/// borrowed as one. State cannot be changed through the view:
+///
+/// ```compile_fail
+/// use blockchain_core::ReadOnlyExternalities;
+///
+/// fn clear(state: &mut dyn ReadOnlyExternalities<()>) {
+///     state.write_storage(b"key".to_vec(), Vec::new());
+/// }
+/// ```
+pub trait ReadOnlyExternalities<Error> {
+	/// Read storage value.
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+	/// Visit a storage value without copying it.
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), Error>;
+	/// Check whether a storage value exists.
+	fn exists_storage(&self, key: &[u8]) -> bool;
+	/// Visit all storage entries, in ascending order of keys.
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8]));
+}
+
+impl<Error, T: StorageExternalities<Error> + ?Sized> ReadOnlyExternalities<Error> for T {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		StorageExternalities::read_storage(self, key)
+	}
+
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), Error> {
+		StorageExternalities::read_storage_ref(self, key, f)
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		StorageExternalities::exists_storage(self, key)
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		StorageExternalities::for_each_storage(self, f)
+	}
+}
+
+/// Externalities providing host extensions, such as crypto or randomness,
+/// looked up by type.
+pub trait ExtensionExternalities {
+	/// Get the extension with the given type id.
+	fn extension(&mut self, type_id: TypeId) -> Option<&mut dyn Any>;
+}
+
+/// Storage changes by key. `None` marks a removed key.
+pub type StateDiff = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Externalities that can group storage changes into nested transactions.
+pub trait TransactionalExternalities {
+	/// Start a new transaction. Transactions nest as a stack.
+	fn start_transaction(&mut self);
+	/// Commit the innermost transaction, merging its changes into the
+	/// enclosing one.
+	fn commit_transaction(&mut self);
+	/// Rollback the innermost transaction, discarding all changes made
+	/// since it started.
+	fn rollback_transaction(&mut self);
 }
 
 /// Block executor
@@ -74,13 +190,155 @@ pub trait BlockExecutor {
 	type Block: Block;
 	/// Externalities type
 	type Externalities: ?Sized;
+	/// Event type emitted during execution
+	type Event;
 
-	/// Execute the block via a block object and given state.
+	/// Execute the block via a block object and given state, returning
+	/// events emitted during the execution.
 	fn execute_block(
 		&self,
 		block: &Self::Block,
 		state: &mut Self::Externalities
+	) -> Result<Vec<Self::Event>, Self::Error>;
+
+	/// Execute the block with the context of its parent. By default the
+	/// context is ignored.
+	fn execute_block_with_context(
+		&self,
+		block: &Self::Block,
+		_context: &BlockContext<<Self::Block as Block>::Identifier>,
+		state: &mut Self::Externalities
+	) -> Result<Vec<Self::Event>, Self::Error> {
+		self.execute_block(block, state)
+	}
+
+	/// Hook run at the start of every block with the given number, before
+	/// any extrinsic. Implementations of `execute_block` and block builders
+	/// are expected to call it. By default nothing is done.
+	fn on_initialize(
+		&self,
+		_number: u64,
+		_state: &mut Self::Externalities
+	) -> Result<(), Self::Error> {
+		Ok(())
+	}
+
+	/// Hook run at the end of every block with the given number, after all
+	/// extrinsics and before the state root is computed. By default nothing
+	/// is done.
+	fn on_finalize(
+		&self,
+		_number: u64,
+		_state: &mut Self::Externalities
+	) -> Result<(), Self::Error> {
+		Ok(())
+	}
+
+	/// Check structural validity of the block without touching state. By
+	/// default no checks are done.
+	fn verify_block(&self, _block: &Self::Block) -> Result<(), Self::Error> {
+		Ok(())
+	}
+
+	/// Whether the error is the state after executing a block differing
+	/// from the state root the block commits to, which import reports
+	/// apart from other execution errors. By default no error is.
+	fn is_state_root_mismatch(&self, _error: &Self::Error) -> bool {
+		false
+	}
+}
+
+/// Verifier of block headers, checking them on their own, without the
+/// block body or state.
+pub trait HeaderVerifier {
+	#[cfg(feature = "std")]
+	/// Error type
+	type Error: stderror::Error + 'static;
+	#[cfg(not(feature = "std"))]
+	/// Error type
+	type Error: 'static;
+	/// Header type
+	type Header: Block;
+
+	/// Check validity of the header, such as its seal.
+	fn verify_header(&self, header: &Self::Header) -> Result<(), Self::Error>;
+
+	/// Check validity of the header with the context of its parent. By
+	/// default the context is ignored.
+	fn verify_header_with_context(
+		&self,
+		header: &Self::Header,
+		_context: &BlockContext<<Self::Header as Block>::Identifier>,
+	) -> Result<(), Self::Error> {
+		self.verify_header(header)
+	}
+}
+
+/// Executor that can apply a precomputed state diff of a block instead of
+/// executing it.
+pub trait DiffExecutor: BlockExecutor {
+	/// Apply the state diff of the block, and check that the resulting state
+	/// matches what the block commits to.
+	fn apply_diff(
+		&self,
+		block: &Self::Block,
+		diff: &StateDiff,
+		state: &mut Self::Externalities,
 	) -> Result<(), Self::Error>;
+
+	/// Apply the state diff of the block with the context of its parent. By
+	/// default the context is ignored.
+	fn apply_diff_with_context(
+		&self,
+		block: &Self::Block,
+		_context: &BlockContext<<Self::Block as Block>::Identifier>,
+		diff: &StateDiff,
+		state: &mut Self::Externalities,
+	) -> Result<(), Self::Error> {
+		self.apply_diff(block, diff, state)
+	}
+}
+
+/// Executor that can build the genesis block.
+pub trait BuildGenesis: BlockExecutor {
+	/// Write the initial storage, and return the genesis block committing
+	/// to it.
+	fn build_genesis(
+		&self,
+		state: &mut Self::Externalities,
+	) -> Result<Self::Block, Self::Error>;
+	/// Check that the state is the one the genesis block commits to. The
+	/// default implementation accepts any state.
+	fn verify_genesis(
+		&self,
+		_block: &Self::Block,
+		_state: &mut Self::Externalities,
+	) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+/// Policy deciding which extrinsics are accepted, on top of the rules of
+/// the executor.
+pub trait ExtrinsicFilter<Extrinsic> {
+	/// Whether the extrinsic is allowed.
+	fn allow(&self, extrinsic: &Extrinsic) -> bool;
+}
+
+/// Filter allowing all extrinsics.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllowAll;
+
+impl<Extrinsic> ExtrinsicFilter<Extrinsic> for AllowAll {
+	fn allow(&self, _extrinsic: &Extrinsic) -> bool {
+		true
+	}
+}
+
+impl<Extrinsic, F: Fn(&Extrinsic) -> bool> ExtrinsicFilter<Extrinsic> for F {
+	fn allow(&self, extrinsic: &Extrinsic) -> bool {
+		self(extrinsic)
+	}
 }
 
 /// Builder executor
@@ -100,13 +358,16 @@ pub trait ExtrinsicBuilder: BlockExecutor {
 		inherent: Self::Inherent,
 	) -> Result<Self::BuildBlock, Self::Error>;
 
-	/// Apply extrinsic to a given block.
+	/// Apply extrinsic to a given block, returning the weight it consumed
+	/// and the events it emitted. Applied extrinsics must be appended to the
+	/// block in the order they are applied, which is the order
+	/// `execute_block` replays them in.
 	fn apply_extrinsic(
 		&self,
 		block: &mut Self::BuildBlock,
 		extrinsic: Self::Extrinsic,
 		state: &mut Self::Externalities,
-	) -> Result<(), Self::Error>;
+	) -> Result<ExtrinsicOutcome<Self::Event>, Self::Error>;
 
 	/// Finalize a block.
 	fn finalize_block(
@@ -114,4 +375,53 @@ pub trait ExtrinsicBuilder: BlockExecutor {
 		block: &mut Self::BuildBlock,
 		state: &mut Self::Externalities,
 	) -> Result<(), Self::Error>;
+
+	/// Maximum number of extrinsics a block can contain.
+	fn max_extrinsics(&self) -> usize {
+		usize::MAX
+	}
+
+	/// Weight of applying the extrinsic. By default extrinsics weigh
+	/// nothing.
+	fn weight(&self, _extrinsic: &Self::Extrinsic) -> u64 {
+		0
+	}
+
+	/// Maximum total weight of extrinsics a block can contain.
+	fn max_weight(&self) -> u64 {
+		u64::MAX
+	}
+
+	/// Total weight used up by the extrinsics of an executed block, as
+	/// accounted by `execute_block`. By default blocks weigh nothing.
+	fn block_weight(&self, _block: &Self::Block) -> u64 {
+		0
+	}
+
+	/// Execute the block with the context of its parent, returning its
+	/// events and the total weight its extrinsics used up, as accounted
+	/// while executing them. By default the block is executed with
+	/// `execute_block_with_context`, and weighed with `block_weight`.
+	fn execute_block_with_weight(
+		&self,
+		block: &Self::Block,
+		context: &BlockContext<<Self::Block as Block>::Identifier>,
+		state: &mut Self::Externalities,
+	) -> Result<(Vec<Self::Event>, u64), Self::Error> {
+		let events = self.execute_block_with_context(block, context, state)?;
+		Ok((events, self.block_weight(block)))
+	}
+
+	/// Priority of the extrinsic when more are pending than fit in a
+	/// block. Higher priority extrinsics are included first. By default
+	/// all extrinsics have the same priority.
+	fn priority(&self, _extrinsic: &Self::Extrinsic) -> u64 {
+		0
+	}
+}
+
+/// Builder executor that can decode extrinsics received as raw bytes.
+pub trait DecodeExtrinsic: ExtrinsicBuilder {
+	/// Validate and decode an extrinsic.
+	fn decode_extrinsic(&self, bytes: &[u8]) -> Result<Self::Extrinsic, Self::Error>;
 }
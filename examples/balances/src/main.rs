@@ -0,0 +1,40 @@
+extern crate parity_codec as codec;
+
+mod runtime;
+
+use std::convert::Infallible;
+use primitive_types::H256;
+use blockchain::{Block as _, StorageExternalities};
+use blockchain::backend::ChainQuery;
+use blockchain::format::format_id_short;
+use blockchain::inherent::{InherentDataProviders, TimestampProvider};
+use blockchain::testing::TestChain;
+use crate::runtime::{Executor, Extrinsic, UnsealedBlock, balance, total_issuance};
+
+fn main() {
+	let alice = H256::repeat_byte(1);
+	let bob = H256::repeat_byte(2);
+	let executor = Executor::new(8).with_endowed(vec![(alice, 1000), (bob, 100)]);
+	let chain = TestChain::new(executor, UnsealedBlock::seal).unwrap();
+
+	let mut providers = InherentDataProviders::new();
+	providers.register(TimestampProvider);
+
+	for amount in &[300, 800] {
+		let transfer = Extrinsic::Transfer { from: alice, to: bob, amount: *amount };
+		match chain.build_block(providers.create_inherent_data(), vec![transfer]) {
+			Ok(block) => println!("Transferred {} in block {}", amount, format_id_short(&block.id())),
+			Err(err) => println!("Transfer of {} rejected: {}", amount, err),
+		}
+	}
+
+	let backend = chain.backend();
+	let state = backend.state_at(&backend.head()).unwrap();
+	let state: &dyn StorageExternalities<Infallible> = &state;
+	println!(
+		"Alice: {}, Bob: {}, total issuance: {}",
+		balance(state, &alice).unwrap(),
+		balance(state, &bob).unwrap(),
+		total_issuance(state).unwrap(),
+	);
+}
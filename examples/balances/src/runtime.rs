@@ -0,0 +1,513 @@
+use primitive_types::H256;
+use blockchain::{
+	Block as BlockT, BlockContext, BlockExecutor, BuildGenesis, ExtrinsicBuilder,
+	StorageExternalities, AsExternalities, EventRecord, ExtrinsicOutcome,
+};
+use blockchain::backend::KeyValueMemoryState;
+use blockchain::consensus::pow::{self, PowBlock};
+use blockchain::inherent::{InherentData, TimestampProvider};
+use blockchain::proof;
+use codec::{Encode, Decode};
+use sha3::{Digest, Sha3_256};
+use std::convert::Infallible;
+use blockchain::storage::{self, StorageMap, ReadDecoded, StateCorruption};
+
+const MAX_EXTRINSICS: usize = 1024;
+
+/// Account identifier.
+pub type AccountId = H256;
+
+/// Balances of accounts.
+pub const BALANCES: StorageMap<AccountId, u128> = StorageMap::new(b"Balances:balance");
+
+/// Storage key of the total supply of all balances.
+pub fn total_issuance_key() -> Vec<u8> {
+	storage::storage_key(b"Balances", b"total_issuance")
+}
+
+/// Storage key of the timestamp of the latest block.
+pub fn timestamp_key() -> Vec<u8> {
+	storage::storage_key(b"Timestamp", b"now")
+}
+
+fn hash(data: &[u8]) -> H256 {
+	H256::from_slice(Sha3_256::digest(data).as_slice())
+}
+
+pub trait BalancesExternalities: StorageExternalities<Infallible> { }
+
+/// Get the Merkle root of the current storage.
+pub fn storage_root(state: &(dyn BalancesExternalities + 'static)) -> H256 {
+	proof::storage_root(state, hash)
+}
+
+impl BalancesExternalities for KeyValueMemoryState { }
+
+impl AsExternalities<dyn BalancesExternalities> for KeyValueMemoryState {
+	fn as_externalities(&mut self) -> &mut (dyn BalancesExternalities + 'static) {
+		self
+	}
+}
+
+/// Commitment of the header to the block body, as the hash of the encoded
+/// extrinsics.
+pub fn extrinsics_root(extrinsics: &[Extrinsic]) -> H256 {
+	hash(&extrinsics.encode())
+}
+
+#[derive(Clone, Debug)]
+pub struct UnsealedBlock {
+	parent_hash: Option<H256>,
+	number: u64,
+	timestamp: u64,
+	difficulty: u64,
+	state_root: H256,
+	extrinsics_root: H256,
+	extrinsics: Vec<Extrinsic>,
+}
+
+impl UnsealedBlock {
+	pub fn seal(self) -> Block {
+		let difficulty = self.difficulty as usize;
+		let block = Block {
+			parent_hash: self.parent_hash,
+			number: self.number,
+			timestamp: self.timestamp,
+			difficulty: self.difficulty,
+			state_root: self.state_root,
+			extrinsics_root: self.extrinsics_root,
+			extrinsics: self.extrinsics,
+			nonce: 0,
+		};
+
+		pow::seal_parallel(block, difficulty)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Block {
+	parent_hash: Option<H256>,
+	number: u64,
+	timestamp: u64,
+	difficulty: u64,
+	state_root: H256,
+	extrinsics_root: H256,
+	extrinsics: Vec<Extrinsic>,
+	nonce: u64,
+}
+
+impl BlockT for Block {
+	type Identifier = H256;
+
+	fn parent_id(&self) -> Option<H256> {
+		self.parent_hash
+	}
+
+	fn id(&self) -> H256 {
+		Self::seal_hash(&self.pre_hash(), self.nonce)
+	}
+
+	fn number(&self) -> u64 {
+		self.number
+	}
+}
+
+impl PowBlock for Block {
+	fn nonce(&self) -> u64 {
+		self.nonce
+	}
+
+	fn set_nonce(&mut self, nonce: u64) {
+		self.nonce = nonce;
+	}
+
+	fn difficulty(&self) -> usize {
+		self.difficulty as usize
+	}
+
+	fn timestamp(&self) -> u64 {
+		self.timestamp
+	}
+
+	fn pre_hash(&self) -> H256 {
+		hash(&(
+			self.parent_hash,
+			self.number,
+			self.timestamp,
+			self.difficulty,
+			self.state_root,
+			self.extrinsics_root,
+		).encode())
+	}
+
+	fn seal_hash(pre_hash: &H256, nonce: u64) -> H256 {
+		hash(&(pre_hash, nonce).encode())
+	}
+}
+
+/// Extrinsic of the balances runtime. Transfers are not signed, so the
+/// sender is trusted as given.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Encode, Decode)]
+pub enum Extrinsic {
+	/// Move `amount` from the balance of `from` to the balance of `to`.
+	Transfer { from: AccountId, to: AccountId, amount: u128 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+	Transferred { from: AccountId, to: AccountId, amount: u128 },
+}
+
+#[derive(Debug)]
+pub enum Error {
+	InvalidSeal,
+	DifficultyMismatch,
+	StateCorruption,
+	StateRootMismatch,
+	ExtrinsicsRootMismatch,
+	ParentMismatch,
+	InvalidTimestamp,
+	InsufficientBalance,
+	BalanceOverflow,
+	BlockFull,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<StateCorruption> for Error {
+	fn from(_: StateCorruption) -> Self {
+		Error::StateCorruption
+	}
+}
+
+/// Read the balance of the account. Accounts without a balance hold
+/// nothing.
+pub fn balance<S>(state: &S, account: &AccountId) -> Result<u128, Error> where
+	S: StorageExternalities<Infallible> + ?Sized,
+{
+	Ok(BALANCES.get(state, account)?.unwrap_or(0))
+}
+
+/// Read the total supply of all balances.
+pub fn total_issuance<S>(state: &S) -> Result<u128, Error> where
+	S: StorageExternalities<Infallible> + ?Sized,
+{
+	Ok(state.read_decoded(&total_issuance_key())?.unwrap_or(0))
+}
+
+#[derive(Clone)]
+pub struct Executor {
+	difficulty: u64,
+	endowed: Vec<(AccountId, u128)>,
+}
+
+impl Executor {
+	/// Create an executor sealing blocks at the given difficulty, in
+	/// leading zero bits of the seal.
+	pub fn new(difficulty: u64) -> Self {
+		Self { difficulty, endowed: Vec::new() }
+	}
+
+	/// Endow the accounts with the given balances at genesis.
+	pub fn with_endowed(mut self, endowed: Vec<(AccountId, u128)>) -> Self {
+		self.endowed = endowed;
+		self
+	}
+
+	/// Move `amount` from `from` to `to`, rejecting transfers that overdraw
+	/// the sender. Total issuance is left unchanged.
+	fn transfer(
+		&self,
+		from: &AccountId,
+		to: &AccountId,
+		amount: u128,
+		state: &mut <Self as BlockExecutor>::Externalities,
+	) -> Result<(), Error> {
+		let from_balance = balance(state, from)?
+			.checked_sub(amount)
+			.ok_or(Error::InsufficientBalance)?;
+		BALANCES.insert(state, from, &from_balance);
+
+		let to_balance = balance(state, to)?
+			.checked_add(amount)
+			.ok_or(Error::BalanceOverflow)?;
+		BALANCES.insert(state, to, &to_balance);
+
+		Ok(())
+	}
+
+	fn apply(
+		&self,
+		extrinsic: &Extrinsic,
+		state: &mut <Self as BlockExecutor>::Externalities,
+	) -> Result<Event, Error> {
+		match extrinsic {
+			Extrinsic::Transfer { from, to, amount } => {
+				self.transfer(from, to, *amount, state)?;
+				Ok(Event::Transferred { from: *from, to: *to, amount: *amount })
+			},
+		}
+	}
+
+	fn read_timestamp(&self, state: &mut <Self as BlockExecutor>::Externalities) -> Result<u64, Error> {
+		Ok(state.read_decoded(&timestamp_key())?.unwrap_or(0))
+	}
+}
+
+impl BlockExecutor for Executor {
+	type Error = Error;
+	type Block = Block;
+	type Externalities = dyn BalancesExternalities + 'static;
+	type Event = EventRecord<Event>;
+
+	fn execute_block(
+		&self,
+		block: &Block,
+		state: &mut Self::Externalities,
+	) -> Result<Vec<EventRecord<Event>>, Error> {
+		self.verify_block(block)?;
+
+		if block.timestamp <= self.read_timestamp(state)? {
+			return Err(Error::InvalidTimestamp);
+		}
+		state.write_storage(timestamp_key(), block.timestamp.encode());
+		self.on_initialize(block.number, state)?;
+
+		if block.extrinsics.len() > MAX_EXTRINSICS {
+			return Err(Error::BlockFull);
+		}
+
+		let mut events = Vec::new();
+		for (index, extrinsic) in block.extrinsics.iter().enumerate() {
+			events.push(EventRecord {
+				index: index as u32,
+				event: self.apply(extrinsic, state)?,
+			});
+		}
+		self.on_finalize(block.number, state)?;
+
+		if storage_root(state) != block.state_root {
+			return Err(Error::StateRootMismatch);
+		}
+
+		Ok(events)
+	}
+
+	fn execute_block_with_context(
+		&self,
+		block: &Block,
+		context: &BlockContext<H256>,
+		state: &mut Self::Externalities,
+	) -> Result<Vec<EventRecord<Event>>, Error> {
+		if block.parent_hash != Some(context.parent_id) ||
+			block.number != context.parent_number + 1
+		{
+			return Err(Error::ParentMismatch);
+		}
+
+		self.execute_block(block, state)
+	}
+
+	fn verify_block(&self, block: &Block) -> Result<(), Error> {
+		if block.difficulty < self.difficulty {
+			return Err(Error::DifficultyMismatch);
+		}
+
+		if extrinsics_root(&block.extrinsics) != block.extrinsics_root {
+			return Err(Error::ExtrinsicsRootMismatch);
+		}
+
+		let seal = Block::seal_hash(&block.pre_hash(), block.nonce);
+		if !pow::meets_difficulty(&seal[..], block.difficulty as usize) {
+			return Err(Error::InvalidSeal);
+		}
+
+		Ok(())
+	}
+
+	fn is_state_root_mismatch(&self, error: &Error) -> bool {
+		matches!(error, Error::StateRootMismatch)
+	}
+}
+
+impl BuildGenesis for Executor {
+	fn build_genesis(
+		&self,
+		state: &mut Self::Externalities,
+	) -> Result<Block, Error> {
+		let mut total_issuance = 0u128;
+		for (account, amount) in &self.endowed {
+			let endowed = balance(state, account)?
+				.checked_add(*amount)
+				.ok_or(Error::BalanceOverflow)?;
+			BALANCES.insert(state, account, &endowed);
+			total_issuance = total_issuance.checked_add(*amount).ok_or(Error::BalanceOverflow)?;
+		}
+		state.write_storage(total_issuance_key(), total_issuance.encode());
+
+		Ok(Block {
+			parent_hash: None,
+			number: 0,
+			timestamp: 0,
+			difficulty: 0,
+			state_root: storage_root(state),
+			extrinsics_root: extrinsics_root(&[]),
+			extrinsics: Vec::new(),
+			nonce: 0,
+		})
+	}
+
+	fn verify_genesis(
+		&self,
+		block: &Block,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		if storage_root(state) != block.state_root {
+			return Err(Error::StateRootMismatch);
+		}
+
+		Ok(())
+	}
+}
+
+impl ExtrinsicBuilder for Executor {
+	type BuildBlock = UnsealedBlock;
+	type Extrinsic = Extrinsic;
+	type Inherent = InherentData;
+
+	fn initialize_block(
+		&self,
+		block: &Block,
+		state: &mut Self::Externalities,
+		inherent: InherentData,
+	) -> Result<UnsealedBlock, Error> {
+		let timestamp = TimestampProvider::timestamp(&inherent)
+			.ok_or(Error::InvalidTimestamp)?;
+		let timestamp = core::cmp::max(timestamp, block.timestamp + 1);
+		state.write_storage(timestamp_key(), timestamp.encode());
+		self.on_initialize(block.number + 1, state)?;
+
+		Ok(UnsealedBlock {
+			parent_hash: Some(block.id()),
+			number: block.number + 1,
+			timestamp,
+			difficulty: self.difficulty,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extrinsics: Vec::new(),
+		})
+	}
+
+	fn apply_extrinsic(
+		&self,
+		block: &mut UnsealedBlock,
+		extrinsic: Extrinsic,
+		state: &mut Self::Externalities,
+	) -> Result<ExtrinsicOutcome<EventRecord<Event>>, Error> {
+		if block.extrinsics.len() >= MAX_EXTRINSICS {
+			return Err(Error::BlockFull);
+		}
+
+		let event = self.apply(&extrinsic, state)?;
+		let index = block.extrinsics.len() as u32;
+		block.extrinsics.push(extrinsic);
+
+		Ok(ExtrinsicOutcome { weight: 0, events: vec![EventRecord { index, event }] })
+	}
+
+	fn finalize_block(
+		&self,
+		block: &mut UnsealedBlock,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		self.on_finalize(block.number, state)?;
+		block.state_root = storage_root(state);
+		block.extrinsics_root = extrinsics_root(&block.extrinsics);
+
+		Ok(())
+	}
+
+	fn max_extrinsics(&self) -> usize {
+		MAX_EXTRINSICS
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use blockchain::backend::ChainQuery;
+	use blockchain::inherent::InherentDataProviders;
+	use blockchain::testing::TestChain;
+
+	fn alice() -> AccountId { H256::repeat_byte(1) }
+	fn bob() -> AccountId { H256::repeat_byte(2) }
+
+	type Chain = TestChain<Executor, fn(UnsealedBlock) -> Block>;
+
+	fn chain() -> Chain {
+		let executor = Executor::new(1).with_endowed(vec![(alice(), 1000), (bob(), 100)]);
+		TestChain::new(executor, UnsealedBlock::seal as fn(UnsealedBlock) -> Block).unwrap()
+	}
+
+	fn inherent() -> InherentData {
+		let mut providers = InherentDataProviders::new();
+		providers.register(TimestampProvider);
+		providers.create_inherent_data()
+	}
+
+	fn head_state(chain: &Chain) -> KeyValueMemoryState {
+		let backend = chain.backend();
+		backend.state_at(&backend.head()).unwrap()
+	}
+
+	#[test]
+	fn transfers_between_accounts() {
+		let chain = chain();
+		let transfer = Extrinsic::Transfer { from: alice(), to: bob(), amount: 300 };
+		chain.build_block(inherent(), vec![transfer]).unwrap();
+
+		let state = head_state(&chain);
+		assert_eq!(balance(&state, &alice()).unwrap(), 700);
+		assert_eq!(balance(&state, &bob()).unwrap(), 400);
+	}
+
+	#[test]
+	fn rejects_overdraw() {
+		let chain = chain();
+		let genesis = chain.head();
+
+		let mut state = head_state(&chain);
+		let result = chain.executor().transfer(&bob(), &alice(), 101, state.as_externalities());
+		assert!(matches!(result, Err(Error::InsufficientBalance)));
+
+		let transfer = Extrinsic::Transfer { from: bob(), to: alice(), amount: 101 };
+		assert!(chain.build_block(inherent(), vec![transfer]).is_err());
+		assert_eq!(chain.head(), genesis);
+		assert_eq!(balance(&head_state(&chain), &bob()).unwrap(), 100);
+	}
+
+	#[test]
+	fn conserves_total_supply() {
+		let chain = chain();
+		let transfers = vec![
+			Extrinsic::Transfer { from: alice(), to: bob(), amount: 250 },
+			Extrinsic::Transfer { from: bob(), to: H256::repeat_byte(3), amount: 300 },
+			Extrinsic::Transfer { from: alice(), to: alice(), amount: 50 },
+		];
+		chain.build_block(inherent(), transfers).unwrap();
+
+		let state = head_state(&chain);
+		let accounts = [alice(), bob(), H256::repeat_byte(3)];
+		let supply = accounts.iter()
+			.map(|account| balance(&state, account).unwrap())
+			.sum::<u128>();
+		assert_eq!(supply, 1100);
+		assert_eq!(total_issuance(&state).unwrap(), supply);
+	}
+}
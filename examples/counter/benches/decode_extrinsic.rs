@@ -0,0 +1,30 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use blockchain::DecodeExtrinsic;
+use parity_codec::Encode;
+use counter::runtime::{Executor, ChainConfig, Extrinsic, Add, Sub};
+
+/// Number of extrinsics decoded per iteration.
+const BATCH: usize = 1024;
+
+fn decode_extrinsic(c: &mut Criterion) {
+	let executor = Executor::new(ChainConfig::default());
+	let batch = (0..BATCH as u128)
+		.map(|i| if i % 2 == 0 {
+			Extrinsic::Add(Add(i * 1_000_003))
+		} else {
+			Extrinsic::Sub(Sub(u128::MAX - i))
+		}.encode())
+		.collect::<Vec<_>>();
+
+	let mut group = c.benchmark_group("decode_extrinsic");
+	group.throughput(Throughput::Elements(BATCH as u64));
+	group.bench_function("add_sub", |b| b.iter(|| {
+		for encoded in &batch {
+			criterion::black_box(executor.decode_extrinsic(criterion::black_box(encoded)).unwrap());
+		}
+	}));
+	group.finish();
+}
+
+criterion_group!(benches, decode_extrinsic);
+criterion_main!(benches);
@@ -0,0 +1,98 @@
+use core::fmt::Debug;
+use codec::{Encode, Decode};
+use crate::hash::Hasher;
+use crate::runtime::{Block, Error, Extrinsic};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+/// Values a codec can encode.
+#[cfg(feature = "serde")]
+pub trait Encodable: Encode + Serialize { }
+#[cfg(feature = "serde")]
+impl<T: Encode + Serialize + ?Sized> Encodable for T { }
+
+/// Values a codec can encode.
+#[cfg(not(feature = "serde"))]
+pub trait Encodable: Encode { }
+#[cfg(not(feature = "serde"))]
+impl<T: Encode + ?Sized> Encodable for T { }
+
+/// Values a codec can decode.
+#[cfg(feature = "serde")]
+pub trait Decodable: Decode + DeserializeOwned { }
+#[cfg(feature = "serde")]
+impl<T: Decode + DeserializeOwned> Decodable for T { }
+
+/// Values a codec can decode.
+#[cfg(not(feature = "serde"))]
+pub trait Decodable: Decode { }
+#[cfg(not(feature = "serde"))]
+impl<T: Decode> Decodable for T { }
+
+/// Encoding used for block ids and transport. Ids are only stable for a
+/// given codec, so nodes of a chain must agree on it.
+pub trait Codec: Clone + Debug + Send + Sync + 'static {
+	/// Encode the value.
+	fn encode<T: Encodable + ?Sized>(value: &T) -> Vec<u8>;
+	/// Decode a value, failing unless all bytes are consumed.
+	fn decode<T: Decodable>(bytes: &[u8]) -> Option<T>;
+
+	/// Decode a block received from the network. By default it is decoded
+	/// as any other value.
+	fn decode_block<H: Hasher>(bytes: &[u8]) -> Result<Block<H, Self>, Error> {
+		Self::decode(bytes).ok_or(Error::InvalidEncoding)
+	}
+
+	/// Decode an extrinsic received from the network. By default it is
+	/// decoded as any other value.
+	fn decode_extrinsic(bytes: &[u8]) -> Result<Extrinsic, Error> {
+		Self::decode(bytes).ok_or(Error::InvalidEncoding)
+	}
+}
+
+/// SCALE codec.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScaleCodec;
+
+impl Codec for ScaleCodec {
+	fn encode<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+		Encode::encode(value)
+	}
+
+	fn decode<T: Decodable>(bytes: &[u8]) -> Option<T> {
+		let input = &mut &bytes[..];
+		let value = Decode::decode(input)?;
+		if !input.is_empty() {
+			return None
+		}
+
+		Some(value)
+	}
+
+	/// Derived decoding of nested extrinsics is unbounded, so blocks and
+	/// extrinsics are decoded with limits instead.
+	fn decode_block<H: Hasher>(bytes: &[u8]) -> Result<Block<H, Self>, Error> {
+		Block::decode_scale(bytes)
+	}
+
+	fn decode_extrinsic(bytes: &[u8]) -> Result<Extrinsic, Error> {
+		Extrinsic::decode_scale(bytes)
+	}
+}
+
+/// JSON codec.
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct JsonCodec;
+
+#[cfg(feature = "serde")]
+impl Codec for JsonCodec {
+	fn encode<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+		serde_json::to_vec(value).expect("Counter types always serialize; qed")
+	}
+
+	fn decode<T: Decodable>(bytes: &[u8]) -> Option<T> {
+		serde_json::from_slice(bytes).ok()
+	}
+}
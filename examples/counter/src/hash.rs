@@ -0,0 +1,68 @@
+use core::fmt::Debug;
+use primitive_types::H256;
+use codec::{Encode, Decode};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+use sha3::{Digest, Sha3_256, Keccak256};
+use blake2::Blake2s;
+
+/// Hash function used for block ids and state roots. The choice is
+/// consensus-critical: nodes using different hashers compute different ids
+/// for the same blocks, and cannot follow each other's chain.
+pub trait Hasher: Clone + Debug + Send + Sync + 'static {
+	/// Hash the data into a 256-bit digest.
+	fn hash(data: &[u8]) -> H256;
+}
+
+/// SHA3-256 hasher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sha3Hasher;
+
+impl Hasher for Sha3Hasher {
+	fn hash(data: &[u8]) -> H256 {
+		H256::from_slice(Sha3_256::digest(data).as_slice())
+	}
+}
+
+/// Keccak-256 hasher, as used by Ethereum. Not the same as SHA3-256,
+/// which pads its input differently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Keccak256Hasher;
+
+impl Hasher for Keccak256Hasher {
+	fn hash(data: &[u8]) -> H256 {
+		H256::from_slice(Keccak256::digest(data).as_slice())
+	}
+}
+
+/// BLAKE2s-256 hasher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Blake2Hasher;
+
+impl Hasher for Blake2Hasher {
+	fn hash(data: &[u8]) -> H256 {
+		H256::from_slice(Blake2s::digest(data).as_slice())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn from_hex(hex: &str) -> H256 {
+		let bytes = (0..hex.len()).step_by(2)
+			.map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+			.collect::<Vec<_>>();
+		H256::from_slice(&bytes)
+	}
+
+	#[test]
+	fn sha3_and_keccak_differ_in_padding() {
+		assert_eq!(Sha3Hasher::hash(b""), from_hex("a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"));
+		assert_eq!(Keccak256Hasher::hash(b""), from_hex("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"));
+		assert_ne!(Sha3Hasher::hash(b"counter"), Keccak256Hasher::hash(b"counter"));
+	}
+}
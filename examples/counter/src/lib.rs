@@ -0,0 +1,9 @@
+extern crate parity_codec as codec;
+
+pub mod encoding;
+pub mod hash;
+pub mod node;
+pub mod runtime;
+#[cfg(feature = "serde")]
+pub mod spec;
+pub mod storage;
@@ -1,18 +1,21 @@
-extern crate parity_codec as codec;
-
-mod runtime;
-
 use blockchain::backend::{SharedMemoryBackend, KeyValueMemoryState, ChainQuery, ImportOperation, ImportLock};
 use blockchain::import::ImportAction;
-use blockchain::{Block as BlockT, SimpleBuilderExecutor, AsExternalities};
+use blockchain::{Block as BlockT, BuildGenesis, AsExternalities};
+use blockchain::builder::BlockBuilder;
+use blockchain::inherent::{InherentDataProviders, TimestampProvider};
+use blockchain::format::{format_id, format_id_short};
 use blockchain_network_simple::{BestDepthImporter, BestDepthStatusProducer};
 use std::thread;
+use std::path::Path;
 use std::collections::HashMap;
-use clap::{App, SubCommand, AppSettings, Arg};
-use crate::runtime::{Block, Executor};
+use clap::{App, SubCommand, AppSettings, Arg, ArgMatches};
+use counter::node;
+use counter::runtime::{Block, Executor, ChainConfig};
+#[cfg(feature = "serde")]
+use counter::spec;
 
 fn main() {
-	let matches = App::new("Blockchain counter demo")
+	let app = App::new("Blockchain counter demo")
 		.setting(AppSettings::SubcommandRequiredElseHelp)
 		.subcommand(SubCommand::with_name("local")
 					.about("Start a local test network"))
@@ -26,7 +29,72 @@ fn main() {
 					.arg(Arg::with_name("author")
 						 .long("author")
 						 .help("Whether to author blocks")))
-		.get_matches();
+		.arg(Arg::with_name("path")
+			 .long("path")
+			 .takes_value(true)
+			 .global(true)
+			 .help("Database path of the node"))
+		.subcommand(SubCommand::with_name("build")
+					.about("Build a block on top of head and import it")
+					.arg(Arg::with_name("add")
+						 .long("add")
+						 .takes_value(true)
+						 .multiple(true)
+						 .number_of_values(1)
+						 .help("Value to add to the counter")))
+		.subcommand(SubCommand::with_name("import")
+					.about("Import a hex encoded block")
+					.arg(Arg::with_name("block")
+						 .required(true)
+						 .help("Hex encoded block")))
+		.subcommand(SubCommand::with_name("state")
+					.about("Print the counter at head"));
+	#[cfg(feature = "serde")]
+	let app = app
+		.arg(Arg::with_name("spec")
+			 .long("spec")
+			 .takes_value(true)
+			 .global(true)
+			 .help("Chain spec file of the node"))
+		.subcommand(SubCommand::with_name("spec")
+					.about("Write the chain spec of the default chain")
+					.arg(Arg::with_name("output")
+						 .required(true)
+						 .help("Path to write the chain spec to")));
+	let matches = app.get_matches();
+
+	let path = Path::new(matches.value_of("path").unwrap_or("counter-db"));
+	let executor = executor(&matches);
+
+	#[cfg(feature = "serde")]
+	{
+		if let Some(matches) = matches.subcommand_matches("spec") {
+			let spec = spec::ChainSpec::from_config(ChainConfig::default()).unwrap();
+			spec::save_chain_spec(Path::new(matches.value_of("output").expect("Output is required")), &spec).unwrap();
+			println!("Wrote chain spec with genesis {}", format_id(&spec.genesis_hash));
+			return
+		}
+	}
+
+	if let Some(matches) = matches.subcommand_matches("build") {
+		let adds = matches.values_of("add")
+			.map(|values| values.map(|value| value.parse().expect("Add value must be a number")).collect())
+			.unwrap_or_else(Vec::new);
+		let block = node::build(path, &executor, &adds).unwrap();
+		println!("Built block {}: {}", format_id(&block.id()), node::encode_block(&block));
+		return
+	}
+
+	if let Some(matches) = matches.subcommand_matches("import") {
+		let block = node::import_hex(path, &executor, matches.value_of("block").expect("Block is required")).unwrap();
+		println!("Imported block {}", format_id(&block.id()));
+		return
+	}
+
+	if let Some(_) = matches.subcommand_matches("state") {
+		println!("Counter: {}", node::state(path, &executor).unwrap());
+		return
+	}
 
 	if let Some(_) = matches.subcommand_matches("local") {
 		local_sync();
@@ -41,12 +109,36 @@ fn main() {
 	}
 }
 
+/// Executor of the chain spec given on the command line, or of the default
+/// chain.
+#[cfg(feature = "serde")]
+fn executor(matches: &ArgMatches) -> Executor {
+	match matches.value_of("spec") {
+		Some(path) => {
+			let spec = spec::load_chain_spec(Path::new(path)).unwrap();
+			let mut genesis_state = KeyValueMemoryState::default();
+			spec::build_genesis_from_spec(&spec, genesis_state.as_externalities()).unwrap();
+			spec.executor()
+		},
+		None => Executor::new(ChainConfig::default()),
+	}
+}
+
+/// Executor of the default chain.
+#[cfg(not(feature = "serde"))]
+fn executor(_matches: &ArgMatches) -> Executor {
+	Executor::new(ChainConfig::default())
+}
+
 fn local_sync() {
-	let genesis_block = Block::genesis();
+	let mut genesis_state = KeyValueMemoryState::default();
+	let genesis_block = Executor::new(ChainConfig::default())
+		.build_genesis(genesis_state.as_externalities())
+		.unwrap();
 	let (backend_build, lock_build) = (
 		SharedMemoryBackend::<_, (), KeyValueMemoryState>::new_with_genesis(
 			genesis_block.clone(),
-			Default::default()
+			genesis_state.clone()
 		),
 		ImportLock::new()
 	);
@@ -58,12 +150,12 @@ fn local_sync() {
 			(
 				SharedMemoryBackend::<_, (), KeyValueMemoryState>::new_with_genesis(
 					genesis_block.clone(),
-					Default::default()
+					genesis_state.clone()
 				),
 				ImportLock::new()
 			)
 		};
-		let importer = BestDepthImporter::new(Executor, backend.clone(), lock.clone());
+		let importer = BestDepthImporter::new(Executor::new(ChainConfig::default()), backend.clone(), lock.clone());
 		let status = BestDepthStatusProducer::new(backend.clone());
 		peers.insert(peer_id, (backend, lock, importer, status));
 	}
@@ -75,13 +167,16 @@ fn local_sync() {
 }
 
 fn libp2p_sync(port: &str, author: bool) {
-	let genesis_block = Block::genesis();
+	let mut genesis_state = KeyValueMemoryState::default();
+	let genesis_block = Executor::new(ChainConfig::default())
+		.build_genesis(genesis_state.as_externalities())
+		.unwrap();
 	let backend = SharedMemoryBackend::<_, (), KeyValueMemoryState>::new_with_genesis(
 		genesis_block.clone(),
-		Default::default()
+		genesis_state
 	);
 	let lock = ImportLock::new();
-	let importer = BestDepthImporter::new(Executor, backend.clone(), lock.clone());
+	let importer = BestDepthImporter::new(Executor::new(ChainConfig::default()), backend.clone(), lock.clone());
 	let status = BestDepthStatusProducer::new(backend.clone());
 	if author {
 		let backend_build = backend.clone();
@@ -94,21 +189,23 @@ fn libp2p_sync(port: &str, author: bool) {
 }
 
 fn builder_thread(backend_build: SharedMemoryBackend<Block, (), KeyValueMemoryState>, lock: ImportLock) {
+	let mut providers = InherentDataProviders::new();
+	providers.register(TimestampProvider);
+
 	loop {
 		let head = backend_build.head();
-		let executor = Executor;
-		println!("Building on top of {}", head);
+		let executor = Executor::new(ChainConfig::default());
+		println!("Building on top of {}", format_id_short(&head));
 
 		// Build a block.
 		let parent_block = backend_build.block_at(&head).unwrap();
-		let mut pending_state = backend_build.state_at(&head).unwrap();
+		let parent_state = backend_build.state_at(&head).unwrap();
 
-		let mut unsealed_block = executor.initialize_block(
-			&parent_block, pending_state.as_externalities(), ()
-		).unwrap();
-		executor.finalize_block(
-			&mut unsealed_block, pending_state.as_externalities(),
+		let inherent = providers.create_inherent_data();
+		let builder = BlockBuilder::new(
+			&executor, &parent_block, parent_state, inherent
 		).unwrap();
+		let (unsealed_block, pending_state) = builder.build().unwrap();
 
 		let block = unsealed_block.seal();
 
@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::path::Path;
+use blockchain::{BuildGenesis, AsExternalities};
+use blockchain::backend::{KeyValueMemoryState, ChainQuery, ImportLock};
+use blockchain::backend::rocksdb::RocksBackend;
+use blockchain::builder::BlockBuilder;
+use blockchain::inherent::{InherentDataProviders, TimestampProvider};
+use blockchain::chain::verify_and_import;
+use blockchain::import::check_genesis;
+use blockchain::format::{encode_hex, decode_hex};
+use crate::encoding::Codec;
+use crate::hash::Sha3Hasher;
+use crate::runtime::{Add, Block, Executor, Extrinsic, MAX_BLOCK_SIZE, counter_key};
+use crate::storage::ReadVersioned;
+
+type NodeBlock<C> = Block<Sha3Hasher, C>;
+type NodeExecutor<C> = Executor<Sha3Hasher, C>;
+type NodeBackend<C> = RocksBackend<NodeBlock<C>, (), KeyValueMemoryState>;
+
+/// Backend opened from disk, with the import lock all imports into it go
+/// through.
+struct OpenBackend<C: Codec> {
+	backend: NodeBackend<C>,
+	import_lock: ImportLock,
+}
+
+impl<C: Codec> OpenBackend<C> {
+	fn open(path: &Path, executor: &NodeExecutor<C>) -> Result<Self, Box<dyn Error>> {
+		let mut genesis_state = KeyValueMemoryState::default();
+		let genesis_block = executor.build_genesis(genesis_state.as_externalities())?;
+
+		let backend = NodeBackend::open_or_create_with_genesis(path, genesis_block.clone(), genesis_state)?;
+		check_genesis(&backend, executor, &genesis_block)?;
+		Ok(Self { backend, import_lock: ImportLock::new() })
+	}
+
+	/// Import the block and move head to the best block, reorganizing at
+	/// most as deep as the chain allows.
+	fn import(&self, executor: &NodeExecutor<C>, block: NodeBlock<C>) -> Result<(), Box<dyn Error>> {
+		let max_reorg_depth = executor.config().max_reorg_depth;
+		verify_and_import(&self.backend, &self.import_lock, executor, block, max_reorg_depth)?;
+		Ok(())
+	}
+}
+
+/// Author and seal a block adding each of the values on top of head, and
+/// import it.
+pub fn build<C: Codec>(
+	path: &Path,
+	executor: &NodeExecutor<C>,
+	adds: &[u128],
+) -> Result<NodeBlock<C>, Box<dyn Error>> {
+	let node = OpenBackend::open(path, executor)?;
+
+	let head = node.backend.head();
+	let parent_block = node.backend.block_at(&head)?;
+	let parent_state = node.backend.state_at(&head)?;
+
+	let mut providers = InherentDataProviders::new();
+	providers.register(TimestampProvider);
+	let inherent = providers.create_inherent_data();
+	let mut builder = BlockBuilder::new(executor, &parent_block, parent_state, inherent)?;
+	for add in adds {
+		builder.push(Extrinsic::Add(Add(*add)))?;
+	}
+	let (unsealed_block, _) = builder.build()?;
+	let block = unsealed_block.seal();
+
+	node.import(executor, block.clone())?;
+	Ok(block)
+}
+
+/// Import a block hex encoded with the codec of the chain.
+pub fn import_hex<C: Codec>(
+	path: &Path,
+	executor: &NodeExecutor<C>,
+	hex: &str,
+) -> Result<NodeBlock<C>, Box<dyn Error>> {
+	let node = OpenBackend::open(path, executor)?;
+
+	let block = NodeBlock::<C>::decode_limited(&decode_hex(hex.trim())?, MAX_BLOCK_SIZE)?;
+	node.import(executor, block.clone())?;
+	Ok(block)
+}
+
+/// Get the counter at head.
+pub fn state<C: Codec>(path: &Path, executor: &NodeExecutor<C>) -> Result<u128, Box<dyn Error>> {
+	let node = OpenBackend::open(path, executor)?;
+
+	let state = node.backend.state_at(&node.backend.head())?;
+	Ok(state.read_versioned::<u128>(&counter_key())?.unwrap_or(0))
+}
+
+/// Hex encoding of the block with the codec of the chain.
+pub fn encode_block<C: Codec>(block: &NodeBlock<C>) -> String {
+	encode_hex(&block.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+	use blockchain::Block as _;
+	use crate::runtime::ChainConfig;
+
+	fn assert_builds_imports_and_reads_counter<C: Codec>(name: &str) {
+		let executor = NodeExecutor::<C>::new_with_hasher(ChainConfig { difficulty: 1, ..ChainConfig::default() });
+		let root = std::env::temp_dir().join(format!("counter-node-{}-{}", name, std::process::id()));
+		let (author, follower) = (root.join("author"), root.join("follower"));
+
+		assert_eq!(state(&author, &executor).unwrap(), 0);
+		let block = build(&author, &executor, &[2, 3]).unwrap();
+		assert_eq!(state(&author, &executor).unwrap(), 5);
+
+		let hex = encode_block(&block);
+		assert_eq!(hex, encode_hex(&C::encode(&block)));
+		assert_eq!(import_hex(&follower, &executor, &hex).unwrap().id(), block.id());
+		assert_eq!(state(&follower, &executor).unwrap(), 5);
+		assert!(import_hex(&follower, &executor, "0x00").is_err());
+
+		let _ = fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn follows_longest_chain_across_imports() {
+		let executor = Executor::new(ChainConfig { difficulty: 1, ..ChainConfig::default() });
+		let root = std::env::temp_dir().join(format!("counter-node-reorg-{}", std::process::id()));
+		let (short, long, follower) = (root.join("short"), root.join("long"), root.join("follower"));
+
+		let short_block = build(&short, &executor, &[2]).unwrap();
+		let long_blocks = [build(&long, &executor, &[5]).unwrap(), build(&long, &executor, &[1]).unwrap()];
+
+		import_hex(&follower, &executor, &encode_block(&short_block)).unwrap();
+		assert_eq!(state(&follower, &executor).unwrap(), 2);
+		for block in &long_blocks {
+			import_hex(&follower, &executor, &encode_block(block)).unwrap();
+		}
+		assert_eq!(state(&follower, &executor).unwrap(), 6);
+
+		// Known blocks are rejected, leaving head on the longest chain.
+		assert!(import_hex(&follower, &executor, &encode_block(&short_block)).is_err());
+		assert_eq!(state(&follower, &executor).unwrap(), 6);
+
+		let _ = fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn builds_imports_and_reads_counter() {
+		assert_builds_imports_and_reads_counter::<crate::encoding::ScaleCodec>("scale");
+		#[cfg(feature = "serde")]
+		assert_builds_imports_and_reads_counter::<crate::encoding::JsonCodec>("json");
+	}
+}
@@ -1,58 +1,368 @@
-use primitive_types::H256;
+use primitive_types::{H256, H512};
 use blockchain::{
-	Block as BlockT, BlockExecutor,
-	SimpleBuilderExecutor, StorageExternalities,
+	Block as BlockT, BlockContext, BlockExecutor, DiffExecutor, BuildGenesis,
+	ExtrinsicBuilder, DecodeExtrinsic, StorageExternalities, AsExternalities, EventRecord,
+	ExtrinsicOutcome, ExtrinsicFilter, AllowAll, HeaderBlock, HeaderVerifier,
 };
-use codec::{Encode, Decode};
-use sha3::{Digest, Sha3_256};
+use blockchain::backend::{
+	KeyValueMemoryState, OverlayExternalities, TracingExternalities, StateDiffCollector, StateDiff,
+};
+use blockchain::consensus::pow::{self, PowBlock};
+use blockchain::inherent::{InherentData, TimestampProvider};
+use blockchain::proof;
+use blockchain::migration::{self, Migration};
+use blockchain::storage::StateCorruption;
+use crate::storage::{self, ReadDecoded, ReadVersioned, STATE_VERSION};
+use crate::hash::{Hasher, Sha3Hasher};
+use crate::encoding::{Codec, ScaleCodec};
+use codec::{Encode, Decode, Compact, Input};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+const MAX_EXTRINSICS: usize = 1024;
+const MAX_BLOCK_WEIGHT: u64 = 100_000;
+const ARITHMETIC_WEIGHT: u64 = 100;
+const NOOP_WEIGHT: u64 = 10;
+const SIGNATURE_WEIGHT: u64 = 1_000;
+const MAX_EXTRINSIC_NESTING: usize = 4;
+
+/// Maximum encoded size of a block accepted from the network.
+pub const MAX_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Consensus parameters of a chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChainConfig {
+	/// Minimum difficulty of blocks, in leading zero bits of the seal.
+	pub difficulty: u64,
+	/// Target interval between blocks, in seconds.
+	pub target_block_interval: u64,
+	/// Maximum number of blocks a reorg may retract.
+	pub max_reorg_depth: Option<usize>,
+	/// Maximum number of seconds a block timestamp may be ahead of the
+	/// local clock.
+	pub max_future_drift: u64,
+	/// Maximum size of a storage value, in bytes.
+	pub max_value_size: usize,
+}
+
+impl Default for ChainConfig {
+	fn default() -> Self {
+		Self {
+			difficulty: 16,
+			target_block_interval: 10,
+			max_reorg_depth: Some(100),
+			max_future_drift: 60,
+			max_value_size: 1024,
+		}
+	}
+}
+
+/// Storage key of the counter.
+pub fn counter_key() -> Vec<u8> {
+	storage::storage_key(b"Counter", b"value")
+}
+
+/// Storage key of the timestamp of the latest block.
+pub fn timestamp_key() -> Vec<u8> {
+	storage::storage_key(b"Timestamp", b"now")
+}
+
+/// Storage key of the difficulty of the latest block.
+pub fn difficulty_key() -> Vec<u8> {
+	storage::storage_key(b"Pow", b"difficulty")
+}
+
+/// Storage key of the number of the latest block.
+pub fn block_number_key() -> Vec<u8> {
+	storage::storage_key(b"System", b"number")
+}
+
+/// Storage version of the counter runtime.
+pub const STORAGE_VERSION: u32 = 2;
+
+/// Migration from the plain `counter` and `timestamp` keys to namespaced
+/// keys.
+pub struct NamespaceKeys;
+
+impl Migration<Infallible> for NamespaceKeys {
+	type Error = Infallible;
+
+	fn version(&self) -> u32 {
+		1
+	}
+
+	fn migrate(&self, state: &mut dyn StorageExternalities<Infallible>) -> Result<(), Infallible> {
+		for (old, new) in &[(&b"counter"[..], counter_key()), (&b"timestamp"[..], timestamp_key())] {
+			if let Some(value) = state.read_storage(old)? {
+				state.remove_storage(old);
+				state.write_storage(new.clone(), value);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Migration prefixing the counter value with its state version.
+pub struct VersionCounter;
+
+impl Migration<Infallible> for VersionCounter {
+	type Error = Infallible;
+
+	fn version(&self) -> u32 {
+		2
+	}
+
+	fn migrate(&self, state: &mut dyn StorageExternalities<Infallible>) -> Result<(), Infallible> {
+		if let Some(value) = state.read_storage(&counter_key())? {
+			let mut versioned = vec![STATE_VERSION];
+			versioned.extend(value);
+			state.write_storage(counter_key(), versioned);
+		}
+
+		Ok(())
+	}
+}
+
+/// Externalities of the counter runtime.
+pub trait CounterExternalities: StorageExternalities<Infallible> { }
+
+/// Get the Merkle root of the current storage.
+pub fn storage_root<H: Hasher>(state: &(dyn CounterExternalities + 'static)) -> H256 {
+	proof::storage_root(state, H::hash)
+}
+
+impl CounterExternalities for KeyValueMemoryState { }
+
+impl AsExternalities<dyn CounterExternalities> for KeyValueMemoryState {
+	fn as_externalities(&mut self) -> &mut (dyn CounterExternalities + 'static) {
+		self
+	}
+}
+
+impl<S: CounterExternalities + 'static> CounterExternalities for TracingExternalities<S> { }
+
+impl<S: CounterExternalities + 'static> AsExternalities<dyn CounterExternalities> for TracingExternalities<S> {
+	fn as_externalities(&mut self) -> &mut (dyn CounterExternalities + 'static) {
+		self
+	}
+}
 
-const DIFFICULTY: usize = 2;
+impl<S: CounterExternalities + 'static> CounterExternalities for StateDiffCollector<S> { }
 
-fn is_all_zero(arr: &[u8]) -> bool {
-	arr.iter().all(|i| *i == 0)
+impl<S: CounterExternalities + 'static> AsExternalities<dyn CounterExternalities> for StateDiffCollector<S> {
+	fn as_externalities(&mut self) -> &mut (dyn CounterExternalities + 'static) {
+		self
+	}
 }
 
 #[derive(Clone, Debug)]
-pub struct UnsealedBlock {
+pub struct UnsealedBlock<H: Hasher = Sha3Hasher, C: Codec = ScaleCodec> {
 	parent_hash: Option<H256>,
+	number: u64,
+	timestamp: u64,
+	difficulty: u64,
+	state_root: H256,
+	extrinsics_root: H256,
 	extrinsics: Vec<Extrinsic>,
+	weight: u64,
+	nonce: u64,
+	_hasher: PhantomData<(H, C)>,
 }
 
-impl UnsealedBlock {
-	pub fn seal(self) -> Block {
-		let mut block = Block {
+impl<H: Hasher, C: Codec> UnsealedBlock<H, C> {
+	fn from_block(block: Block<H, C>, weight: u64) -> Self {
+		UnsealedBlock {
+			parent_hash: block.parent_hash,
+			number: block.number,
+			timestamp: block.timestamp,
+			difficulty: block.difficulty,
+			state_root: block.state_root,
+			extrinsics_root: block.extrinsics_root,
+			extrinsics: block.extrinsics,
+			weight,
+			nonce: block.nonce,
+			_hasher: PhantomData,
+		}
+	}
+
+	fn into_block(self) -> Block<H, C> {
+		Block {
 			parent_hash: self.parent_hash,
+			number: self.number,
+			timestamp: self.timestamp,
+			difficulty: self.difficulty,
+			state_root: self.state_root,
+			extrinsics_root: self.extrinsics_root,
 			extrinsics: self.extrinsics,
-			nonce: 0,
-		};
-
-		while !is_all_zero(&block.id()[0..DIFFICULTY]) {
-			block.nonce += 1;
+			nonce: self.nonce,
+			_hasher: PhantomData,
 		}
+	}
+
+	/// Try at most `max_iterations` nonces. On failure the unsealed block is
+	/// returned, resuming from the next untried nonce.
+	pub fn try_seal(self, max_iterations: u64) -> Result<Block<H, C>, Self> {
+		let weight = self.weight;
+		let difficulty = self.difficulty as usize;
+
+		pow::try_seal(self.into_block(), difficulty, max_iterations)
+			.map_err(|block| UnsealedBlock::from_block(block, weight))
+	}
 
-		block
+	/// Seal with a nonce found outside of the node, such as by a mining
+	/// pool. If the nonce does not meet the difficulty, the unsealed block
+	/// is returned unchanged.
+	pub fn seal_with_nonce(mut self, nonce: u64) -> Result<Block<H, C>, Self> {
+		let previous = self.nonce;
+		self.nonce = nonce;
+
+		self.try_seal(1).map_err(|mut unsealed| {
+			unsealed.nonce = previous;
+			unsealed
+		})
+	}
+
+	/// Grind the nonce until the block is sealed, or until `cancel` is set,
+	/// in which case the unsealed block is returned.
+	pub fn seal_cancellable(self, cancel: &AtomicBool) -> Result<Block<H, C>, Self> {
+		let weight = self.weight;
+		let difficulty = self.difficulty as usize;
+
+		pow::seal_cancellable(self.into_block(), difficulty, cancel)
+			.map_err(|block| UnsealedBlock::from_block(block, weight))
+	}
+
+	pub fn seal(self) -> Block<H, C> {
+		let difficulty = self.difficulty as usize;
+
+		pow::seal_parallel(self.into_block(), difficulty)
 	}
 }
 
 #[derive(Clone, Debug, Encode, Decode)]
-pub struct Block {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Block<H: Hasher = Sha3Hasher, C: Codec = ScaleCodec> {
 	parent_hash: Option<H256>,
+	number: u64,
+	timestamp: u64,
+	difficulty: u64,
+	state_root: H256,
+	extrinsics_root: H256,
 	extrinsics: Vec<Extrinsic>,
 	nonce: u64,
+	#[codec(skip)]
+	#[cfg_attr(feature = "serde", serde(skip))]
+	_hasher: PhantomData<(H, C)>,
 }
 
-impl Block {
-	pub fn genesis() -> Self {
-		Block {
-			parent_hash: None,
-			extrinsics: Vec::new(),
-			nonce: 0,
+impl<H: Hasher, C: Codec> Block<H, C> {
+	pub fn timestamp(&self) -> u64 {
+		self.timestamp
+	}
+
+	/// Compact representation of the block, referring to extrinsics by
+	/// hash.
+	pub fn to_compact(&self) -> CompactBlock<H, C> {
+		CompactBlock {
+			parent_hash: self.parent_hash,
+			number: self.number,
+			timestamp: self.timestamp,
+			difficulty: self.difficulty,
+			state_root: self.state_root,
+			extrinsics_root: self.extrinsics_root,
+			extrinsic_hashes: self.extrinsics.iter().map(extrinsic_hash::<H, C>).collect(),
+			nonce: self.nonce,
+			_hasher: PhantomData,
+		}
+	}
+
+	/// Encoding of the block with the codec of the chain.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		C::encode(self)
+	}
+
+	/// Decode a block encoded with the codec of the chain.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+		C::decode(bytes).ok_or(Error::InvalidEncoding)
+	}
+
+	/// Decode a block encoded with the codec of the chain, of at most
+	/// `max_len` bytes, with at most the maximum number of extrinsics and
+	/// levels of nested signed payloads.
+	pub fn decode_limited(bytes: &[u8], max_len: usize) -> Result<Self, Error> {
+		if bytes.len() > max_len {
+			return Err(Error::BlockTooLarge);
+		}
+
+		let block = C::decode_block(bytes)?;
+		if block.extrinsics.len() > MAX_EXTRINSICS {
+			return Err(Error::BlockTooLarge);
+		}
+		if block.extrinsics.iter().any(|extrinsic| extrinsic.nesting() > MAX_EXTRINSIC_NESTING) {
+			return Err(Error::InvalidEncoding);
+		}
+
+		Ok(block)
+	}
+
+	/// Decode a SCALE encoded block. The extrinsic count is checked before
+	/// anything is allocated for it.
+	pub(crate) fn decode_scale(bytes: &[u8]) -> Result<Self, Error> {
+		let input = &mut &bytes[..];
+		let parent_hash = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+		let number = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+		let timestamp = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+		let difficulty = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+		let state_root = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+		let extrinsics_root = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+
+		let Compact(len) = <Compact<u32>>::decode(input).ok_or(Error::InvalidEncoding)?;
+		let len = len as usize;
+		// Every extrinsic takes at least one byte.
+		if len > MAX_EXTRINSICS || len > input.len() {
+			return Err(Error::BlockTooLarge);
+		}
+		let mut extrinsics = Vec::with_capacity(len);
+		for _ in 0..len {
+			extrinsics.push(Extrinsic::decode_limited(input, MAX_EXTRINSIC_NESTING)?);
+		}
+
+		let nonce = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+		if !input.is_empty() {
+			return Err(Error::InvalidEncoding);
 		}
+
+		Ok(Block {
+			parent_hash, number, timestamp, difficulty, state_root, extrinsics_root,
+			extrinsics, nonce, _hasher: PhantomData,
+		})
 	}
 }
 
-impl BlockT for Block {
+/// Header of a block, committing to its body through the extrinsics root.
+/// It has the same id as its block, and can be verified without the body.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Header<H: Hasher = Sha3Hasher, C: Codec = ScaleCodec> {
+	parent_hash: Option<H256>,
+	number: u64,
+	timestamp: u64,
+	difficulty: u64,
+	state_root: H256,
+	extrinsics_root: H256,
+	nonce: u64,
+	#[codec(skip)]
+	#[cfg_attr(feature = "serde", serde(skip))]
+	_hasher: PhantomData<(H, C)>,
+}
+
+impl<H: Hasher, C: Codec> BlockT for Header<H, C> {
 	type Identifier = H256;
 
 	fn parent_id(&self) -> Option<H256> {
@@ -60,126 +370,2059 @@ impl BlockT for Block {
 	}
 
 	fn id(&self) -> H256 {
-		H256::from_slice(Sha3_256::digest(&self.encode()).as_slice())
+		Self::seal_hash(&self.pre_hash(), self.nonce)
+	}
+
+	fn number(&self) -> u64 {
+		self.number
 	}
 }
 
-#[derive(Clone, Debug, Encode, Decode)]
-pub enum Extrinsic {
-	Add(u128),
+impl<H: Hasher, C: Codec> PowBlock for Header<H, C> {
+	fn nonce(&self) -> u64 {
+		self.nonce
+	}
+
+	fn set_nonce(&mut self, nonce: u64) {
+		self.nonce = nonce;
+	}
+
+	fn difficulty(&self) -> usize {
+		self.difficulty as usize
+	}
+
+	fn timestamp(&self) -> u64 {
+		self.timestamp
+	}
+
+	fn pre_hash(&self) -> H256 {
+		H::hash(&C::encode(&(
+			self.parent_hash,
+			self.number,
+			self.timestamp,
+			self.difficulty,
+			self.state_root,
+			self.extrinsics_root,
+		)))
+	}
+
+	fn seal_hash(pre_hash: &H256, nonce: u64) -> H256 {
+		H::hash(&C::encode(&(pre_hash, nonce)))
+	}
 }
 
-#[derive(Debug)]
-pub enum Error {
-	Backend(Box<dyn std::error::Error>),
-	DifficultyTooLow,
-	StateCorruption,
+/// Commitment of the header to the block body, as the hash of the encoded
+/// extrinsics.
+pub fn extrinsics_root<H: Hasher, C: Codec>(extrinsics: &[Extrinsic]) -> H256 {
+	H::hash(&C::encode(extrinsics))
 }
 
-impl std::fmt::Display for Error {
+/// Hash of an encoded extrinsic.
+pub fn extrinsic_hash<H: Hasher, C: Codec>(extrinsic: &Extrinsic) -> H256 {
+	H::hash(&C::encode(extrinsic))
+}
+
+/// Block header with the hashes of its extrinsics, to be reconstructed
+/// from extrinsics a peer already has.
+#[derive(Clone, Debug, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompactBlock<H: Hasher = Sha3Hasher, C: Codec = ScaleCodec> {
+	parent_hash: Option<H256>,
+	number: u64,
+	timestamp: u64,
+	difficulty: u64,
+	state_root: H256,
+	extrinsics_root: H256,
+	extrinsic_hashes: Vec<H256>,
+	nonce: u64,
+	#[codec(skip)]
+	#[cfg_attr(feature = "serde", serde(skip))]
+	_hasher: PhantomData<(H, C)>,
+}
+
+/// Hashes of extrinsics missing to reconstruct a compact block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingExtrinsics(pub Vec<H256>);
+
+impl std::fmt::Display for MissingExtrinsics {
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
 		write!(f, "{:?}", self)
 	}
 }
 
-impl std::error::Error for Error { }
+impl std::error::Error for MissingExtrinsics { }
 
-impl From<Error> for blockchain::import::Error {
-	fn from(error: Error) -> Self {
-		blockchain::import::Error::Executor(Box::new(error))
+impl<H: Hasher, C: Codec> CompactBlock<H, C> {
+	/// Reconstruct the block from known extrinsics, such as those pending in
+	/// a pool. Fails with the hashes of all extrinsics that are not known.
+	pub fn reconstruct(&self, known: &[Extrinsic]) -> Result<Block<H, C>, MissingExtrinsics> {
+		let known = known.iter()
+			.map(|extrinsic| (extrinsic_hash::<H, C>(extrinsic), extrinsic))
+			.collect::<std::collections::HashMap<_, _>>();
+
+		let mut extrinsics = Vec::with_capacity(self.extrinsic_hashes.len());
+		let mut missing = Vec::new();
+		for hash in &self.extrinsic_hashes {
+			match known.get(hash) {
+				Some(extrinsic) => extrinsics.push((*extrinsic).clone()),
+				None => missing.push(*hash),
+			}
+		}
+
+		if !missing.is_empty() {
+			return Err(MissingExtrinsics(missing));
+		}
+
+		Ok(Block {
+			parent_hash: self.parent_hash,
+			number: self.number,
+			timestamp: self.timestamp,
+			difficulty: self.difficulty,
+			state_root: self.state_root,
+			extrinsics_root: self.extrinsics_root,
+			extrinsics,
+			nonce: self.nonce,
+			_hasher: PhantomData,
+		})
 	}
 }
 
-#[derive(Clone)]
-pub struct Executor;
+impl<H: Hasher, C: Codec> PartialEq for Block<H, C> {
+	fn eq(&self, other: &Self) -> bool {
+		self.encode() == other.encode()
+	}
+}
 
-impl Executor {
-	fn read_counter(&self, state: &mut <Self as BlockExecutor>::Externalities) -> Result<u128, Error> {
-		Ok(
-			match state.read_storage(b"counter").expect("Error is infallible; qed") {
-				Some(counter) => {
-					u128::decode(&mut counter.as_slice()).ok_or(Error::StateCorruption)?
-				},
-				None => 0,
-			}
-		)
+impl<H: Hasher, C: Codec> Eq for Block<H, C> { }
+
+impl<H: Hasher, C: Codec> core::hash::Hash for Block<H, C> {
+	fn hash<S: core::hash::Hasher>(&self, state: &mut S) {
+		self.encode().hash(state)
+	}
+}
+
+impl<H: Hasher, C: Codec> BlockT for Block<H, C> {
+	type Identifier = H256;
+
+	fn parent_id(&self) -> Option<H256> {
+		self.parent_hash
+	}
+
+	fn id(&self) -> H256 {
+		Self::seal_hash(&self.pre_hash(), self.nonce)
 	}
 
-	fn write_counter(&self, counter: u128, state: &mut <Self as BlockExecutor>::Externalities) {
-		state.write_storage(b"counter".to_vec(), counter.encode());
+	fn number(&self) -> u64 {
+		self.number
 	}
 }
 
-impl BlockExecutor for Executor {
-	type Error = Error;
-	type Block = Block;
-	type Externalities = dyn StorageExternalities<Infallible> + 'static;
+impl<H: Hasher, C: Codec> PowBlock for Block<H, C> {
+	fn nonce(&self) -> u64 {
+		self.nonce
+	}
 
-	fn execute_block(
-		&self,
-		block: &Self::Block,
-		state: &mut Self::Externalities,
-	) -> Result<(), Error> {
-		if !is_all_zero(&block.id()[0..DIFFICULTY]) {
-			return Err(Error::DifficultyTooLow);
+	fn set_nonce(&mut self, nonce: u64) {
+		self.nonce = nonce;
+	}
+
+	fn difficulty(&self) -> usize {
+		self.difficulty as usize
+	}
+
+	fn timestamp(&self) -> u64 {
+		self.timestamp
+	}
+
+	fn pre_hash(&self) -> H256 {
+		self.header().pre_hash()
+	}
+
+	fn seal_hash(pre_hash: &H256, nonce: u64) -> H256 {
+		Header::<H, C>::seal_hash(pre_hash, nonce)
+	}
+}
+
+impl<H: Hasher, C: Codec> HeaderBlock for Block<H, C> {
+	type Header = Header<H, C>;
+
+	fn header(&self) -> Header<H, C> {
+		Header {
+			parent_hash: self.parent_hash,
+			number: self.number,
+			timestamp: self.timestamp,
+			difficulty: self.difficulty,
+			state_root: self.state_root,
+			extrinsics_root: self.extrinsics_root,
+			nonce: self.nonce,
+			_hasher: PhantomData,
 		}
+	}
+}
 
-		let mut counter = self.read_counter(state)?;
+/// Call of an extrinsic, applied to the counter.
+pub trait Call {
+	/// Weight of applying the call.
+	fn weight(&self) -> u64;
+	/// Weight actually consumed by applying the call, at most its weight.
+	/// By default the call consumes all of its weight.
+	fn actual_weight(&self) -> u64 {
+		self.weight()
+	}
+	/// Apply the call to the counter, returning the new counter.
+	fn dispatch(&self, counter: u128, verifier: &dyn Verifier) -> Result<u128, Error>;
+}
 
-		for extrinsic in &block.extrinsics {
-			match extrinsic {
-				Extrinsic::Add(add) => counter += add,
-			}
+/// Add to the counter.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Add(pub u128);
+
+impl Call for Add {
+	fn weight(&self) -> u64 {
+		ARITHMETIC_WEIGHT
+	}
+
+	fn actual_weight(&self) -> u64 {
+		if self.0 == 0 { NOOP_WEIGHT } else { ARITHMETIC_WEIGHT }
+	}
+
+	fn dispatch(&self, counter: u128, _verifier: &dyn Verifier) -> Result<u128, Error> {
+		counter.checked_add(self.0).ok_or(Error::CounterOverflow)
+	}
+}
+
+/// Subtract from the counter.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sub(pub u128);
+
+impl Call for Sub {
+	fn weight(&self) -> u64 {
+		ARITHMETIC_WEIGHT
+	}
+
+	fn actual_weight(&self) -> u64 {
+		if self.0 == 0 { NOOP_WEIGHT } else { ARITHMETIC_WEIGHT }
+	}
+
+	fn dispatch(&self, counter: u128, _verifier: &dyn Verifier) -> Result<u128, Error> {
+		counter.checked_sub(self.0).ok_or(Error::CounterUnderflow)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Extrinsic {
+	Add(Add),
+	Sub(Sub),
+	Signed {
+		payload: Box<Extrinsic>,
+		signer: H256,
+		signature: H512,
+	},
+}
+
+impl Extrinsic {
+	/// Priority of the extrinsic in the pool. Larger additions are included
+	/// first.
+	pub fn priority(&self) -> u64 {
+		match self {
+			Extrinsic::Add(Add(value)) => core::cmp::min(*value, u64::MAX as u128) as u64,
+			Extrinsic::Sub(_) => 0,
+			Extrinsic::Signed { payload, .. } => payload.priority(),
+		}
+	}
+
+	/// Levels of signed payloads nested in the extrinsic.
+	fn nesting(&self) -> usize {
+		match self {
+			Extrinsic::Add(_) | Extrinsic::Sub(_) => 0,
+			Extrinsic::Signed { payload, .. } => payload.nesting() + 1,
 		}
+	}
 
-		self.write_counter(counter, state);
+	/// Decode a SCALE encoded extrinsic, allowing at most the maximum
+	/// levels of nested signed payloads, and failing unless all bytes are
+	/// consumed.
+	pub(crate) fn decode_scale(mut bytes: &[u8]) -> Result<Self, Error> {
+		let extrinsic = Self::decode_limited(&mut bytes, MAX_EXTRINSIC_NESTING)?;
+		if !bytes.is_empty() {
+			return Err(Error::InvalidEncoding);
+		}
 
-		Ok(())
+		Ok(extrinsic)
+	}
+
+	/// Decode an extrinsic, allowing at most `depth` levels of nested
+	/// signed payloads.
+	fn decode_limited(input: &mut &[u8], depth: usize) -> Result<Self, Error> {
+		match input.read_byte().ok_or(Error::InvalidEncoding)? {
+			0 => Ok(Extrinsic::Add(Decode::decode(input).ok_or(Error::InvalidEncoding)?)),
+			1 => Ok(Extrinsic::Sub(Decode::decode(input).ok_or(Error::InvalidEncoding)?)),
+			2 if depth > 0 => {
+				let payload = Box::new(Self::decode_limited(input, depth - 1)?);
+				let signer = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+				let signature = Decode::decode(input).ok_or(Error::InvalidEncoding)?;
+				Ok(Extrinsic::Signed { payload, signer, signature })
+			},
+			_ => Err(Error::InvalidEncoding),
+		}
 	}
 }
 
-impl SimpleBuilderExecutor for Executor {
-	type BuildBlock = UnsealedBlock;
-	type Extrinsic = Extrinsic;
-	type Inherent = ();
+impl Call for Extrinsic {
+	fn weight(&self) -> u64 {
+		match self {
+			Extrinsic::Add(call) => call.weight(),
+			Extrinsic::Sub(call) => call.weight(),
+			Extrinsic::Signed { payload, .. } => payload.weight().saturating_add(SIGNATURE_WEIGHT),
+		}
+	}
 
-	fn initialize_block(
-		&self,
-		block: &Self::Block,
-		_state: &mut Self::Externalities,
-		_inherent: (),
-	) -> Result<Self::BuildBlock, Self::Error> {
-		Ok(UnsealedBlock {
-			parent_hash: Some(block.id()),
-			extrinsics: Vec::new(),
-		})
+	fn actual_weight(&self) -> u64 {
+		match self {
+			Extrinsic::Add(call) => call.actual_weight(),
+			Extrinsic::Sub(call) => call.actual_weight(),
+			Extrinsic::Signed { payload, .. } => payload.actual_weight().saturating_add(SIGNATURE_WEIGHT),
+		}
 	}
 
-	fn apply_extrinsic(
-		&self,
-		block: &mut Self::BuildBlock,
-		extrinsic: Self::Extrinsic,
-		state: &mut Self::Externalities,
-	) -> Result<(), Self::Error> {
-		let mut counter = self.read_counter(state)?;
+	fn dispatch(&self, counter: u128, verifier: &dyn Verifier) -> Result<u128, Error> {
+		match self {
+			Extrinsic::Add(call) => call.dispatch(counter, verifier),
+			Extrinsic::Sub(call) => call.dispatch(counter, verifier),
+			Extrinsic::Signed { payload, signer, signature } => {
+				if !verifier.verify(&payload.encode(), signer, signature) {
+					return Err(Error::BadSignature);
+				}
 
-		match &extrinsic {
-			Extrinsic::Add(add) => {
-				counter += add;
+				payload.dispatch(counter, verifier)
 			},
 		}
+	}
+}
 
-		self.write_counter(counter, state);
-		block.extrinsics.push(extrinsic);
+/// Signature verification for signed extrinsics.
+pub trait Verifier {
+	/// Check whether the signature of the payload by signer is valid.
+	fn verify(&self, payload: &[u8], signer: &H256, signature: &H512) -> bool;
+}
 
-		Ok(())
+#[derive(Clone, Default)]
+pub struct Ed25519Verifier;
+
+impl Verifier for Ed25519Verifier {
+	fn verify(&self, payload: &[u8], signer: &H256, signature: &H512) -> bool {
+		let public = match ed25519_dalek::PublicKey::from_bytes(&signer[..]) {
+			Ok(public) => public,
+			Err(_) => return false,
+		};
+		let signature = match ed25519_dalek::Signature::from_bytes(&signature[..]) {
+			Ok(signature) => signature,
+			Err(_) => return false,
+		};
+
+		ed25519_dalek::Verifier::verify(&public, payload, &signature).is_ok()
 	}
+}
 
-	fn finalize_block(
-		&self,
-		_block: &mut Self::BuildBlock,
-		_state: &mut Self::Externalities,
-	) -> Result<(), Self::Error> {
-		Ok(())
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+	CounterChanged { old: u128, new: u128 },
+}
+
+#[derive(Debug)]
+pub enum Error {
+	Backend(Box<dyn std::error::Error + Send + Sync + 'static>),
+	InvalidSeal,
+	DifficultyMismatch,
+	StateCorruption(Vec<u8>),
+	StateRootMismatch,
+	ExtrinsicsRootMismatch,
+	InvalidTimestamp,
+	CounterOverflow,
+	CounterUnderflow,
+	BadSignature,
+	BlockFull,
+	BlockWeightExceeded,
+	BlockTooLarge,
+	InvalidEncoding,
+	BadExtrinsic,
+	ParentMismatch,
+	InherentMismatch,
+	UnsupportedStateVersion(u8),
+	ValueTooLarge,
+	Filtered,
+	GenesisHashMismatch,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Backend(err) => Some(err.as_ref()),
+			_ => None,
+		}
+	}
+}
+
+impl Error {
+	/// Whether the error comes from the storage rather than the block, so
+	/// that executing the block again may succeed.
+	pub fn is_transient(&self) -> bool {
+		matches!(self, Error::Backend(_))
+	}
+
+	/// Whether the error comes from state the block cannot be executed on,
+	/// so that executing the block again would fail the same way.
+	pub fn is_poison(&self) -> bool {
+		matches!(self, Error::StateCorruption(_))
+	}
+}
+
+impl From<StateCorruption> for Error {
+	fn from(StateCorruption(key): StateCorruption) -> Self {
+		Error::StateCorruption(key)
+	}
+}
+
+impl From<Error> for blockchain::import::Error {
+	fn from(error: Error) -> Self {
+		blockchain::import::Error::Executor(Box::new(error))
+	}
+}
+
+#[derive(Clone)]
+pub struct Executor<H: Hasher = Sha3Hasher, C: Codec = ScaleCodec> {
+	config: ChainConfig,
+	genesis_counter: u128,
+	genesis_storage: Vec<(Vec<u8>, Vec<u8>)>,
+	verifier: Arc<dyn Verifier + Send + Sync>,
+	clock: Arc<dyn Fn() -> u64 + Send + Sync>,
+	filter: Arc<dyn ExtrinsicFilter<Extrinsic> + Send + Sync>,
+	_hasher: PhantomData<(H, C)>,
+}
+
+impl Executor {
+	pub fn new(config: ChainConfig) -> Self {
+		Self::new_with_hasher(config)
+	}
+}
+
+impl<H: Hasher, C: Codec> Executor<H, C> {
+	/// Create an executor whose blocks are hashed with `H`, over their
+	/// encoding with `C`.
+	pub fn new_with_hasher(config: ChainConfig) -> Self {
+		Self {
+			config,
+			genesis_counter: 0,
+			genesis_storage: Vec::new(),
+			verifier: Arc::new(Ed25519Verifier),
+			clock: Arc::new(TimestampProvider::now),
+			filter: Arc::new(AllowAll),
+			_hasher: PhantomData,
+		}
+	}
+
+	pub fn config(&self) -> &ChainConfig {
+		&self.config
+	}
+
+	pub fn with_verifier<V: Verifier + Send + Sync + 'static>(mut self, verifier: V) -> Self {
+		self.verifier = Arc::new(verifier);
+		self
+	}
+
+	/// Use the given clock, in unix seconds, to check block timestamps.
+	pub fn with_clock<T: Fn() -> u64 + Send + Sync + 'static>(mut self, clock: T) -> Self {
+		self.clock = Arc::new(clock);
+		self
+	}
+
+	/// Reject extrinsics not allowed by the filter, with `Error::Filtered`,
+	/// when building blocks. The filter is local policy, so blocks imported
+	/// from peers are executed regardless of it.
+	pub fn with_filter<F>(mut self, filter: F) -> Self where
+		F: ExtrinsicFilter<Extrinsic> + Send + Sync + 'static,
+	{
+		self.filter = Arc::new(filter);
+		self
+	}
+
+	pub fn with_genesis_counter(mut self, counter: u128) -> Self {
+		self.genesis_counter = counter;
+		self
+	}
+
+	/// Write the given storage values into the genesis state, on top of
+	/// those of the runtime.
+	pub fn with_genesis_storage(mut self, storage: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+		self.genesis_storage = storage;
+		self
+	}
+
+	/// Apply the extrinsic on an overlay of the state, returning the storage
+	/// changes it would make. The state itself is left untouched.
+	pub fn dry_run_extrinsic(
+		&self,
+		extrinsic: &Extrinsic,
+		state: &mut <Self as BlockExecutor>::Externalities,
+	) -> Result<StateDiff, Error> {
+		let mut overlay = OverlayExternalities::new(state);
+		let counter = extrinsic.dispatch(self.read_counter(&overlay)?, self.verifier.as_ref())?;
+		self.write_counter(counter, &mut overlay)?;
+
+		Ok(overlay.into_changes())
+	}
+
+	/// Check the block against the state of its parent, after running
+	/// pending migrations, as both execution and diff application must.
+	fn check_block(
+		&self,
+		block: &Block<H, C>,
+		state: &mut <Self as BlockExecutor>::Externalities,
+	) -> Result<(), Error> {
+		self.verify_block(block)?;
+		self.migrate(state)?;
+
+		let parent_timestamp = self.read_timestamp(state)?;
+		if block.timestamp <= parent_timestamp {
+			return Err(Error::InvalidTimestamp);
+		}
+
+		// Re-derive the timestamp inherent locally. An author claiming a time
+		// further ahead than the allowed skew is lying about its inherent.
+		if block.timestamp > (self.clock)().saturating_add(self.config.max_future_drift) {
+			return Err(Error::InherentMismatch);
+		}
+		self.check_difficulty(block, state)
+	}
+
+	/// Execute the block, returning its events and the weight its
+	/// extrinsics used up.
+	fn execute(
+		&self,
+		block: &Block<H, C>,
+		state: &mut <Self as BlockExecutor>::Externalities,
+	) -> Result<(Vec<EventRecord<Event>>, u64), Error> {
+		self.check_block(block, state)?;
+		self.write_timestamp(block.timestamp, state)?;
+		self.write_difficulty(block.difficulty, state)?;
+		self.on_initialize(block.number, state)?;
+
+		if block.extrinsics.len() > self.max_extrinsics() {
+			return Err(Error::BlockFull);
+		}
+
+		// Each extrinsic must fit at its estimated weight, while only its
+		// actual weight is used up, as when the block was built.
+		let mut weight = 0u64;
+		for extrinsic in &block.extrinsics {
+			if weight.saturating_add(self.weight(extrinsic)) > self.max_weight() {
+				return Err(Error::BlockWeightExceeded);
+			}
+			weight = weight.saturating_add(extrinsic.actual_weight());
+		}
+
+		let mut counter = self.read_counter(state)?;
+		let mut events = Vec::new();
+
+		for (index, extrinsic) in block.extrinsics.iter().enumerate() {
+			let new = extrinsic.dispatch(counter, self.verifier.as_ref())?;
+			events.push(EventRecord {
+				index: index as u32,
+				event: Event::CounterChanged { old: counter, new },
+			});
+			counter = new;
+		}
+
+		self.write_counter(counter, state)?;
+		self.on_finalize(block.number, state)?;
+
+		if storage_root::<H>(state) != block.state_root {
+			return Err(Error::StateRootMismatch);
+		}
+
+		Ok((events, weight))
+	}
+
+	/// Execute the block twice, each time on a copy of the state, and
+	/// panic if the two executions diverge, which reveals nondeterminism in
+	/// the runtime.
+	#[cfg(feature = "determinism-check")]
+	fn check_determinism(&self, block: &Block<H, C>, state: &(dyn CounterExternalities + 'static)) {
+		let mut entries = Vec::new();
+		state.for_each_storage(&mut |key, value| entries.push((key.to_vec(), value.to_vec())));
+
+		let mut first = KeyValueMemoryState::new_with_storage(entries.clone());
+		let mut second = KeyValueMemoryState::new_with_storage(entries);
+		let first_ok = self.execute(block, &mut first).is_ok();
+		let second_ok = self.execute(block, &mut second).is_ok();
+
+		assert!(
+			first_ok == second_ok && first.as_ref() == second.as_ref(),
+			"Executing block #{} twice diverged; the runtime is not deterministic",
+			block.number,
+		);
+	}
+
+	/// Run pending storage migrations, before anything else reads the
+	/// state.
+	fn migrate(&self, state: &mut <Self as BlockExecutor>::Externalities) -> Result<(), Error> {
+		migration::run_migrations(state, &[&NamespaceKeys, &VersionCounter])
+			.map_err(|_| Error::StateCorruption(migration::VERSION_KEY.to_vec()))?;
+		Ok(())
+	}
+
+	fn read_counter<S>(&self, state: &S) -> Result<u128, Error> where
+		S: StorageExternalities<Infallible> + ?Sized,
+	{
+		Ok(state.read_versioned::<u128>(&counter_key())?.unwrap_or(0))
+	}
+
+	/// Write a storage value, rejecting values over the configured size
+	/// limit without writing them.
+	fn write_value<S>(&self, key: Vec<u8>, value: Vec<u8>, state: &mut S) -> Result<(), Error> where
+		S: StorageExternalities<Infallible> + ?Sized,
+	{
+		if value.len() > self.config.max_value_size {
+			return Err(Error::ValueTooLarge);
+		}
+
+		state.write_storage(key, value);
+		Ok(())
+	}
+
+	fn write_counter<S>(&self, counter: u128, state: &mut S) -> Result<(), Error> where
+		S: StorageExternalities<Infallible> + ?Sized,
+	{
+		self.write_value(counter_key(), storage::versioned(&counter), state)
+	}
+
+	fn read_timestamp(&self, state: &mut <Self as BlockExecutor>::Externalities) -> Result<u64, Error> {
+		Ok(state.read_decoded::<u64>(&timestamp_key())?.unwrap_or(0))
+	}
+
+	fn write_timestamp(&self, timestamp: u64, state: &mut <Self as BlockExecutor>::Externalities) -> Result<(), Error> {
+		self.write_value(timestamp_key(), timestamp.encode(), state)
+	}
+
+	fn read_difficulty(&self, state: &mut <Self as BlockExecutor>::Externalities) -> Result<u64, Error> {
+		Ok(state.read_decoded::<u64>(&difficulty_key())?.unwrap_or(0))
+	}
+
+	fn write_difficulty(&self, difficulty: u64, state: &mut <Self as BlockExecutor>::Externalities) -> Result<(), Error> {
+		self.write_value(difficulty_key(), difficulty.encode(), state)
+	}
+
+	/// Difficulty of a block with the given timestamp, retargeted from its
+	/// parent and never below the configured minimum.
+	fn expected_difficulty(&self, parent_timestamp: u64, timestamp: u64, parent_difficulty: u64) -> u64 {
+		let difficulty = pow::next_difficulty(
+			parent_timestamp,
+			timestamp,
+			parent_difficulty as usize,
+			self.config.target_block_interval,
+		) as u64;
+		core::cmp::max(difficulty, self.config.difficulty)
+	}
+
+	/// Check that the block claims the difficulty retargeted from the
+	/// parent, whose timestamp and difficulty are read from the state.
+	fn check_difficulty(&self, block: &Block<H, C>, state: &mut <Self as BlockExecutor>::Externalities) -> Result<(), Error> {
+		let parent_timestamp = self.read_timestamp(state)?;
+		let parent_difficulty = self.read_difficulty(state)?;
+		if block.difficulty != self.expected_difficulty(parent_timestamp, block.timestamp, parent_difficulty) {
+			return Err(Error::DifficultyMismatch);
+		}
+
+		Ok(())
+	}
+}
+
+impl<H: Hasher, C: Codec> BlockExecutor for Executor<H, C> {
+	type Error = Error;
+	type Block = Block<H, C>;
+	type Externalities = dyn CounterExternalities + 'static;
+	type Event = EventRecord<Event>;
+
+	fn execute_block(
+		&self,
+		block: &Self::Block,
+		state: &mut Self::Externalities,
+	) -> Result<Vec<EventRecord<Event>>, Error> {
+		#[cfg(feature = "determinism-check")]
+		self.check_determinism(block, state);
+
+		self.execute(block, state).map(|(events, _)| events)
+	}
+
+	fn execute_block_with_context(
+		&self,
+		block: &Self::Block,
+		context: &BlockContext<H256>,
+		state: &mut Self::Externalities,
+	) -> Result<Vec<EventRecord<Event>>, Error> {
+		self.execute_block_with_weight(block, context, state).map(|(events, _)| events)
+	}
+
+	fn on_initialize(
+		&self,
+		number: u64,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		self.write_value(block_number_key(), number.encode(), state)
+	}
+
+	fn verify_block(&self, block: &Self::Block) -> Result<(), Error> {
+		self.verify_header(&block.header())?;
+
+		// The seal only covers the header, which commits to the body through
+		// the extrinsics root.
+		if extrinsics_root::<H, C>(&block.extrinsics) != block.extrinsics_root {
+			return Err(Error::ExtrinsicsRootMismatch);
+		}
+
+		Ok(())
+	}
+
+	fn is_state_root_mismatch(&self, error: &Error) -> bool {
+		matches!(error, Error::StateRootMismatch)
+	}
+}
+
+impl<H: Hasher, C: Codec> HeaderVerifier for Executor<H, C> {
+	type Error = Error;
+	type Header = Header<H, C>;
+
+	fn verify_header(&self, header: &Header<H, C>) -> Result<(), Error> {
+		// Only the minimum can be checked without the parent. The retargeted
+		// difficulty is enforced on execution.
+		if header.difficulty < self.config.difficulty {
+			return Err(Error::DifficultyMismatch);
+		}
+
+		// The seal is valid if the nonce, hashed with the pre-hash, meets the
+		// claimed difficulty.
+		let seal = Header::<H, C>::seal_hash(&header.pre_hash(), header.nonce);
+		if !pow::meets_difficulty(&seal[..], header.difficulty as usize) {
+			return Err(Error::InvalidSeal);
+		}
+
+		Ok(())
+	}
+
+	fn verify_header_with_context(
+		&self,
+		header: &Header<H, C>,
+		context: &BlockContext<H256>,
+	) -> Result<(), Error> {
+		if header.parent_hash != Some(context.parent_id) ||
+			header.number != context.parent_number + 1
+		{
+			return Err(Error::ParentMismatch);
+		}
+
+		self.verify_header(header)
+	}
+}
+
+impl<H: Hasher, C: Codec> DiffExecutor for Executor<H, C> {
+	fn apply_diff(
+		&self,
+		block: &Self::Block,
+		diff: &StateDiff,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		self.check_block(block, state)?;
+
+		for (key, value) in diff {
+			match value {
+				Some(value) => state.write_storage(key.clone(), value.clone()),
+				None => state.remove_storage(key),
+			}
+		}
+
+		if storage_root::<H>(state) != block.state_root {
+			return Err(Error::StateRootMismatch);
+		}
+
+		Ok(())
+	}
+
+	fn apply_diff_with_context(
+		&self,
+		block: &Self::Block,
+		context: &BlockContext<H256>,
+		diff: &StateDiff,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		self.verify_header_with_context(&block.header(), context)?;
+		self.apply_diff(block, diff, state)
+	}
+}
+
+impl<H: Hasher, C: Codec> BuildGenesis for Executor<H, C> {
+	fn build_genesis(
+		&self,
+		state: &mut Self::Externalities,
+	) -> Result<Block<H, C>, Error> {
+		migration::set_storage_version(state, STORAGE_VERSION);
+		self.write_counter(self.genesis_counter, state)?;
+		for (key, value) in &self.genesis_storage {
+			self.write_value(key.clone(), value.clone(), state)?;
+		}
+
+		Ok(Block {
+			parent_hash: None,
+			number: 0,
+			timestamp: 0,
+			difficulty: 0,
+			state_root: storage_root::<H>(state),
+			extrinsics_root: extrinsics_root::<H, C>(&[]),
+			extrinsics: Vec::new(),
+			nonce: 0,
+			_hasher: PhantomData,
+		})
+	}
+
+	fn verify_genesis(
+		&self,
+		block: &Block<H, C>,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		if storage_root::<H>(state) != block.state_root {
+			return Err(Error::StateRootMismatch);
+		}
+
+		Ok(())
+	}
+}
+
+impl<H: Hasher, C: Codec> ExtrinsicBuilder for Executor<H, C> {
+	type BuildBlock = UnsealedBlock<H, C>;
+	type Extrinsic = Extrinsic;
+	type Inherent = InherentData;
+
+	fn initialize_block(
+		&self,
+		block: &Self::Block,
+		state: &mut Self::Externalities,
+		inherent: InherentData,
+	) -> Result<Self::BuildBlock, Self::Error> {
+		self.migrate(state)?;
+
+		// A clock that has not moved since the parent still builds on top of
+		// it, one second later.
+		let timestamp = TimestampProvider::timestamp(&inherent)
+			.ok_or(Error::InvalidTimestamp)?;
+		let timestamp = core::cmp::max(timestamp, block.timestamp + 1);
+		let difficulty = self.expected_difficulty(block.timestamp, timestamp, block.difficulty);
+		self.write_timestamp(timestamp, state)?;
+		self.write_difficulty(difficulty, state)?;
+		self.on_initialize(block.number + 1, state)?;
+
+		Ok(UnsealedBlock {
+			parent_hash: Some(block.id()),
+			number: block.number + 1,
+			timestamp,
+			difficulty,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extrinsics: Vec::new(),
+			weight: 0,
+			nonce: 0,
+			_hasher: PhantomData,
+		})
+	}
+
+	fn apply_extrinsic(
+		&self,
+		block: &mut Self::BuildBlock,
+		extrinsic: Self::Extrinsic,
+		state: &mut Self::Externalities,
+	) -> Result<ExtrinsicOutcome<EventRecord<Event>>, Self::Error> {
+		if !self.filter.allow(&extrinsic) {
+			return Err(Error::Filtered);
+		}
+
+		if block.extrinsics.len() >= self.max_extrinsics() {
+			return Err(Error::BlockFull);
+		}
+
+		let weight = self.weight(&extrinsic);
+		if block.weight.saturating_add(weight) > self.max_weight() {
+			return Err(Error::BlockWeightExceeded);
+		}
+
+		let old = self.read_counter(state)?;
+		let new = extrinsic.dispatch(old, self.verifier.as_ref())?;
+
+		self.write_counter(new, state)?;
+		let weight = extrinsic.actual_weight();
+		let index = block.extrinsics.len() as u32;
+		block.extrinsics.push(extrinsic);
+		block.weight += weight;
+
+		Ok(ExtrinsicOutcome {
+			weight,
+			events: vec![EventRecord { index, event: Event::CounterChanged { old, new } }],
+		})
+	}
+
+	fn finalize_block(
+		&self,
+		block: &mut Self::BuildBlock,
+		state: &mut Self::Externalities,
+	) -> Result<(), Self::Error> {
+		self.on_finalize(block.number, state)?;
+		block.state_root = storage_root::<H>(state);
+		block.extrinsics_root = extrinsics_root::<H, C>(&block.extrinsics);
+
+		Ok(())
+	}
+
+	fn max_extrinsics(&self) -> usize {
+		MAX_EXTRINSICS
+	}
+
+	fn weight(&self, extrinsic: &Extrinsic) -> u64 {
+		extrinsic.weight()
+	}
+
+	fn max_weight(&self) -> u64 {
+		MAX_BLOCK_WEIGHT
+	}
+
+	fn block_weight(&self, block: &Block<H, C>) -> u64 {
+		block.extrinsics.iter()
+			.fold(0, |weight, extrinsic| weight.saturating_add(extrinsic.actual_weight()))
+	}
+
+	fn execute_block_with_weight(
+		&self,
+		block: &Self::Block,
+		context: &BlockContext<H256>,
+		state: &mut Self::Externalities,
+	) -> Result<(Vec<EventRecord<Event>>, u64), Error> {
+		if block.parent_hash != Some(context.parent_id) ||
+			block.number != context.parent_number + 1
+		{
+			return Err(Error::ParentMismatch);
+		}
+
+		#[cfg(feature = "determinism-check")]
+		self.check_determinism(block, state);
+
+		self.execute(block, state)
+	}
+
+	fn priority(&self, extrinsic: &Extrinsic) -> u64 {
+		extrinsic.priority()
+	}
+}
+
+impl<H: Hasher, C: Codec> DecodeExtrinsic for Executor<H, C> {
+	fn decode_extrinsic(&self, bytes: &[u8]) -> Result<Extrinsic, Error> {
+		match C::decode_extrinsic(bytes) {
+			Ok(extrinsic) if extrinsic.nesting() <= MAX_EXTRINSIC_NESTING => Ok(extrinsic),
+			_ => Err(Error::BadExtrinsic),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use blockchain::ById;
+	use blockchain::backend::{ChainQuery, AccessKind, SharedMemoryBackend, ImportLock};
+	use blockchain::builder::BlockBuilder;
+	use blockchain::import::{import_block, import_block_with_diff, import_if_deeper, check_genesis, ImportError};
+	use blockchain::inherent::{InherentDataProviders, TimestampProvider};
+	use blockchain::pool::TransactionPool;
+	use blockchain::sync::apply_headers;
+	use blockchain::testing::TestChain;
+	use std::collections::HashSet;
+
+	type TestExecutor = Executor<Sha3Hasher, ScaleCodec>;
+
+	fn config() -> ChainConfig {
+		ChainConfig { difficulty: 1, target_block_interval: 0, ..ChainConfig::default() }
+	}
+
+	fn inherent() -> InherentData {
+		let mut providers = InherentDataProviders::new();
+		providers.register(TimestampProvider);
+		providers.create_inherent_data()
+	}
+
+	type Chain = TestChain<TestExecutor, fn(UnsealedBlock) -> Block>;
+
+	fn chain(executor: TestExecutor) -> Chain {
+		TestChain::new(executor, UnsealedBlock::seal as fn(UnsealedBlock) -> Block).unwrap()
+	}
+
+	fn build_on_head(chain: &Chain, extrinsics: Vec<Extrinsic>) -> Block {
+		build_with(chain, chain.executor(), extrinsics)
+	}
+
+	/// Build a block on top of the head of the chain, with another executor
+	/// than the chain's.
+	fn build_with(chain: &Chain, executor: &TestExecutor, extrinsics: Vec<Extrinsic>) -> Block {
+		let backend = chain.backend();
+		let head = backend.head();
+		let parent = backend.block_at(&head).unwrap();
+		let state = backend.state_at(&head).unwrap();
+
+		let mut builder = BlockBuilder::new(executor, &parent, state, inherent()).unwrap();
+		for extrinsic in extrinsics {
+			builder.push(extrinsic).unwrap();
+		}
+		builder.build().unwrap().0.seal()
+	}
+
+	fn import(chain: &Chain, block: Block) -> Result<(), Error> {
+		match import_block(chain.backend(), chain.import_lock(), chain.executor(), block) {
+			Ok(()) => Ok(()),
+			Err(ImportError::Execution(err)) | Err(ImportError::StateRootMismatch(err)) => Err(err),
+			Err(err) => panic!("unexpected import error {:?}", err),
+		}
+	}
+
+	#[test]
+	fn fast_path_decodes_extrinsics_as_reference() {
+		let executor = TestExecutor::new(config());
+		let extrinsics = (0..64u128)
+			.map(|i| if i % 2 == 0 {
+				Extrinsic::Add(Add(i * 1_000_003))
+			} else {
+				Extrinsic::Sub(Sub(u128::MAX - i))
+			})
+			.collect::<Vec<_>>();
+
+		for extrinsic in &extrinsics {
+			let encoded = extrinsic.encode();
+			let reference = Extrinsic::decode(&mut &encoded[..]).unwrap();
+			assert_eq!(&reference, extrinsic);
+			assert_eq!(executor.decode_extrinsic(&encoded).unwrap(), reference);
+		}
+
+		let batch = extrinsics.encode();
+		let input = &mut &batch[..];
+		let Compact(len) = <Compact<u32>>::decode(input).unwrap();
+		let decoded = (0..len)
+			.map(|_| Extrinsic::decode_limited(input, MAX_EXTRINSIC_NESTING).unwrap())
+			.collect::<Vec<_>>();
+		assert!(input.is_empty());
+		assert_eq!(decoded, <Vec<Extrinsic>>::decode(&mut &batch[..]).unwrap());
+	}
+
+	#[test]
+	fn rejects_block_sealed_below_retargeted_difficulty() {
+		let executor = Executor::new(ChainConfig { target_block_interval: 1000, ..config() });
+		let chain = chain(executor);
+		chain.build_block(inherent(), Vec::new()).unwrap();
+
+		let honest = build_on_head(&chain, Vec::new());
+		assert_eq!(honest.difficulty, 2);
+
+		let mut cheap = honest.clone();
+		cheap.difficulty = 1;
+		let cheap = pow::seal(cheap, 1);
+		assert!(matches!(import(&chain, cheap), Err(Error::DifficultyMismatch)));
+
+		assert!(import(&chain, honest).is_ok());
+	}
+
+	#[test]
+	fn imports_block_with_locally_filtered_extrinsic() {
+		let no_sub = |extrinsic: &Extrinsic| !matches!(extrinsic, Extrinsic::Sub(_));
+		let chain = chain(Executor::new(config()).with_filter(no_sub));
+		let permissive = Executor::new(config());
+
+		let block = build_with(&chain, &permissive, vec![Extrinsic::Add(Add(2)), Extrinsic::Sub(Sub(1))]);
+		assert!(import(&chain, block).is_ok());
+
+		let head = chain.head();
+		let state = chain.backend().state_at(&head.id()).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &head, state, inherent()).unwrap();
+		assert!(matches!(builder.push(Extrinsic::Sub(Sub(1))), Err(Error::Filtered)));
+		assert!(builder.push(Extrinsic::Add(Add(1))).is_ok());
+	}
+
+	#[test]
+	fn numbers_blocks_from_genesis() {
+		let chain = chain(Executor::new(config()));
+		assert_eq!(chain.head().number(), 0);
+
+		for number in 1..=10 {
+			let parent = chain.head();
+			let block = chain.build_block(inherent(), Vec::new()).unwrap();
+			assert_eq!(block.number(), number);
+			assert_eq!(block.parent_id(), Some(parent.id()));
+		}
+	}
+
+	#[test]
+	fn rejects_block_executed_on_mutated_state() {
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+
+		let head = chain.backend().head();
+		let mut state = chain.backend().state_at(&head).unwrap();
+		let state = state.as_externalities();
+		let mut value = state.read_storage(&counter_key()).unwrap().unwrap();
+		*value.last_mut().unwrap() ^= 1;
+		state.write_storage(counter_key(), value);
+
+		assert!(matches!(
+			chain.executor().execute_block(&block, state),
+			Err(Error::StateRootMismatch)
+		));
+		assert!(import(&chain, block).is_ok());
+	}
+
+	#[test]
+	fn checks_counter_arithmetic() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let state = chain.backend().state_at(&head).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+
+		assert!(matches!(builder.push(Extrinsic::Sub(Sub(1))), Err(Error::CounterUnderflow)));
+		builder.push(Extrinsic::Add(Add(5))).unwrap();
+		builder.push(Extrinsic::Sub(Sub(3))).unwrap();
+		builder.push(Extrinsic::Add(Add(1))).unwrap();
+
+		let (_, mut state) = builder.build().unwrap();
+		assert_eq!(chain.executor().read_counter(state.as_externalities()).unwrap(), 3);
+
+		let state = chain.backend().state_at(&head).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+		builder.push(Extrinsic::Add(Add(u128::MAX))).unwrap();
+		assert!(matches!(builder.push(Extrinsic::Add(Add(1))), Err(Error::CounterOverflow)));
+
+		let (_, mut state) = builder.build().unwrap();
+		assert_eq!(chain.executor().read_counter(state.as_externalities()).unwrap(), u128::MAX);
+	}
+
+	#[test]
+	fn seals_blocks_of_one_chain_at_their_own_difficulty() {
+		let chain = chain(Executor::new(ChainConfig { target_block_interval: 1000, ..config() }));
+
+		for difficulty in 1..=3 {
+			let block = chain.build_block(inherent(), Vec::new()).unwrap();
+			assert_eq!(block.difficulty, difficulty);
+			assert!(pow::meets_difficulty(&block.id()[..], difficulty as usize));
+			assert_eq!(chain.head(), block);
+		}
+	}
+
+	#[test]
+	fn rejects_invalid_timestamps() {
+		let chain = chain(Executor::new(config()));
+		let parent = chain.build_block(inherent(), Vec::new()).unwrap();
+		let honest = build_on_head(&chain, Vec::new());
+
+		let with_timestamp = |timestamp: u64| {
+			let mut block = honest.clone();
+			block.timestamp = timestamp;
+			pow::seal(block, honest.difficulty as usize)
+		};
+
+		let equal = with_timestamp(parent.timestamp);
+		assert!(matches!(import(&chain, equal), Err(Error::InvalidTimestamp)));
+
+		let decreasing = with_timestamp(parent.timestamp - 1);
+		assert!(matches!(import(&chain, decreasing), Err(Error::InvalidTimestamp)));
+
+		let far_future = with_timestamp(parent.timestamp + config().max_future_drift + 1000);
+		assert!(matches!(import(&chain, far_future), Err(Error::InherentMismatch)));
+
+		assert!(import(&chain, honest).is_ok());
+	}
+
+	#[test]
+	fn emits_change_event_per_extrinsic() {
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2)), Extrinsic::Add(Add(3))]);
+
+		let head = chain.backend().head();
+		let mut state = chain.backend().state_at(&head).unwrap();
+		let events = chain.executor().execute_block(&block, state.as_externalities()).unwrap();
+
+		assert_eq!(events, vec![
+			EventRecord { index: 0, event: Event::CounterChanged { old: 0, new: 2 } },
+			EventRecord { index: 1, event: Event::CounterChanged { old: 2, new: 5 } },
+		]);
+	}
+
+	#[test]
+	fn builds_genesis_with_initial_counter() {
+		let executor = Executor::new(config()).with_genesis_counter(5);
+		let mut state = KeyValueMemoryState::default();
+		let genesis = executor.build_genesis(state.as_externalities()).unwrap();
+
+		assert_eq!(executor.read_counter(state.as_externalities()).unwrap(), 5);
+		assert_eq!(genesis.state_root, storage_root::<Sha3Hasher>(state.as_externalities()));
+		assert!(executor.verify_genesis(&genesis, state.as_externalities()).is_ok());
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn round_trips_sealed_block_through_json() {
+		let chain = chain(Executor::new(config()));
+		let block = chain.build_block(inherent(), vec![Extrinsic::Add(Add(2))]).unwrap();
+
+		let json = serde_json::to_string(&block).unwrap();
+		assert!(json.contains(&format!("{:?}", block.state_root)));
+
+		let decoded: Block = serde_json::from_str(&json).unwrap();
+		assert_eq!(decoded.id(), block.id());
+	}
+
+	#[test]
+	fn error_is_send_and_sync() {
+		fn assert_send_sync<T: Send + Sync>() { }
+		assert_send_sync::<Error>();
+	}
+
+	#[test]
+	fn verifies_signed_extrinsics() {
+		let secret = ed25519_dalek::SecretKey::from_bytes(&[7; 32]).unwrap();
+		let public = ed25519_dalek::PublicKey::from(&secret);
+		let keypair = ed25519_dalek::Keypair { secret, public };
+		let sign = |payload: Extrinsic| {
+			let signature = ed25519_dalek::Signer::sign(&keypair, &payload.encode());
+			Extrinsic::Signed {
+				payload: Box::new(payload),
+				signer: H256::from_slice(public.as_bytes()),
+				signature: H512::from_slice(&signature.to_bytes()),
+			}
+		};
+
+		let valid = sign(Extrinsic::Add(Add(2)));
+		let mut tampered = sign(Extrinsic::Add(Add(2)));
+		if let Extrinsic::Signed { payload, .. } = &mut tampered {
+			**payload = Extrinsic::Add(Add(3));
+		}
+
+		let verifier = Ed25519Verifier;
+		assert_eq!(valid.dispatch(0, &verifier).unwrap(), 2);
+		assert!(matches!(tampered.dispatch(0, &verifier), Err(Error::BadSignature)));
+
+		let chain = chain(Executor::new(config()));
+		let block = chain.build_block(inherent(), vec![valid]).unwrap();
+		assert_eq!(chain.head(), block);
+	}
+
+	#[test]
+	fn rejects_extrinsic_over_block_limit() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let state = chain.backend().state_at(&head).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+
+		for _ in 0..MAX_EXTRINSICS {
+			builder.push(Extrinsic::Add(Add(0))).unwrap();
+		}
+		assert!(matches!(builder.push(Extrinsic::Add(Add(5))), Err(Error::BlockFull)));
+
+		let (block, mut state) = builder.build().unwrap();
+		assert_eq!(block.extrinsics.len(), MAX_EXTRINSICS);
+		assert_eq!(chain.executor().read_counter(state.as_externalities()).unwrap(), 0);
+	}
+
+	thread_local! {
+		static HASHED_LENGTHS: std::cell::RefCell<Vec<usize>> = const { std::cell::RefCell::new(Vec::new()) };
+	}
+
+	/// SHA3-256 hasher recording the length of every input it hashes.
+	#[derive(Clone, Copy, Debug, Default)]
+	struct CountingHasher;
+
+	impl Hasher for CountingHasher {
+		fn hash(data: &[u8]) -> H256 {
+			HASHED_LENGTHS.with(|lengths| lengths.borrow_mut().push(data.len()));
+			Sha3Hasher::hash(data)
+		}
+	}
+
+	#[test]
+	fn seals_without_rehashing_extrinsics() {
+		let extrinsics = (0..256).map(|i| Extrinsic::Add(Add(i))).collect::<Vec<_>>();
+		let body_len = extrinsics.encode().len();
+		let block = Block::<CountingHasher, ScaleCodec> {
+			parent_hash: Some(H256::repeat_byte(1)),
+			number: 1,
+			timestamp: 1,
+			difficulty: 8,
+			state_root: H256::repeat_byte(2),
+			extrinsics_root: extrinsics_root::<CountingHasher, ScaleCodec>(&extrinsics),
+			extrinsics,
+			nonce: 0,
+			_hasher: PhantomData,
+		};
+
+		HASHED_LENGTHS.with(|lengths| lengths.borrow_mut().clear());
+		let sealed = pow::seal(block.clone(), 8);
+		let lengths = HASHED_LENGTHS.with(|lengths| lengths.replace(Vec::new()));
+
+		assert!(pow::meets_difficulty(&sealed.id()[..], 8));
+		assert_eq!(sealed.pre_hash(), block.pre_hash());
+		assert_eq!(sealed.id(), Block::<CountingHasher, ScaleCodec>::seal_hash(&block.pre_hash(), sealed.nonce));
+		assert_eq!(lengths.len() as u64, sealed.nonce + 2);
+		assert!(lengths.iter().all(|len| *len < body_len));
+	}
+
+	#[test]
+	fn verifies_block_difficulty_without_state() {
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+		assert!(chain.executor().verify_block(&block).is_ok());
+
+		let strict = Executor::<Sha3Hasher, ScaleCodec>::new(ChainConfig { difficulty: 4, ..config() });
+		assert!(matches!(strict.verify_block(&block), Err(Error::DifficultyMismatch)));
+
+		let mut unsealed = block.clone();
+		while pow::meets_difficulty(&unsealed.id()[..], unsealed.difficulty as usize) {
+			unsealed.nonce += 1;
+		}
+		assert!(matches!(chain.executor().verify_block(&unsealed), Err(Error::InvalidSeal)));
+	}
+
+	#[test]
+	fn rejects_extrinsic_over_block_weight() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let state = chain.backend().state_at(&head).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+
+		let count = MAX_BLOCK_WEIGHT / ARITHMETIC_WEIGHT;
+		for _ in 0..count {
+			builder.push(Extrinsic::Add(Add(1))).unwrap();
+		}
+		assert_eq!(builder.weight(), MAX_BLOCK_WEIGHT);
+		assert!(matches!(builder.push(Extrinsic::Add(Add(1))), Err(Error::BlockWeightExceeded)));
+
+		let (block, mut state) = builder.build().unwrap();
+		assert_eq!(block.extrinsics.len() as u64, count);
+		assert_eq!(chain.executor().read_counter(state.as_externalities()).unwrap(), count as u128);
+	}
+
+	#[test]
+	fn rejects_oversized_extrinsics_length_prefix() {
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+		let encoded = block.encode();
+		assert_eq!(<Block>::decode_limited(&encoded, encoded.len()).unwrap().id(), block.id());
+		assert!(matches!(<Block>::decode_limited(&encoded, encoded.len() - 1), Err(Error::BlockTooLarge)));
+
+		let mut crafted = (
+			block.parent_hash, block.number, block.timestamp, block.difficulty,
+			block.state_root, block.extrinsics_root,
+		).encode();
+		Compact(u32::MAX).encode_to(&mut crafted);
+		assert!(matches!(<Block>::decode_limited(&crafted, 1024 * 1024), Err(Error::BlockTooLarge)));
+	}
+
+	fn sample_block<H: Hasher>() -> Block<H, ScaleCodec> {
+		let extrinsics = vec![Extrinsic::Add(Add(2))];
+		Block {
+			parent_hash: Some(H256::repeat_byte(1)),
+			number: 1,
+			timestamp: 1,
+			difficulty: 0,
+			state_root: H256::repeat_byte(2),
+			extrinsics_root: extrinsics_root::<H, ScaleCodec>(&extrinsics),
+			extrinsics,
+			nonce: 0,
+			_hasher: PhantomData,
+		}
+	}
+
+	#[test]
+	fn hashers_give_stable_distinct_ids() {
+		let sha3 = sample_block::<Sha3Hasher>().id();
+		let blake2 = sample_block::<crate::hash::Blake2Hasher>().id();
+		let keccak = sample_block::<crate::hash::Keccak256Hasher>().id();
+
+		assert_eq!(sha3, sample_block::<Sha3Hasher>().id());
+		assert_eq!(blake2, sample_block::<crate::hash::Blake2Hasher>().id());
+		assert_ne!(sha3, blake2);
+		assert_ne!(sha3, keccak);
+		assert_ne!(blake2, keccak);
+	}
+
+	#[test]
+	fn dedupes_blocks_in_hash_set() {
+		let first = sample_block::<Sha3Hasher>();
+		let mut second = first.clone();
+		second.nonce = 1;
+
+		let blocks = vec![first.clone(), second.clone(), first.clone()];
+		assert_eq!(blocks.iter().cloned().collect::<HashSet<_>>().len(), 2);
+		assert_eq!(blocks.into_iter().map(ById).collect::<HashSet<_>>().len(), 2);
+	}
+
+	#[test]
+	fn tags_events_with_extrinsic_index() {
+		let chain = chain(Executor::new(config()));
+		let extrinsics = vec![Extrinsic::Add(Add(1)), Extrinsic::Sub(Sub(1)), Extrinsic::Add(Add(4))];
+		let block = build_on_head(&chain, extrinsics);
+
+		let head = chain.backend().head();
+		let mut state = chain.backend().state_at(&head).unwrap();
+		let events = chain.executor().execute_block(&block, state.as_externalities()).unwrap();
+
+		assert_eq!(events.iter().map(|event| event.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+		assert!(events.iter().all(|event| !event.is_block_event()));
+	}
+
+	#[test]
+	fn dry_run_reports_diff_without_writing() {
+		let executor = Executor::new(config()).with_genesis_counter(5);
+		let mut state = KeyValueMemoryState::default();
+		executor.build_genesis(state.as_externalities()).unwrap();
+		let before = state.as_ref().clone();
+
+		let diff = executor.dry_run_extrinsic(&Extrinsic::Add(Add(2)), state.as_externalities()).unwrap();
+
+		assert_eq!(diff.len(), 1);
+		assert_eq!(diff.get(&counter_key()), Some(&Some(storage::versioned(&7u128))));
+		assert_eq!(state.as_ref(), &before);
+		assert_eq!(executor.read_counter(state.as_externalities()).unwrap(), 5);
+	}
+
+	#[test]
+	fn rejects_nonce_not_meeting_difficulty() {
+		let executor = TestExecutor::new(config());
+		let mut block = sample_block::<Sha3Hasher>();
+		block.difficulty = 8;
+		while pow::meets_difficulty(&block.id()[..], 8) {
+			block.nonce += 1;
+		}
+
+		assert!(matches!(executor.verify_block(&block), Err(Error::InvalidSeal)));
+		assert!(executor.verify_block(&pow::seal(block, 8)).is_ok());
+	}
+
+	#[test]
+	fn authored_block_carries_timestamp_inherent() {
+		let chain = chain(Executor::new(config()));
+		let data = inherent();
+		let timestamp = TimestampProvider::timestamp(&data).unwrap();
+
+		let block = chain.build_block(data, Vec::new()).unwrap();
+		assert_eq!(block.timestamp(), timestamp);
+	}
+
+	#[test]
+	fn decodes_only_well_formed_extrinsics() {
+		let executor = TestExecutor::new(config());
+		let extrinsic = Extrinsic::Add(Add(300));
+		let encoded = extrinsic.encode();
+
+		assert_eq!(executor.decode_extrinsic(&encoded).unwrap(), extrinsic);
+		assert!(matches!(executor.decode_extrinsic(&encoded[..encoded.len() - 1]), Err(Error::BadExtrinsic)));
+		assert!(matches!(executor.decode_extrinsic(&[0xff]), Err(Error::BadExtrinsic)));
+
+		let mut trailing = encoded.clone();
+		trailing.push(0);
+		assert!(matches!(executor.decode_extrinsic(&trailing), Err(Error::BadExtrinsic)));
+	}
+
+	#[test]
+	fn packs_light_extrinsics_around_heavy_ones() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let state = chain.backend().state_at(&head).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+
+		let light = (MAX_BLOCK_WEIGHT - ARITHMETIC_WEIGHT) / ARITHMETIC_WEIGHT;
+		let heavy = Extrinsic::Signed {
+			payload: Box::new(Extrinsic::Add(Add(1))),
+			signer: H256::zero(),
+			signature: H512::zero(),
+		};
+		let extrinsics = (0..light).map(|_| Extrinsic::Add(Add(1)))
+			.chain(vec![heavy, Extrinsic::Add(Add(0))]);
+
+		let included = builder.push_many(extrinsics);
+		assert_eq!(included.len() as u64, light + 1);
+		assert_eq!(included.last(), Some(&Extrinsic::Add(Add(0))));
+		assert_eq!(builder.weight(), light * ARITHMETIC_WEIGHT + NOOP_WEIGHT);
+
+		let (block, mut state) = builder.build().unwrap();
+		assert_eq!(block.extrinsics, included);
+		assert_eq!(chain.executor().read_counter(state.as_externalities()).unwrap(), light as u128);
+	}
+
+	#[test]
+	fn checks_parent_against_context() {
+		let chain = chain(Executor::new(config()));
+		let genesis = chain.backend().block_at(&chain.backend().head()).unwrap();
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(1))]);
+		let state = chain.backend().state_at(&genesis.id()).unwrap();
+
+		let context = BlockContext { parent_id: genesis.id(), parent_number: 0 };
+		let mut valid = state.clone();
+		assert!(chain.executor().execute_block_with_context(&block, &context, valid.as_externalities()).is_ok());
+
+		let wrong_parent = BlockContext { parent_id: H256::repeat_byte(9), parent_number: 0 };
+		let mut mismatched = state.clone();
+		assert!(matches!(
+			chain.executor().execute_block_with_context(&block, &wrong_parent, mismatched.as_externalities()),
+			Err(Error::ParentMismatch)
+		));
+
+		let wrong_number = BlockContext { parent_id: genesis.id(), parent_number: 1 };
+		let mut mismatched = state;
+		assert!(matches!(
+			chain.executor().execute_block_with_context(&block, &wrong_number, mismatched.as_externalities()),
+			Err(Error::ParentMismatch)
+		));
+	}
+
+	#[test]
+	fn runs_chains_with_different_configs() {
+		let low = chain(Executor::new(config()));
+		let high = chain(Executor::new(ChainConfig { difficulty: 6, ..config() }));
+		assert_eq!(low.executor().config().difficulty, 1);
+		assert_eq!(high.executor().config().difficulty, 6);
+
+		let low_block = low.build_block(inherent(), Vec::new()).unwrap();
+		let high_block = high.build_block(inherent(), Vec::new()).unwrap();
+		assert_eq!(low_block.difficulty, 1);
+		assert_eq!(high_block.difficulty, 6);
+		assert!(pow::meets_difficulty(&high_block.id()[..], 6));
+
+		assert!(low.executor().verify_block(&high_block).is_ok());
+		assert!(matches!(high.executor().verify_block(&low_block), Err(Error::DifficultyMismatch)));
+	}
+
+	#[test]
+	fn applying_diff_checks_timestamps_as_full_execution() {
+		let chain = chain(Executor::new(config()));
+		let parent = chain.build_block(inherent(), Vec::new()).unwrap();
+		let honest = build_on_head(&chain, vec![Extrinsic::Add(Add(3))]);
+
+		let head = chain.backend().head();
+		let before = chain.backend().state_at(&head).unwrap();
+		let mut after = before.clone();
+		chain.executor().execute_block(&honest, after.as_externalities()).unwrap();
+		let diff = after.as_ref().iter()
+			.filter(|(key, value)| before.as_ref().get(*key) != Some(value))
+			.map(|(key, value)| (key.clone(), Some(value.clone())))
+			.collect::<StateDiff>();
+
+		let with_timestamp = |timestamp: u64| {
+			let mut block = honest.clone();
+			block.timestamp = timestamp;
+			pow::seal(block, honest.difficulty as usize)
+		};
+		let import_diff = |block: Block| {
+			import_block_with_diff(chain.backend(), chain.import_lock(), chain.executor(), block, Some(&diff))
+		};
+
+		assert!(matches!(import_diff(with_timestamp(parent.timestamp)), Err(ImportError::Execution(Error::InvalidTimestamp))));
+		assert!(matches!(
+			import_diff(with_timestamp(parent.timestamp + config().max_future_drift + 1000)),
+			Err(ImportError::Execution(Error::InherentMismatch))
+		));
+		assert!(import_diff(honest).is_ok());
+	}
+
+	#[test]
+	fn applying_diff_matches_full_execution() {
+		let executed = chain(Executor::new(config()));
+		let diffed = chain(Executor::new(config()));
+		let block = build_on_head(&executed, vec![Extrinsic::Add(Add(3)), Extrinsic::Sub(Sub(1))]);
+
+		let head = executed.backend().head();
+		let before = executed.backend().state_at(&head).unwrap();
+		let mut after = before.clone();
+		executed.executor().execute_block(&block, after.as_externalities()).unwrap();
+		let mut diff = after.as_ref().iter()
+			.filter(|(key, value)| before.as_ref().get(*key) != Some(value))
+			.map(|(key, value)| (key.clone(), Some(value.clone())))
+			.collect::<StateDiff>();
+		assert!(!diff.is_empty());
+
+		import_block(executed.backend(), executed.import_lock(), executed.executor(), block.clone()).unwrap();
+		import_block_with_diff(diffed.backend(), diffed.import_lock(), diffed.executor(), block.clone(), Some(&diff)).unwrap();
+		let mut executed_state = executed.backend().state_at(&block.id()).unwrap();
+		let mut diffed_state = diffed.backend().state_at(&block.id()).unwrap();
+		assert_eq!(storage_root::<Sha3Hasher>(diffed_state.as_externalities()), block.state_root);
+		assert_eq!(
+			storage_root::<Sha3Hasher>(diffed_state.as_externalities()),
+			storage_root::<Sha3Hasher>(executed_state.as_externalities()),
+		);
+
+		let tampered = chain(Executor::new(config()));
+		diff.insert(counter_key(), Some(storage::versioned(&7u128)));
+		assert!(import_block_with_diff(tampered.backend(), tampered.import_lock(), tampered.executor(), block, Some(&diff)).is_err());
+	}
+
+	#[test]
+	fn traces_counter_read_then_write() {
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+
+		let head = chain.backend().head();
+		let mut state = TracingExternalities::new(chain.backend().state_at(&head).unwrap());
+		chain.executor().execute_block(&block, state.as_externalities()).unwrap();
+
+		let counter = state.trace().into_iter()
+			.filter(|access| access.key == counter_key())
+			.map(|access| (access.kind, access.value_len))
+			.collect::<Vec<_>>();
+		let len = storage::versioned(&0u128).len();
+		assert_eq!(counter, vec![(AccessKind::Read, Some(len)), (AccessKind::Write, Some(len))]);
+	}
+
+	#[test]
+	fn rejects_timestamp_disagreeing_with_inherent() {
+		let now = TimestampProvider::now();
+		let chain = chain(Executor::new(config()).with_clock(move || now));
+		let honest = build_on_head(&chain, Vec::new());
+
+		let mut tampered = honest.clone();
+		tampered.timestamp = now + config().max_future_drift + 1;
+		let tampered = pow::seal(tampered, honest.difficulty as usize);
+		assert!(matches!(import(&chain, tampered), Err(Error::InherentMismatch)));
+
+		assert!(import(&chain, honest).is_ok());
+	}
+
+	#[test]
+	fn reports_extrinsics_missing_from_pool() {
+		let chain = chain(Executor::new(config()));
+		let extrinsics = vec![Extrinsic::Add(Add(1)), Extrinsic::Add(Add(2)), Extrinsic::Sub(Sub(1))];
+		let block = build_on_head(&chain, extrinsics.clone());
+		let compact = block.to_compact();
+
+		let mut pool = TransactionPool::new(chain.executor().clone());
+		for extrinsic in extrinsics.iter().filter(|extrinsic| **extrinsic != extrinsics[1]) {
+			assert!(pool.submit(extrinsic.clone()));
+		}
+		match compact.reconstruct(pool.pending()) {
+			Err(MissingExtrinsics(missing)) =>
+				assert_eq!(missing, vec![extrinsic_hash::<Sha3Hasher, ScaleCodec>(&extrinsics[1])]),
+			Ok(_) => panic!("reconstructed a block with a missing extrinsic"),
+		}
+
+		assert!(pool.submit(extrinsics[1].clone()));
+		assert_eq!(compact.reconstruct(pool.pending()).unwrap(), block);
+	}
+
+	#[test]
+	fn dispatches_each_variant_to_its_call() {
+		struct AcceptAll;
+
+		impl Verifier for AcceptAll {
+			fn verify(&self, _payload: &[u8], _signer: &H256, _signature: &H512) -> bool {
+				true
+			}
+		}
+
+		let signed = |payload: Extrinsic| Extrinsic::Signed {
+			payload: Box::new(payload),
+			signer: H256::zero(),
+			signature: H512::zero(),
+		};
+
+		assert_eq!(Extrinsic::Add(Add(3)).dispatch(5, &AcceptAll).unwrap(), 8);
+		assert_eq!(Extrinsic::Sub(Sub(3)).dispatch(5, &AcceptAll).unwrap(), 2);
+		assert_eq!(signed(Extrinsic::Add(Add(3))).dispatch(5, &AcceptAll).unwrap(), 8);
+		assert_eq!(signed(Extrinsic::Sub(Sub(3))).dispatch(5, &AcceptAll).unwrap(), 2);
+		assert!(matches!(Extrinsic::Sub(Sub(6)).dispatch(5, &AcceptAll), Err(Error::CounterUnderflow)));
+		assert!(matches!(Extrinsic::Add(Add(1)).dispatch(u128::MAX, &AcceptAll), Err(Error::CounterOverflow)));
+	}
+
+	#[test]
+	fn accepts_timestamps_within_future_drift() {
+		let now = TimestampProvider::now() + 1000;
+		let executor = Executor::new(ChainConfig { max_future_drift: 5, ..config() }).with_clock(move || now);
+		let chain = chain(executor);
+
+		let authored_at = |timestamp: u64| {
+			let head = chain.backend().head();
+			let parent = chain.backend().block_at(&head).unwrap();
+			let state = chain.backend().state_at(&head).unwrap();
+			let mut data = InherentData::new();
+			data.insert(blockchain::inherent::TIMESTAMP_INHERENT, timestamp.to_le_bytes().to_vec());
+
+			let builder = BlockBuilder::new(chain.executor(), &parent, state, data).unwrap();
+			builder.build().unwrap().0.seal()
+		};
+
+		assert!(matches!(import(&chain, authored_at(now + 6)), Err(Error::InherentMismatch)));
+		assert!(import(&chain, authored_at(now + 5)).is_ok());
+	}
+
+	#[test]
+	fn migrates_old_layout_to_namespaced_keys() {
+		let executor = TestExecutor::new(config());
+		let mut state = KeyValueMemoryState::new_with_storage(vec![
+			(b"counter".to_vec(), 7u128.encode()),
+			(b"timestamp".to_vec(), 5u64.encode()),
+		]);
+
+		executor.migrate(state.as_externalities()).unwrap();
+		assert_eq!(migration::storage_version(state.as_externalities()).unwrap(), STORAGE_VERSION);
+		assert_eq!(executor.read_counter(state.as_externalities()).unwrap(), 7);
+		assert_eq!(executor.read_timestamp(state.as_externalities()).unwrap(), 5);
+		assert!(!state.as_externalities().exists_storage(b"counter"));
+		assert!(!state.as_externalities().exists_storage(b"timestamp"));
+
+		executor.migrate(state.as_externalities()).unwrap();
+		assert_eq!(executor.read_counter(state.as_externalities()).unwrap(), 7);
+	}
+
+	#[test]
+	fn detects_incompatible_genesis() {
+		let zero = Executor::new(config());
+		let five = Executor::new(config()).with_genesis_counter(5);
+		let genesis = |executor: &TestExecutor| {
+			let mut state = KeyValueMemoryState::default();
+			let block = executor.build_genesis(state.as_externalities()).unwrap();
+			(block, state)
+		};
+		let (zero_block, _) = genesis(&zero);
+		let (five_block, five_state) = genesis(&five);
+		assert_ne!(zero_block.id(), five_block.id());
+		assert_ne!(zero_block.state_root, five_block.state_root);
+
+		let chain = chain(zero.clone());
+		assert!(check_genesis(chain.backend(), &zero, &zero_block).is_ok());
+		assert!(matches!(check_genesis(chain.backend(), &five, &five_block), Err(ImportError::GenesisMismatch)));
+
+		let tampered = SharedMemoryBackend::<_, (), _>::new_with_genesis(zero_block.clone(), five_state);
+		assert!(matches!(
+			check_genesis(&tampered, &zero, &zero_block),
+			Err(ImportError::Execution(Error::StateRootMismatch))
+		));
+	}
+
+	#[test]
+	fn accumulates_reported_extrinsic_weights() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let state = chain.backend().state_at(&head).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+
+		let mut reported = 0;
+		for extrinsic in [Extrinsic::Add(Add(2)), Extrinsic::Add(Add(0)), Extrinsic::Sub(Sub(1))] {
+			reported += builder.push(extrinsic).unwrap().weight;
+		}
+		assert_eq!(reported, 2 * ARITHMETIC_WEIGHT + NOOP_WEIGHT);
+		assert_eq!(builder.weight(), reported);
+
+		let (block, _) = builder.build().unwrap();
+		let block = block.seal();
+		assert_eq!(chain.executor().block_weight(&block), reported);
+
+		// Execution accounts the same weight as building.
+		let context = BlockContext { parent_id: head, parent_number: parent.number };
+		let mut state = chain.backend().state_at(&head).unwrap();
+		let (_, executed) = chain.executor()
+			.execute_block_with_weight(&block, &context, state.as_externalities()).unwrap();
+		assert_eq!(executed, reported);
+	}
+
+	#[test]
+	fn seals_with_external_nonce_only_if_valid() {
+		let chain = chain(Executor::new(ChainConfig { difficulty: 8, ..config() }));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let state = chain.backend().state_at(&head).unwrap();
+		let builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+		let (unsealed, _) = builder.build().unwrap();
+
+		let sealed = unsealed.clone().seal();
+		let wrong = (0..).find(|nonce| {
+			!pow::meets_difficulty(&<Block as PowBlock>::seal_hash(&sealed.pre_hash(), *nonce)[..], 8)
+		}).unwrap();
+
+		let rejected = unsealed.clone().seal_with_nonce(wrong).unwrap_err();
+		assert_eq!(rejected.nonce, unsealed.nonce);
+		assert_eq!(rejected.seal_with_nonce(sealed.nonce).unwrap(), sealed);
+		assert!(chain.executor().verify_block(&sealed).is_ok());
+	}
+
+	#[test]
+	fn packs_higher_priority_extrinsics_first_when_full() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let mut pool = TransactionPool::new(chain.executor().clone());
+
+		let capacity = MAX_BLOCK_WEIGHT / ARITHMETIC_WEIGHT;
+		for _ in 0..capacity {
+			assert!(pool.submit(Extrinsic::Add(Add(1))));
+		}
+		assert!(pool.submit(Extrinsic::Add(Add(50))));
+
+		let ready = pool.ready(&parent, chain.backend().state_at(&head).unwrap(), inherent());
+		assert_eq!(ready.len() as u64, capacity);
+		assert_eq!(ready[0], Extrinsic::Add(Add(50)));
+		assert!(ready[1..].iter().all(|extrinsic| *extrinsic == Extrinsic::Add(Add(1))));
+
+		assert_eq!(pool.len() as u64, capacity + 1);
+	}
+
+	#[test]
+	fn rejects_swapped_extrinsic() {
+		let chain = chain(Executor::new(config()));
+		let honest = build_on_head(&chain, vec![Extrinsic::Add(Add(1)), Extrinsic::Add(Add(2))]);
+
+		let mut swapped = honest.clone();
+		swapped.extrinsics[1] = Extrinsic::Add(Add(3));
+		let swapped = pow::seal(swapped, honest.difficulty as usize);
+		assert!(matches!(import(&chain, swapped), Err(Error::ExtrinsicsRootMismatch)));
+
+		assert!(import(&chain, honest).is_ok());
+	}
+
+	#[test]
+	fn rejects_value_over_size_limit_leaving_state_clean() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let mut state = chain.backend().state_at(&head).unwrap();
+		let before = state.clone();
+		let limit = config().max_value_size;
+
+		let written = chain.executor().write_value(b"large".to_vec(), vec![0; limit + 1], state.as_externalities());
+		assert!(matches!(written, Err(Error::ValueTooLarge)));
+		assert_eq!(state.as_ref(), before.as_ref());
+
+		chain.executor().write_value(b"large".to_vec(), vec![0; limit], state.as_externalities()).unwrap();
+		assert_eq!(state.as_ref().get(&b"large"[..]), Some(&vec![0; limit]));
+	}
+
+	#[test]
+	fn collects_counter_change_with_old_and_new_values() {
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+
+		let head = chain.backend().head();
+		let mut state = StateDiffCollector::new(chain.backend().state_at(&head).unwrap());
+		chain.executor().execute_block(&block, state.as_externalities()).unwrap();
+
+		let changes = state.changes();
+		let counter = changes.get(&counter_key()).unwrap();
+		assert_eq!(counter.old, Some(storage::versioned(&0u128)));
+		assert_eq!(counter.new, Some(storage::versioned(&2u128)));
+	}
+
+	fn assert_round_trips<C: Codec>() {
+		let extrinsics = vec![Extrinsic::Add(Add(2)), Extrinsic::Sub(Sub(1))];
+		let block = Block::<Sha3Hasher, C> {
+			parent_hash: Some(H256::repeat_byte(1)),
+			number: 1,
+			timestamp: 1,
+			difficulty: 0,
+			state_root: H256::repeat_byte(2),
+			extrinsics_root: extrinsics_root::<Sha3Hasher, C>(&extrinsics),
+			extrinsics,
+			nonce: 0,
+			_hasher: PhantomData,
+		};
+
+		let decoded = Block::<Sha3Hasher, C>::from_bytes(&block.to_bytes()).unwrap();
+		assert_eq!(decoded.to_bytes(), block.to_bytes());
+		assert_eq!(decoded.id(), block.id());
+		assert_eq!(decoded.extrinsics_root, extrinsics_root::<Sha3Hasher, C>(&decoded.extrinsics));
+	}
+
+	fn assert_decodes_with_limits<C: Codec>() {
+		let executor = Executor::<Sha3Hasher, C>::new_with_hasher(config());
+		let nested = |depth| (0..depth).fold(Extrinsic::Add(Add(2)), |payload, _| Extrinsic::Signed {
+			payload: Box::new(payload),
+			signer: H256::repeat_byte(1),
+			signature: H512::repeat_byte(2),
+		});
+
+		let allowed = nested(MAX_EXTRINSIC_NESTING);
+		assert_eq!(executor.decode_extrinsic(&C::encode(&allowed)).unwrap(), allowed);
+		let too_deep = C::encode(&nested(MAX_EXTRINSIC_NESTING + 1));
+		assert!(matches!(executor.decode_extrinsic(&too_deep), Err(Error::BadExtrinsic)));
+
+		let mut block = Block::<Sha3Hasher, C> {
+			parent_hash: Some(H256::repeat_byte(1)),
+			number: 1,
+			timestamp: 1,
+			difficulty: 0,
+			state_root: H256::repeat_byte(2),
+			extrinsics_root: H256::repeat_byte(3),
+			extrinsics: vec![allowed],
+			nonce: 0,
+			_hasher: PhantomData,
+		};
+		let encoded = block.to_bytes();
+		assert_eq!(Block::<Sha3Hasher, C>::decode_limited(&encoded, encoded.len()).unwrap().id(), block.id());
+		assert!(matches!(
+			Block::<Sha3Hasher, C>::decode_limited(&encoded, encoded.len() - 1),
+			Err(Error::BlockTooLarge)
+		));
+
+		block.extrinsics = vec![nested(MAX_EXTRINSIC_NESTING + 1)];
+		assert!(matches!(
+			Block::<Sha3Hasher, C>::decode_limited(&block.to_bytes(), MAX_BLOCK_SIZE),
+			Err(Error::InvalidEncoding)
+		));
+	}
+
+	#[test]
+	fn decodes_blocks_and_extrinsics_with_limits_under_each_codec() {
+		assert_decodes_with_limits::<ScaleCodec>();
+		#[cfg(feature = "serde")]
+		assert_decodes_with_limits::<crate::encoding::JsonCodec>();
+	}
+
+	#[test]
+	fn round_trips_blocks_under_each_codec() {
+		assert_round_trips::<ScaleCodec>();
+		#[cfg(feature = "serde")]
+		assert_round_trips::<crate::encoding::JsonCodec>();
+	}
+
+	#[test]
+	fn records_block_number_before_extrinsics() {
+		let chain = chain(Executor::new(config()));
+		for number in 1..=2u64 {
+			let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+			let head = chain.backend().head();
+			let mut state = TracingExternalities::new(chain.backend().state_at(&head).unwrap());
+			chain.executor().execute_block(&block, state.as_externalities()).unwrap();
+
+			let trace = state.trace();
+			let position = |key: Vec<u8>, kind| trace.iter()
+				.position(|access| access.key == key && access.kind == kind)
+				.unwrap();
+			assert!(position(block_number_key(), AccessKind::Write) < position(counter_key(), AccessKind::Read));
+			assert_eq!(state.read_decoded::<u64>(&block_number_key()).unwrap(), Some(number));
+
+			assert!(import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block).unwrap());
+		}
+	}
+
+	#[test]
+	fn executes_extrinsics_in_authoring_order() {
+		let chain = chain(Executor::new(config()));
+		let extrinsics = vec![Extrinsic::Add(Add(1)), Extrinsic::Sub(Sub(1)), Extrinsic::Add(Add(5))];
+		let block = build_on_head(&chain, extrinsics.clone());
+		assert_eq!(block.extrinsics, extrinsics);
+
+		let head = chain.backend().head();
+		let mut state = chain.backend().state_at(&head).unwrap();
+		chain.executor().execute_block(&block, state.as_externalities()).unwrap();
+		assert_eq!(chain.executor().read_counter(&state).unwrap(), 5);
+
+		// Applied in another order, the `Sub` underflows.
+		let mut reordered = block.clone();
+		reordered.extrinsics.swap(0, 1);
+		reordered.extrinsics_root = extrinsics_root::<Sha3Hasher, ScaleCodec>(&reordered.extrinsics);
+		let reordered = pow::seal(reordered, block.difficulty as usize);
+		assert!(matches!(import(&chain, reordered), Err(Error::CounterUnderflow)));
+	}
+
+	#[test]
+	fn follows_chain_of_headers_only() {
+		let chain = chain(Executor::new(config()));
+		let genesis = chain.head();
+		let blocks = (1..=3u128)
+			.map(|value| chain.build_block(inherent(), vec![Extrinsic::Add(Add(value))]).unwrap())
+			.collect::<Vec<_>>();
+
+		let headers = SharedMemoryBackend::<Header, (), ()>::new_with_genesis(genesis.header(), ());
+		let lock = ImportLock::new();
+		let imported = apply_headers(&headers, &lock, chain.executor(), blocks.iter().map(|block| block.header()));
+		assert_eq!(imported.unwrap(), 3);
+		for block in &blocks {
+			assert_eq!(headers.block_at(&block.id()).unwrap(), block.header());
+		}
+
+		let mut unlinked = blocks[2].header();
+		unlinked.number += 1;
+		let difficulty = unlinked.difficulty as usize;
+		let unlinked = pow::seal(unlinked, difficulty);
+		assert!(apply_headers(&headers, &lock, chain.executor(), Some(unlinked.clone())).is_err());
+		assert!(!headers.contains(&unlinked.id()).unwrap());
+	}
+
+	#[test]
+	fn downcasts_backend_error_source() {
+		use std::error::Error as _;
+		use std::io;
+
+		let error = Error::Backend(Box::new(io::Error::new(io::ErrorKind::NotFound, "missing trie node")));
+		let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+		assert_eq!(source.kind(), io::ErrorKind::NotFound);
+
+		assert!(Error::CounterUnderflow.source().is_none());
+	}
+
+	#[test]
+	fn refunded_weight_fits_extra_extrinsics() {
+		let chain = chain(Executor::new(config()));
+		let head = chain.backend().head();
+		let parent = chain.backend().block_at(&head).unwrap();
+		let state = chain.backend().state_at(&head).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &parent, state, inherent()).unwrap();
+
+		// At their estimated weight, only `MAX_BLOCK_WEIGHT / ARITHMETIC_WEIGHT`
+		// extrinsics would fit.
+		let noops = 10;
+		let extrinsics = (0..noops).map(|_| Extrinsic::Add(Add(0)))
+			.chain((0..MAX_BLOCK_WEIGHT).map(|_| Extrinsic::Add(Add(1))));
+		let included = builder.push_many(extrinsics);
+		let refunded = noops * (ARITHMETIC_WEIGHT - NOOP_WEIGHT);
+		assert_eq!(builder.refunded_weight(), refunded);
+		assert_eq!(included.len() as u64, MAX_BLOCK_WEIGHT / ARITHMETIC_WEIGHT + refunded / ARITHMETIC_WEIGHT);
+		assert_eq!(builder.weight(), MAX_BLOCK_WEIGHT);
+
+		let block = builder.build().unwrap().0.seal();
+		assert!(import(&chain, block).is_ok());
+	}
+
+	#[cfg(feature = "determinism-check")]
+	#[test]
+	fn deterministic_execution_passes_double_check() {
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+		assert!(import(&chain, block).is_ok());
+	}
+
+	#[cfg(feature = "determinism-check")]
+	#[test]
+	#[should_panic(expected = "the runtime is not deterministic")]
+	fn nondeterministic_execution_trips_double_check() {
+		use std::sync::atomic::Ordering;
+
+		let chain = chain(Executor::new(config()));
+		let block = build_on_head(&chain, vec![Extrinsic::Add(Add(2))]);
+
+		// Every other execution sees a clock far behind the block timestamp.
+		let now = TimestampProvider::now();
+		let behind = AtomicBool::new(false);
+		let flaky = Executor::<Sha3Hasher, ScaleCodec>::new(config())
+			.with_clock(move || if behind.fetch_xor(true, Ordering::Relaxed) { 0 } else { now });
+
+		let head = chain.backend().head();
+		let mut state = chain.backend().state_at(&head).unwrap();
+		let _ = flaky.execute_block(&block, state.as_externalities());
 	}
 }
@@ -0,0 +1,109 @@
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+use primitive_types::H256;
+use serde::{Serialize, Deserialize};
+use blockchain::{Block as BlockT, BuildGenesis, AsExternalities};
+use blockchain::backend::KeyValueMemoryState;
+use blockchain::rpc::Bytes;
+use crate::runtime::{Block, Executor, ChainConfig, CounterExternalities, Error};
+
+/// Parameters and genesis of a counter chain, as loaded from JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainSpec {
+	/// Consensus parameters of the chain.
+	pub config: ChainConfig,
+	/// Storage values of the genesis state, hex encoded.
+	pub genesis: Vec<(Bytes, Bytes)>,
+	/// Expected hash of the genesis block.
+	pub genesis_hash: H256,
+}
+
+impl ChainSpec {
+	/// Chain spec of the config, with the genesis state the runtime builds
+	/// for it.
+	pub fn from_config(config: ChainConfig) -> Result<Self, Error> {
+		let mut state = KeyValueMemoryState::default();
+		let block = Executor::new(config).build_genesis(state.as_externalities())?;
+		let genesis = state.as_ref().iter()
+			.map(|(key, value)| (Bytes(key.clone()), Bytes(value.clone())))
+			.collect();
+
+		Ok(Self { config, genesis, genesis_hash: block.id() })
+	}
+
+	/// Executor of the chain, building the genesis of the spec.
+	pub fn executor(&self) -> Executor {
+		let storage = self.genesis.iter()
+			.map(|(key, value)| (key.0.clone(), value.0.clone()))
+			.collect();
+
+		Executor::new(self.config).with_genesis_storage(storage)
+	}
+}
+
+/// Load a chain spec from a JSON file.
+pub fn load_chain_spec(path: &Path) -> Result<ChainSpec, Box<dyn StdError>> {
+	Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Save a chain spec to a JSON file.
+pub fn save_chain_spec(path: &Path, spec: &ChainSpec) -> Result<(), Box<dyn StdError>> {
+	fs::write(path, serde_json::to_vec_pretty(spec)?)?;
+	Ok(())
+}
+
+/// Build the genesis of the spec into the externalities, checking it
+/// against the expected genesis hash.
+pub fn build_genesis_from_spec(
+	spec: &ChainSpec,
+	state: &mut (dyn CounterExternalities + 'static),
+) -> Result<Block, Error> {
+	let block = spec.executor().build_genesis(state)?;
+	if block.id() != spec.genesis_hash {
+		return Err(Error::GenesisHashMismatch)
+	}
+
+	Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runtime::counter_key;
+	use crate::storage::ReadVersioned;
+
+	#[test]
+	fn round_trips_chain_spec_through_file() {
+		let spec = ChainSpec::from_config(ChainConfig::default()).unwrap();
+		let path = std::env::temp_dir().join(format!("counter-spec-{}.json", std::process::id()));
+
+		save_chain_spec(&path, &spec).unwrap();
+		let loaded = load_chain_spec(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(loaded, spec);
+	}
+
+	#[test]
+	fn builds_genesis_matching_spec() {
+		let spec = ChainSpec::from_config(ChainConfig::default()).unwrap();
+
+		let mut state = KeyValueMemoryState::default();
+		let block = build_genesis_from_spec(&spec, state.as_externalities()).unwrap();
+		assert_eq!(block.id(), spec.genesis_hash);
+		assert_eq!(state.read_versioned::<u128>(&counter_key()).unwrap(), Some(0));
+	}
+
+	#[test]
+	fn rejects_genesis_hash_mismatch() {
+		let mut spec = ChainSpec::from_config(ChainConfig::default()).unwrap();
+		spec.genesis_hash = H256::repeat_byte(1);
+
+		let mut state = KeyValueMemoryState::default();
+		assert!(matches!(
+			build_genesis_from_spec(&spec, state.as_externalities()),
+			Err(Error::GenesisHashMismatch)
+		));
+	}
+}
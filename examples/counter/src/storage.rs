@@ -0,0 +1,62 @@
+use std::convert::Infallible;
+use blockchain::StorageExternalities;
+pub use blockchain::storage::{ReadDecoded, storage_key};
+use codec::{Encode, Decode};
+use crate::runtime::Error;
+
+/// Version byte prefixed to versioned storage values.
+pub const STATE_VERSION: u8 = 1;
+
+/// Versioned decoding reads over storage externalities.
+pub trait ReadVersioned {
+	/// Read and decode the versioned value at key. Values of an unknown
+	/// version are rejected with `UnsupportedStateVersion`.
+	fn read_versioned<T: Decode>(&self, key: &[u8]) -> Result<Option<T>, Error>;
+}
+
+impl<S: StorageExternalities<Infallible> + ?Sized> ReadVersioned for S {
+	fn read_versioned<T: Decode>(&self, key: &[u8]) -> Result<Option<T>, Error> {
+		let mut decoded = None;
+		self.read_storage_ref(key, &mut |value| {
+			decoded = value.map(|value| match value.split_first() {
+				Some((&STATE_VERSION, mut value)) => {
+					T::decode(&mut value).ok_or_else(|| Error::StateCorruption(key.to_vec()))
+				},
+				Some((&version, _)) => Err(Error::UnsupportedStateVersion(version)),
+				None => Err(Error::StateCorruption(key.to_vec())),
+			});
+		}).expect("Error is infallible; qed");
+
+		decoded.transpose()
+	}
+}
+
+/// Encode the value, prefixed with the current state version.
+pub fn versioned<T: Encode>(value: &T) -> Vec<u8> {
+	let mut encoded = vec![STATE_VERSION];
+	value.encode_to(&mut encoded);
+	encoded
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use blockchain::backend::KeyValueMemoryState;
+
+	#[test]
+	fn reads_only_known_state_versions() {
+		let key = storage_key(b"Counter", b"value");
+		let mut unknown = versioned(&7u128);
+		unknown[0] = STATE_VERSION + 1;
+
+		let state = KeyValueMemoryState::new_with_storage(vec![(key.clone(), versioned(&7u128))]);
+		assert_eq!(state.read_versioned::<u128>(&key).unwrap(), Some(7));
+		assert_eq!(versioned(&7u128)[0], STATE_VERSION);
+
+		let state = KeyValueMemoryState::new_with_storage(vec![(key.clone(), unknown)]);
+		match state.read_versioned::<u128>(&key) {
+			Err(Error::UnsupportedStateVersion(version)) => assert_eq!(version, STATE_VERSION + 1),
+			other => panic!("unexpected read result {:?}", other),
+		}
+	}
+}
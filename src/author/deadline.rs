@@ -0,0 +1,39 @@
+use std::time::{Duration, Instant};
+
+/// Time by which an authored block should be sealed. The time expected to
+/// seal the block is reserved at the end, so that packing extrinsics stops
+/// early enough for sealing to finish in time.
+pub struct Deadline {
+	at: Instant,
+	seal_time: Duration,
+	clock: Box<dyn Fn() -> Instant + Send + Sync>,
+}
+
+impl Deadline {
+	/// Deadline at the given instant, reserving `seal_time` for sealing.
+	pub fn new(at: Instant, seal_time: Duration) -> Self {
+		Self { at, seal_time, clock: Box::new(Instant::now) }
+	}
+
+	/// Deadline `interval` from now, such as the target block interval.
+	pub fn after(interval: Duration, seal_time: Duration) -> Self {
+		Self::new(Instant::now() + interval, seal_time)
+	}
+
+	/// Use the given clock instead of the system one.
+	pub fn with_clock<C: Fn() -> Instant + Send + Sync + 'static>(mut self, clock: C) -> Self {
+		self.clock = Box::new(clock);
+		self
+	}
+
+	/// Time left before the deadline.
+	pub fn remaining(&self) -> Duration {
+		self.at.saturating_duration_since((self.clock)())
+	}
+
+	/// Whether enough time is left to keep packing extrinsics and still
+	/// seal the block before the deadline.
+	pub fn can_pack(&self) -> bool {
+		self.remaining() > self.seal_time
+	}
+}
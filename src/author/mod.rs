@@ -0,0 +1,313 @@
+//! Block authoring on top of the best block.
+
+use std::{fmt, error as stderror};
+use std::sync::atomic::{AtomicBool, Ordering};
+use log::*;
+use crate::{Block, BlockExecutor, ExtrinsicBuilder, AsExternalities, TransactionalExternalities};
+use crate::backend::{Store, ChainQuery, SharedCommittable, Operation, ImportLock};
+use crate::builder::BlockBuilder;
+use crate::import::{import_if_best, ImportError};
+use crate::pool::TransactionPool;
+use crate::format::format_id_short;
+
+mod deadline;
+
+pub use self::deadline::Deadline;
+
+#[derive(Debug)]
+/// Error of block authoring.
+pub enum AuthorError<BE, EE> {
+	/// Backend error.
+	Backend(BE),
+	/// Execution error.
+	Execution(EE),
+}
+
+impl<BE: fmt::Debug, EE: fmt::Debug> fmt::Display for AuthorError<BE, EE> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl<BE: fmt::Debug, EE: fmt::Debug> stderror::Error for AuthorError<BE, EE> { }
+
+type CancellableResult<Ba, E> = Result<
+	Option<<E as BlockExecutor>::Block>,
+	ImportError<<Ba as Store>::Error, <E as BlockExecutor>::Error>,
+>;
+
+/// Author an empty block on top of the current head of the backend with the
+/// given inherent, and seal it. The block is not imported.
+pub fn author_empty_block<Ba, E, F>(
+	executor: &E,
+	backend: &Ba,
+	inherent: E::Inherent,
+	seal: F,
+) -> Result<E::Block, AuthorError<Ba::Error, E::Error>> where
+	Ba: ChainQuery<Block=E::Block>,
+	Ba::State: AsExternalities<E::Externalities> + TransactionalExternalities,
+	E: ExtrinsicBuilder,
+	F: FnOnce(E::BuildBlock) -> E::Block,
+{
+	let head = backend.head();
+	let parent_block = backend.block_at(&head).map_err(AuthorError::Backend)?;
+	let parent_state = backend.state_at(&head).map_err(AuthorError::Backend)?;
+
+	let builder = BlockBuilder::new(executor, &parent_block, parent_state, inherent)
+		.map_err(AuthorError::Execution)?;
+	let (unsealed_block, _) = builder.build().map_err(AuthorError::Execution)?;
+
+	Ok(seal(unsealed_block))
+}
+
+/// Author a block on top of the current head from the ready extrinsics of
+/// the pool, seal and import it, and set it as the new head. Included
+/// extrinsics are pruned from the pool. If head changed while the block was
+/// sealed, it is not imported, the pool is left as it was, and `StaleTip` is
+/// returned.
+pub fn author_block<Ba, E, F>(
+	executor: &E,
+	backend: &Ba,
+	import_lock: &ImportLock,
+	pool: &mut TransactionPool<E>,
+	inherent: E::Inherent,
+	seal: F,
+) -> Result<E::Block, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery<Block=E::Block> + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities> + TransactionalExternalities,
+	E: ExtrinsicBuilder,
+	E::Extrinsic: Clone + PartialEq,
+	E::Inherent: Clone,
+	<E::Block as Block>::Identifier: AsRef<[u8]>,
+	F: FnOnce(E::BuildBlock) -> E::Block,
+{
+	let cancel = AtomicBool::new(false);
+	let block = author_block_cancellable(
+		executor, backend, import_lock, pool, inherent, &cancel,
+		|unsealed_block, _| Some(seal(unsealed_block)),
+	)?;
+
+	Ok(block.expect("Sealing is never cancelled; qed"))
+}
+
+/// Same as `author_block`, but sealing can be abandoned, for example when a
+/// new best block arrives. `seal` is given the cancellation flag and returns
+/// `None` once it is set. A cancelled block is neither imported nor pruned
+/// from the pool, and `None` is returned so that authoring can restart on
+/// the new head.
+pub fn author_block_cancellable<Ba, E, F>(
+	executor: &E,
+	backend: &Ba,
+	import_lock: &ImportLock,
+	pool: &mut TransactionPool<E>,
+	inherent: E::Inherent,
+	cancel: &AtomicBool,
+	seal: F,
+) -> CancellableResult<Ba, E> where
+	Ba: ChainQuery<Block=E::Block> + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities> + TransactionalExternalities,
+	E: ExtrinsicBuilder,
+	E::Extrinsic: Clone + PartialEq,
+	E::Inherent: Clone,
+	<E::Block as Block>::Identifier: AsRef<[u8]>,
+	F: FnOnce(E::BuildBlock, &AtomicBool) -> Option<E::Block>,
+{
+	author(executor, backend, import_lock, pool, inherent, None, |unsealed_block| {
+		if cancel.load(Ordering::Relaxed) {
+			return None
+		}
+		seal(unsealed_block, cancel)
+	})
+}
+
+/// Same as `author_block`, but extrinsics are only pulled from the pool
+/// while the deadline leaves enough time to seal the block, so that a full
+/// pool does not delay the block past its target time.
+pub fn author_block_with_deadline<Ba, E, F>(
+	executor: &E,
+	backend: &Ba,
+	import_lock: &ImportLock,
+	pool: &mut TransactionPool<E>,
+	inherent: E::Inherent,
+	deadline: &Deadline,
+	seal: F,
+) -> Result<E::Block, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery<Block=E::Block> + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities> + TransactionalExternalities,
+	E: ExtrinsicBuilder,
+	E::Extrinsic: Clone + PartialEq,
+	E::Inherent: Clone,
+	<E::Block as Block>::Identifier: AsRef<[u8]>,
+	F: FnOnce(E::BuildBlock) -> E::Block,
+{
+	let block = author(
+		executor, backend, import_lock, pool, inherent, Some(deadline),
+		|unsealed_block| Some(seal(unsealed_block)),
+	)?;
+
+	Ok(block.expect("Sealing is never cancelled; qed"))
+}
+
+fn author<Ba, E, F>(
+	executor: &E,
+	backend: &Ba,
+	import_lock: &ImportLock,
+	pool: &mut TransactionPool<E>,
+	inherent: E::Inherent,
+	deadline: Option<&Deadline>,
+	seal: F,
+) -> CancellableResult<Ba, E> where
+	Ba: ChainQuery<Block=E::Block> + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities> + TransactionalExternalities,
+	E: ExtrinsicBuilder,
+	E::Extrinsic: Clone + PartialEq,
+	E::Inherent: Clone,
+	<E::Block as Block>::Identifier: AsRef<[u8]>,
+	F: FnOnce(E::BuildBlock) -> Option<E::Block>,
+{
+	let head = backend.head();
+	let parent_block = backend.block_at(&head).map_err(ImportError::Backend)?;
+
+	let ready = pool.ready(
+		&parent_block,
+		backend.state_at(&head).map_err(ImportError::Backend)?,
+		inherent.clone(),
+	);
+
+	let mut builder = BlockBuilder::new(
+		executor,
+		&parent_block,
+		backend.state_at(&head).map_err(ImportError::Backend)?,
+		inherent,
+	).map_err(ImportError::Execution)?;
+	let included = builder.push_many(ready.into_iter().take_while(|_| match deadline {
+		Some(deadline) => deadline.can_pack(),
+		None => true,
+	}));
+	let (unsealed_block, _) = builder.build().map_err(ImportError::Execution)?;
+
+	let block = match seal(unsealed_block) {
+		Some(block) => block,
+		None => {
+			debug!("Authoring on top of {} cancelled", format_id_short(&head));
+			return Ok(None)
+		},
+	};
+	let id = block.id();
+	import_if_best(backend, import_lock, executor, block.clone(), &head)?;
+
+	info!("Authored block #{} ({}) with {} extrinsics", block.number(), format_id_short(&id), included.len());
+	pool.prune(&included);
+	Ok(Some(block))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+	use std::sync::atomic::AtomicU64;
+	use std::time::{Duration, Instant};
+	use crate::import::import_if_deeper;
+	use crate::consensus::pow;
+	use crate::testing::runtime::{chain, fork, read_counter, seal, Extrinsic};
+
+	#[test]
+	fn authors_chain_of_empty_blocks() {
+		let chain = chain();
+
+		for number in 1..=3 {
+			let parent = chain.head();
+			let block = author_empty_block(chain.executor(), chain.backend(), (), seal).unwrap();
+			assert_eq!(block.number(), number);
+			assert_eq!(block.parent_id(), Some(parent.id()));
+			assert!(block.extrinsics.is_empty());
+
+			assert!(import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap());
+			assert_eq!(chain.head(), block);
+		}
+	}
+
+	#[test]
+	fn authors_block_from_pool() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut pool = TransactionPool::new(chain.executor().clone());
+		for value in 1..=3 {
+			assert!(pool.submit(Extrinsic::Add(value)));
+		}
+
+		let block = author_block(chain.executor(), chain.backend(), chain.import_lock(), &mut pool, (), seal).unwrap();
+
+		assert_eq!(block.parent_id(), Some(genesis.id()));
+		assert_eq!(block.extrinsics, vec![Extrinsic::Add(1), Extrinsic::Add(2), Extrinsic::Add(3)]);
+		assert_eq!(chain.head(), block);
+		assert_eq!(read_counter(&chain.backend().state_at(&block.id()).unwrap()), 6);
+		assert!(pool.is_empty());
+	}
+
+	#[test]
+	fn cancelled_block_is_neither_imported_nor_pruned() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut pool = TransactionPool::new(chain.executor().clone());
+		assert!(pool.submit(Extrinsic::Add(1)));
+		let cancel = AtomicBool::new(false);
+
+		let authored = author_block_cancellable(
+			chain.executor(), chain.backend(), chain.import_lock(), &mut pool, (), &cancel,
+			|mut block, cancel| {
+				block.difficulty = 256;
+				cancel.store(true, Ordering::Relaxed);
+				pow::seal_cancellable(block, 256, cancel).ok()
+			},
+		).unwrap();
+
+		assert!(authored.is_none());
+		assert_eq!(chain.head(), genesis);
+		assert_eq!(pool.len(), 1);
+	}
+
+	#[test]
+	fn stops_packing_when_deadline_approaches() {
+		let chain = chain();
+		let mut pool = TransactionPool::new(chain.executor().clone());
+		for value in 1..=10 {
+			assert!(pool.submit(Extrinsic::Add(value)));
+		}
+
+		// Every extrinsic takes a second to pack, and sealing takes four.
+		let start = Instant::now();
+		let elapsed = Arc::new(AtomicU64::new(0));
+		let clock = elapsed.clone();
+		let deadline = Deadline::new(start + Duration::from_secs(10), pow::expected_seal_time(4, 4))
+			.with_clock(move || start + Duration::from_secs(clock.fetch_add(1, Ordering::Relaxed) + 1));
+
+		let block = author_block_with_deadline(
+			chain.executor(), chain.backend(), chain.import_lock(), &mut pool, (), &deadline, seal,
+		).unwrap();
+
+		assert_eq!(block.extrinsics, (1..=5).map(Extrinsic::Add).collect::<Vec<_>>());
+		assert_eq!(read_counter(&chain.backend().state_at(&block.id()).unwrap()), 15);
+		assert_eq!(pool.len(), 5);
+	}
+
+	#[test]
+	fn block_sealed_on_stale_head_is_neither_imported_nor_pruned() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut pool = TransactionPool::new(chain.executor().clone());
+		assert!(pool.submit(Extrinsic::Add(1)));
+		let deeper = fork(&chain, &genesis, 2, 7);
+
+		let result = author_block(chain.executor(), chain.backend(), chain.import_lock(), &mut pool, (), |block| {
+			for block in &deeper {
+				import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+			}
+			seal(block)
+		});
+
+		assert!(matches!(result, Err(ImportError::StaleTip)));
+		assert_eq!(chain.head(), deeper[1]);
+		assert_eq!(pool.len(), 1);
+	}
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use crate::{StorageExternalities, TransactionalExternalities, StateDiff};
+
+/// Change of a storage value. `None` marks an absent value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateChange {
+	/// Value before the first change.
+	pub old: Option<Vec<u8>>,
+	/// Value after the last change.
+	pub new: Option<Vec<u8>>,
+}
+
+/// Externalities collecting the storage changes made through them, with
+/// values before and after, for example to report the changes of an
+/// executed block. Changes rolled back by a transaction are discarded.
+pub struct StateDiffCollector<S> {
+	inner: S,
+	changes: BTreeMap<Vec<u8>, StateChange>,
+	checkpoints: Vec<BTreeMap<Vec<u8>, StateChange>>,
+}
+
+impl<S> StateDiffCollector<S> {
+	/// Collect changes made to the given externalities.
+	pub fn new(inner: S) -> Self {
+		Self { inner, changes: BTreeMap::new(), checkpoints: Vec::new() }
+	}
+
+	/// Changes collected so far, excluding keys set back to their old
+	/// value.
+	pub fn changes(&self) -> BTreeMap<Vec<u8>, StateChange> {
+		self.changes.iter()
+			.filter(|(_, change)| change.old != change.new)
+			.map(|(key, change)| (key.clone(), change.clone()))
+			.collect()
+	}
+
+	/// Collected changes as a state diff, which can be applied on top of
+	/// the old state.
+	pub fn diff(&self) -> StateDiff {
+		self.changes().into_iter()
+			.map(|(key, change)| (key, change.new))
+			.collect()
+	}
+
+	/// Stop collecting, returning the externalities and the changes.
+	pub fn into_parts(self) -> (S, BTreeMap<Vec<u8>, StateChange>) {
+		let changes = self.changes();
+		(self.inner, changes)
+	}
+
+	fn record<E>(&mut self, key: &[u8], new: Option<Vec<u8>>) where
+		S: StorageExternalities<E>,
+	{
+		match self.changes.get_mut(key) {
+			Some(change) => change.new = new,
+			None => {
+				// A value failing to read is recorded as absent.
+				let old = self.inner.read_storage(key).unwrap_or(None);
+				self.changes.insert(key.to_vec(), StateChange { old, new });
+			},
+		}
+	}
+}
+
+impl<E, S: StorageExternalities<E>> StorageExternalities<E> for StateDiffCollector<S> {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, E> {
+		self.inner.read_storage(key)
+	}
+
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), E> {
+		self.inner.read_storage_ref(key, f)
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		self.inner.exists_storage(key)
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.record(&key, Some(value.clone()));
+		self.inner.write_storage(key, value);
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		self.record(key, None);
+		self.inner.remove_storage(key);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		self.inner.for_each_storage(f);
+	}
+}
+
+impl<S: TransactionalExternalities> TransactionalExternalities for StateDiffCollector<S> {
+	fn start_transaction(&mut self) {
+		self.checkpoints.push(self.changes.clone());
+		self.inner.start_transaction();
+	}
+
+	fn commit_transaction(&mut self) {
+		self.checkpoints.pop();
+		self.inner.commit_transaction();
+	}
+
+	fn rollback_transaction(&mut self) {
+		if let Some(changes) = self.checkpoints.pop() {
+			self.changes = changes;
+		}
+		self.inner.rollback_transaction();
+	}
+}
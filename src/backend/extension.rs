@@ -0,0 +1,139 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use crate::{StorageExternalities, TransactionalExternalities, ExtensionExternalities};
+
+/// Registry of host extensions, keyed by their type.
+#[derive(Default)]
+pub struct Extensions {
+	extensions: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl Extensions {
+	/// Create an empty registry.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Register an extension, replacing any extension of the same type.
+	pub fn register<T: Any + Send>(&mut self, extension: T) {
+		self.extensions.insert(TypeId::of::<T>(), Box::new(extension));
+	}
+
+	/// Get the extension of the given type.
+	pub fn get_mut<T: Any + Send>(&mut self) -> Option<&mut T> {
+		self.extensions.get_mut(&TypeId::of::<T>())
+			.and_then(|extension| extension.downcast_mut())
+	}
+}
+
+/// Externalities with host extensions on top of another externalities.
+pub struct ExtendedExternalities<S> {
+	inner: S,
+	extensions: Extensions,
+}
+
+impl<S> ExtendedExternalities<S> {
+	/// Provide the extensions on top of the given externalities.
+	pub fn new(inner: S, extensions: Extensions) -> Self {
+		Self { inner, extensions }
+	}
+
+	/// Get the extensions.
+	pub fn extensions(&mut self) -> &mut Extensions {
+		&mut self.extensions
+	}
+
+	/// Remove the extensions, returning the underlying externalities.
+	pub fn into_inner(self) -> S {
+		self.inner
+	}
+}
+
+impl<S> ExtensionExternalities for ExtendedExternalities<S> {
+	fn extension(&mut self, type_id: TypeId) -> Option<&mut dyn Any> {
+		self.extensions.extensions.get_mut(&type_id)
+			.map(|extension| extension.as_mut() as &mut dyn Any)
+	}
+}
+
+impl<E, S: StorageExternalities<E>> StorageExternalities<E> for ExtendedExternalities<S> {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, E> {
+		self.inner.read_storage(key)
+	}
+
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), E> {
+		self.inner.read_storage_ref(key, f)
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		self.inner.exists_storage(key)
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.inner.write_storage(key, value);
+	}
+
+	fn write_storage_batch(&mut self, entries: Vec<(Vec<u8>, Vec<u8>)>) {
+		self.inner.write_storage_batch(entries);
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		self.inner.remove_storage(key);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		self.inner.for_each_storage(f);
+	}
+
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		self.inner.clear_prefix(prefix);
+	}
+}
+
+impl<S: TransactionalExternalities> TransactionalExternalities for ExtendedExternalities<S> {
+	fn start_transaction(&mut self) {
+		self.inner.start_transaction();
+	}
+
+	fn commit_transaction(&mut self) {
+		self.inner.commit_transaction();
+	}
+
+	fn rollback_transaction(&mut self) {
+		self.inner.rollback_transaction();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::convert::Infallible;
+	use crate::backend::KeyValueMemoryState;
+
+	struct RandomnessExtension(u64);
+
+	impl RandomnessExtension {
+		fn next(&mut self) -> u64 {
+			self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+			self.0
+		}
+	}
+
+	#[test]
+	fn retrieves_registered_extension() {
+		let mut extensions = Extensions::new();
+		extensions.register(RandomnessExtension(1));
+		let mut ext = ExtendedExternalities::new(KeyValueMemoryState::default(), extensions);
+
+		let randomness = ext.extension(TypeId::of::<RandomnessExtension>())
+			.and_then(|extension| extension.downcast_mut::<RandomnessExtension>())
+			.expect("Extension is registered; qed");
+		let value = randomness.next();
+		assert_eq!(ext.extensions().get_mut::<RandomnessExtension>().unwrap().0, value);
+		assert!(ext.extension(TypeId::of::<u32>()).is_none());
+
+		StorageExternalities::<Infallible>::write_storage(&mut ext, b"key".to_vec(), b"value".to_vec());
+		let state = ext.into_inner();
+		assert_eq!(StorageExternalities::<Infallible>::read_storage(&state, b"key").unwrap(), Some(b"value".to_vec()));
+	}
+}
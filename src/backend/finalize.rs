@@ -0,0 +1,25 @@
+use crate::Block;
+use crate::backend::{ChainQuery, ChainSettlement, OperationError};
+
+/// Mark a canonical block as finalized. Blocks up to the finalized block
+/// can no longer be reorganized. Finalizing a block that is not canonical,
+/// or is below the currently finalized block, is an invalid operation.
+pub fn finalize<Ba>(
+	backend: &mut Ba,
+	id: &<Ba::Block as Block>::Identifier,
+) -> Result<(), Ba::Error> where
+	Ba: ChainQuery + ChainSettlement,
+	Ba::Error: OperationError,
+{
+	if !backend.is_canon(id)? {
+		return Err(Ba::Error::invalid_operation())
+	}
+
+	if backend.depth_at(id)? < backend.depth_at(&backend.finalized())? {
+		return Err(Ba::Error::invalid_operation())
+	}
+
+	backend.set_finalized(id.clone());
+
+	Ok(())
+}
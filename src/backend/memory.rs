@@ -2,7 +2,7 @@ use std::{fmt, error as stderror};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use crate::{Block, Auxiliary};
-use crate::backend::{Store, BlockData, ChainQuery, ChainSettlement, Operation, Committable, SharedCommittable, OperationError};
+use crate::backend::{Store, BlockData, ChainQuery, ChainSettlement, Operation, Committable, SharedCommittable, OperationError, Prunable, Finalizable, Reindexable, ReindexOutcome, prune, finalize, reindex};
 
 #[derive(Debug)]
 /// Memory errors
@@ -38,10 +38,24 @@ pub struct MemoryDatabase<B: Block, A: Auxiliary<B>, S> {
 	blocks_and_states: HashMap<B::Identifier, BlockData<B, S>>,
 	head: B::Identifier,
 	genesis: B::Identifier,
+	finalized: B::Identifier,
 	canon_depth_mappings: HashMap<usize, B::Identifier>,
 	auxiliaries: HashMap<A::Key, A>,
 }
 
+impl<B: Block, A: Auxiliary<B>, S: Clone> Clone for MemoryDatabase<B, A, S> {
+	fn clone(&self) -> Self {
+		Self {
+			blocks_and_states: self.blocks_and_states.clone(),
+			head: self.head.clone(),
+			genesis: self.genesis.clone(),
+			finalized: self.finalized.clone(),
+			canon_depth_mappings: self.canon_depth_mappings.clone(),
+			auxiliaries: self.auxiliaries.clone(),
+		}
+	}
+}
+
 impl<B: Block, A: Auxiliary<B>, S: Clone> Store for MemoryDatabase<B, A, S> {
 	type Block = B;
 	type State = S;
@@ -52,6 +66,7 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> Store for MemoryDatabase<B, A, S> {
 impl<B: Block, A: Auxiliary<B>, S: Clone> ChainQuery for MemoryDatabase<B, A, S> {
 	fn head(&self) -> B::Identifier { self.head.clone() }
 	fn genesis(&self) -> B::Identifier { self.genesis.clone() }
+	fn finalized(&self) -> B::Identifier { self.finalized.clone() }
 
 	fn contains(
 		&self,
@@ -144,6 +159,21 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> ChainSettlement for MemoryDatabase<B,
 			.expect("Internal database error")
 			.children.push(child);
 	}
+	fn remove_block(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+	) {
+		self.blocks_and_states.remove(id);
+	}
+	fn remove_child(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+		child: &<Self::Block as Block>::Identifier,
+	) {
+		self.blocks_and_states.get_mut(id)
+			.expect("Internal database error")
+			.children.retain(|c| c != child);
+	}
 	fn set_canon(
 		&mut self,
 		id: <Self::Block as Block>::Identifier,
@@ -185,6 +215,12 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> ChainSettlement for MemoryDatabase<B,
 	) {
 		self.head = head;
 	}
+	fn set_finalized(
+		&mut self,
+		finalized: <Self::Block as Block>::Identifier
+	) {
+		self.finalized = finalized;
+	}
 }
 
 /// Memory backend
@@ -193,7 +229,7 @@ pub struct MemoryBackend<B: Block, A: Auxiliary<B>, S>(MemoryDatabase<B, A, S>);
 impl<B: Block, A: Auxiliary<B>, S: Clone> MemoryBackend<B, A, S> {
 	/// Create a new memory backend from genesis.
 	pub fn new_with_genesis(block: B, genesis_state: S) -> Self {
-		assert!(block.parent_id().is_none(), "with_genesis must be provided with a genesis block");
+		assert!(block.is_genesis(), "with_genesis must be provided with a genesis block");
 
 		let genesis_id = block.id();
 		let mut blocks_and_states = HashMap::new();
@@ -215,9 +251,26 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> MemoryBackend<B, A, S> {
 			canon_depth_mappings,
 			auxiliaries: Default::default(),
 			genesis: genesis_id.clone(),
+			finalized: genesis_id.clone(),
 			head: genesis_id,
 		})
 	}
+
+	/// Take a deep copy of the backend.
+	pub fn snapshot(&self) -> Self {
+		self.clone()
+	}
+
+	/// Restore the backend to a snapshot.
+	pub fn restore(&mut self, snapshot: Self) {
+		*self = snapshot;
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S: Clone> Clone for MemoryBackend<B, A, S> {
+	fn clone(&self) -> Self {
+		Self(self.0.clone())
+	}
 }
 
 impl<B: Block, A: Auxiliary<B>, S: Clone> Store for MemoryBackend<B, A, S> {
@@ -234,6 +287,9 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> ChainQuery for MemoryBackend<B, A, S>
 	fn head(&self) -> <Self::Block as Block>::Identifier {
 		self.0.head()
 	}
+	fn finalized(&self) -> <Self::Block as Block>::Identifier {
+		self.0.finalized()
+	}
 	fn contains(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
@@ -295,6 +351,34 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> Committable for MemoryBackend<B, A, S>
 	}
 }
 
+impl<B: Block, A: Auxiliary<B>, S: Clone> Prunable for MemoryBackend<B, A, S> {
+	fn prune(
+		&mut self,
+		keep_from: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error> {
+		prune(&mut self.0, keep_from)
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S: Clone> Finalizable for MemoryBackend<B, A, S> {
+	fn finalize(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error> {
+		finalize(&mut self.0, id)
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S: Clone> Reindexable for MemoryBackend<B, A, S> {
+	fn reindex(
+		&mut self,
+		from_depth: usize,
+		progress: &mut dyn FnMut(usize, usize) -> bool,
+	) -> Result<ReindexOutcome, Self::Error> {
+		reindex(&mut self.0, from_depth, progress)
+	}
+}
+
 /// Shared memory backend
 pub struct SharedMemoryBackend<B: Block, A: Auxiliary<B>, S>(
 	Arc<RwLock<MemoryBackend<B, A, S>>>
@@ -305,6 +389,17 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> SharedMemoryBackend<B, A, S> {
 	pub fn new_with_genesis(block: B, genesis_state: S) -> Self {
 		Self(Arc::new(RwLock::new(MemoryBackend::new_with_genesis(block, genesis_state))))
 	}
+
+	/// Take a deep copy of the backend, not shared with this one.
+	pub fn snapshot(&self) -> Self {
+		Self(Arc::new(RwLock::new(self.0.read().expect("Lock is poisoned").snapshot())))
+	}
+
+	/// Restore the backend, and all its clones, to a snapshot.
+	pub fn restore(&self, snapshot: Self) {
+		let snapshot = snapshot.0.read().expect("Lock is poisoned").snapshot();
+		self.0.write().expect("Lock is poisoned").restore(snapshot);
+	}
 }
 
 impl<B: Block, A: Auxiliary<B>, S: Clone> Store for SharedMemoryBackend<B, A, S> {
@@ -321,6 +416,9 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> ChainQuery for SharedMemoryBackend<B,
 	fn head(&self) -> <Self::Block as Block>::Identifier {
 		self.0.read().expect("Lock is poisoned").head()
 	}
+	fn finalized(&self) -> <Self::Block as Block>::Identifier {
+		self.0.read().expect("Lock is poisoned").finalized()
+	}
 	fn contains(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
@@ -387,3 +485,250 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> SharedCommittable for SharedMemoryBack
 		self.0.write().expect("Lock is poisoned").commit(operation)
 	}
 }
+
+impl<B: Block, A: Auxiliary<B>, S: Clone> Prunable for SharedMemoryBackend<B, A, S> {
+	fn prune(
+		&mut self,
+		keep_from: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error> {
+		self.0.write().expect("Lock is poisoned").prune(keep_from)
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S: Clone> Finalizable for SharedMemoryBackend<B, A, S> {
+	fn finalize(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error> {
+		self.0.write().expect("Lock is poisoned").finalize(id)
+	}
+}
+
+impl<B: Block, A: Auxiliary<B>, S: Clone> Reindexable for SharedMemoryBackend<B, A, S> {
+	fn reindex(
+		&mut self,
+		from_depth: usize,
+		progress: &mut dyn FnMut(usize, usize) -> bool,
+	) -> Result<ReindexOutcome, Self::Error> {
+		self.0.write().expect("Lock is poisoned").reindex(from_depth, progress)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::iter;
+	use crate::chain::best_block;
+	use crate::import::{import_block, import_if_deeper, ImportError};
+	use crate::testing::runtime::{chain, fork, hash, read_counter, seal, TestBlock};
+
+	#[test]
+	fn stores_blocks_and_children_by_id() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 3, 1);
+		for block in &blocks {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let backend = chain.backend();
+		for (parent, block) in iter::once(&genesis).chain(blocks.iter()).zip(blocks.iter()) {
+			assert_eq!(backend.block_at(&block.id()).unwrap(), *block);
+			assert_eq!(backend.children_at(&parent.id()).unwrap(), vec![block.id()]);
+		}
+		assert!(backend.children_at(&blocks[2].id()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn siblings_execute_on_parent_state() {
+		let chain = chain();
+		let genesis = chain.head();
+		let parent = fork(&chain, &genesis, 1, 2).remove(0);
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), parent.clone()).unwrap();
+
+		let first = fork(&chain, &parent, 1, 3).remove(0);
+		let second = fork(&chain, &parent, 1, 5).remove(0);
+		for block in &[first.clone(), second.clone()] {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let backend = chain.backend();
+		assert_eq!(read_counter(&backend.state_at(&parent.id()).unwrap()), 2);
+		assert_eq!(read_counter(&backend.state_at(&first.id()).unwrap()), 5);
+		assert_eq!(read_counter(&backend.state_at(&second.id()).unwrap()), 7);
+	}
+
+	#[test]
+	fn prunes_abandoned_fork() {
+		let chain = chain();
+		let genesis = chain.head();
+		let abandoned = fork(&chain, &genesis, 2, 2);
+		let canonical = fork(&chain, &genesis, 3, 1);
+		for block in abandoned.iter().chain(canonical.iter()) {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		assert_eq!(chain.head(), canonical[2]);
+
+		let mut backend = chain.backend().clone();
+		assert!(backend.prune(&abandoned[1].id()).is_err());
+		backend.prune(&canonical[1].id()).unwrap();
+
+		for block in &abandoned {
+			assert!(!backend.contains(&block.id()).unwrap());
+		}
+		for block in &canonical {
+			assert!(backend.contains(&block.id()).unwrap());
+		}
+		assert_eq!(backend.children_at(&genesis.id()).unwrap(), vec![canonical[0].id()]);
+	}
+
+	#[test]
+	fn rejects_imports_below_finalized() {
+		let chain = chain();
+		let genesis = chain.head();
+		let canonical = fork(&chain, &genesis, 3, 1);
+		let side = fork(&chain, &genesis, 1, 2).remove(0);
+		for block in canonical.iter().chain(iter::once(&side)) {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let mut backend = chain.backend().clone();
+		assert!(backend.finalize(&side.id()).is_err());
+		backend.finalize(&canonical[1].id()).unwrap();
+		assert_eq!(backend.finalized(), canonical[1].id());
+		assert!(backend.finalize(&canonical[0].id()).is_err());
+
+		let competing = fork(&chain, &canonical[0], 1, 5).remove(0);
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), competing),
+			Err(ImportError::BelowFinalized)
+		));
+		let extending = fork(&chain, &side, 1, 5).remove(0);
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), extending),
+			Err(ImportError::BelowFinalized)
+		));
+		assert_eq!(best_block(chain.backend()).unwrap(), canonical[2].id());
+	}
+
+	#[test]
+	fn restores_snapshot_after_mutation() {
+		let chain = chain();
+		let genesis = chain.head();
+		let snapshot = chain.backend().snapshot();
+
+		let blocks = fork(&chain, &genesis, 2, 3);
+		for block in &blocks {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		assert_eq!(chain.head(), blocks[1]);
+		assert!(!snapshot.contains(&blocks[0].id()).unwrap());
+
+		let shared = chain.backend().clone();
+		chain.backend().restore(snapshot);
+
+		assert_eq!(chain.head(), genesis);
+		assert_eq!(shared.head(), genesis.id());
+		assert_eq!(read_counter(&shared.state_at(&shared.head()).unwrap()), 0);
+		for block in &blocks {
+			assert!(!shared.contains(&block.id()).unwrap());
+		}
+	}
+
+	#[test]
+	fn reports_canonical_blocks_across_reorg() {
+		let chain = chain();
+		let genesis = chain.head();
+		let first = fork(&chain, &genesis, 2, 1);
+		for block in &first {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		let second = fork(&chain, &genesis, 3, 2);
+		assert!(first.iter().all(|block| chain.backend().is_canonical(&block.id())));
+		assert!(!chain.backend().is_canonical(&second[0].id()));
+
+		for block in &second {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		assert_eq!(chain.head(), second[2]);
+		assert!(chain.backend().is_canonical(&genesis.id()));
+		assert!(second.iter().all(|block| chain.backend().is_canonical(&block.id())));
+		assert!(first.iter().all(|block| !chain.backend().is_canonical(&block.id())));
+	}
+
+	#[test]
+	fn reindex_repairs_corrupted_canonical_index() {
+		let chain = chain();
+		let genesis = chain.head();
+		let canonical = fork(&chain, &genesis, 4, 1);
+		let side = fork(&chain, &genesis, 1, 2).remove(0);
+		for block in canonical.iter().chain(iter::once(&side)) {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		{
+			let mut backend = chain.backend().0.write().expect("Lock is poisoned");
+			let database = &mut backend.0;
+			database.insert_canon_depth_mapping(1, side.id());
+			database.set_canon(side.id(), true);
+			database.set_canon(canonical[0].id(), false);
+			database.remove_canon_depth_mapping(&3);
+			database.insert_canon_depth_mapping(5, side.id());
+		}
+
+		let mut backend = chain.backend().clone();
+		let outcome = backend.reindex(0, &mut |depth, _| depth < 1).unwrap();
+		assert_eq!(outcome, ReindexOutcome::Aborted { resume_from: 2 });
+		assert_eq!(backend.lookup_canon_depth(1).unwrap(), Some(canonical[0].id()));
+		assert!(!backend.is_canon(&side.id()).unwrap());
+		assert_eq!(backend.lookup_canon_depth(3).unwrap(), None);
+
+		assert_eq!(backend.reindex(2, &mut |_, _| true).unwrap(), ReindexOutcome::Completed);
+		assert_eq!(backend.lookup_canon_depth(0).unwrap(), Some(genesis.id()));
+		for (index, block) in canonical.iter().enumerate() {
+			assert_eq!(backend.lookup_canon_depth(index + 1).unwrap(), Some(block.id()));
+			assert!(backend.is_canon(&block.id()).unwrap());
+		}
+		assert_eq!(backend.lookup_canon_depth(5).unwrap(), None);
+	}
+
+	#[test]
+	fn reads_storage_as_of_past_block() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 4, 3);
+		for block in &blocks {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let counter = |block: &TestBlock| chain.backend().storage_at(&block.id(), b"counter").unwrap();
+		assert_eq!(counter(&blocks[1]), Some(6u64.to_be_bytes().to_vec()));
+		assert_eq!(counter(&blocks[3]), Some(12u64.to_be_bytes().to_vec()));
+		assert_eq!(counter(&genesis), Some(0u64.to_be_bytes().to_vec()));
+		assert_eq!(chain.backend().storage_at(&blocks[1].id(), b"missing").unwrap(), None);
+	}
+
+	#[test]
+	fn rejects_block_whose_stored_parent_hashes_differently() {
+		let chain = chain();
+		let genesis = chain.head();
+		let parent = fork(&chain, &genesis, 1, 1).remove(0);
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), parent.clone()).unwrap();
+
+		// Store the parent a second time, under an id it does not hash to.
+		let forged = hash(b"forged");
+		{
+			let mut backend = chain.backend().0.write().expect("Lock is poisoned");
+			let state = backend.0.state_at(&parent.id()).unwrap();
+			backend.0.insert_block(forged, parent.clone(), state, 1, Vec::new(), false);
+			backend.0.push_child(genesis.id(), forged);
+		}
+
+		let mut block = fork(&chain, &parent, 1, 2).remove(0);
+		block.parent = Some(forged);
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), seal(block)),
+			Err(ImportError::ParentHashMismatch)
+		));
+	}
+}
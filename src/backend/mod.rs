@@ -2,15 +2,38 @@
 
 mod memory;
 mod route;
+mod prune;
+mod finalize;
 mod traits;
 mod operation;
 mod state;
+mod overlay;
+mod tracing;
+mod collector;
+mod root_cache;
+mod extension;
+mod snapshot;
+mod reindex;
+mod state_cache;
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb;
 
 pub use self::memory::{MemoryBackend, MemoryDatabase, SharedMemoryBackend, Error as MemoryError};
 pub use self::route::{tree_route, TreeRoute};
+pub use self::prune::prune;
+pub use self::finalize::finalize;
+pub use self::reindex::{reindex, ReindexOutcome};
 pub use self::operation::{BlockData, ImportOperation, Operation};
-pub use self::traits::{Store, ChainQuery, ChainSettlement, OperationError, Committable, SharedCommittable};
+pub use self::traits::{Store, ChainQuery, ChainSettlement, OperationError, Committable, SharedCommittable, Prunable, Finalizable, Reindexable};
 pub use self::state::KeyValueMemoryState;
+pub use self::overlay::OverlayExternalities;
+pub use self::tracing::{TracingExternalities, StorageAccess, AccessKind};
+pub use self::collector::{StateDiffCollector, StateChange};
+pub use self::root_cache::CachedRootExternalities;
+pub use self::extension::{Extensions, ExtendedExternalities};
+pub use self::snapshot::{export_state, import_state, SnapshotError};
+pub use self::state_cache::StateCache;
+pub use crate::StateDiff;
 
 use std::sync::{Arc, Mutex, MutexGuard};
 
@@ -16,7 +16,20 @@ pub struct BlockData<B: Block, S> {
 	pub is_canon: bool,
 }
 
+impl<B: Block, S: Clone> Clone for BlockData<B, S> {
+	fn clone(&self) -> Self {
+		Self {
+			block: self.block.clone(),
+			state: self.state.clone(),
+			depth: self.depth,
+			children: self.children.clone(),
+			is_canon: self.is_canon,
+		}
+	}
+}
+
 /// Import operation.
+#[derive(Clone)]
 pub struct ImportOperation<B, S> {
 	/// Block to be imported.
 	pub block: B,
@@ -114,6 +127,38 @@ impl<B: Block, S, A: Auxiliary<B>> Operation<B, S, A> {
 			if !head_exists {
 				return Err(Ba::Error::invalid_operation());
 			}
+
+			// The new head must not revert the finalized block. The finalized
+			// block is canonical, so the walk stops at the first canonical
+			// ancestor, which descends from it if at or above its depth.
+			let finalized = backend.finalized();
+			let finalized_depth = backend.depth_at(&finalized)?;
+			let mut current = new_head.clone();
+			loop {
+				let (depth, parent_id) = match importing.get(&current) {
+					Some(data) => (data.depth, data.block.parent_id()),
+					None => {
+						let depth = backend.depth_at(&current)?;
+						if backend.is_canon(&current)? {
+							if depth < finalized_depth {
+								return Err(Ba::Error::invalid_operation());
+							}
+							break;
+						}
+						(depth, backend.block_at(&current)?.parent_id())
+					},
+				};
+
+				if depth <= finalized_depth {
+					if depth < finalized_depth || current != finalized {
+						return Err(Ba::Error::invalid_operation());
+					}
+					break;
+				}
+
+				current = parent_id
+					.expect("Block is above finalized depth; it must have a parent; qed");
+			}
 		}
 
 		// Do precheck to make sure auxiliary is valid.
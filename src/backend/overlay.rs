@@ -0,0 +1,161 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use crate::{StorageExternalities, StateDiff};
+
+/// Externalities buffering reads and writes on top of another externalities.
+/// Changes are only flushed to the underlying externalities on commit, and
+/// dropping the overlay discards them.
+pub struct OverlayExternalities<'a, S: ?Sized> {
+	inner: &'a mut S,
+	changes: StateDiff,
+	cache: RefCell<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl<'a, S: ?Sized> OverlayExternalities<'a, S> {
+	/// Create a new overlay on top of the given externalities.
+	pub fn new(inner: &'a mut S) -> Self {
+		Self {
+			inner,
+			changes: BTreeMap::new(),
+			cache: RefCell::new(BTreeMap::new()),
+		}
+	}
+
+	/// Pending changes of the overlay.
+	pub fn changes(&self) -> &StateDiff {
+		&self.changes
+	}
+
+	/// Discard the overlay, returning its pending changes.
+	pub fn into_changes(self) -> StateDiff {
+		self.changes
+	}
+
+	/// Flush all pending changes into the underlying externalities.
+	pub fn commit<E>(self) where
+		S: StorageExternalities<E>,
+	{
+		for (key, value) in self.changes {
+			match value {
+				Some(value) => self.inner.write_storage(key, value),
+				None => self.inner.remove_storage(&key),
+			}
+		}
+	}
+}
+
+impl<'a, E, S: StorageExternalities<E> + ?Sized> StorageExternalities<E> for OverlayExternalities<'a, S> {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, E> {
+		if let Some(value) = self.changes.get(key) {
+			return Ok(value.clone())
+		}
+
+		if let Some(value) = self.cache.borrow().get(key) {
+			return Ok(value.clone())
+		}
+
+		let value = self.inner.read_storage(key)?;
+		self.cache.borrow_mut().insert(key.to_vec(), value.clone());
+		Ok(value)
+	}
+
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), E> {
+		if let Some(value) = self.changes.get(key) {
+			f(value.as_deref());
+			return Ok(())
+		}
+
+		if let Some(value) = self.cache.borrow().get(key) {
+			f(value.as_deref());
+			return Ok(())
+		}
+
+		let mut cache = self.cache.borrow_mut();
+		self.inner.read_storage_ref(key, &mut |value| {
+			cache.insert(key.to_vec(), value.map(|value| value.to_vec()));
+			f(value);
+		})
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		if let Some(value) = self.changes.get(key) {
+			return value.is_some()
+		}
+
+		if let Some(value) = self.cache.borrow().get(key) {
+			return value.is_some()
+		}
+
+		self.inner.exists_storage(key)
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.changes.insert(key, Some(value));
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		self.changes.insert(key.to_vec(), None);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		let mut storage = BTreeMap::new();
+		self.inner.for_each_storage(&mut |key, value| {
+			storage.insert(key.to_vec(), value.to_vec());
+		});
+
+		for (key, value) in &self.changes {
+			match value {
+				Some(value) => { storage.insert(key.clone(), value.clone()); },
+				None => { storage.remove(key); },
+			}
+		}
+
+		for (key, value) in &storage {
+			f(key, value);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::convert::Infallible;
+	use crate::backend::KeyValueMemoryState;
+
+	fn read<S: StorageExternalities<Infallible> + ?Sized>(state: &S, key: &[u8]) -> Option<Vec<u8>> {
+		state.read_storage(key).unwrap()
+	}
+
+	#[test]
+	fn uncommitted_writes_are_not_visible_to_inner() {
+		let mut inner = KeyValueMemoryState::new_with_storage(vec![(b"a".to_vec(), b"1".to_vec())]);
+
+		let mut overlay = OverlayExternalities::new(&mut inner);
+		StorageExternalities::<Infallible>::write_storage(&mut overlay, b"b".to_vec(), b"2".to_vec());
+		StorageExternalities::<Infallible>::remove_storage(&mut overlay, b"a");
+		assert_eq!(read(&overlay, b"a"), None);
+		assert_eq!(read(&overlay, b"b"), Some(b"2".to_vec()));
+		drop(overlay);
+
+		assert_eq!(read(&inner, b"a"), Some(b"1".to_vec()));
+		assert_eq!(read(&inner, b"b"), None);
+
+		let mut overlay = OverlayExternalities::new(&mut inner);
+		StorageExternalities::<Infallible>::write_storage(&mut overlay, b"b".to_vec(), b"2".to_vec());
+		overlay.commit::<Infallible>();
+
+		assert_eq!(read(&inner, b"b"), Some(b"2".to_vec()));
+	}
+
+	#[test]
+	fn empty_value_exists() {
+		let mut inner = KeyValueMemoryState::new_with_storage(vec![(b"a".to_vec(), b"1".to_vec())]);
+		let mut overlay = OverlayExternalities::new(&mut inner);
+		StorageExternalities::<Infallible>::write_storage(&mut overlay, b"empty".to_vec(), Vec::new());
+		StorageExternalities::<Infallible>::remove_storage(&mut overlay, b"a");
+
+		assert!(StorageExternalities::<Infallible>::exists_storage(&overlay, b"empty"));
+		assert!(!StorageExternalities::<Infallible>::exists_storage(&overlay, b"a"));
+		assert!(!StorageExternalities::<Infallible>::exists_storage(&overlay, b"absent"));
+	}
+}
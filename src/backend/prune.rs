@@ -0,0 +1,44 @@
+use crate::Block;
+use crate::backend::{ChainQuery, ChainSettlement, OperationError};
+
+/// Prune the backend, keeping only the canonical chain from genesis to
+/// `keep_from` and all descendants of `keep_from`. Blocks on forks that
+/// branch off the canonical chain below `keep_from` are removed.
+pub fn prune<Ba>(
+	backend: &mut Ba,
+	keep_from: &<Ba::Block as Block>::Identifier,
+) -> Result<(), Ba::Error> where
+	Ba: ChainQuery + ChainSettlement,
+	Ba::Error: OperationError,
+{
+	if !backend.is_canon(keep_from)? {
+		return Err(Ba::Error::invalid_operation())
+	}
+
+	let keep_depth = backend.depth_at(keep_from)?;
+	let mut pruning = Vec::new();
+
+	for depth in 0..keep_depth {
+		let canon = backend.lookup_canon_depth(depth)?
+			.expect("Depth is below a canonical block; it must exist; qed");
+		let next_canon = backend.lookup_canon_depth(depth + 1)?
+			.expect("Depth is below or at a canonical block; it must exist; qed");
+
+		for child in backend.children_at(&canon)? {
+			if child != next_canon {
+				pruning.push((canon.clone(), child));
+			}
+		}
+	}
+
+	for (parent, child) in pruning {
+		let mut removing = vec![child.clone()];
+		while let Some(id) = removing.pop() {
+			removing.append(&mut backend.children_at(&id)?);
+			backend.remove_block(&id);
+		}
+		backend.remove_child(&parent, &child);
+	}
+
+	Ok(())
+}
@@ -0,0 +1,67 @@
+use crate::Block;
+use crate::backend::{ChainQuery, ChainSettlement};
+
+/// Outcome of a reindex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReindexOutcome {
+	/// The whole canonical chain was reindexed.
+	Completed,
+	/// The reindex was cancelled. Depths below `resume_from` are indexed,
+	/// and the reindex can be resumed from there.
+	Aborted {
+		/// Depth to resume the reindex from.
+		resume_from: usize,
+	},
+}
+
+/// Rebuild the canonical index of the backend from its stored blocks. The
+/// canonical chain is recomputed by walking from head to genesis through
+/// parent ids, then its blocks are mapped by depth and marked canonical,
+/// from `from_depth` upwards, while every block branching off it is marked
+/// not canonical.
+///
+/// After each depth, `progress` is called with the reindexed depth and the
+/// depth of head, and the reindex is cancelled if it returns `false`. The
+/// index is then correct below the returned `resume_from` depth.
+pub fn reindex<Ba>(
+	backend: &mut Ba,
+	from_depth: usize,
+	progress: &mut dyn FnMut(usize, usize) -> bool,
+) -> Result<ReindexOutcome, Ba::Error> where
+	Ba: ChainQuery + ChainSettlement,
+{
+	let mut chain = Vec::new();
+	let mut current = Some(backend.head());
+	while let Some(id) = current {
+		current = backend.block_at(&id)?.parent_id();
+		chain.push(id);
+	}
+	chain.reverse();
+
+	let head_depth = chain.len() - 1;
+	for (depth, id) in chain.iter().enumerate().skip(from_depth) {
+		backend.set_canon(id.clone(), true);
+		backend.insert_canon_depth_mapping(depth, id.clone());
+
+		let mut retracting = backend.children_at(id)?;
+		retracting.retain(|child| Some(child) != chain.get(depth + 1));
+		while let Some(id) = retracting.pop() {
+			retracting.append(&mut backend.children_at(&id)?);
+			backend.set_canon(id, false);
+		}
+
+		if !progress(depth, head_depth) {
+			return Ok(ReindexOutcome::Aborted { resume_from: depth + 1 })
+		}
+	}
+
+	// Mappings left above head are stale.
+	let mut depth = chain.len();
+	while backend.lookup_canon_depth(depth)?.is_some() {
+		backend.remove_canon_depth_mapping(&depth);
+		depth += 1;
+	}
+
+	Ok(ReindexOutcome::Completed)
+}
+
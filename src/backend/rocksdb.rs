@@ -0,0 +1,778 @@
+//! RocksDB backend and externalities.
+
+use std::{fmt, error as stderror};
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+use parity_codec::{Encode, Decode};
+use rocksdb::{DB, Options, WriteBatch, IteratorMode};
+use crate::{Block, Auxiliary, StorageExternalities};
+use crate::backend::{Store, ChainQuery, ChainSettlement, Operation, SharedCommittable, OperationError, Prunable, Finalizable, Reindexable, ReindexOutcome, prune, finalize, reindex};
+
+const COLUMN_META: &str = "meta";
+const COLUMN_BLOCKS: &str = "blocks";
+const COLUMN_STATES: &str = "states";
+const COLUMN_CANON_DEPTHS: &str = "canon_depths";
+const COLUMN_AUXILIARIES: &str = "auxiliaries";
+const COLUMN_STORAGE: &str = "storage";
+
+const COLUMNS: [&str; 5] = [
+	COLUMN_META, COLUMN_BLOCKS, COLUMN_STATES, COLUMN_CANON_DEPTHS, COLUMN_AUXILIARIES,
+];
+
+const KEY_HEAD: &[u8] = b"head";
+const KEY_GENESIS: &[u8] = b"genesis";
+const KEY_FINALIZED: &[u8] = b"finalized";
+
+#[derive(Debug)]
+/// RocksDB errors
+pub enum Error {
+	/// Invalid Operation
+	InvalidOperation,
+	/// Trying to import a block that is genesis
+	IsGenesis,
+	/// Query does not exist
+	NotExist,
+	/// Stored value cannot be decoded
+	Corrupted,
+	/// Underlying database error
+	Backend(rocksdb::Error),
+}
+
+impl OperationError for Error {
+	fn invalid_operation() -> Self {
+		Error::InvalidOperation
+	}
+
+	fn block_is_genesis() -> Self {
+		Error::IsGenesis
+	}
+}
+
+impl From<rocksdb::Error> for Error {
+	fn from(error: rocksdb::Error) -> Self {
+		Error::Backend(error)
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for Error { }
+
+fn open_database<P: AsRef<Path>>(path: P, columns: &[&str]) -> Result<DB, rocksdb::Error> {
+	let mut options = Options::default();
+	options.create_if_missing(true);
+	options.create_missing_column_families(true);
+
+	DB::open_cf(&options, path, columns)
+}
+
+/// Persistent key-value externalities backed by RocksDB. Writes and
+/// removals are buffered in memory, and visible to reads right away, but
+/// only reach the database on `commit`. Buffered changes not committed are
+/// lost when the externalities are dropped.
+#[derive(Clone)]
+pub struct RocksExternalities {
+	db: Arc<DB>,
+	/// Buffered changes by key. `None` marks a removed key.
+	pending: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl RocksExternalities {
+	/// Open or create the externalities database at the given path.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, rocksdb::Error> {
+		Ok(Self {
+			db: Arc::new(open_database(path, &[COLUMN_STORAGE])?),
+			pending: BTreeMap::new(),
+		})
+	}
+
+	/// Write all buffered changes to the database at once. On error nothing
+	/// is written, and the changes stay buffered.
+	pub fn commit(&mut self) -> Result<(), rocksdb::Error> {
+		let column = self.db.cf_handle(COLUMN_STORAGE)
+			.expect("Column is created on open; qed");
+		let mut batch = WriteBatch::default();
+		for (key, value) in &self.pending {
+			match value {
+				Some(value) => batch.put_cf(column, key, value)?,
+				None => batch.delete_cf(column, key)?,
+			}
+		}
+		self.db.write(batch)?;
+		self.pending.clear();
+
+		Ok(())
+	}
+}
+
+impl StorageExternalities<rocksdb::Error> for RocksExternalities {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+		if let Some(value) = self.pending.get(key) {
+			return Ok(value.clone())
+		}
+
+		let column = self.db.cf_handle(COLUMN_STORAGE)
+			.expect("Column is created on open; qed");
+		Ok(self.db.get_cf(column, key)?.map(|value| value.to_vec()))
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.pending.insert(key, Some(value));
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		self.pending.insert(key.to_vec(), None);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		let column = self.db.cf_handle(COLUMN_STORAGE)
+			.expect("Column is created on open; qed");
+		let iter = self.db.iterator_cf(column, IteratorMode::Start)
+			.expect("Database read failed");
+
+		// Both the database and the buffer are sorted by key, so they are
+		// merged in one pass, with buffered changes taking precedence.
+		let mut pending = self.pending.iter().peekable();
+		for (key, value) in iter {
+			while let Some((pending_key, pending_value)) = pending.next_if(|(pending_key, _)| pending_key.as_slice() < &key[..]) {
+				if let Some(pending_value) = pending_value {
+					f(pending_key, pending_value);
+				}
+			}
+
+			match pending.next_if(|(pending_key, _)| pending_key.as_slice() == &key[..]) {
+				Some((_, Some(pending_value))) => f(&key, pending_value),
+				Some((_, None)) => (),
+				None => f(&key, &value),
+			}
+		}
+
+		for (pending_key, pending_value) in pending {
+			if let Some(pending_value) = pending_value {
+				f(pending_key, pending_value);
+			}
+		}
+	}
+}
+
+/// Stored block metadata: block, depth, children and canonical flag.
+type StoredBlock<B> = (B, u64, Vec<<B as Block>::Identifier>, bool);
+
+/// Backend persisted in RocksDB. Blocks are stored in a column family keyed
+/// by encoded id.
+pub struct RocksBackend<B, A, S> {
+	db: Arc<DB>,
+	_marker: PhantomData<(B, A, S)>,
+}
+
+impl<B, A, S> Clone for RocksBackend<B, A, S> {
+	fn clone(&self) -> Self {
+		Self {
+			db: self.db.clone(),
+			_marker: PhantomData,
+		}
+	}
+}
+
+impl<B, A, S> RocksBackend<B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	/// Open an existing backend at the given path.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+		let backend = Self {
+			db: Arc::new(open_database(path, &COLUMNS)?),
+			_marker: PhantomData,
+		};
+
+		if backend.database().get(COLUMN_META, KEY_GENESIS)?.is_none() {
+			return Err(Error::NotExist)
+		}
+
+		Ok(backend)
+	}
+
+	/// Open the backend at the given path, initializing it with genesis if
+	/// it is empty.
+	pub fn open_or_create_with_genesis<P: AsRef<Path>>(
+		path: P,
+		block: B,
+		genesis_state: S,
+	) -> Result<Self, Error> {
+		assert!(block.is_genesis(), "with_genesis must be provided with a genesis block");
+
+		let backend = Self {
+			db: Arc::new(open_database(path, &COLUMNS)?),
+			_marker: PhantomData,
+		};
+
+		let genesis_id = block.id();
+		match backend.database().get(COLUMN_META, KEY_GENESIS)? {
+			Some(stored) => {
+				if stored != genesis_id.encode() {
+					return Err(Error::InvalidOperation)
+				}
+			},
+			None => {
+				let mut database = backend.database();
+				database.insert_block(
+					genesis_id.clone(), block, genesis_state, 0, Vec::new(), true
+				);
+				database.insert_canon_depth_mapping(0, genesis_id.clone());
+				database.put(COLUMN_META, KEY_GENESIS.to_vec(), genesis_id.encode());
+				database.set_head(genesis_id);
+				database.flush()?;
+			},
+		}
+
+		Ok(backend)
+	}
+
+	fn database(&self) -> RocksDatabase<'_, B, A, S> {
+		RocksDatabase {
+			db: &self.db,
+			changes: BTreeMap::new(),
+			_marker: PhantomData,
+		}
+	}
+}
+
+/// View of the database with pending changes, flushed in one write batch.
+struct RocksDatabase<'a, B, A, S> {
+	db: &'a DB,
+	changes: BTreeMap<(&'static str, Vec<u8>), Option<Vec<u8>>>,
+	_marker: PhantomData<(B, A, S)>,
+}
+
+impl<'a, B, A, S> RocksDatabase<'a, B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	fn get(&self, column: &'static str, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		if let Some(value) = self.changes.get(&(column, key.to_vec())) {
+			return Ok(value.clone())
+		}
+
+		let handle = self.db.cf_handle(column)
+			.expect("Columns are created on open; qed");
+		Ok(self.db.get_cf(handle, key)?.map(|value| value.to_vec()))
+	}
+
+	fn get_decoded<T: Decode>(&self, column: &'static str, key: &[u8]) -> Result<Option<T>, Error> {
+		match self.get(column, key)? {
+			Some(value) => Ok(Some(T::decode(&mut value.as_slice()).ok_or(Error::Corrupted)?)),
+			None => Ok(None),
+		}
+	}
+
+	fn put(&mut self, column: &'static str, key: Vec<u8>, value: Vec<u8>) {
+		self.changes.insert((column, key), Some(value));
+	}
+
+	fn delete(&mut self, column: &'static str, key: Vec<u8>) {
+		self.changes.insert((column, key), None);
+	}
+
+	fn stored_block(&self, id: &B::Identifier) -> Result<StoredBlock<B>, Error> {
+		self.get_decoded(COLUMN_BLOCKS, &id.encode())?
+			.ok_or(Error::NotExist)
+	}
+
+	fn flush(self) -> Result<(), Error> {
+		let mut batch = WriteBatch::default();
+		for ((column, key), value) in self.changes {
+			let handle = self.db.cf_handle(column)
+				.expect("Columns are created on open; qed");
+			match value {
+				Some(value) => batch.put_cf(handle, key, value)?,
+				None => batch.delete_cf(handle, key)?,
+			}
+		}
+
+		Ok(self.db.write(batch)?)
+	}
+}
+
+impl<'a, B, A, S> Store for RocksDatabase<'a, B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	type Block = B;
+	type State = S;
+	type Auxiliary = A;
+	type Error = Error;
+}
+
+impl<'a, B, A, S> ChainQuery for RocksDatabase<'a, B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	fn head(&self) -> B::Identifier {
+		self.get_decoded(COLUMN_META, KEY_HEAD)
+			.expect("Database read failed")
+			.expect("Head is set on genesis creation; qed")
+	}
+
+	fn genesis(&self) -> B::Identifier {
+		self.get_decoded(COLUMN_META, KEY_GENESIS)
+			.expect("Database read failed")
+			.expect("Genesis is checked on open; qed")
+	}
+
+	fn finalized(&self) -> B::Identifier {
+		self.get_decoded(COLUMN_META, KEY_FINALIZED)
+			.expect("Database read failed")
+			.unwrap_or_else(|| self.genesis())
+	}
+
+	fn contains(
+		&self,
+		id: &B::Identifier
+	) -> Result<bool, Error> {
+		Ok(self.get(COLUMN_BLOCKS, &id.encode())?.is_some())
+	}
+
+	fn is_canon(
+		&self,
+		id: &B::Identifier
+	) -> Result<bool, Error> {
+		Ok(self.stored_block(id)?.3)
+	}
+
+	fn lookup_canon_depth(
+		&self,
+		depth: usize,
+	) -> Result<Option<B::Identifier>, Error> {
+		self.get_decoded(COLUMN_CANON_DEPTHS, &(depth as u64).encode())
+	}
+
+	fn auxiliary(
+		&self,
+		key: &A::Key
+	) -> Result<Option<A>, Error> {
+		self.get_decoded(COLUMN_AUXILIARIES, &key.encode())
+	}
+
+	fn children_at(
+		&self,
+		id: &B::Identifier,
+	) -> Result<Vec<B::Identifier>, Error> {
+		Ok(self.stored_block(id)?.2)
+	}
+
+	fn depth_at(
+		&self,
+		id: &B::Identifier
+	) -> Result<usize, Error> {
+		Ok(self.stored_block(id)?.1 as usize)
+	}
+
+	fn block_at(
+		&self,
+		id: &B::Identifier,
+	) -> Result<B, Error> {
+		Ok(self.stored_block(id)?.0)
+	}
+
+	fn state_at(
+		&self,
+		id: &B::Identifier,
+	) -> Result<Self::State, Error> {
+		self.get_decoded(COLUMN_STATES, &id.encode())?
+			.ok_or(Error::NotExist)
+	}
+}
+
+impl<'a, B, A, S> ChainSettlement for RocksDatabase<'a, B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	fn insert_block(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		block: Self::Block,
+		state: Self::State,
+		depth: usize,
+		children: Vec<<Self::Block as Block>::Identifier>,
+		is_canon: bool
+	) {
+		let key = id.encode();
+		self.put(COLUMN_BLOCKS, key.clone(), (block, depth as u64, children, is_canon).encode());
+		self.put(COLUMN_STATES, key, state.encode());
+	}
+	fn push_child(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		child: <Self::Block as Block>::Identifier,
+	) {
+		let mut stored = self.stored_block(&id)
+			.expect("Internal database error");
+		stored.2.push(child);
+		self.put(COLUMN_BLOCKS, id.encode(), stored.encode());
+	}
+	fn remove_block(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+	) {
+		let key = id.encode();
+		self.delete(COLUMN_BLOCKS, key.clone());
+		self.delete(COLUMN_STATES, key);
+	}
+	fn remove_child(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+		child: &<Self::Block as Block>::Identifier,
+	) {
+		let mut stored = self.stored_block(id)
+			.expect("Internal database error");
+		stored.2.retain(|c| c != child);
+		self.put(COLUMN_BLOCKS, id.encode(), stored.encode());
+	}
+	fn set_canon(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		is_canon: bool
+	) {
+		let mut stored = self.stored_block(&id)
+			.expect("Internal database error");
+		stored.3 = is_canon;
+		self.put(COLUMN_BLOCKS, id.encode(), stored.encode());
+	}
+	fn insert_canon_depth_mapping(
+		&mut self,
+		depth: usize,
+		id: <Self::Block as Block>::Identifier,
+	) {
+		self.put(COLUMN_CANON_DEPTHS, (depth as u64).encode(), id.encode());
+	}
+	fn remove_canon_depth_mapping(
+		&mut self,
+		depth: &usize
+	) {
+		self.delete(COLUMN_CANON_DEPTHS, (*depth as u64).encode());
+	}
+	fn insert_auxiliary(
+		&mut self,
+		key: <Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+		value: Self::Auxiliary
+	) {
+		self.put(COLUMN_AUXILIARIES, key.encode(), value.encode());
+	}
+	fn remove_auxiliary(
+		&mut self,
+		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+	) {
+		self.delete(COLUMN_AUXILIARIES, key.encode());
+	}
+	fn set_head(
+		&mut self,
+		head: <Self::Block as Block>::Identifier
+	) {
+		self.put(COLUMN_META, KEY_HEAD.to_vec(), head.encode());
+	}
+	fn set_finalized(
+		&mut self,
+		finalized: <Self::Block as Block>::Identifier
+	) {
+		self.put(COLUMN_META, KEY_FINALIZED.to_vec(), finalized.encode());
+	}
+}
+
+impl<B, A, S> Store for RocksBackend<B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	type Block = B;
+	type State = S;
+	type Auxiliary = A;
+	type Error = Error;
+}
+
+impl<B, A, S> ChainQuery for RocksBackend<B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	fn genesis(&self) -> <Self::Block as Block>::Identifier {
+		self.database().genesis()
+	}
+	fn head(&self) -> <Self::Block as Block>::Identifier {
+		self.database().head()
+	}
+	fn finalized(&self) -> <Self::Block as Block>::Identifier {
+		self.database().finalized()
+	}
+	fn contains(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		self.database().contains(hash)
+	}
+	fn is_canon(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		self.database().is_canon(hash)
+	}
+	fn lookup_canon_depth(
+		&self,
+		depth: usize,
+	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error> {
+		self.database().lookup_canon_depth(depth)
+	}
+	fn auxiliary(
+		&self,
+		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+	) -> Result<Option<Self::Auxiliary>, Self::Error> {
+		self.database().auxiliary(key)
+	}
+	fn depth_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<usize, Self::Error> {
+		self.database().depth_at(hash)
+	}
+	fn children_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Vec<<Self::Block as Block>::Identifier>, Self::Error> {
+		self.database().children_at(hash)
+	}
+	fn state_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::State, Self::Error> {
+		self.database().state_at(hash)
+	}
+	fn block_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::Block, Self::Error> {
+		self.database().block_at(hash)
+	}
+}
+
+impl<B, A, S> SharedCommittable for RocksBackend<B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	type Operation = Operation<Self::Block, Self::State, Self::Auxiliary>;
+
+	fn commit(
+		&self,
+		operation: Operation<Self::Block, Self::State, Self::Auxiliary>,
+	) -> Result<(), Self::Error> {
+		let mut database = self.database();
+		operation.settle(&mut database)?;
+		database.flush()
+	}
+}
+
+impl<B, A, S> Prunable for RocksBackend<B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	fn prune(
+		&mut self,
+		keep_from: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error> {
+		let mut database = self.database();
+		prune(&mut database, keep_from)?;
+		database.flush()
+	}
+}
+
+impl<B, A, S> Finalizable for RocksBackend<B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	fn finalize(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error> {
+		let mut database = self.database();
+		finalize(&mut database, id)?;
+		database.flush()
+	}
+}
+
+impl<B, A, S> Reindexable for RocksBackend<B, A, S> where
+	B: Block + Encode + Decode,
+	B::Identifier: Encode + Decode,
+	A: Auxiliary<B> + Encode + Decode,
+	A::Key: Encode,
+	S: Encode + Decode,
+{
+	fn reindex(
+		&mut self,
+		from_depth: usize,
+		progress: &mut dyn FnMut(usize, usize) -> bool,
+	) -> Result<ReindexOutcome, Self::Error> {
+		let mut database = self.database();
+		let outcome = reindex(&mut database, from_depth, progress)?;
+		database.flush()?;
+		Ok(outcome)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+	use super::*;
+	use crate::{BuildGenesis, AsExternalities};
+	use crate::backend::{KeyValueMemoryState, ImportLock};
+	use crate::import::import_if_deeper;
+	use crate::testing::runtime::{child, Extrinsic, TestBlock, TestExecutor};
+
+	fn path(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("blockchain-rocksdb-{}-{}", std::process::id(), name));
+		let _ = std::fs::remove_dir_all(&path);
+		path
+	}
+
+	fn read(externalities: &RocksExternalities, key: &[u8]) -> Option<Vec<u8>> {
+		StorageExternalities::read_storage(externalities, key).unwrap()
+	}
+
+	#[test]
+	fn externalities_persist_committed_changes() {
+		let path = path("externalities-commit");
+		{
+			let mut externalities = RocksExternalities::open(&path).unwrap();
+			externalities.write_storage(b"a".to_vec(), b"1".to_vec());
+			externalities.write_storage(b"b".to_vec(), b"2".to_vec());
+			externalities.commit().unwrap();
+
+			externalities.remove_storage(b"b");
+			externalities.write_storage(b"c".to_vec(), b"3".to_vec());
+			assert_eq!(read(&externalities, b"b"), None);
+			assert_eq!(read(&externalities, b"c"), Some(b"3".to_vec()));
+			externalities.commit().unwrap();
+		}
+
+		let externalities = RocksExternalities::open(&path).unwrap();
+		assert_eq!(read(&externalities, b"a"), Some(b"1".to_vec()));
+		assert_eq!(read(&externalities, b"b"), None);
+		assert_eq!(read(&externalities, b"c"), Some(b"3".to_vec()));
+		drop(externalities);
+		let _ = std::fs::remove_dir_all(&path);
+	}
+
+	#[test]
+	fn externalities_drop_uncommitted_changes() {
+		let path = path("externalities-drop");
+		{
+			let mut externalities = RocksExternalities::open(&path).unwrap();
+			externalities.write_storage(b"a".to_vec(), b"1".to_vec());
+		}
+
+		let externalities = RocksExternalities::open(&path).unwrap();
+		assert_eq!(read(&externalities, b"a"), None);
+		drop(externalities);
+		let _ = std::fs::remove_dir_all(&path);
+	}
+
+	#[test]
+	fn externalities_iterate_buffered_changes_in_order() {
+		let path = path("externalities-iterate");
+		let mut externalities = RocksExternalities::open(&path).unwrap();
+		externalities.write_storage(b"b".to_vec(), b"1".to_vec());
+		externalities.write_storage(b"d".to_vec(), b"2".to_vec());
+		externalities.commit().unwrap();
+
+		externalities.write_storage(b"a".to_vec(), b"3".to_vec());
+		externalities.write_storage(b"b".to_vec(), b"4".to_vec());
+		externalities.remove_storage(b"d");
+		externalities.write_storage(b"e".to_vec(), b"5".to_vec());
+
+		let mut entries = Vec::new();
+		externalities.for_each_storage(&mut |key, value| entries.push((key.to_vec(), value.to_vec())));
+		assert_eq!(entries, vec![
+			(b"a".to_vec(), b"3".to_vec()),
+			(b"b".to_vec(), b"4".to_vec()),
+			(b"e".to_vec(), b"5".to_vec()),
+		]);
+		drop(externalities);
+		let _ = std::fs::remove_dir_all(&path);
+	}
+
+	#[test]
+	fn backend_keeps_imported_blocks_across_reopen() {
+		let path = path("backend-reopen");
+		let executor = TestExecutor::default();
+		let mut genesis_state = KeyValueMemoryState::default();
+		let genesis = executor.build_genesis(genesis_state.as_externalities()).unwrap();
+		let block = child(&genesis, &mut genesis_state.clone(), vec![Extrinsic::Add(1)]);
+		{
+			let backend = RocksBackend::<TestBlock, (), KeyValueMemoryState>::open_or_create_with_genesis(
+				&path, genesis.clone(), genesis_state.clone(),
+			).unwrap();
+			assert!(import_if_deeper(&backend, &ImportLock::new(), &executor, block.clone()).unwrap());
+		}
+
+		let backend = RocksBackend::<TestBlock, (), KeyValueMemoryState>::open(&path).unwrap();
+		assert_eq!(backend.genesis(), genesis.id());
+		assert_eq!(backend.head(), block.id());
+		assert_eq!(backend.block_at(&block.id()).unwrap(), block);
+		drop(backend);
+		let _ = std::fs::remove_dir_all(&path);
+	}
+
+	#[test]
+	fn batched_writes_are_all_visible() {
+		let path = path("externalities-batch");
+		let entries = (0..100u32)
+			.map(|index| (index.to_be_bytes().to_vec(), index.to_le_bytes().to_vec()))
+			.collect::<Vec<_>>();
+		{
+			let mut externalities = RocksExternalities::open(&path).unwrap();
+			externalities.write_storage_batch(entries.clone());
+			for (key, value) in &entries {
+				assert_eq!(read(&externalities, key), Some(value.clone()));
+			}
+			externalities.commit().unwrap();
+		}
+
+		let externalities = RocksExternalities::open(&path).unwrap();
+		let mut stored = Vec::new();
+		externalities.for_each_storage(&mut |key, value| stored.push((key.to_vec(), value.to_vec())));
+		assert_eq!(stored, entries);
+		drop(externalities);
+		let _ = std::fs::remove_dir_all(&path);
+	}
+}
@@ -0,0 +1,132 @@
+use std::cell::{Cell, RefCell};
+use crate::{StorageExternalities, TransactionalExternalities};
+use crate::proof;
+
+/// Externalities caching the storage root of the wrapped ones. Any change
+/// to the storage invalidates the cached root, which is then recomputed
+/// over the whole storage on the next `storage_root` call.
+pub struct CachedRootExternalities<S, T> {
+	inner: S,
+	hash: fn(&[u8]) -> T,
+	root: RefCell<Option<T>>,
+	computations: Cell<usize>,
+}
+
+impl<S, T: AsRef<[u8]> + Clone> CachedRootExternalities<S, T> {
+	/// Cache the storage root of the given externalities, computed with
+	/// the given hash function.
+	pub fn new(inner: S, hash: fn(&[u8]) -> T) -> Self {
+		Self { inner, hash, root: RefCell::new(None), computations: Cell::new(0) }
+	}
+
+	/// Merkle root of the storage, as `proof::storage_root`. The cached
+	/// root is returned if the storage did not change since it was
+	/// computed.
+	pub fn storage_root<E>(&self) -> T where
+		S: StorageExternalities<E>,
+	{
+		if let Some(root) = self.root.borrow().as_ref() {
+			return root.clone()
+		}
+
+		let root = proof::storage_root(&self.inner, self.hash);
+		self.computations.set(self.computations.get() + 1);
+		*self.root.borrow_mut() = Some(root.clone());
+		root
+	}
+
+	/// Number of times the storage root was computed rather than taken
+	/// from the cache.
+	pub fn computations(&self) -> usize {
+		self.computations.get()
+	}
+
+	/// Stop caching, returning the wrapped externalities.
+	pub fn into_inner(self) -> S {
+		self.inner
+	}
+
+	fn invalidate(&mut self) {
+		*self.root.get_mut() = None;
+	}
+}
+
+impl<E, S: StorageExternalities<E>, T: AsRef<[u8]> + Clone> StorageExternalities<E> for CachedRootExternalities<S, T> {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, E> {
+		self.inner.read_storage(key)
+	}
+
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), E> {
+		self.inner.read_storage_ref(key, f)
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		self.inner.exists_storage(key)
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.invalidate();
+		self.inner.write_storage(key, value);
+	}
+
+	fn write_storage_batch(&mut self, entries: Vec<(Vec<u8>, Vec<u8>)>) {
+		self.invalidate();
+		self.inner.write_storage_batch(entries);
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		self.invalidate();
+		self.inner.remove_storage(key);
+	}
+
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		self.invalidate();
+		self.inner.clear_prefix(prefix);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		self.inner.for_each_storage(f);
+	}
+}
+
+impl<S: TransactionalExternalities, T: AsRef<[u8]> + Clone> TransactionalExternalities for CachedRootExternalities<S, T> {
+	fn start_transaction(&mut self) {
+		self.inner.start_transaction();
+	}
+
+	fn commit_transaction(&mut self) {
+		self.inner.commit_transaction();
+	}
+
+	fn rollback_transaction(&mut self) {
+		self.invalidate();
+		self.inner.rollback_transaction();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::convert::Infallible;
+	use crate::backend::KeyValueMemoryState;
+	use crate::testing::runtime::hash;
+
+	#[test]
+	fn recomputes_root_only_after_changes() {
+		let state = KeyValueMemoryState::new_with_storage(vec![(b"counter".to_vec(), vec![1])]);
+		let mut cached = CachedRootExternalities::new(state, hash);
+
+		let root = cached.storage_root::<Infallible>();
+		assert_eq!(cached.storage_root::<Infallible>(), root);
+		assert_eq!(cached.computations(), 1);
+
+		StorageExternalities::<Infallible>::write_storage(&mut cached, b"counter".to_vec(), vec![2]);
+		let changed = cached.storage_root::<Infallible>();
+		assert_ne!(changed, root);
+		assert_eq!(cached.storage_root::<Infallible>(), changed);
+		assert_eq!(cached.computations(), 2);
+
+		let state = cached.into_inner();
+		assert_eq!(proof::storage_root::<_, Infallible, _, _>(&state, hash), changed);
+	}
+}
@@ -0,0 +1,120 @@
+use std::{fmt, error as stderror};
+use core::convert::{Infallible, TryFrom};
+use crate::StorageExternalities;
+use crate::backend::KeyValueMemoryState;
+use crate::proof::storage_root;
+
+#[derive(Debug)]
+/// Errors of importing a state snapshot.
+pub enum SnapshotError {
+	/// Snapshot is not a valid encoding of storage entries
+	Malformed,
+	/// Root of the snapshot differs from the expected one
+	RootMismatch,
+}
+
+impl fmt::Display for SnapshotError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for SnapshotError { }
+
+fn push_bytes(blob: &mut Vec<u8>, bytes: &[u8]) {
+	let len = u32::try_from(bytes.len()).expect("Storage entries are smaller than 4 GiB; qed");
+	blob.extend_from_slice(&len.to_le_bytes());
+	blob.extend_from_slice(bytes);
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+	if input.len() < 4 {
+		return None
+	}
+	let (len, rest) = input.split_at(4);
+	let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+
+	if rest.len() < len {
+		return None
+	}
+	let (bytes, rest) = rest.split_at(len);
+	*input = rest;
+	Some(bytes)
+}
+
+/// Export all storage entries of the state as a portable blob, with
+/// length prefixed keys and values in ascending key order.
+pub fn export_state<S, E>(state: &S) -> Vec<u8> where
+	S: StorageExternalities<E> + ?Sized,
+{
+	let mut blob = Vec::new();
+	state.for_each_storage(&mut |key, value| {
+		push_bytes(&mut blob, key);
+		push_bytes(&mut blob, value);
+	});
+	blob
+}
+
+/// Replace the storage of the state with the entries of an exported blob.
+/// The storage root of the entries must equal `expected_root`, such as the
+/// state root of a trusted block, otherwise the state is left untouched.
+pub fn import_state<S, E, T, F>(
+	state: &mut S,
+	blob: &[u8],
+	expected_root: &T,
+	hash: F,
+) -> Result<(), SnapshotError> where
+	S: StorageExternalities<E> + ?Sized,
+	T: AsRef<[u8]> + Clone + PartialEq,
+	F: Fn(&[u8]) -> T,
+{
+	let mut input = blob;
+	let mut entries = Vec::new();
+	while !input.is_empty() {
+		let key = take_bytes(&mut input).ok_or(SnapshotError::Malformed)?;
+		let value = take_bytes(&mut input).ok_or(SnapshotError::Malformed)?;
+		entries.push((key.to_vec(), value.to_vec()));
+	}
+
+	let snapshot = KeyValueMemoryState::new_with_storage(entries.iter().cloned());
+	if storage_root::<_, Infallible, _, _>(&snapshot, hash) != *expected_root {
+		return Err(SnapshotError::RootMismatch)
+	}
+
+	state.clear_prefix(&[]);
+	state.write_storage_batch(entries);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::runtime::hash;
+
+	#[test]
+	fn imports_exported_state_matching_root() {
+		let source = KeyValueMemoryState::new_with_storage(vec![
+			(b"counter".to_vec(), 3u64.to_le_bytes().to_vec()),
+			(b"empty".to_vec(), Vec::new()),
+			(b"timestamp".to_vec(), 7u64.to_le_bytes().to_vec()),
+		]);
+		let root = storage_root::<_, Infallible, _, _>(&source, hash);
+		let blob = export_state::<_, Infallible>(&source);
+
+		let mut target = KeyValueMemoryState::new_with_storage(vec![(b"stale".to_vec(), vec![1])]);
+		let wrong_root = hash(b"wrong");
+		assert!(matches!(
+			import_state::<_, Infallible, _, _>(&mut target, &blob, &wrong_root, hash),
+			Err(SnapshotError::RootMismatch)
+		));
+		assert!(matches!(
+			import_state::<_, Infallible, _, _>(&mut target, &blob[..blob.len() - 1], &root, hash),
+			Err(SnapshotError::Malformed)
+		));
+		assert_eq!(target.as_ref().keys().collect::<Vec<_>>(), vec![&b"stale".to_vec()]);
+
+		import_state::<_, Infallible, _, _>(&mut target, &blob, &root, hash).unwrap();
+		assert_eq!(target.as_ref(), source.as_ref());
+		assert_eq!(storage_root::<_, Infallible, _, _>(&target, hash), root);
+	}
+}
@@ -1,23 +1,90 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::error as stderror;
 use core::convert::Infallible;
-use crate::StorageExternalities;
+use crate::{StorageExternalities, TransactionalExternalities};
 
-/// State stored in memory.
+/// State stored in memory. Keys are kept sorted.
 #[derive(Clone, Default)]
 pub struct KeyValueMemoryState {
-	storage: HashMap<Vec<u8>, Vec<u8>>,
+	storage: BTreeMap<Vec<u8>, Vec<u8>>,
+	/// Original values of keys touched in each open transaction.
+	transactions: Vec<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
 }
 
-impl AsRef<HashMap<Vec<u8>, Vec<u8>>> for KeyValueMemoryState {
-	fn as_ref(&self) -> &HashMap<Vec<u8>, Vec<u8>> {
-		&self.storage
+impl KeyValueMemoryState {
+	/// Create a new memory state seeded with the given key value pairs.
+	pub fn new_with_storage<I>(storage: I) -> Self where
+		I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>,
+	{
+		Self {
+			storage: storage.into_iter().collect(),
+			transactions: Vec::new(),
+		}
+	}
+
+	fn note_original(&mut self, key: &[u8]) {
+		if let Some(journal) = self.transactions.last_mut() {
+			if !journal.contains_key(key) {
+				journal.insert(key.to_vec(), self.storage.get(key).cloned());
+			}
+		}
+	}
+
+	fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.note_original(&key);
+		self.storage.insert(key, value);
+	}
+
+	fn remove(&mut self, key: &[u8]) {
+		self.note_original(key);
+		self.storage.remove(key);
+	}
+
+	fn remove_prefix(&mut self, prefix: &[u8]) {
+		let keys = self.storage.range(prefix.to_vec()..)
+			.map(|(key, _)| key)
+			.take_while(|key| key.starts_with(prefix))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		for key in keys {
+			self.remove(&key);
+		}
+	}
+}
+
+impl TransactionalExternalities for KeyValueMemoryState {
+	fn start_transaction(&mut self) {
+		self.transactions.push(BTreeMap::new());
+	}
+
+	fn commit_transaction(&mut self) {
+		let journal = self.transactions.pop()
+			.expect("Commit is called without an open transaction");
+
+		if let Some(parent) = self.transactions.last_mut() {
+			for (key, original) in journal {
+				parent.entry(key).or_insert(original);
+			}
+		}
+	}
+
+	fn rollback_transaction(&mut self) {
+		let journal = self.transactions.pop()
+			.expect("Rollback is called without an open transaction");
+
+		for (key, original) in journal {
+			match original {
+				Some(value) => { self.storage.insert(key, value); },
+				None => { self.storage.remove(&key); },
+			}
+		}
 	}
 }
 
-impl AsMut<HashMap<Vec<u8>, Vec<u8>>> for KeyValueMemoryState {
-	fn as_mut(&mut self) -> &mut HashMap<Vec<u8>, Vec<u8>> {
-		&mut self.storage
+impl AsRef<BTreeMap<Vec<u8>, Vec<u8>>> for KeyValueMemoryState {
+	fn as_ref(&self) -> &BTreeMap<Vec<u8>, Vec<u8>> {
+		&self.storage
 	}
 }
 
@@ -26,12 +93,31 @@ impl StorageExternalities<Infallible> for KeyValueMemoryState {
 		Ok(self.storage.get(key).map(|value| value.to_vec()))
 	}
 
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), Infallible> {
+		f(self.storage.get(key).map(|value| value.as_slice()));
+		Ok(())
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		self.storage.contains_key(key)
+	}
+
 	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
-		self.storage.insert(key, value);
+		self.insert(key, value);
 	}
 
 	fn remove_storage(&mut self, key: &[u8]) {
-		self.storage.remove(key);
+		self.remove(key);
+	}
+
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		self.remove_prefix(prefix);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		for (key, value) in &self.storage {
+			f(key, value);
+		}
 	}
 }
 
@@ -40,11 +126,174 @@ impl StorageExternalities<Box<dyn stderror::Error>> for KeyValueMemoryState {
 		Ok(self.storage.get(key).map(|value| value.to_vec()))
 	}
 
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), Box<dyn stderror::Error>> {
+		f(self.storage.get(key).map(|value| value.as_slice()));
+		Ok(())
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		self.storage.contains_key(key)
+	}
+
 	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
-		self.storage.insert(key, value);
+		self.insert(key, value);
 	}
 
 	fn remove_storage(&mut self, key: &[u8]) {
-		self.storage.remove(key);
+		self.remove(key);
+	}
+
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		self.remove_prefix(prefix);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		for (key, value) in &self.storage {
+			f(key, value);
+		}
+	}
+}
+
+/// Encodes the committed storage. Open transactions are not encoded.
+#[cfg(feature = "codec")]
+impl parity_codec::Encode for KeyValueMemoryState {
+	fn encode_to<T: parity_codec::Output>(&self, dest: &mut T) {
+		self.storage.encode_to(dest)
+	}
+}
+
+#[cfg(feature = "codec")]
+impl parity_codec::Decode for KeyValueMemoryState {
+	fn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {
+		parity_codec::Decode::decode(input).map(|storage| Self {
+			storage,
+			transactions: Vec::new(),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn read(state: &KeyValueMemoryState, key: &[u8]) -> Option<Vec<u8>> {
+		StorageExternalities::<Infallible>::read_storage(state, key).unwrap()
+	}
+
+	fn write(state: &mut KeyValueMemoryState, key: &[u8], value: &[u8]) {
+		StorageExternalities::<Infallible>::write_storage(state, key.to_vec(), value.to_vec());
+	}
+
+	#[test]
+	fn seeds_initial_storage() {
+		let state = KeyValueMemoryState::new_with_storage(vec![
+			(b"b".to_vec(), b"2".to_vec()),
+			(b"a".to_vec(), b"1".to_vec()),
+		]);
+
+		assert_eq!(read(&state, b"a"), Some(b"1".to_vec()));
+		assert_eq!(read(&state, b"b"), Some(b"2".to_vec()));
+		assert_eq!(read(&state, b"c"), None);
+		assert!(KeyValueMemoryState::default().as_ref().is_empty());
+	}
+
+	#[test]
+	fn removed_value_reads_as_none() {
+		let mut state = KeyValueMemoryState::default();
+		write(&mut state, b"a", b"1");
+		StorageExternalities::<Infallible>::remove_storage(&mut state, b"a");
+
+		assert_eq!(read(&state, b"a"), None);
+		assert!(!StorageExternalities::<Infallible>::exists_storage(&state, b"a"));
+		assert!(state.as_ref().is_empty());
+	}
+
+	#[test]
+	fn rollback_restores_journaled_writes() {
+		let mut state = KeyValueMemoryState::new_with_storage(vec![(b"a".to_vec(), b"1".to_vec())]);
+
+		state.start_transaction();
+		write(&mut state, b"a", b"2");
+		write(&mut state, b"b", b"3");
+		StorageExternalities::<Infallible>::remove_storage(&mut state, b"a");
+		state.rollback_transaction();
+
+		assert_eq!(read(&state, b"a"), Some(b"1".to_vec()));
+		assert_eq!(read(&state, b"b"), None);
+	}
+
+	#[test]
+	fn nested_commit_is_rolled_back_with_parent() {
+		let mut state = KeyValueMemoryState::default();
+
+		state.start_transaction();
+		write(&mut state, b"a", b"1");
+		state.start_transaction();
+		write(&mut state, b"a", b"2");
+		write(&mut state, b"b", b"3");
+		state.commit_transaction();
+		assert_eq!(read(&state, b"a"), Some(b"2".to_vec()));
+		state.rollback_transaction();
+
+		assert_eq!(read(&state, b"a"), None);
+		assert_eq!(read(&state, b"b"), None);
+	}
+
+	#[test]
+	fn iterates_storage_in_key_order() {
+		let mut state = KeyValueMemoryState::default();
+		write(&mut state, b"c", b"3");
+		write(&mut state, b"a", b"1");
+		write(&mut state, b"b", b"2");
+
+		let mut entries = Vec::new();
+		StorageExternalities::<Infallible>::for_each_storage(&state, &mut |key, value| {
+			entries.push((key.to_vec(), value.to_vec()))
+		});
+
+		assert_eq!(entries, vec![
+			(b"a".to_vec(), b"1".to_vec()),
+			(b"b".to_vec(), b"2".to_vec()),
+			(b"c".to_vec(), b"3".to_vec()),
+		]);
+	}
+
+	#[test]
+	fn clears_only_the_given_prefix() {
+		let mut state = KeyValueMemoryState::default();
+		write(&mut state, b"counter::value", b"1");
+		write(&mut state, b"counter::owner", b"2");
+		write(&mut state, b"balances::alice", b"3");
+		write(&mut state, b"balances::bob", b"4");
+
+		StorageExternalities::<Infallible>::clear_prefix(&mut state, b"counter::");
+
+		assert_eq!(read(&state, b"counter::value"), None);
+		assert_eq!(read(&state, b"counter::owner"), None);
+		assert_eq!(read(&state, b"balances::alice"), Some(b"3".to_vec()));
+		assert_eq!(read(&state, b"balances::bob"), Some(b"4".to_vec()));
+	}
+
+	#[test]
+	fn empty_value_exists() {
+		let mut state = KeyValueMemoryState::default();
+		write(&mut state, b"empty", b"");
+
+		assert!(StorageExternalities::<Infallible>::exists_storage(&state, b"empty"));
+		assert!(!StorageExternalities::<Infallible>::exists_storage(&state, b"absent"));
+	}
+
+	#[test]
+	fn visits_stored_value_without_copying() {
+		let mut state = KeyValueMemoryState::default();
+		write(&mut state, b"a", b"value");
+		let stored = state.storage[&b"a"[..]].as_ptr();
+
+		let mut visited = None;
+		StorageExternalities::<Infallible>::read_storage_ref(&state, b"a", &mut |value| {
+			visited = value.map(|value| (value.as_ptr(), value.to_vec()));
+		}).unwrap();
+
+		assert_eq!(visited, Some((stored, b"value".to_vec())));
 	}
 }
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use core::convert::Infallible;
+use crate::StorageExternalities;
+use crate::backend::ChainQuery;
+
+/// Cache of storage values read from the state of the head. Values are
+/// kept until `invalidate` is called, which must happen whenever the head
+/// changes, as `import_block_with_cache` does.
+#[derive(Clone, Debug, Default)]
+pub struct StateCache {
+	values: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl StateCache {
+	/// Create a new empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Read the value at key in the state of the head of the backend,
+	/// taking it from the cache if it was read before.
+	pub fn read_head<Ba>(&mut self, backend: &Ba, key: &[u8]) -> Result<Option<Vec<u8>>, Ba::Error> where
+		Ba: ChainQuery,
+		Ba::State: StorageExternalities<Infallible>,
+	{
+		if let Some(value) = self.values.get(key) {
+			return Ok(value.clone())
+		}
+
+		let state = backend.state_at(&backend.head())?;
+		let value = state.read_storage(key).expect("Error is infallible; qed");
+		self.values.insert(key.to_vec(), value.clone());
+		Ok(value)
+	}
+
+	/// Drop all cached values.
+	pub fn invalidate(&mut self) {
+		self.values.clear();
+	}
+}
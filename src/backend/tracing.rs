@@ -0,0 +1,99 @@
+use std::cell::RefCell;
+use crate::{StorageExternalities, TransactionalExternalities};
+
+/// Kind of a storage access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+	/// Value is read.
+	Read,
+	/// Value is written.
+	Write,
+	/// Value is removed.
+	Remove,
+}
+
+/// Record of a storage access.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageAccess {
+	/// Accessed key.
+	pub key: Vec<u8>,
+	/// Kind of the access.
+	pub kind: AccessKind,
+	/// Length of the value read or written, `None` if it does not exist.
+	pub value_len: Option<usize>,
+}
+
+/// Externalities recording every storage access in order, so that traces
+/// of the same execution on different nodes can be compared.
+pub struct TracingExternalities<S> {
+	inner: S,
+	trace: RefCell<Vec<StorageAccess>>,
+}
+
+impl<S> TracingExternalities<S> {
+	/// Trace accesses to the given externalities.
+	pub fn new(inner: S) -> Self {
+		Self { inner, trace: RefCell::new(Vec::new()) }
+	}
+
+	/// Accesses recorded so far.
+	pub fn trace(&self) -> Vec<StorageAccess> {
+		self.trace.borrow().clone()
+	}
+
+	/// Stop tracing, returning the externalities and the recorded accesses.
+	pub fn into_parts(self) -> (S, Vec<StorageAccess>) {
+		(self.inner, self.trace.into_inner())
+	}
+
+	fn record(&self, key: &[u8], kind: AccessKind, value_len: Option<usize>) {
+		self.trace.borrow_mut().push(StorageAccess { key: key.to_vec(), kind, value_len });
+	}
+}
+
+impl<E, S: StorageExternalities<E>> StorageExternalities<E> for TracingExternalities<S> {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, E> {
+		let value = self.inner.read_storage(key)?;
+		self.record(key, AccessKind::Read, value.as_ref().map(|value| value.len()));
+		Ok(value)
+	}
+
+	fn read_storage_ref(&self, key: &[u8], f: &mut dyn FnMut(Option<&[u8]>)) -> Result<(), E> {
+		self.inner.read_storage_ref(key, &mut |value| {
+			self.record(key, AccessKind::Read, value.map(|value| value.len()));
+			f(value);
+		})
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		self.read_storage(key).map(|value| value.is_some()).unwrap_or(false)
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		self.record(&key, AccessKind::Write, Some(value.len()));
+		self.inner.write_storage(key, value);
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		self.record(key, AccessKind::Remove, None);
+		self.inner.remove_storage(key);
+	}
+
+	fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+		self.inner.for_each_storage(f);
+	}
+}
+
+impl<S: TransactionalExternalities> TransactionalExternalities for TracingExternalities<S> {
+	fn start_transaction(&mut self) {
+		self.inner.start_transaction();
+	}
+
+	fn commit_transaction(&mut self) {
+		self.inner.commit_transaction();
+	}
+
+	fn rollback_transaction(&mut self) {
+		self.inner.rollback_transaction();
+	}
+}
@@ -1,5 +1,7 @@
 use std::error as stderror;
-use crate::{Block, Auxiliary};
+use core::convert::Infallible;
+use crate::{Block, Auxiliary, StorageExternalities};
+use crate::backend::ReindexOutcome;
 
 /// Backend store definition for a block context.
 pub trait Store {
@@ -27,6 +29,8 @@ pub trait ChainQuery: Store {
 	fn genesis(&self) -> <Self::Block as Block>::Identifier;
 	/// Get the head of the chain.
 	fn head(&self) -> <Self::Block as Block>::Identifier;
+	/// Get the finalized block of the chain.
+	fn finalized(&self) -> <Self::Block as Block>::Identifier;
 
 	/// Check whether a hash is contained in the chain.
 	fn contains(
@@ -64,6 +68,21 @@ pub trait ChainQuery: Store {
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<Vec<<Self::Block as Block>::Identifier>, Self::Error>;
 
+	/// Check whether a block is on the canonical chain, via the canonical
+	/// depth index. Unknown blocks are not canonical.
+	fn is_canonical(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> bool {
+		match self.depth_at(hash) {
+			Ok(depth) => match self.lookup_canon_depth(depth) {
+				Ok(Some(canon)) => canon == *hash,
+				_ => false,
+			},
+			Err(_) => false,
+		}
+	}
+
 	/// Get the state object of a block.
 	fn state_at(
 		&self,
@@ -75,6 +94,19 @@ pub trait ChainQuery: Store {
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<Self::Block, Self::Error>;
+
+	/// Read a storage value as of a block, from the state stored for it.
+	/// Blocks need not be canonical, but pruned blocks cannot be queried.
+	fn storage_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+		key: &[u8],
+	) -> Result<Option<Vec<u8>>, Self::Error> where
+		Self::State: StorageExternalities<Infallible>,
+	{
+		let state = self.state_at(hash)?;
+		Ok(state.read_storage(key).unwrap_or_else(|never| match never { }))
+	}
 }
 
 /// Database settlement for chain backend.
@@ -95,6 +127,17 @@ pub trait ChainSettlement: Store {
 		id: <Self::Block as Block>::Identifier,
 		child: <Self::Block as Block>::Identifier,
 	);
+	/// Remove a block from the database.
+	fn remove_block(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+	);
+	/// Remove a child from a block.
+	fn remove_child(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+		child: &<Self::Block as Block>::Identifier,
+	);
 	/// Set canon.
 	fn set_canon(
 		&mut self,
@@ -128,6 +171,11 @@ pub trait ChainSettlement: Store {
 		&mut self,
 		head: <Self::Block as Block>::Identifier
 	);
+	/// Set finalized block.
+	fn set_finalized(
+		&mut self,
+		finalized: <Self::Block as Block>::Identifier
+	);
 }
 
 /// Committable backend.
@@ -153,3 +201,33 @@ pub trait SharedCommittable: Store + Clone {
 		operation: Self::Operation,
 	) -> Result<(), Self::Error>;
 }
+
+/// Backend that can discard blocks no longer needed.
+pub trait Prunable: Store {
+	/// Remove blocks that are neither on the canonical chain up to
+	/// `keep_from`, nor descendants of it.
+	fn prune(
+		&mut self,
+		keep_from: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error>;
+}
+
+/// Backend that can finalize blocks.
+pub trait Finalizable: Store {
+	/// Mark a canonical block as finalized.
+	fn finalize(
+		&mut self,
+		id: &<Self::Block as Block>::Identifier,
+	) -> Result<(), Self::Error>;
+}
+
+/// Backend whose canonical index can be rebuilt from its stored blocks.
+pub trait Reindexable: Store {
+	/// Rebuild the canonical index from `from_depth` upwards, reporting
+	/// progress and allowing cancellation after each depth.
+	fn reindex(
+		&mut self,
+		from_depth: usize,
+		progress: &mut dyn FnMut(usize, usize) -> bool,
+	) -> Result<ReindexOutcome, Self::Error>;
+}
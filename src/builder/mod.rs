@@ -0,0 +1,152 @@
+//! Block builder driving an extrinsic builder executor.
+
+use crate::{ExtrinsicBuilder, ExtrinsicOutcome, DecodeExtrinsic, AsExternalities, TransactionalExternalities};
+
+/// Block builder holding the in-progress block and its state.
+pub struct BlockBuilder<'a, E: ExtrinsicBuilder, S> {
+	executor: &'a E,
+	block: E::BuildBlock,
+	state: S,
+	extrinsics: usize,
+	weight: u64,
+	refunded: u64,
+}
+
+impl<'a, E: ExtrinsicBuilder, S> BlockBuilder<'a, E, S> where
+	S: AsExternalities<E::Externalities> + TransactionalExternalities,
+{
+	/// Initialize a new block on top of the parent block and its state.
+	pub fn new(
+		executor: &'a E,
+		parent_block: &E::Block,
+		mut state: S,
+		inherent: E::Inherent,
+	) -> Result<Self, E::Error> {
+		let block = executor.initialize_block(
+			parent_block, state.as_externalities(), inherent
+		)?;
+
+		Ok(Self { executor, block, state, extrinsics: 0, weight: 0, refunded: 0 })
+	}
+
+	/// Apply an extrinsic to the block, returning its outcome. The weight
+	/// reported by the executor is added to the block's weight, and what is
+	/// left of its estimated weight is refunded to the block's budget. On
+	/// error, state changes made by the extrinsic are rolled back.
+	pub fn push(
+		&mut self,
+		extrinsic: E::Extrinsic,
+	) -> Result<ExtrinsicOutcome<E::Event>, E::Error> {
+		let estimated = self.executor.weight(&extrinsic);
+		self.state.start_transaction();
+
+		match self.executor.apply_extrinsic(
+			&mut self.block, extrinsic, self.state.as_externalities()
+		) {
+			Ok(outcome) => {
+				self.state.commit_transaction();
+				self.extrinsics += 1;
+				self.weight = self.weight.saturating_add(outcome.weight);
+				self.refunded = self.refunded.saturating_add(estimated.saturating_sub(outcome.weight));
+				Ok(outcome)
+			},
+			Err(err) => {
+				self.state.rollback_transaction();
+				Err(err)
+			},
+		}
+	}
+
+	/// Total weight consumed by the extrinsics applied so far.
+	pub fn weight(&self) -> u64 {
+		self.weight
+	}
+
+	/// Total weight refunded so far, estimated for the applied extrinsics
+	/// but not consumed by them.
+	pub fn refunded_weight(&self) -> u64 {
+		self.refunded
+	}
+
+	/// Apply extrinsics in order, skipping any that would exceed the
+	/// block's extrinsic or weight limit, or that fail to apply. Returns the
+	/// included extrinsics, in the order they were applied, which is their
+	/// order in the block.
+	pub fn push_many<I>(&mut self, extrinsics: I) -> Vec<E::Extrinsic> where
+		I: IntoIterator<Item=E::Extrinsic>,
+		E::Extrinsic: Clone,
+	{
+		let mut included = Vec::new();
+
+		for extrinsic in extrinsics {
+			let fits = self.extrinsics < self.executor.max_extrinsics() &&
+				self.weight.saturating_add(self.executor.weight(&extrinsic)) <= self.executor.max_weight();
+
+			if fits && self.push(extrinsic.clone()).is_ok() {
+				included.push(extrinsic);
+			}
+		}
+
+		included
+	}
+
+	/// Decode an extrinsic and apply it to the block.
+	pub fn push_encoded(
+		&mut self,
+		bytes: &[u8],
+	) -> Result<ExtrinsicOutcome<E::Event>, E::Error> where
+		E: DecodeExtrinsic,
+	{
+		let extrinsic = self.executor.decode_extrinsic(bytes)?;
+		self.push(extrinsic)
+	}
+
+	/// Finalize the block, returning it with the resulting state.
+	pub fn build(mut self) -> Result<(E::BuildBlock, S), E::Error> {
+		self.executor.finalize_block(&mut self.block, self.state.as_externalities())?;
+
+		Ok((self.block, self.state))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::runtime::{self, Extrinsic};
+	use crate::Block;
+	use crate::backend::ChainQuery;
+	use crate::import::import_block;
+
+	#[test]
+	fn failing_extrinsic_leaves_state_unchanged() {
+		let chain = runtime::chain();
+		let genesis = chain.head();
+		let state = chain.backend().state_at(&genesis.id()).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &genesis, state, ()).unwrap();
+
+		builder.push(Extrinsic::Add(2)).unwrap();
+		assert!(builder.push(Extrinsic::Fail).is_err());
+
+		let (block, mut state) = builder.build().unwrap();
+		assert_eq!(block.extrinsics, vec![Extrinsic::Add(2)]);
+		assert_eq!(runtime::read_counter(state.as_externalities()), 2);
+	}
+
+	#[test]
+	fn builds_block_from_extrinsics() {
+		let chain = runtime::chain();
+		let genesis = chain.head();
+		let state = chain.backend().state_at(&genesis.id()).unwrap();
+		let mut builder = BlockBuilder::new(chain.executor(), &genesis, state, ()).unwrap();
+
+		for value in 1..=3 {
+			builder.push(Extrinsic::Add(value)).unwrap();
+		}
+
+		let (block, mut state) = builder.build().unwrap();
+		assert_eq!(block.extrinsics, vec![Extrinsic::Add(1), Extrinsic::Add(2), Extrinsic::Add(3)]);
+		assert_eq!(runtime::read_counter(state.as_externalities()), 6);
+		let block = runtime::seal(block);
+		assert!(import_block(chain.backend(), chain.import_lock(), chain.executor(), block).is_ok());
+	}
+}
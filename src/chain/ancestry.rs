@@ -0,0 +1,380 @@
+use std::{fmt, error as stderror};
+use std::collections::HashSet;
+use core::cmp::Ordering;
+use crate::Block;
+use crate::backend::ChainQuery;
+
+/// Maximum number of blocks an ancestry walk visits.
+pub const MAX_ANCESTRY_DEPTH: usize = 1 << 24;
+
+#[derive(Debug)]
+/// Errors of walking the ancestry of a block.
+pub enum AncestryError<E> {
+	/// Ancestry revisits a block, so the backend is corrupted
+	CyclicChain,
+	/// Ancestry is longer than the maximum depth of the walk
+	TooDeep,
+	/// Backend error
+	Backend(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for AncestryError<E> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl<E: fmt::Debug> stderror::Error for AncestryError<E> { }
+
+/// Iterator over a block and its ancestors, from the block towards
+/// genesis. It ends after genesis, or after the first error. Visited ids
+/// are tracked, so that a backend whose ancestry loops fails with
+/// `CyclicChain` instead of walking forever.
+pub struct Ancestors<'a, Ba: ChainQuery> {
+	backend: &'a Ba,
+	next: Option<<Ba::Block as Block>::Identifier>,
+	visited: HashSet<<Ba::Block as Block>::Identifier>,
+	max_depth: usize,
+}
+
+impl<'a, Ba: ChainQuery> Ancestors<'a, Ba> {
+	fn new(backend: &'a Ba, id: <Ba::Block as Block>::Identifier) -> Self {
+		Self { backend, next: Some(id), visited: HashSet::new(), max_depth: MAX_ANCESTRY_DEPTH }
+	}
+
+	/// Fail with `TooDeep` instead of visiting more than `max_depth`
+	/// blocks.
+	pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+		self.max_depth = max_depth;
+		self
+	}
+}
+
+impl<'a, Ba: ChainQuery> Iterator for Ancestors<'a, Ba> {
+	type Item = Result<Ba::Block, AncestryError<Ba::Error>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let id = self.next.take()?;
+
+		if self.visited.len() >= self.max_depth {
+			return Some(Err(AncestryError::TooDeep))
+		}
+		if !self.visited.insert(id.clone()) {
+			return Some(Err(AncestryError::CyclicChain))
+		}
+
+		match self.backend.block_at(&id) {
+			Ok(block) => {
+				self.next = block.parent_id();
+				Some(Ok(block))
+			},
+			Err(err) => Some(Err(AncestryError::Backend(err))),
+		}
+	}
+}
+
+/// Walk a block and its ancestors, from the block towards genesis.
+pub fn ancestors<'a, Ba: ChainQuery>(
+	backend: &'a Ba,
+	id: &<Ba::Block as Block>::Identifier,
+) -> Ancestors<'a, Ba> {
+	Ancestors::new(backend, id.clone())
+}
+
+/// Walk the canonical chain, from the head towards genesis.
+pub fn best_chain<Ba: ChainQuery>(backend: &Ba) -> Ancestors<'_, Ba> {
+	Ancestors::new(backend, backend.head())
+}
+
+/// Check whether `id` is `ancestor` itself or one of its descendants.
+pub fn is_descendant<Ba: ChainQuery>(
+	backend: &Ba,
+	id: &<Ba::Block as Block>::Identifier,
+	ancestor: &<Ba::Block as Block>::Identifier,
+) -> Result<bool, AncestryError<Ba::Error>> {
+	let ancestor_depth = backend.depth_at(ancestor).map_err(AncestryError::Backend)?;
+	let depth = backend.depth_at(id).map_err(AncestryError::Backend)?;
+	if depth < ancestor_depth {
+		return Ok(false)
+	}
+
+	let mut blocks = ancestors(backend, id);
+	for _ in ancestor_depth..depth {
+		blocks.next().expect("Block is above ancestor depth; it must have a parent; qed")?;
+	}
+
+	let block = blocks.next().expect("Iterator yields every block down to genesis; qed")?;
+	Ok(&block.id() == ancestor)
+}
+
+/// Check whether `id` is the finalized block or one of its descendants.
+/// The finalized block is always canonical, so a canonical block descends
+/// from it if it is at or above the finalized depth. The walk therefore
+/// stops at the first canonical ancestor, or once it reaches the finalized
+/// depth, instead of going down to the finalized block.
+pub fn descends_from_finalized<Ba: ChainQuery>(
+	backend: &Ba,
+	id: &<Ba::Block as Block>::Identifier,
+) -> Result<bool, AncestryError<Ba::Error>> {
+	let finalized_depth = backend.depth_at(&backend.finalized()).map_err(AncestryError::Backend)?;
+
+	let mut blocks = ancestors(backend, id);
+	let mut current = id.clone();
+	loop {
+		let depth = backend.depth_at(&current).map_err(AncestryError::Backend)?;
+		if backend.is_canon(&current).map_err(AncestryError::Backend)? {
+			return Ok(depth >= finalized_depth)
+		}
+		if depth <= finalized_depth {
+			return Ok(false)
+		}
+
+		let block = blocks.next().expect("Iterator yields every block down to genesis; qed")?;
+		current = block.parent_id()
+			.expect("Block is above finalized depth; it must have a parent; qed");
+	}
+}
+
+/// Get the lowest common ancestor of two blocks, which is one of them if
+/// it is an ancestor of the other. The higher block is walked back to the
+/// number of the lower one first, then both are walked back together until
+/// they meet. Returns `None` if they do not share a genesis.
+pub fn common_ancestor<Ba: ChainQuery>(
+	backend: &Ba,
+	a: &<Ba::Block as Block>::Identifier,
+	b: &<Ba::Block as Block>::Identifier,
+) -> Result<Option<<Ba::Block as Block>::Identifier>, AncestryError<Ba::Error>> {
+	let mut a_blocks = ancestors(backend, a);
+	let mut b_blocks = ancestors(backend, b);
+	let mut a = a_blocks.next().expect("Iterator yields at least the given block; qed")?;
+	let mut b = b_blocks.next().expect("Iterator yields at least the given block; qed")?;
+
+	loop {
+		let (step_a, step_b) = match a.number().cmp(&b.number()) {
+			Ordering::Greater => (true, false),
+			Ordering::Less => (false, true),
+			Ordering::Equal if a.id() == b.id() => return Ok(Some(a.id())),
+			Ordering::Equal => (true, true),
+		};
+
+		if step_a {
+			a = match a_blocks.next() {
+				Some(block) => block?,
+				None => return Ok(None),
+			};
+		}
+		if step_b {
+			b = match b_blocks.next() {
+				Some(block) => block?,
+				None => return Ok(None),
+			};
+		}
+	}
+}
+
+/// Get all descendants of a block, excluding the block itself, in
+/// breadth-first order.
+pub fn descendants<Ba: ChainQuery>(
+	backend: &Ba,
+	id: &<Ba::Block as Block>::Identifier,
+) -> Result<Vec<<Ba::Block as Block>::Identifier>, Ba::Error> {
+	let mut descendants = backend.children_at(id)?;
+	let mut index = 0;
+
+	while index < descendants.len() {
+		let children = backend.children_at(&descendants[index])?;
+		descendants.extend(children);
+		index += 1;
+	}
+
+	Ok(descendants)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashMap;
+	use crate::import::{import_block, import_if_deeper};
+	use crate::backend::{Finalizable, Store, MemoryError};
+	use crate::testing::runtime::{chain, fork, Id, TestBlock};
+
+	#[test]
+	fn lists_descendants_breadth_first() {
+		let chain = chain();
+		let genesis = chain.head();
+		let long = fork(&chain, &genesis, 2, 1);
+		let short = fork(&chain, &genesis, 1, 2);
+		for block in long.iter().chain(short.iter()) {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		let side = fork(&chain, &long[0], 1, 3);
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), side[0].clone()).unwrap();
+
+		assert_eq!(
+			descendants(chain.backend(), &genesis.id()).unwrap(),
+			vec![long[0].id(), short[0].id(), long[1].id(), side[0].id()],
+		);
+		assert_eq!(descendants(chain.backend(), &long[0].id()).unwrap(), vec![long[1].id(), side[0].id()]);
+		assert!(descendants(chain.backend(), &long[1].id()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn walks_best_chain_from_head_to_genesis() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 5, 1);
+		for block in &blocks {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let walked = best_chain(chain.backend()).collect::<Result<Vec<_>, _>>().unwrap();
+		let expected = blocks.iter().rev().chain(Some(&genesis)).cloned().collect::<Vec<_>>();
+		assert_eq!(walked, expected);
+		assert_eq!(ancestors(chain.backend(), &blocks[1].id()).count(), 3);
+	}
+
+	#[test]
+	fn finds_branch_point_and_ancestor_as_common_ancestor() {
+		let chain = chain();
+		let genesis = chain.head();
+		let canonical = fork(&chain, &genesis, 3, 1);
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), canonical[0].clone()).unwrap();
+		let side = fork(&chain, &canonical[0], 2, 2);
+		for block in canonical[1..].iter().chain(side.iter()) {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let lca = |a: &TestBlock, b: &TestBlock| common_ancestor(chain.backend(), &a.id(), &b.id()).unwrap();
+		assert_eq!(lca(&canonical[2], &side[1]), Some(canonical[0].id()));
+		assert_eq!(lca(&side[0], &canonical[2]), Some(canonical[0].id()));
+		assert_eq!(lca(&canonical[2], &canonical[1]), Some(canonical[1].id()));
+		assert_eq!(lca(&genesis, &side[1]), Some(genesis.id()));
+	}
+
+	#[test]
+	fn checks_descent_from_finalized_up_to_first_canonical_ancestor() {
+		let chain = chain();
+		let genesis = chain.head();
+		let canonical = fork(&chain, &genesis, 4, 1);
+		for block in &canonical {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		let above = fork(&chain, &canonical[2], 3, 2);
+		let below = fork(&chain, &canonical[0], 4, 3);
+		for block in above.iter().chain(below.iter()) {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		chain.backend().clone().finalize(&canonical[1].id()).unwrap();
+
+		let descends = |block: &TestBlock| descends_from_finalized(chain.backend(), &block.id()).unwrap();
+		assert!(descends(&canonical[1]));
+		assert!(descends(&canonical[3]));
+		assert!(descends(&above[2]));
+		assert!(!descends(&canonical[0]));
+		assert!(!descends(&genesis));
+
+		// Blocks of the fork below the finalized block are rejected, even
+		// above the finalized depth.
+		assert!(!descends(&below[1]));
+		assert!(!descends(&below[3]));
+		for block in canonical.iter().chain(above.iter()).chain(below.iter()) {
+			assert_eq!(
+				descends(block),
+				is_descendant(chain.backend(), &block.id(), &canonical[1].id()).unwrap(),
+			);
+		}
+	}
+
+	/// Backend whose blocks `1`, `2` and `3` each claim the next one as
+	/// parent, with `3` pointing back to `1`. Block `0` stands alone at
+	/// depth zero.
+	struct CyclicBackend(HashMap<Id, (TestBlock, usize)>);
+
+	impl CyclicBackend {
+		fn new() -> Self {
+			let block = |parent: Option<u8>, number| TestBlock {
+				parent: parent.map(|parent| [parent; 8]),
+				number,
+				timestamp: number,
+				difficulty: 0,
+				state_root: [0; 8],
+				extrinsics_root: [0; 8],
+				extrinsics: Vec::new(),
+				nonce: 0,
+			};
+
+			Self(vec![
+				([0; 8], (block(None, 0), 0)),
+				([1; 8], (block(Some(2), 5), 5)),
+				([2; 8], (block(Some(3), 4), 4)),
+				([3; 8], (block(Some(1), 3), 3)),
+			].into_iter().collect())
+		}
+
+		fn get(&self, id: &Id) -> Result<&(TestBlock, usize), MemoryError> {
+			self.0.get(id).ok_or(MemoryError::NotExist)
+		}
+	}
+
+	impl Store for CyclicBackend {
+		type Block = TestBlock;
+		type State = ();
+		type Auxiliary = ();
+		type Error = MemoryError;
+	}
+
+	impl ChainQuery for CyclicBackend {
+		fn genesis(&self) -> Id { [0; 8] }
+		fn head(&self) -> Id { [1; 8] }
+		fn finalized(&self) -> Id { [0; 8] }
+
+		fn contains(&self, id: &Id) -> Result<bool, MemoryError> {
+			Ok(self.0.contains_key(id))
+		}
+
+		fn is_canon(&self, _: &Id) -> Result<bool, MemoryError> {
+			Ok(false)
+		}
+
+		fn lookup_canon_depth(&self, _: usize) -> Result<Option<Id>, MemoryError> {
+			Ok(None)
+		}
+
+		fn auxiliary(&self, _: &()) -> Result<Option<()>, MemoryError> {
+			Ok(None)
+		}
+
+		fn depth_at(&self, id: &Id) -> Result<usize, MemoryError> {
+			Ok(self.get(id)?.1)
+		}
+
+		fn children_at(&self, _: &Id) -> Result<Vec<Id>, MemoryError> {
+			Ok(Vec::new())
+		}
+
+		fn state_at(&self, id: &Id) -> Result<(), MemoryError> {
+			self.get(id).map(|_| ())
+		}
+
+		fn block_at(&self, id: &Id) -> Result<TestBlock, MemoryError> {
+			Ok(self.get(id)?.0.clone())
+		}
+	}
+
+	#[test]
+	fn detects_cyclic_ancestry() {
+		let backend = CyclicBackend::new();
+
+		let walked = ancestors(&backend, &[1; 8]).collect::<Vec<_>>();
+		assert_eq!(walked.len(), 4);
+		assert!(walked[..3].iter().all(|block| block.is_ok()));
+		assert!(matches!(walked[3], Err(AncestryError::CyclicChain)));
+
+		assert!(matches!(is_descendant(&backend, &[1; 8], &[0; 8]), Err(AncestryError::CyclicChain)));
+		assert!(matches!(descends_from_finalized(&backend, &[1; 8]), Err(AncestryError::CyclicChain)));
+		assert!(matches!(
+			ancestors(&backend, &[1; 8]).with_max_depth(2).nth(2),
+			Some(Err(AncestryError::TooDeep))
+		));
+	}
+}
@@ -0,0 +1,71 @@
+use std::convert::TryFrom;
+use crate::Block;
+use crate::backend::ChainQuery;
+
+/// Get the canonical block at the given number. The backend indexes the
+/// canonical chain by depth, which is remapped on every head change, so the
+/// result always follows the current head.
+pub fn block_at_number<Ba: ChainQuery>(
+	backend: &Ba,
+	number: u64,
+) -> Result<Option<<Ba::Block as Block>::Identifier>, Ba::Error> {
+	match usize::try_from(number) {
+		Ok(depth) => backend.lookup_canon_depth(depth),
+		Err(_) => Ok(None),
+	}
+}
+
+/// Get the canonical blocks numbered from `from` up to, but excluding, `to`.
+/// The range stops early at the current head.
+pub fn canonical_range<Ba: ChainQuery>(
+	backend: &Ba,
+	from: u64,
+	to: u64,
+) -> Result<Vec<<Ba::Block as Block>::Identifier>, Ba::Error> {
+	let mut range = Vec::new();
+
+	for number in from..to {
+		match block_at_number(backend, number)? {
+			Some(id) => range.push(id),
+			None => break,
+		}
+	}
+
+	Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::chain::apply_reorg;
+	use crate::import::{import_block, import_if_deeper};
+	use crate::testing::runtime::{chain, fork, Error as TestError};
+
+	#[test]
+	fn follows_canonical_chain_across_reorg() {
+		let chain = chain();
+		let genesis = chain.head();
+		let old = fork(&chain, &genesis, 2, 1);
+		let new = fork(&chain, &genesis, 3, 5);
+		for block in &old {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		for block in &new {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let backend = chain.backend();
+		assert_eq!(block_at_number(backend, 0).unwrap(), Some(genesis.id()));
+		assert_eq!(block_at_number(backend, 1).unwrap(), Some(old[0].id()));
+		assert_eq!(block_at_number(backend, 3).unwrap(), None);
+		assert_eq!(canonical_range(backend, 1, 5).unwrap(), vec![old[0].id(), old[1].id()]);
+
+		apply_reorg::<_, TestError>(backend, chain.import_lock(), &old[1].id(), &new[2].id(), None).unwrap();
+
+		assert_eq!(block_at_number(backend, 0).unwrap(), Some(genesis.id()));
+		assert_eq!(block_at_number(backend, 1).unwrap(), Some(new[0].id()));
+		assert_eq!(block_at_number(backend, 3).unwrap(), Some(new[2].id()));
+		assert_eq!(block_at_number(backend, u64::MAX).unwrap(), None);
+		assert_eq!(canonical_range(backend, 1, 5).unwrap(), new.iter().map(|block| block.id()).collect::<Vec<_>>());
+	}
+}
@@ -0,0 +1,145 @@
+use crate::Block;
+use crate::backend::ChainQuery;
+
+/// Rule choosing the best block of a backend among candidate blocks, such
+/// as its leaves.
+pub trait ForkChoice<Ba: ChainQuery> {
+	/// Choose the best of the candidates, which are never empty.
+	fn choose_best(
+		&self,
+		backend: &Ba,
+		candidates: &[<Ba::Block as Block>::Identifier],
+	) -> Result<<Ba::Block as Block>::Identifier, Ba::Error>;
+}
+
+/// Longest chain fork choice. The candidate with the highest block number
+/// is the best, and ties are broken by the smallest block id.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LongestChain;
+
+impl<Ba: ChainQuery> ForkChoice<Ba> for LongestChain where
+	<Ba::Block as Block>::Identifier: Ord,
+{
+	fn choose_best(
+		&self,
+		backend: &Ba,
+		candidates: &[<Ba::Block as Block>::Identifier],
+	) -> Result<<Ba::Block as Block>::Identifier, Ba::Error> {
+		let mut best: Option<(u64, &<Ba::Block as Block>::Identifier)> = None;
+
+		for id in candidates {
+			let number = backend.block_at(id)?.number();
+			let is_better = match &best {
+				Some((best_number, best_id)) =>
+					number > *best_number || (number == *best_number && id < *best_id),
+				None => true,
+			};
+
+			if is_better {
+				best = Some((number, id));
+			}
+		}
+
+		Ok(best.expect("Candidates are never empty; qed").1.clone())
+	}
+}
+
+/// Get the leaves descending from the finalized block, which are the
+/// candidates of fork choice.
+pub fn leaves<Ba: ChainQuery>(
+	backend: &Ba,
+) -> Result<Vec<<Ba::Block as Block>::Identifier>, Ba::Error> {
+	let mut leaves = Vec::new();
+	let mut pending = vec![backend.finalized()];
+
+	while let Some(id) = pending.pop() {
+		let children = backend.children_at(&id)?;
+
+		if children.is_empty() {
+			leaves.push(id);
+		}
+
+		pending.extend(children);
+	}
+
+	Ok(leaves)
+}
+
+/// Get the best leaf descending from the finalized block, according to the
+/// fork choice rule.
+pub fn best_block_with<Ba, F>(
+	backend: &Ba,
+	fork_choice: &F,
+) -> Result<<Ba::Block as Block>::Identifier, Ba::Error> where
+	Ba: ChainQuery,
+	F: ForkChoice<Ba> + ?Sized,
+{
+	let leaves = leaves(backend)?;
+	fork_choice.choose_best(backend, &leaves)
+}
+
+/// Longest chain fork choice. Return the leaf descending from the
+/// finalized block with the highest block number, and break ties by the
+/// smallest block id.
+pub fn best_block<Ba: ChainQuery>(
+	backend: &Ba,
+) -> Result<<Ba::Block as Block>::Identifier, Ba::Error> where
+	<Ba::Block as Block>::Identifier: Ord,
+{
+	best_block_with(backend, &LongestChain)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::consensus::pow::{self, MostWork};
+	use crate::import::import_block;
+	use crate::testing::runtime::{chain, fork, Chain, TestBlock};
+
+	fn import(chain: &Chain, blocks: &[TestBlock]) {
+		for block in blocks {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+	}
+
+	#[test]
+	fn deeper_leaf_is_best() {
+		let chain = chain();
+		let genesis = chain.head();
+		let shorter = fork(&chain, &genesis, 2, 1);
+		let longer = fork(&chain, &genesis, 3, 2);
+		import(&chain, &longer);
+		import(&chain, &shorter);
+
+		assert_eq!(leaves(chain.backend()).unwrap().len(), 2);
+		assert_eq!(best_block(chain.backend()).unwrap(), longer[2].id());
+	}
+
+	#[test]
+	fn tie_is_broken_by_smallest_id() {
+		let chain = chain();
+		let genesis = chain.head();
+		let first = fork(&chain, &genesis, 2, 1);
+		let second = fork(&chain, &genesis, 2, 2);
+		import(&chain, &first);
+		import(&chain, &second);
+
+		let smallest = core::cmp::min(first[1].id(), second[1].id());
+		assert_eq!(best_block(chain.backend()).unwrap(), smallest);
+	}
+
+	#[test]
+	fn longest_chain_and_most_work_disagree() {
+		let chain = chain();
+		let genesis = chain.head();
+		let long = fork(&chain, &genesis, 3, 1);
+		let mut heavy = fork(&chain, &genesis, 1, 5).remove(0);
+		heavy.difficulty = 8;
+		let heavy = pow::seal(heavy, 8);
+		import(&chain, &long);
+		import(&chain, core::slice::from_ref(&heavy));
+
+		assert_eq!(best_block_with(chain.backend(), &LongestChain).unwrap(), long[2].id());
+		assert_eq!(best_block_with(chain.backend(), &MostWork::default()).unwrap(), heavy.id());
+	}
+}
@@ -0,0 +1,18 @@
+//! Chain selection on top of a backend.
+
+mod fork_choice;
+mod ancestry;
+mod canon;
+mod reorg;
+mod replay;
+mod select;
+
+pub use self::fork_choice::{ForkChoice, LongestChain, best_block, best_block_with, leaves};
+pub use self::ancestry::{
+	Ancestors, AncestryError, ancestors, best_chain, is_descendant, descends_from_finalized,
+	common_ancestor, descendants, MAX_ANCESTRY_DEPTH,
+};
+pub use self::canon::{block_at_number, canonical_range};
+pub use self::reorg::{apply_reorg, Reorg};
+pub use self::replay::replay_from;
+pub use self::select::{verify_and_import, verify_and_import_with, ImportOutcome};
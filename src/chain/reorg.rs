@@ -0,0 +1,149 @@
+use log::*;
+use crate::Block;
+use crate::backend::{Store, ChainQuery, SharedCommittable, Operation, ImportLock, tree_route};
+use crate::import::{ImportAction, ImportError};
+use crate::format::format_id_short;
+
+/// Blocks switched by a reorg.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reorg<Id> {
+	/// Blocks leaving the canonical chain, from the old best block towards
+	/// the common ancestor.
+	pub retracted: Vec<Id>,
+	/// Blocks joining the canonical chain, from the common ancestor towards
+	/// the new best block.
+	pub enacted: Vec<Id>,
+}
+
+type ReorgResult<Ba, EE> = Result<
+	Reorg<<<Ba as Store>::Block as Block>::Identifier>,
+	ImportError<<Ba as Store>::Error, EE>,
+>;
+
+/// Switch head from `old_best` to `new_best`. The blocks of the new branch
+/// were executed when imported, so the state of each of them is already
+/// stored and only head moves. Head must still be `old_best`, or the reorg
+/// fails with `StaleTip`. Retracting the finalized block is rejected, and so
+/// is a common ancestor more than `max_reorg_depth` blocks below `old_best`.
+pub fn apply_reorg<Ba, EE>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	old_best: &<Ba::Block as Block>::Identifier,
+	new_best: &<Ba::Block as Block>::Identifier,
+	max_reorg_depth: Option<usize>,
+) -> ReorgResult<Ba, EE> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let action = ImportAction::new(backend, import_lock.lock());
+	if backend.head() != *old_best {
+		return Err(ImportError::StaleTip)
+	}
+
+	commit_reorg(action, old_best, new_best, max_reorg_depth)
+}
+
+/// Same as `apply_reorg`, under the import lock held by the action, once
+/// head is known to still be `old_best`.
+pub(crate) fn commit_reorg<Ba, EE>(
+	mut action: ImportAction<Ba>,
+	old_best: &<Ba::Block as Block>::Identifier,
+	new_best: &<Ba::Block as Block>::Identifier,
+	max_reorg_depth: Option<usize>,
+) -> ReorgResult<Ba, EE> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let backend = action.backend();
+
+	let route = tree_route(backend, old_best, new_best).map_err(ImportError::Backend)?;
+	let common_depth = backend.depth_at(route.common_block()).map_err(ImportError::Backend)?;
+	let finalized_depth = backend.depth_at(&backend.finalized()).map_err(ImportError::Backend)?;
+	if !route.retracted().is_empty() && common_depth < finalized_depth {
+		return Err(ImportError::BelowFinalized)
+	}
+	if let Some(max_reorg_depth) = max_reorg_depth {
+		let old_depth = backend.depth_at(old_best).map_err(ImportError::Backend)?;
+		if old_depth.saturating_sub(common_depth) > max_reorg_depth {
+			return Err(ImportError::ReorgTooDeep)
+		}
+	}
+
+	action.set_head(new_best.clone());
+	action.commit().map_err(ImportError::Backend)?;
+	info!(
+		"Reorg from {} to {}, retracting {} and enacting {} blocks",
+		format_id_short(old_best), format_id_short(new_best), route.retracted().len(), route.enacted().len(),
+	);
+
+	Ok(Reorg {
+		retracted: route.retracted().to_vec(),
+		enacted: route.enacted().to_vec(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::import::{import_block, import_if_deeper};
+	use crate::testing::runtime::{chain, fork, read_counter, Chain, TestBlock, Error as TestError};
+
+	/// Chain with head on a branch of two blocks adding 1, and an imported
+	/// competing branch of three blocks adding 5.
+	fn competing_branches() -> (Chain, Vec<TestBlock>, Vec<TestBlock>) {
+		let chain = chain();
+		let genesis = chain.head();
+		let old = fork(&chain, &genesis, 2, 1);
+		let new = fork(&chain, &genesis, 3, 5);
+		for block in &old {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		for block in &new {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		assert_eq!(chain.head(), old[1]);
+
+		(chain, old, new)
+	}
+
+	#[test]
+	fn switches_to_new_branch() {
+		let (chain, old, new) = competing_branches();
+
+		let reorg = apply_reorg::<_, TestError>(
+			chain.backend(), chain.import_lock(), &old[1].id(), &new[2].id(), None,
+		).unwrap();
+
+		assert_eq!(reorg.retracted, vec![old[1].id(), old[0].id()]);
+		assert_eq!(reorg.enacted, new.iter().map(|block| block.id()).collect::<Vec<_>>());
+		assert_eq!(chain.head(), new[2]);
+		assert_eq!(read_counter(&chain.backend().state_at(&new[2].id()).unwrap()), 15);
+	}
+
+	#[test]
+	fn refuses_reorg_deeper_than_limit() {
+		let (chain, old, new) = competing_branches();
+
+		let result = apply_reorg::<_, TestError>(
+			chain.backend(), chain.import_lock(), &old[1].id(), &new[2].id(), Some(1),
+		);
+		assert!(matches!(result, Err(ImportError::ReorgTooDeep)));
+		assert_eq!(chain.head(), old[1]);
+
+		apply_reorg::<_, TestError>(
+			chain.backend(), chain.import_lock(), &old[1].id(), &new[2].id(), Some(2),
+		).unwrap();
+		assert_eq!(chain.head(), new[2]);
+	}
+
+	#[test]
+	fn refuses_reorg_from_stale_head() {
+		let (chain, old, new) = competing_branches();
+
+		let result = apply_reorg::<_, TestError>(
+			chain.backend(), chain.import_lock(), &old[0].id(), &new[2].id(), None,
+		);
+		assert!(matches!(result, Err(ImportError::StaleTip)));
+		assert_eq!(chain.head(), old[1]);
+	}
+}
@@ -0,0 +1,72 @@
+use crate::{Block, BlockContext, BlockExecutor, AsExternalities};
+use crate::backend::ChainQuery;
+use crate::import::ImportError;
+
+/// Bring a state left at `last_committed` up to date with head, for example
+/// after an unclean shutdown. The canonical blocks following
+/// `last_committed` are executed again, in order, on top of `state`.
+/// Returns the number of replayed blocks. A `last_committed` that is no
+/// longer canonical is rejected with `NotCanonical`.
+pub fn replay_from<Ba, E, S>(
+	backend: &Ba,
+	executor: &E,
+	last_committed: &<Ba::Block as Block>::Identifier,
+	state: &mut S,
+) -> Result<usize, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery,
+	E: BlockExecutor<Block=Ba::Block>,
+	S: AsExternalities<E::Externalities> + ?Sized,
+{
+	if !backend.is_canon(last_committed).map_err(ImportError::Backend)? {
+		return Err(ImportError::NotCanonical)
+	}
+
+	let mut parent = backend.block_at(last_committed).map_err(ImportError::Backend)?;
+	let mut depth = backend.depth_at(last_committed).map_err(ImportError::Backend)?;
+	let mut replayed = 0;
+
+	while let Some(id) = backend.lookup_canon_depth(depth + 1).map_err(ImportError::Backend)? {
+		let block = backend.block_at(&id).map_err(ImportError::Backend)?;
+		let context = BlockContext { parent_id: parent.id(), parent_number: parent.number() };
+		executor.execute_block_with_context(&block, &context, state.as_externalities())
+			.map_err(ImportError::Execution)?;
+
+		parent = block;
+		depth += 1;
+		replayed += 1;
+	}
+
+	Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::import::{import_block, import_if_deeper};
+	use crate::testing::runtime::{chain, fork, read_counter};
+
+	#[test]
+	fn restores_counter_after_crash() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 4, 3);
+		for block in &blocks {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		// The node crashed with its state only committed up to the first
+		// block.
+		let mut state = chain.backend().state_at(&blocks[0].id()).unwrap();
+		assert_eq!(read_counter(&state), 3);
+
+		assert_eq!(replay_from(chain.backend(), chain.executor(), &blocks[0].id(), &mut state).unwrap(), 3);
+		assert_eq!(read_counter(&state), 12);
+
+		let side = fork(&chain, &genesis, 1, 1).remove(0);
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), side.clone()).unwrap();
+		assert!(matches!(
+			replay_from(chain.backend(), chain.executor(), &side.id(), &mut state),
+			Err(ImportError::NotCanonical)
+		));
+	}
+}
@@ -0,0 +1,149 @@
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, ChainQuery, SharedCommittable, Operation, ImportLock};
+use crate::import::{import_block, ImportAction, ImportError};
+use crate::chain::{ForkChoice, LongestChain, best_block_with};
+use crate::chain::reorg::commit_reorg;
+
+/// Outcome of importing a block with `verify_and_import`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportOutcome<Id> {
+	/// Id of the imported block.
+	pub imported_id: Id,
+	/// Whether the imported block became the best block.
+	pub new_best: bool,
+	/// Blocks that left the canonical chain, from the old best block
+	/// towards the common ancestor.
+	pub retracted: Vec<Id>,
+	/// Blocks that joined the canonical chain, from the common ancestor
+	/// towards the new best block.
+	pub enacted: Vec<Id>,
+}
+
+type OutcomeResult<Ba, E> = Result<
+	ImportOutcome<<<Ba as Store>::Block as Block>::Identifier>,
+	ImportError<<Ba as Store>::Error, <E as BlockExecutor>::Error>,
+>;
+
+/// Import a block, then run the longest chain fork choice and move head to
+/// its best block. A best block extending the current head is enacted
+/// directly, and any other one as through `apply_reorg`, limited to
+/// `max_reorg_depth`. Choosing the best block and moving head happen under
+/// the same import lock, so concurrent imports cannot move head to a
+/// worse block.
+pub fn verify_and_import<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	block: Ba::Block,
+	max_reorg_depth: Option<usize>,
+) -> OutcomeResult<Ba, E> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	<Ba::Block as Block>::Identifier: Ord,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	verify_and_import_with(backend, import_lock, executor, &LongestChain, block, max_reorg_depth)
+}
+
+/// Same as `verify_and_import`, choosing the best block with the given fork
+/// choice rule instead of the longest chain.
+pub fn verify_and_import_with<Ba, E, F>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	fork_choice: &F,
+	block: Ba::Block,
+	max_reorg_depth: Option<usize>,
+) -> OutcomeResult<Ba, E> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	F: ForkChoice<Ba> + ?Sized,
+{
+	let imported_id = block.id();
+	let parent_id = block.parent_id();
+	import_block(backend, import_lock, executor, block)?;
+
+	let mut action = ImportAction::new(backend, import_lock.lock());
+	let old_best = backend.head();
+	let best = best_block_with(backend, fork_choice).map_err(ImportError::Backend)?;
+	if best == old_best {
+		return Ok(ImportOutcome { imported_id, new_best: false, retracted: Vec::new(), enacted: Vec::new() })
+	}
+
+	let (retracted, enacted) = if best == imported_id && parent_id.as_ref() == Some(&old_best) {
+		action.set_head(best.clone());
+		action.commit().map_err(ImportError::Backend)?;
+
+		(Vec::new(), vec![best.clone()])
+	} else {
+		let reorg = commit_reorg(action, &old_best, &best, max_reorg_depth)?;
+		(reorg.retracted, reorg.enacted)
+	};
+
+	Ok(ImportOutcome { new_best: best == imported_id, imported_id, retracted, enacted })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Barrier;
+	use std::thread;
+	use crate::testing::runtime::{chain, fork};
+
+	#[test]
+	fn reports_reorg_enacted_by_import() {
+		let chain = chain();
+		let genesis = chain.head();
+		let canonical = fork(&chain, &genesis, 2, 1);
+		for block in &canonical {
+			let outcome = verify_and_import(chain.backend(), chain.import_lock(), chain.executor(), block.clone(), None).unwrap();
+			assert_eq!(outcome, ImportOutcome {
+				imported_id: block.id(),
+				new_best: true,
+				retracted: Vec::new(),
+				enacted: vec![block.id()],
+			});
+		}
+
+		let side = fork(&chain, &genesis, 3, 2);
+		for block in &side[..2] {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		let outcome = verify_and_import(chain.backend(), chain.import_lock(), chain.executor(), side[2].clone(), None).unwrap();
+
+		assert_eq!(outcome, ImportOutcome {
+			imported_id: side[2].id(),
+			new_best: true,
+			retracted: vec![canonical[1].id(), canonical[0].id()],
+			enacted: side.iter().map(|block| block.id()).collect(),
+		});
+		assert_eq!(chain.head(), side[2]);
+	}
+
+	#[test]
+	fn concurrent_imports_leave_head_on_best_block() {
+		let chain = chain();
+		let genesis = chain.head();
+		let branches = (1..=4).map(|len| fork(&chain, &genesis, len, len as u64)).collect::<Vec<_>>();
+		let barrier = Barrier::new(branches.len());
+
+		thread::scope(|scope| {
+			for branch in &branches {
+				let (chain, barrier) = (&chain, &barrier);
+				scope.spawn(move || {
+					barrier.wait();
+					for block in branch {
+						verify_and_import(
+							chain.backend(), chain.import_lock(), chain.executor(), block.clone(), None,
+						).unwrap();
+					}
+				});
+			}
+		});
+
+		assert_eq!(chain.head(), branches[3][3]);
+	}
+}
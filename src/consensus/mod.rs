@@ -0,0 +1,6 @@
+//! Consensus engines.
+
+pub mod pow;
+mod verify;
+
+pub use self::verify::{verify_chain, verify_chain_parallel, VerifyError};
@@ -0,0 +1,494 @@
+//! Proof of work consensus based on leading zero bits of block id.
+
+use core::cmp::{Ordering, Reverse};
+use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+use log::*;
+use crate::Block;
+use crate::backend::ChainQuery;
+use crate::chain::{ForkChoice, leaves};
+
+/// A block that can be sealed by proof of work. The block id must equal
+/// `seal_hash(&pre_hash(), nonce())`, so that sealing only needs to hash
+/// the pre-hash and nonce on each attempt.
+pub trait PowBlock: Block {
+	/// Get the nonce of the block.
+	fn nonce(&self) -> u64;
+	/// Set the nonce of the block.
+	fn set_nonce(&mut self, nonce: u64);
+	/// Difficulty claimed by the block, in leading zero bits of its id.
+	fn difficulty(&self) -> usize;
+	/// Timestamp claimed by the block, in unix seconds.
+	fn timestamp(&self) -> u64;
+	/// Hash of the block excluding the nonce.
+	fn pre_hash(&self) -> Self::Identifier;
+	/// Compute the block id from the pre-hash and a nonce.
+	fn seal_hash(pre_hash: &Self::Identifier, nonce: u64) -> Self::Identifier;
+}
+
+/// Check whether the hash has at least `difficulty` leading zero bits,
+/// counting from the most significant bit of the first byte. Difficulty 0
+/// means no proof of work, and accepts every hash, even an empty one.
+pub fn meets_difficulty(hash: &[u8], difficulty: usize) -> bool {
+	let (bytes, bits) = (difficulty / 8, difficulty % 8);
+
+	if hash.len() < bytes + (bits > 0) as usize {
+		return false
+	}
+
+	hash[..bytes].iter().all(|b| *b == 0) && (bits == 0 || hash[bytes] >> (8 - bits) == 0)
+}
+
+/// Grind the nonce of the block until its id meets the given difficulty.
+/// At difficulty 0 the block is returned at once, with its nonce unchanged.
+pub fn seal<B: PowBlock>(mut block: B, difficulty: usize) -> B where
+	B::Identifier: AsRef<[u8]>,
+{
+	loop {
+		match try_seal(block, difficulty, u64::MAX) {
+			Ok(sealed) => return sealed,
+			Err(unsealed) => block = unsealed,
+		}
+	}
+}
+
+/// Try at most `max_iterations` nonces, starting from the nonce of the
+/// block. On failure the block is returned with its nonce advanced past the
+/// tried ones, so that sealing can be resumed.
+pub fn try_seal<B: PowBlock>(mut block: B, difficulty: usize, max_iterations: u64) -> Result<B, B> where
+	B::Identifier: AsRef<[u8]>,
+{
+	let pre_hash = block.pre_hash();
+	let mut nonce = block.nonce();
+
+	for iteration in 0..max_iterations {
+		if meets_difficulty(B::seal_hash(&pre_hash, nonce).as_ref(), difficulty) {
+			debug!("Found seal with nonce {} after {} iterations", nonce, iteration + 1);
+			block.set_nonce(nonce);
+			return Ok(block)
+		}
+		nonce = nonce.wrapping_add(1);
+	}
+
+	block.set_nonce(nonce);
+	Err(block)
+}
+
+/// Grind the nonce of the block until its id meets the given difficulty, or
+/// until `cancel` is set, which is checked before each attempt. A cancelled
+/// block is returned with its nonce advanced past the tried ones.
+pub fn seal_cancellable<B: PowBlock>(mut block: B, difficulty: usize, cancel: &AtomicBool) -> Result<B, B> where
+	B::Identifier: AsRef<[u8]>,
+{
+	let pre_hash = block.pre_hash();
+	let mut nonce = block.nonce();
+	let mut iterations = 0u64;
+
+	while !cancel.load(AtomicOrdering::Relaxed) {
+		iterations += 1;
+		if meets_difficulty(B::seal_hash(&pre_hash, nonce).as_ref(), difficulty) {
+			debug!("Found seal with nonce {} after {} iterations", nonce, iterations);
+			block.set_nonce(nonce);
+			return Ok(block)
+		}
+		nonce = nonce.wrapping_add(1);
+	}
+
+	block.set_nonce(nonce);
+	Err(block)
+}
+
+/// Grind the nonce on all available cores. Each worker scans a disjoint
+/// set of nonces, and the first one finding a valid seal stops the others.
+/// The returned nonce is valid, but not necessarily the lowest one. At
+/// difficulty 0 no worker is spawned, as with `seal`.
+pub fn seal_parallel<B>(mut block: B, difficulty: usize) -> B where
+	B: PowBlock + 'static,
+	B::Identifier: AsRef<[u8]> + Send,
+{
+	let workers = thread::available_parallelism()
+		.map(|n| n.get())
+		.unwrap_or(1);
+
+	if workers <= 1 || difficulty == 0 {
+		return seal(block, difficulty)
+	}
+
+	let pre_hash = block.pre_hash();
+	let found = Arc::new(AtomicBool::new(false));
+	let handles = (0..workers).map(|index| {
+		let pre_hash = pre_hash.clone();
+		let found = found.clone();
+
+		thread::spawn(move || {
+			let mut nonce = index as u64;
+
+			while !found.load(AtomicOrdering::Relaxed) {
+				if meets_difficulty(B::seal_hash(&pre_hash, nonce).as_ref(), difficulty) {
+					found.store(true, AtomicOrdering::Relaxed);
+					return Some(nonce)
+				}
+				nonce = nonce.wrapping_add(workers as u64);
+			}
+
+			None
+		})
+	}).collect::<Vec<_>>();
+
+	// Every worker is joined before returning, so that none keeps hashing
+	// once the block is sealed.
+	let nonces = handles.into_iter()
+		.map(|handle| handle.join().expect("Sealing worker panicked"))
+		.collect::<Vec<_>>();
+	let nonce = nonces.into_iter()
+		.flatten()
+		.next()
+		.expect("Workers only stop after one of them found a seal; qed");
+
+	debug!("Found seal with nonce {} using {} workers", nonce, workers);
+	block.set_nonce(nonce);
+	block
+}
+
+/// Calculate the difficulty of a child block from its parent. Difficulty
+/// is raised by one when the block interval is shorter than the target
+/// interval, and lowered by one when it is longer.
+pub fn next_difficulty(
+	parent_timestamp: u64,
+	timestamp: u64,
+	parent_difficulty: usize,
+	target_interval: u64,
+) -> usize {
+	let interval = timestamp.saturating_sub(parent_timestamp);
+
+	match interval.cmp(&target_interval) {
+		Ordering::Less => parent_difficulty.saturating_add(1),
+		Ordering::Greater => parent_difficulty.saturating_sub(1),
+		Ordering::Equal => parent_difficulty,
+	}
+}
+
+/// Expected number of hashes to find a seal of the given difficulty, as
+/// `2^difficulty`, saturating at `u128::MAX`.
+pub fn work(difficulty: usize) -> u128 {
+	if difficulty >= 128 {
+		u128::MAX
+	} else {
+		1 << difficulty
+	}
+}
+
+/// Expected time to find a seal of the given difficulty, grinding at
+/// `hash_rate` hashes per second.
+pub fn expected_seal_time(difficulty: usize, hash_rate: u64) -> Duration {
+	let nanos = work(difficulty).saturating_mul(1_000_000_000) / u128::from(hash_rate.max(1));
+	u64::try_from(nanos).map(Duration::from_nanos).unwrap_or(Duration::MAX)
+}
+
+/// Cache of the total work of blocks, so that ancestors are only walked
+/// once.
+pub struct WorkCache<Id> {
+	total_works: HashMap<Id, u128>,
+}
+
+impl<Id> Default for WorkCache<Id> {
+	fn default() -> Self {
+		Self { total_works: HashMap::new() }
+	}
+}
+
+impl<Id: Clone + Eq + core::hash::Hash> WorkCache<Id> {
+	/// Create an empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Total work of the block and all its ancestors, excluding genesis.
+	pub fn total_work<Ba>(&mut self, backend: &Ba, id: &Id) -> Result<u128, Ba::Error> where
+		Ba: ChainQuery,
+		Ba::Block: PowBlock + Block<Identifier=Id>,
+	{
+		let mut uncached = Vec::new();
+		let mut current = backend.block_at(id)?;
+
+		let mut total_work = loop {
+			if let Some(total_work) = self.total_works.get(&current.id()) {
+				break *total_work
+			}
+
+			match current.parent_id() {
+				Some(parent_id) => {
+					let parent = backend.block_at(&parent_id)?;
+					uncached.push(current);
+					current = parent;
+				},
+				None => {
+					self.total_works.insert(current.id(), 0);
+					break 0
+				},
+			}
+		};
+
+		for block in uncached.into_iter().rev() {
+			total_work = total_work.saturating_add(work(block.difficulty()));
+			self.total_works.insert(block.id(), total_work);
+		}
+
+		Ok(total_work)
+	}
+
+	/// Heaviest chain fork choice. Return the leaf descending from the
+	/// finalized block with the highest total work. Leaves of equal work
+	/// are ordered by the earliest timestamp, as a proxy for the one seen
+	/// first, and only then by the smallest block id.
+	pub fn best_block<Ba>(&mut self, backend: &Ba) -> Result<Id, Ba::Error> where
+		Ba: ChainQuery,
+		Ba::Block: PowBlock + Block<Identifier=Id>,
+		Id: Ord,
+	{
+		let leaves = leaves(backend)?;
+		self.choose_best(backend, &leaves)
+	}
+
+	/// Choose the candidate with the highest total work, ordered as in
+	/// `best_block`. Candidates must not be empty.
+	pub fn choose_best<Ba>(&mut self, backend: &Ba, candidates: &[Id]) -> Result<Id, Ba::Error> where
+		Ba: ChainQuery,
+		Ba::Block: PowBlock + Block<Identifier=Id>,
+		Id: Ord,
+	{
+		let mut best: Option<(u128, Reverse<u64>, Reverse<Id>)> = None;
+
+		for id in candidates {
+			let total_work = self.total_work(backend, id)?;
+			let timestamp = backend.block_at(id)?.timestamp();
+			let candidate = (total_work, Reverse(timestamp), Reverse(id.clone()));
+
+			let is_better = match &best {
+				Some(best) => candidate > *best,
+				None => true,
+			};
+
+			if is_better {
+				best = Some(candidate);
+			}
+		}
+
+		Ok((best.expect("Candidates are never empty; qed").2).0)
+	}
+}
+
+/// Heaviest chain fork choice rule, as `WorkCache::best_block`. Total works
+/// are cached across choices.
+pub struct MostWork<Id> {
+	cache: Mutex<WorkCache<Id>>,
+}
+
+impl<Id> Default for MostWork<Id> {
+	fn default() -> Self {
+		Self { cache: Mutex::new(WorkCache::default()) }
+	}
+}
+
+impl<Id> MostWork<Id> {
+	/// Create the rule with an empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl<Ba> ForkChoice<Ba> for MostWork<<Ba::Block as Block>::Identifier> where
+	Ba: ChainQuery,
+	Ba::Block: PowBlock,
+	<Ba::Block as Block>::Identifier: Ord + core::hash::Hash,
+{
+	fn choose_best(
+		&self,
+		backend: &Ba,
+		candidates: &[<Ba::Block as Block>::Identifier],
+	) -> Result<<Ba::Block as Block>::Identifier, Ba::Error> {
+		self.cache.lock().expect("Lock is poisoned").choose_best(backend, candidates)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::BlockExecutor;
+	use crate::import::import_block;
+	use crate::testing::runtime::{chain, fork, Chain, TestBlock};
+
+	#[test]
+	fn seal_parallel_meets_difficulty() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut block = fork(&chain, &genesis, 1, 1).remove(0);
+		block.difficulty = 12;
+
+		let sealed = seal_parallel(block.clone(), 12);
+		assert!(meets_difficulty(&sealed.id(), 12));
+		assert_eq!(sealed.pre_hash(), block.pre_hash());
+	}
+
+	#[test]
+	fn seal_meets_difficulty() {
+		let chain = chain();
+		let genesis = chain.head();
+		let block = fork(&chain, &genesis, 1, 1).remove(0);
+
+		for &(difficulty, max_first_byte) in &[(1, 0x7f), (3, 0x1f), (8, 0)] {
+			let sealed = seal(block.clone(), difficulty);
+			assert!(meets_difficulty(&sealed.id(), difficulty));
+			assert!(sealed.id()[0] <= max_first_byte);
+			assert_eq!(sealed.pre_hash(), block.pre_hash());
+		}
+	}
+
+	#[test]
+	fn next_difficulty_tracks_block_interval() {
+		assert_eq!(next_difficulty(100, 105, 4, 10), 5);
+		assert_eq!(next_difficulty(100, 120, 4, 10), 3);
+		assert_eq!(next_difficulty(100, 110, 4, 10), 4);
+		assert_eq!(next_difficulty(100, 120, 0, 10), 0);
+	}
+
+	#[test]
+	fn heavier_short_chain_beats_longer_light_one() {
+		let chain = chain();
+		let genesis = chain.head();
+		let light = fork(&chain, &genesis, 3, 1);
+		let mut heavy = fork(&chain, &genesis, 1, 5).remove(0);
+		heavy.difficulty = 8;
+		let heavy = seal(heavy, 8);
+		for block in light.iter().chain(Some(&heavy)) {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let mut cache = WorkCache::new();
+		assert_eq!(cache.total_work(chain.backend(), &light[2].id()).unwrap(), 3);
+		assert_eq!(cache.total_work(chain.backend(), &heavy.id()).unwrap(), 256);
+		assert_eq!(cache.best_block(chain.backend()).unwrap(), heavy.id());
+	}
+
+	#[test]
+	fn try_seal_resumes_within_budget() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut block = fork(&chain, &genesis, 1, 1).remove(0);
+		block.difficulty = 16;
+
+		let unsealed = try_seal(block.clone(), 16, 1).unwrap_err();
+		assert_eq!(unsealed.nonce(), block.nonce() + 1);
+
+		let mut pending = unsealed;
+		let sealed = loop {
+			match try_seal(pending, 16, 1 << 12) {
+				Ok(sealed) => break sealed,
+				Err(unsealed) => pending = unsealed,
+			}
+		};
+		assert!(meets_difficulty(&sealed.id(), 16));
+		assert_eq!(sealed, seal(block, 16));
+	}
+
+	#[test]
+	fn cancelling_aborts_grinding() {
+		let chain = chain();
+		let genesis = chain.head();
+		let block = fork(&chain, &genesis, 1, 1).remove(0);
+		let cancel = AtomicBool::new(false);
+
+		let started = std::time::Instant::now();
+		let unsealed = thread::scope(|scope| {
+			scope.spawn(|| {
+				thread::sleep(Duration::from_millis(20));
+				cancel.store(true, AtomicOrdering::Relaxed);
+			});
+			seal_cancellable(block.clone(), 256, &cancel).unwrap_err()
+		});
+
+		assert!(started.elapsed() < Duration::from_secs(5));
+		assert!(unsealed.nonce() > block.nonce());
+		assert_eq!(unsealed.pre_hash(), block.pre_hash());
+	}
+
+	/// Single block fork on top of `parent`, with the given timestamp.
+	fn leaf(chain: &Chain, parent: &TestBlock, value: u64, timestamp: u64) -> TestBlock {
+		let mut block = fork(chain, parent, 1, value).remove(0);
+		block.timestamp = timestamp;
+		let difficulty = block.difficulty;
+		seal(block, difficulty)
+	}
+
+	#[test]
+	fn equal_work_prefers_earliest_timestamp_over_smallest_id() {
+		let chain = chain();
+		let genesis = chain.head();
+		let early = leaf(&chain, &genesis, 1, 1);
+		let late = (2..).map(|value| leaf(&chain, &genesis, value, 2))
+			.find(|late| late.id() < early.id())
+			.expect("Half of the ids are smaller; qed");
+		for block in [&early, &late] {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let mut cache = WorkCache::new();
+		assert_eq!(cache.total_work(chain.backend(), &early.id()).unwrap(), cache.total_work(chain.backend(), &late.id()).unwrap());
+		assert_eq!(cache.best_block(chain.backend()).unwrap(), early.id());
+	}
+
+	#[test]
+	fn equal_work_and_timestamp_prefers_smallest_id() {
+		let chain = chain();
+		let genesis = chain.head();
+		let first = leaf(&chain, &genesis, 1, 1);
+		let second = leaf(&chain, &genesis, 2, 1);
+		for block in [&first, &second] {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let mut cache = WorkCache::new();
+		assert_eq!(cache.best_block(chain.backend()).unwrap(), core::cmp::min(first.id(), second.id()));
+	}
+
+	#[test]
+	fn checks_difficulty_at_bit_boundaries() {
+		let cases: [(&[u8], usize, bool); 12] = [
+			(&[0x01, 0xff], 7, true),
+			(&[0x02, 0xff], 7, false),
+			(&[0x00, 0xff], 8, true),
+			(&[0x01, 0xff], 8, false),
+			(&[0x00, 0x7f], 9, true),
+			(&[0x00, 0x80], 9, false),
+			(&[0x01, 0x00], 9, false),
+			(&[0x00], 8, true),
+			(&[0x00], 9, false),
+			(&[0x00, 0x00], 16, true),
+			(&[], 0, true),
+			(&[], 1, false),
+		];
+
+		for &(hash, difficulty, meets) in &cases {
+			assert_eq!(meets_difficulty(hash, difficulty), meets, "{:?} at {} bits", hash, difficulty);
+		}
+	}
+
+	#[test]
+	fn zero_difficulty_seals_without_grinding() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut block = fork(&chain, &genesis, 1, 1).remove(0);
+		block.difficulty = 0;
+		block.nonce = 0;
+
+		assert_eq!(seal(block.clone(), 0), block);
+		assert_eq!(seal_parallel(block.clone(), 0), block);
+		assert!(meets_difficulty(&block.id(), 0));
+		assert!(meets_difficulty(&[], 0));
+		assert!(chain.executor().verify_block(&block).is_ok());
+	}
+}
@@ -0,0 +1,119 @@
+use std::{fmt, error as stderror};
+use std::thread;
+use crate::{Block, BlockExecutor};
+
+#[derive(Debug)]
+/// Errors of chain verification.
+pub enum VerifyError<EE> {
+	/// Block does not refer to the previous block as its parent
+	BrokenLink,
+	/// Block number does not follow the previous block
+	NumberMismatch,
+	/// Block failed the executor's checks
+	Invalid(EE),
+}
+
+impl<EE: fmt::Debug> fmt::Display for VerifyError<EE> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl<EE: fmt::Debug> stderror::Error for VerifyError<EE> { }
+
+/// Check that blocks form a chain, each block referring to the previous one
+/// as its parent with the next number, and that each block passes
+/// `verify_block`. State is not touched. On failure, returns the index of
+/// the first invalid block.
+pub fn verify_chain<E: BlockExecutor>(
+	executor: &E,
+	blocks: &[E::Block],
+) -> Result<(), (usize, VerifyError<E::Error>)> {
+	for (index, block) in blocks.iter().enumerate() {
+		if index > 0 {
+			let previous = &blocks[index - 1];
+
+			if block.parent_id() != Some(previous.id()) {
+				return Err((index, VerifyError::BrokenLink))
+			}
+
+			if block.number() != previous.number() + 1 {
+				return Err((index, VerifyError::NumberMismatch))
+			}
+		}
+
+		executor.verify_block(block).map_err(|err| (index, VerifyError::Invalid(err)))?;
+	}
+
+	Ok(())
+}
+
+/// Same as `verify_chain`, running `verify_block` on `threads` workers, each
+/// verifying a contiguous chunk of the blocks. Links between blocks are
+/// checked on the calling thread.
+pub fn verify_chain_parallel<E>(
+	executor: &E,
+	blocks: &[E::Block],
+	threads: usize,
+) -> Result<(), (usize, VerifyError<E::Error>)> where
+	E: BlockExecutor + Sync,
+	E::Block: Sync,
+	E::Error: Send,
+{
+	let chunk_size = core::cmp::max(1, blocks.len().div_ceil(core::cmp::max(1, threads)));
+
+	let invalid = thread::scope(|scope| {
+		let handles = blocks.chunks(chunk_size).enumerate().map(|(chunk, blocks)| {
+			scope.spawn(move || {
+				blocks.iter().enumerate().find_map(|(index, block)| {
+					executor.verify_block(block).err()
+						.map(|err| (chunk * chunk_size + index, err))
+				})
+			})
+		}).collect::<Vec<_>>();
+
+		handles.into_iter()
+			.filter_map(|handle| handle.join().expect("Verification worker panicked"))
+			.next()
+	});
+
+	for (index, block) in blocks.iter().enumerate().skip(1) {
+		if invalid.as_ref().map(|(invalid, _)| *invalid <= index).unwrap_or(false) {
+			break
+		}
+
+		let previous = &blocks[index - 1];
+
+		if block.parent_id() != Some(previous.id()) {
+			return Err((index, VerifyError::BrokenLink))
+		}
+
+		if block.number() != previous.number() + 1 {
+			return Err((index, VerifyError::NumberMismatch))
+		}
+	}
+
+	match invalid {
+		Some((index, err)) => Err((index, VerifyError::Invalid(err))),
+		None => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::runtime::{chain, fork};
+
+	#[test]
+	fn finds_broken_parent_link() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut blocks = fork(&chain, &genesis, 5, 1);
+		assert!(verify_chain(chain.executor(), &blocks).is_ok());
+		assert!(verify_chain_parallel(chain.executor(), &blocks, 2).is_ok());
+
+		blocks[3] = fork(&chain, &genesis, 4, 2).remove(3);
+		assert!(matches!(verify_chain(chain.executor(), &blocks), Err((3, VerifyError::BrokenLink))));
+		assert!(matches!(verify_chain_parallel(chain.executor(), &blocks, 2), Err((3, VerifyError::BrokenLink))));
+	}
+}
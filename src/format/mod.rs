@@ -0,0 +1,124 @@
+//! Human readable rendering of block identifiers, and the hex codec used
+//! wherever bytes are shown as text.
+
+use std::{fmt, error as stderror};
+
+/// Number of leading and trailing bytes kept by `format_id_short`.
+const SHORT_BYTES: usize = 2;
+
+/// Render the identifier as `0x` prefixed hex.
+pub fn format_id<T: AsRef<[u8]>>(id: &T) -> String {
+	encode_hex(id.as_ref())
+}
+
+/// Render the identifier as `0x` prefixed hex, keeping only the leading and
+/// trailing bytes, such as `0x1a2b…ff00`. Short identifiers are rendered in
+/// full.
+pub fn format_id_short<T: AsRef<[u8]>>(id: &T) -> String {
+	let bytes = id.as_ref();
+	if bytes.len() <= SHORT_BYTES * 2 {
+		return format_id(&bytes)
+	}
+
+	let mut out = String::from("0x");
+	push_hex(&mut out, &bytes[..SHORT_BYTES]);
+	out.push('…');
+	push_hex(&mut out, &bytes[bytes.len() - SHORT_BYTES..]);
+	out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Errors of decoding hex.
+pub enum HexError {
+	/// Number of digits is odd
+	OddLength,
+	/// Character is not a hex digit
+	InvalidDigit,
+}
+
+impl fmt::Display for HexError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for HexError { }
+
+/// Encode the bytes as `0x` prefixed lowercase hex.
+pub fn encode_hex(bytes: &[u8]) -> String {
+	let mut out = String::with_capacity(2 + bytes.len() * 2);
+	out.push_str("0x");
+	push_hex(&mut out, bytes);
+	out
+}
+
+/// Decode hex, with or without the `0x` prefix, in either case.
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>, HexError> {
+	let hex = hex.strip_prefix("0x").unwrap_or(hex).as_bytes();
+	if hex.len() % 2 == 1 {
+		return Err(HexError::OddLength)
+	}
+
+	hex.chunks(2)
+		.map(|pair| Ok(digit(pair[0])? << 4 | digit(pair[1])?))
+		.collect()
+}
+
+fn digit(c: u8) -> Result<u8, HexError> {
+	match c {
+		b'0'..=b'9' => Ok(c - b'0'),
+		b'a'..=b'f' => Ok(c - b'a' + 10),
+		b'A'..=b'F' => Ok(c - b'A' + 10),
+		_ => Err(HexError::InvalidDigit),
+	}
+}
+
+fn push_hex(out: &mut String, bytes: &[u8]) {
+	const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+	for byte in bytes {
+		out.push(DIGITS[(byte >> 4) as usize] as char);
+		out.push(DIGITS[(byte & 0xf) as usize] as char);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn formats_hash_in_full_and_short() {
+		let mut id = [0u8; 32];
+		id[0] = 0x1a;
+		id[1] = 0x2b;
+		id[30] = 0xff;
+
+		assert_eq!(
+			format_id(&id),
+			"0x1a2b00000000000000000000000000000000000000000000000000000000ff00",
+		);
+		assert_eq!(format_id_short(&id), "0x1a2b…ff00");
+	}
+
+	#[test]
+	fn decodes_what_it_encodes() {
+		let bytes = vec![0x00, 0x1a, 0xff, 0x7e];
+		assert_eq!(encode_hex(&bytes), "0x001aff7e");
+		assert_eq!(decode_hex(&encode_hex(&bytes)), Ok(bytes.clone()));
+		assert_eq!(decode_hex("001AFF7E"), Ok(bytes));
+		assert_eq!(decode_hex("0x"), Ok(Vec::new()));
+	}
+
+	#[test]
+	fn rejects_invalid_hex() {
+		assert_eq!(decode_hex("0x123"), Err(HexError::OddLength));
+		assert_eq!(decode_hex("0xzz"), Err(HexError::InvalidDigit));
+		assert_eq!(decode_hex("é"), Err(HexError::InvalidDigit));
+	}
+
+	#[test]
+	fn formats_short_id_in_full() {
+		assert_eq!(format_id(&[]), "0x");
+		assert_eq!(format_id_short(&[0xde, 0xad, 0xbe, 0xef]), "0xdeadbeef");
+	}
+}
@@ -59,8 +59,12 @@ impl<'a, Ba: Store> ImportAction<'a, Ba> where
 		self.pending.remove_auxiliaries.push(aux_key);
 	}
 
-	/// Commit operation and drop import lock.
+	/// Commit operation and drop import lock. The lock is held until the
+	/// operation is committed.
 	pub fn commit(self) -> Result<(), Ba::Error> {
-		Ok(self.backend.commit(self.into())?)
+		let ImportAction { backend, pending, _guard: guard } = self;
+		backend.commit(pending)?;
+		drop(guard);
+		Ok(())
 	}
 }
@@ -0,0 +1,165 @@
+use std::{fmt, error as stderror};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock};
+use crate::import::{ImportQueue, ImportError};
+
+/// Behavior of a full bounded import queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+	/// Reject new blocks with `QueueFull`.
+	Reject,
+	/// Block the submitter until there is room.
+	Block,
+}
+
+#[derive(Debug)]
+/// Errors of submitting to a bounded import queue. The block is given back.
+pub enum SubmitError<B> {
+	/// The queue is full
+	QueueFull(B),
+	/// The worker has stopped
+	Stopped(B),
+}
+
+impl<B: fmt::Debug> fmt::Display for SubmitError<B> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl<B: fmt::Debug> stderror::Error for SubmitError<B> { }
+
+/// Import queue with a bounded number of pending blocks, imported in order
+/// by a dedicated worker thread through an `ImportQueue`, so orphans are
+/// buffered as well. The worker stops when the queue is dropped, after
+/// importing the pending blocks.
+pub struct BoundedImportQueue<B: Block> {
+	sender: Option<SyncSender<B>>,
+	backpressure: Backpressure,
+	worker: Option<JoinHandle<()>>,
+}
+
+impl<B: Block + Send + 'static> BoundedImportQueue<B> {
+	/// Start a worker importing into the backend. At most `capacity` blocks
+	/// are pending, and at most `max_orphans` are buffered as orphans. The
+	/// result of each import is passed to `on_import`.
+	pub fn new<Ba, E, F>(
+		backend: Ba,
+		import_lock: ImportLock,
+		executor: E,
+		capacity: usize,
+		max_orphans: usize,
+		backpressure: Backpressure,
+		mut on_import: F,
+	) -> Self where
+		Ba: Store<Block=B> + ChainQuery + SharedCommittable<Operation=Operation<B, <Ba as Store>::State, <Ba as Store>::Auxiliary>> + Send + 'static,
+		Ba::State: AsExternalities<E::Externalities>,
+		E: BlockExecutor<Block=B> + Send + 'static,
+		B::Identifier: AsRef<[u8]>,
+		F: FnMut(Result<usize, ImportError<Ba::Error, E::Error>>) + Send + 'static,
+	{
+		let (sender, receiver) = mpsc::sync_channel::<B>(capacity);
+
+		let worker = thread::spawn(move || {
+			let mut queue = ImportQueue::new(max_orphans);
+
+			for block in receiver {
+				on_import(queue.import(&backend, &import_lock, &executor, block));
+			}
+		});
+
+		Self { sender: Some(sender), backpressure, worker: Some(worker) }
+	}
+
+	/// Submit a block for import.
+	pub fn submit(&self, block: B) -> Result<(), SubmitError<B>> {
+		let sender = self.sender.as_ref()
+			.expect("Sender is only taken on drop; qed");
+
+		match self.backpressure {
+			Backpressure::Reject => sender.try_send(block).map_err(|err| match err {
+				TrySendError::Full(block) => SubmitError::QueueFull(block),
+				TrySendError::Disconnected(block) => SubmitError::Stopped(block),
+			}),
+			Backpressure::Block => sender.send(block)
+				.map_err(|err| SubmitError::Stopped(err.0)),
+		}
+	}
+}
+
+impl<B: Block> Drop for BoundedImportQueue<B> {
+	fn drop(&mut self) {
+		self.sender.take();
+		if let Some(worker) = self.worker.take() {
+			let _ = worker.join();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::mpsc::Receiver;
+	use crate::backend::MemoryError;
+	use crate::testing::runtime::{chain, fork, Chain, TestBlock, Error as TestError};
+
+	fn queue<F>(chain: &Chain, backpressure: Backpressure, on_import: F) -> BoundedImportQueue<TestBlock> where
+		F: FnMut(Result<usize, ImportError<MemoryError, TestError>>) + Send + 'static,
+	{
+		BoundedImportQueue::new(
+			chain.backend().clone(), chain.import_lock().clone(), chain.executor().clone(),
+			1, 4, backpressure, on_import,
+		)
+	}
+
+	#[test]
+	fn rejects_blocks_over_capacity() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 3, 1);
+
+		let (started_sender, started) = mpsc::channel();
+		let (release, released): (_, Receiver<()>) = mpsc::channel();
+		let queue = queue(&chain, Backpressure::Reject, move |result| {
+			result.unwrap();
+			let _ = started_sender.send(());
+			let _ = released.recv();
+		});
+
+		queue.submit(blocks[0].clone()).unwrap();
+		started.recv().unwrap();
+		queue.submit(blocks[1].clone()).unwrap();
+		match queue.submit(blocks[2].clone()) {
+			Err(SubmitError::QueueFull(block)) => assert_eq!(block, blocks[2]),
+			other => panic!("expected a full queue, got {:?}", other),
+		}
+
+		drop(release);
+		drop(queue);
+		assert!(chain.backend().contains(&blocks[1].id()).unwrap());
+		assert!(!chain.backend().contains(&blocks[2].id()).unwrap());
+	}
+
+	#[test]
+	fn blocking_submitter_imports_orphans_in_order() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 3, 1);
+
+		let (results, imported) = mpsc::channel();
+		let queue = queue(&chain, Backpressure::Block, move |result| {
+			let _ = results.send(result.unwrap());
+		});
+		for block in blocks.iter().rev() {
+			queue.submit(block.clone()).unwrap();
+		}
+
+		drop(queue);
+		assert_eq!(imported.iter().collect::<Vec<_>>(), vec![0, 0, 3]);
+		for block in &blocks {
+			assert!(chain.backend().contains(&block.id()).unwrap());
+		}
+	}
+}
@@ -0,0 +1,56 @@
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock, StateCache};
+use crate::import::{import_if_deeper, ImportError};
+
+/// Same as `import_if_deeper`, and the state cache is invalidated if the
+/// block became the new head, so that it never serves values of a replaced
+/// head. Returns whether the block became head.
+pub fn import_block_with_cache<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	cache: &mut StateCache,
+	block: Ba::Block,
+) -> Result<bool, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let is_new_best = import_if_deeper(backend, import_lock, executor, block)?;
+	if is_new_best {
+		cache.invalidate();
+	}
+
+	Ok(is_new_best)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::runtime::{chain, fork, TestBlock};
+
+	#[test]
+	fn reorg_invalidates_cached_head_values() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut cache = StateCache::new();
+		let replaced = fork(&chain, &genesis, 1, 5);
+		let longer = fork(&chain, &genesis, 2, 3);
+		let import = |cache: &mut StateCache, block: &TestBlock| {
+			import_block_with_cache(chain.backend(), chain.import_lock(), chain.executor(), cache, block.clone())
+				.unwrap()
+		};
+		let counter = |value: u64| Some(value.to_be_bytes().to_vec());
+
+		assert!(import(&mut cache, &replaced[0]));
+		assert_eq!(cache.read_head(chain.backend(), b"counter").unwrap(), counter(5));
+
+		assert!(!import(&mut cache, &longer[0]));
+		assert_eq!(cache.read_head(chain.backend(), b"counter").unwrap(), counter(5));
+
+		assert!(import(&mut cache, &longer[1]));
+		assert_eq!(chain.head(), longer[1]);
+		assert_eq!(cache.read_head(chain.backend(), b"counter").unwrap(), counter(6));
+	}
+}
@@ -0,0 +1,18 @@
+use std::time::Duration;
+use crate::import::ImportError;
+
+/// Callbacks fired by the import pipeline. All methods default to no-op.
+pub trait Metrics<BE, EE> {
+	/// A block of the given number was imported.
+	fn on_block_imported(&self, _number: u64) { }
+	/// Importing a block failed.
+	fn on_import_failed(&self, _error: &ImportError<BE, EE>) { }
+	/// Time spent executing a block, whether it succeeded or not.
+	fn on_execution_time(&self, _duration: Duration) { }
+}
+
+/// Metrics that record nothing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMetrics;
+
+impl<BE, EE> Metrics<BE, EE> for NoopMetrics { }
@@ -2,8 +2,28 @@
 
 mod action;
 mod traits;
+mod pipeline;
+mod metrics;
+mod queue;
+mod notification;
+mod retry;
+mod quarantine;
+mod write_back;
+mod parallel;
+mod bounded;
+mod cache;
 
 pub use self::action::ImportAction;
+pub use self::pipeline::{import_block, import_if_best, import_if_deeper, import_block_with_metrics, import_block_with_diff, check_genesis, ImportError};
+pub use self::metrics::{Metrics, NoopMetrics};
+pub use self::queue::ImportQueue;
+pub use self::bounded::{BoundedImportQueue, Backpressure, SubmitError};
+pub use self::notification::{BlockImportNotification, ImportNotifications, import_block_with_notifications};
+pub use self::retry::{RetryPolicy, import_block_with_retry};
+pub use self::quarantine::{Quarantine, import_block_with_quarantine, DEFAULT_MAX_QUARANTINED};
+pub use self::write_back::{WriteBack, CommitPolicy, BlockJournal};
+pub use self::parallel::import_blocks_parallel;
+pub use self::cache::import_block_with_cache;
 pub use self::traits::{RawImporter, SharedRawImporter, BlockImporter, SharedBlockImporter};
 
 use std::sync::{Arc, Mutex};
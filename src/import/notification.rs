@@ -0,0 +1,171 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use crate::{Block, BlockContext, ExtrinsicBuilder, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock};
+use crate::import::{ImportError, NoopMetrics};
+use crate::import::pipeline::{import, SetHead};
+
+/// Notification of an imported block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockImportNotification<Id> {
+	/// Id of the imported block.
+	pub id: Id,
+	/// Number of the imported block.
+	pub number: u64,
+	/// Whether the block became the new head.
+	pub is_new_best: bool,
+	/// Total weight used up by the extrinsics of the block.
+	pub block_weight: u64,
+	/// Maximum total weight of a block.
+	pub weight_limit: u64,
+}
+
+impl<Id> BlockImportNotification<Id> {
+	/// Fraction of the weight limit used up by the block, between `0.0`
+	/// for an empty block and `1.0` for a full one.
+	pub fn fullness(&self) -> f64 {
+		if self.weight_limit == 0 {
+			return 0.0
+		}
+
+		self.block_weight as f64 / self.weight_limit as f64
+	}
+}
+
+/// Subscribers of block import notifications. Each subscriber has a
+/// bounded channel; notifications to a lagging subscriber are dropped
+/// instead of blocking the importer.
+pub struct ImportNotifications<Id> {
+	subscribers: Arc<Mutex<Vec<SyncSender<BlockImportNotification<Id>>>>>,
+}
+
+impl<Id> Clone for ImportNotifications<Id> {
+	fn clone(&self) -> Self {
+		Self { subscribers: self.subscribers.clone() }
+	}
+}
+
+impl<Id> Default for ImportNotifications<Id> {
+	fn default() -> Self {
+		Self { subscribers: Arc::new(Mutex::new(Vec::new())) }
+	}
+}
+
+impl<Id: Clone> ImportNotifications<Id> {
+	/// Create a new notification hub without subscribers.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Subscribe to notifications, buffering at most `buffer` of them.
+	pub fn subscribe(&self, buffer: usize) -> Receiver<BlockImportNotification<Id>> {
+		let (sender, receiver) = mpsc::sync_channel(buffer);
+		self.subscribers.lock().expect("Lock is poisoned").push(sender);
+		receiver
+	}
+
+	/// Send a notification to all subscribers. Disconnected subscribers are
+	/// removed.
+	pub fn notify(&self, notification: BlockImportNotification<Id>) {
+		self.subscribers.lock().expect("Lock is poisoned").retain(|subscriber| {
+			match subscriber.try_send(notification.clone()) {
+				Ok(()) | Err(TrySendError::Full(_)) => true,
+				Err(TrySendError::Disconnected(_)) => false,
+			}
+		});
+	}
+}
+
+/// Import a block, set it as head if it is deeper than the current head,
+/// and notify subscribers, reporting how full the block is. The weight of
+/// the block is the one accounted while executing it.
+pub fn import_block_with_notifications<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	notifications: &ImportNotifications<<Ba::Block as Block>::Identifier>,
+	block: Ba::Block,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: ExtrinsicBuilder<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let id = block.id();
+	let number = block.number();
+	let weight_limit = executor.max_weight();
+
+	let mut block_weight = 0;
+	let execute = |block: &Ba::Block, context: &BlockContext<_>, state: &mut E::Externalities| {
+		executor.execute_block_with_weight(block, context, state)
+			.map(|(_, weight)| block_weight = weight)
+	};
+	let is_new_best = import(backend, import_lock, executor, &NoopMetrics, block, SetHead::IfDeeper, execute)?;
+
+	notifications.notify(BlockImportNotification { id, number, is_new_best, block_weight, weight_limit });
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::TestChain;
+	use crate::testing::runtime::{chain, failing, fork, seal, TestExecutor};
+
+	#[test]
+	fn reports_new_best_and_weight() {
+		let chain = chain();
+		let genesis = chain.head();
+		let notifications = ImportNotifications::new();
+		let receiver = notifications.subscribe(4);
+		let long = fork(&chain, &genesis, 2, 5);
+		let short = fork(&chain, &genesis, 1, 3);
+
+		for block in long.iter().chain(short.iter()) {
+			import_block_with_notifications(
+				chain.backend(), chain.import_lock(), chain.executor(), &notifications, block.clone(),
+			).unwrap();
+		}
+
+		let received = receiver.try_iter().collect::<Vec<_>>();
+		assert_eq!(received.len(), 3);
+		assert_eq!((received[0].id, received[0].is_new_best, received[0].block_weight), (long[0].id(), true, 5));
+		assert_eq!((received[1].id, received[1].is_new_best), (long[1].id(), true));
+		assert_eq!((received[2].id, received[2].is_new_best, received[2].block_weight), (short[0].id(), false, 3));
+		assert_eq!(received[2].weight_limit, u64::MAX);
+		assert_eq!(chain.head(), long[1]);
+	}
+
+	#[test]
+	fn reports_fullness_of_block_against_finite_weight_limit() {
+		let executor = TestExecutor { max_weight: Some(100), ..Default::default() };
+		let chain = TestChain::new(executor, seal as fn(_) -> _).unwrap();
+		let genesis = chain.head();
+		let notifications = ImportNotifications::new();
+		let receiver = notifications.subscribe(4);
+		let block = fork(&chain, &genesis, 1, 70).remove(0);
+
+		// Blocks failing execution are not reported.
+		assert!(import_block_with_notifications(
+			chain.backend(), chain.import_lock(), chain.executor(), &notifications, failing(block.clone()),
+		).is_err());
+		import_block_with_notifications(
+			chain.backend(), chain.import_lock(), chain.executor(), &notifications, block.clone(),
+		).unwrap();
+
+		let received = receiver.try_iter().collect::<Vec<_>>();
+		assert_eq!(received.len(), 1);
+		assert_eq!((received[0].id, received[0].block_weight, received[0].weight_limit), (block.id(), 70, 100));
+		assert!((received[0].fullness() - 0.7).abs() < f64::EPSILON);
+	}
+
+	#[test]
+	fn fullness_is_fraction_of_weight_limit() {
+		let notification = BlockImportNotification {
+			id: (), number: 1, is_new_best: true, block_weight: 25, weight_limit: 100,
+		};
+		assert_eq!(notification.fullness(), 0.25);
+		assert_eq!(BlockImportNotification { weight_limit: 0, ..notification }.fullness(), 0.0);
+	}
+}
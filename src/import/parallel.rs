@@ -0,0 +1,82 @@
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock};
+use crate::consensus::{verify_chain_parallel, VerifyError};
+use crate::import::{import_block, ImportError};
+
+/// Import a chain of blocks in two stages. Blocks are first verified with
+/// `verify_block` on `threads` workers, then executed and imported in order
+/// on the calling thread. Blocks before the first one failing verification
+/// are still imported. Head is not changed. Returns the number of imported
+/// blocks.
+pub fn import_blocks_parallel<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	blocks: Vec<Ba::Block>,
+	threads: usize,
+) -> Result<usize, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::Block: Sync,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block> + Sync,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	E::Error: Send,
+{
+	let (valid, invalid) = match verify_chain_parallel(executor, &blocks, threads) {
+		Ok(()) => (blocks.len(), None),
+		Err((index, err)) => (index, Some(err)),
+	};
+
+	let unknown_parent = blocks.get(valid)
+		.and_then(|block| block.parent_id())
+		.map(|parent_id| parent_id.as_ref().to_vec())
+		.unwrap_or_default();
+
+	let mut imported = 0;
+	for block in blocks.into_iter().take(valid) {
+		import_block(backend, import_lock, executor, block)?;
+		imported += 1;
+	}
+
+	match invalid {
+		None => Ok(imported),
+		Some(VerifyError::BrokenLink) => Err(ImportError::UnknownParent(unknown_parent)),
+		Some(VerifyError::NumberMismatch) => Err(ImportError::Malformed),
+		Some(VerifyError::Invalid(err)) => Err(ImportError::Execution(err)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::runtime::{chain, fork, read_counter, Extrinsic, Error as TestError};
+
+	#[test]
+	fn verifies_in_parallel_and_imports_in_order() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 50, 1);
+
+		assert_eq!(import_blocks_parallel(chain.backend(), chain.import_lock(), chain.executor(), blocks.clone(), 4).unwrap(), 50);
+		for (index, block) in blocks.iter().enumerate() {
+			let state = chain.backend().state_at(&block.id()).unwrap();
+			assert_eq!(read_counter(&state), index as u64 + 1);
+		}
+	}
+
+	#[test]
+	fn imports_blocks_before_first_invalid_one() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut blocks = fork(&chain, &genesis, 50, 1);
+		// Changing the body without the extrinsics root fails verification.
+		blocks[30].extrinsics.push(Extrinsic::Add(1));
+
+		assert!(matches!(
+			import_blocks_parallel(chain.backend(), chain.import_lock(), chain.executor(), blocks.clone(), 4),
+			Err(ImportError::Execution(TestError::ExtrinsicsRootMismatch))
+		));
+		assert!(chain.backend().contains(&blocks[29].id()).unwrap());
+		assert!(!chain.backend().contains(&blocks[30].id()).unwrap());
+	}
+}
@@ -0,0 +1,700 @@
+use std::{fmt, error as stderror};
+use std::time::Instant;
+use log::*;
+use crate::{Block, BlockContext, BlockExecutor, BuildGenesis, DiffExecutor, StateDiff, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock};
+use crate::import::{ImportAction, Metrics, NoopMetrics};
+use crate::chain::{descends_from_finalized, AncestryError};
+use crate::format::{format_id, format_id_short};
+
+#[derive(Debug)]
+/// Errors of the import pipeline.
+pub enum ImportError<BE, EE> {
+	/// Parent of the block is not in the backend, carrying the bytes of the
+	/// parent id
+	UnknownParent(Vec<u8>),
+	/// Block stored under the parent id of the block has a different id
+	ParentHashMismatch,
+	/// Block number does not follow the number of its parent, or the block
+	/// has no parent but a non-zero number
+	Malformed,
+	/// Block is a genesis, but the backend already has one
+	GenesisAlreadyExists,
+	/// Genesis differs from the one in the backend
+	GenesisMismatch,
+	/// Block is already in the backend
+	AlreadyImported,
+	/// Block does not descend from the finalized block
+	BelowFinalized,
+	/// Reorg retracts more blocks than allowed
+	ReorgTooDeep,
+	/// Block previously failed with a poisoning error
+	Quarantined,
+	/// Block is not on the canonical chain
+	NotCanonical,
+	/// Ancestry of the block loops, so the backend is corrupted
+	CyclicChain,
+	/// Ancestry of the block is too long to walk
+	AncestryTooDeep,
+	/// Head changed since the block was built
+	StaleTip,
+	/// Backend error
+	Backend(BE),
+	/// State after executing the block differs from the state root it
+	/// commits to
+	StateRootMismatch(EE),
+	/// Executor error
+	Execution(EE),
+}
+
+impl<BE: fmt::Display, EE: fmt::Display> fmt::Display for ImportError<BE, EE> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ImportError::UnknownParent(parent_id) => write!(f, "Unknown parent {}", format_id(parent_id)),
+			ImportError::ParentHashMismatch => write!(f, "Stored parent has a different id"),
+			ImportError::Malformed => write!(f, "Block number does not follow its parent"),
+			ImportError::GenesisAlreadyExists => write!(f, "Genesis already exists"),
+			ImportError::GenesisMismatch => write!(f, "Genesis differs from the one in the backend"),
+			ImportError::AlreadyImported => write!(f, "Block is already imported"),
+			ImportError::BelowFinalized => write!(f, "Block does not descend from the finalized block"),
+			ImportError::ReorgTooDeep => write!(f, "Reorg is too deep"),
+			ImportError::Quarantined => write!(f, "Block is quarantined"),
+			ImportError::NotCanonical => write!(f, "Block is not canonical"),
+			ImportError::CyclicChain => write!(f, "Ancestry of the block loops"),
+			ImportError::AncestryTooDeep => write!(f, "Ancestry of the block is too deep"),
+			ImportError::StaleTip => write!(f, "Head changed since the block was built"),
+			ImportError::Backend(err) => write!(f, "Backend error: {}", err),
+			ImportError::StateRootMismatch(err) => write!(f, "State root mismatch: {}", err),
+			ImportError::Execution(err) => write!(f, "Execution error: {}", err),
+		}
+	}
+}
+
+impl<BE, EE> From<AncestryError<BE>> for ImportError<BE, EE> {
+	fn from(error: AncestryError<BE>) -> Self {
+		match error {
+			AncestryError::CyclicChain => ImportError::CyclicChain,
+			AncestryError::TooDeep => ImportError::AncestryTooDeep,
+			AncestryError::Backend(err) => ImportError::Backend(err),
+		}
+	}
+}
+
+impl<BE, EE> stderror::Error for ImportError<BE, EE> where
+	BE: stderror::Error + 'static,
+	EE: stderror::Error + 'static,
+{
+	fn source(&self) -> Option<&(dyn stderror::Error + 'static)> {
+		match self {
+			ImportError::Backend(err) => Some(err),
+			ImportError::StateRootMismatch(err) => Some(err),
+			ImportError::Execution(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+/// How an import changes head, decided under the same import lock as the
+/// import itself.
+pub(crate) enum SetHead<'a, Id> {
+	/// Head is not changed.
+	Never,
+	/// Head must still be the given block, and becomes the imported block.
+	IfHeadIs(&'a Id),
+	/// The imported block becomes head if it is deeper than head.
+	IfDeeper,
+}
+
+/// Import a block by executing it on top of its parent's state. The backend
+/// is only changed when the execution succeeds. Head is not changed. Blocks
+/// already in the backend, or at or below the finalized height, are rejected
+/// without being executed, as are blocks whose parent, as stored in the
+/// backend, does not hash to the parent id they claim.
+pub fn import_block<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	block: Ba::Block,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	import_block_with_metrics(backend, import_lock, executor, &NoopMetrics, block)
+}
+
+/// Same as `import_block`, reporting progress to the given metrics.
+pub fn import_block_with_metrics<Ba, E, M>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	metrics: &M,
+	block: Ba::Block,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	M: Metrics<Ba::Error, E::Error> + ?Sized,
+{
+	let number = block.number();
+	let execute = |block: &Ba::Block, context: &BlockContext<_>, state: &mut E::Externalities| {
+		executor.execute_block_with_context(block, context, state).map(|_| ())
+	};
+
+	match import(backend, import_lock, executor, metrics, block, SetHead::Never, execute) {
+		Ok(_) => {
+			metrics.on_block_imported(number);
+			Ok(())
+		},
+		Err(err) => {
+			metrics.on_import_failed(&err);
+			Err(err)
+		},
+	}
+}
+
+/// Same as `import_block`, but when a state diff is given, it is applied
+/// on top of the parent's state and checked against the block instead of
+/// executing the block.
+pub fn import_block_with_diff<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	block: Ba::Block,
+	diff: Option<&StateDiff>,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: DiffExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let diff = match diff {
+		Some(diff) => diff,
+		None => return import_block(backend, import_lock, executor, block),
+	};
+
+	let execute = |block: &Ba::Block, context: &BlockContext<_>, state: &mut E::Externalities| {
+		executor.apply_diff_with_context(block, context, diff, state)
+	};
+
+	import(backend, import_lock, executor, &NoopMetrics, block, SetHead::Never, execute)
+		.map(|_| ())
+}
+
+/// Same as `import_block`, but only if head is still `expected_head`, in
+/// which case the block also becomes the new head. Head is checked and set
+/// under the import lock, so of concurrent imports expecting the same head,
+/// at most one succeeds and the others fail with `StaleTip`, and should be
+/// rebuilt on top of the new head.
+pub fn import_if_best<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	block: Ba::Block,
+	expected_head: &<Ba::Block as Block>::Identifier,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let execute = |block: &Ba::Block, context: &BlockContext<_>, state: &mut E::Externalities| {
+		executor.execute_block_with_context(block, context, state).map(|_| ())
+	};
+
+	import(backend, import_lock, executor, &NoopMetrics, block, SetHead::IfHeadIs(expected_head), execute)
+		.map(|_| ())
+}
+
+/// Same as `import_block`, and the block also becomes the new head if it is
+/// deeper than the current head. Depths are compared and head is set under
+/// the same import lock as the import, so concurrent imports cannot move
+/// head to a shallower block. Returns whether the block became head.
+pub fn import_if_deeper<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	block: Ba::Block,
+) -> Result<bool, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let execute = |block: &Ba::Block, context: &BlockContext<_>, state: &mut E::Externalities| {
+		executor.execute_block_with_context(block, context, state).map(|_| ())
+	};
+
+	import(backend, import_lock, executor, &NoopMetrics, block, SetHead::IfDeeper, execute)
+}
+
+/// Check that the backend was created from the given genesis block, and
+/// that its stored genesis state is the one the block commits to. A
+/// different genesis block is reported as `GenesisMismatch`, and a genesis
+/// state rejected by the executor as `Execution`. Nodes failing the check
+/// cannot follow each other's chain.
+pub fn check_genesis<Ba, E>(
+	backend: &Ba,
+	executor: &E,
+	genesis: &Ba::Block,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BuildGenesis<Block=Ba::Block>,
+{
+	let genesis_id = backend.genesis();
+	if genesis.id() != genesis_id {
+		return Err(ImportError::GenesisMismatch)
+	}
+
+	let block = backend.block_at(&genesis_id).map_err(ImportError::Backend)?;
+	let mut state = backend.state_at(&genesis_id).map_err(ImportError::Backend)?;
+	executor.verify_genesis(&block, state.as_externalities())
+		.map_err(ImportError::Execution)
+}
+
+/// Classify an error of executing a block, reporting state root mismatches
+/// apart from other execution failures.
+pub(crate) fn execution_error<BE, E: BlockExecutor + ?Sized>(
+	executor: &E,
+	error: E::Error,
+) -> ImportError<BE, E::Error> {
+	if executor.is_state_root_mismatch(&error) {
+		ImportError::StateRootMismatch(error)
+	} else {
+		ImportError::Execution(error)
+	}
+}
+
+/// Lookup of executed blocks not yet committed to the backend.
+pub(crate) type Pending<'a, B> = dyn Fn(&<B as Block>::Identifier) -> Option<&'a B> + 'a;
+
+type ContextResult<Ba, EE> = Result<
+	BlockContext<<<Ba as Store>::Block as Block>::Identifier>,
+	ImportError<<Ba as Store>::Error, EE>,
+>;
+
+/// Check a block before executing it, under the import lock, and return
+/// its context. Blocks already imported, at or below the finalized height,
+/// not numbered right after their parent, or whose parent is unknown,
+/// stored under another id, or does not descend from the finalized block
+/// are rejected. `pending` looks up executed blocks not yet committed to the
+/// backend, which are accepted as parents.
+pub(crate) fn check_block<'a, Ba, EE>(
+	backend: &Ba,
+	block: &Ba::Block,
+	pending: &Pending<'a, Ba::Block>,
+) -> ContextResult<Ba, EE> where
+	Ba: ChainQuery,
+	Ba::Block: 'a,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let id = block.id();
+	if pending(&id).is_some() || backend.contains(&id).map_err(ImportError::Backend)? {
+		return Err(ImportError::AlreadyImported)
+	}
+
+	let parent_id = match block.parent_id() {
+		Some(parent_id) => parent_id,
+		None if block.number() != 0 => return Err(ImportError::Malformed),
+		None => return Err(ImportError::GenesisAlreadyExists),
+	};
+
+	// Blocks at or below the finalized height can never descend from it, so
+	// they are rejected before looking up their ancestry.
+	let finalized = backend.finalized();
+	let finalized_number = backend.depth_at(&finalized).map_err(ImportError::Backend)? as u64;
+	if block.number() <= finalized_number {
+		return Err(ImportError::BelowFinalized)
+	}
+
+	// Pending ancestors were checked when executed, but finalization may
+	// have moved since, so the ancestry check starts from the first
+	// committed one.
+	let mut parent_number = None;
+	let mut committed = parent_id.clone();
+	while let Some(ancestor) = pending(&committed) {
+		parent_number.get_or_insert(ancestor.number());
+		committed = ancestor.parent_id().expect("Pending blocks are never genesis; qed");
+	}
+
+	let parent_number = match parent_number {
+		Some(parent_number) => parent_number,
+		None => {
+			if !backend.contains(&parent_id).map_err(ImportError::Backend)? {
+				return Err(ImportError::UnknownParent(parent_id.as_ref().to_vec()))
+			}
+			let parent = backend.block_at(&parent_id).map_err(ImportError::Backend)?;
+			if parent.id() != parent_id {
+				return Err(ImportError::ParentHashMismatch)
+			}
+			parent.number()
+		},
+	};
+	if block.number() != parent_number + 1 {
+		return Err(ImportError::Malformed)
+	}
+	if !descends_from_finalized(backend, &committed)? {
+		return Err(ImportError::BelowFinalized)
+	}
+
+	Ok(BlockContext { parent_id, parent_number })
+}
+
+/// Execute a checked block on its parent state, reporting the execution
+/// time to the metrics.
+pub(crate) fn execute_checked<B, BE, E, M, F>(
+	executor: &E,
+	metrics: &M,
+	block: &B,
+	context: &BlockContext<B::Identifier>,
+	state: &mut E::Externalities,
+	execute: F,
+) -> Result<(), ImportError<BE, E::Error>> where
+	B: Block,
+	B::Identifier: AsRef<[u8]>,
+	E: BlockExecutor + ?Sized,
+	M: Metrics<BE, E::Error> + ?Sized,
+	F: FnOnce(&B, &BlockContext<B::Identifier>, &mut E::Externalities) -> Result<(), E::Error>,
+{
+	let started = Instant::now();
+	let executed = execute(block, context, state);
+	metrics.on_execution_time(started.elapsed());
+	if let Err(err) = &executed {
+		warn!("Execution of block #{} ({}) failed: {}", block.number(), format_id_short(&block.id()), err);
+	}
+	executed.map_err(|err| execution_error(executor, err))
+}
+
+/// Check, execute and import a block under the import lock, changing head
+/// as given. Returns whether the block became head.
+pub(crate) fn import<Ba, E, M, F>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	metrics: &M,
+	block: Ba::Block,
+	set_head: SetHead<<Ba::Block as Block>::Identifier>,
+	execute: F,
+) -> Result<bool, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block> + ?Sized,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	M: Metrics<Ba::Error, E::Error> + ?Sized,
+	F: FnOnce(&Ba::Block, &BlockContext<<Ba::Block as Block>::Identifier>, &mut E::Externalities) -> Result<(), E::Error>,
+{
+	let mut action = ImportAction::new(backend, import_lock.lock());
+
+	if let SetHead::IfHeadIs(expected_head) = set_head {
+		if backend.head() != *expected_head {
+			return Err(ImportError::StaleTip)
+		}
+	}
+
+	let context = check_block(backend, &block, &|_| None)?;
+	let mut state = backend.state_at(&context.parent_id).map_err(ImportError::Backend)?;
+	execute_checked(executor, metrics, &block, &context, state.as_externalities(), execute)?;
+
+	let is_new_head = match set_head {
+		SetHead::Never => false,
+		SetHead::IfHeadIs(_) => true,
+		SetHead::IfDeeper => {
+			let depth = backend.depth_at(&context.parent_id).map_err(ImportError::Backend)? + 1;
+			let head_depth = backend.depth_at(&backend.head()).map_err(ImportError::Backend)?;
+			depth > head_depth
+		},
+	};
+
+	let (id, number) = (block.id(), block.number());
+	action.import_block(block, state);
+	if is_new_head {
+		action.set_head(id.clone());
+	}
+	action.commit().map_err(ImportError::Backend)?;
+	info!("Imported block #{} ({})", number, format_id_short(&id));
+	Ok(is_new_head)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Barrier;
+	use std::thread;
+	use crate::backend::{ChainQuery, Finalizable, KeyValueMemoryState, MemoryError};
+	use crate::testing::runtime::{chain, child, failing, fork, hash, seal, Extrinsic, Error as TestError};
+
+	#[test]
+	fn imports_valid_block() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut state = chain.backend().state_at(&genesis.id()).unwrap();
+		let block = child(&genesis, &mut state, vec![Extrinsic::Add(1)]);
+
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		assert!(chain.backend().contains(&block.id()).unwrap());
+	}
+
+	#[test]
+	fn rejects_unknown_parent() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut state = chain.backend().state_at(&genesis.id()).unwrap();
+		let parent = child(&genesis, &mut state, vec![Extrinsic::Add(1)]);
+		let block = child(&parent, &mut state, vec![Extrinsic::Add(2)]);
+
+		match import_block(chain.backend(), chain.import_lock(), chain.executor(), block) {
+			Err(err @ ImportError::UnknownParent(_)) => {
+				assert_eq!(err.to_string(), format!("Unknown parent {}", format_id(&parent.id())));
+			},
+			other => panic!("unexpected import result {:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_state_root_mismatch() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut state = chain.backend().state_at(&genesis.id()).unwrap();
+		let mut block = child(&genesis, &mut state, vec![Extrinsic::Add(1)]);
+		block.state_root = [0; 8];
+
+		match import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()) {
+			Err(ImportError::StateRootMismatch(_)) => (),
+			other => panic!("unexpected import result {:?}", other),
+		}
+		assert!(!chain.backend().contains(&block.id()).unwrap());
+	}
+
+	#[test]
+	fn rejects_blocks_of_foreign_chain() {
+		let chain = chain();
+		assert_eq!(chain.backend().genesis(), chain.head().id());
+
+		let mut foreign_state = KeyValueMemoryState::default();
+		let mut foreign_genesis = chain.executor().build_genesis(&mut foreign_state).unwrap();
+		foreign_genesis.timestamp = 1;
+		let block = child(&foreign_genesis, &mut foreign_state, vec![Extrinsic::Add(1)]);
+
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block),
+			Err(ImportError::UnknownParent(_))
+		));
+		assert!(matches!(
+			check_genesis(chain.backend(), chain.executor(), &foreign_genesis),
+			Err(ImportError::GenesisMismatch)
+		));
+		assert!(check_genesis(chain.backend(), chain.executor(), &chain.head()).is_ok());
+	}
+
+	#[derive(Default)]
+	struct Recorder {
+		imported: std::cell::RefCell<Vec<u64>>,
+		failed: std::cell::RefCell<Vec<String>>,
+		executions: std::cell::Cell<usize>,
+	}
+
+	impl<BE: fmt::Display, EE: fmt::Display> Metrics<BE, EE> for Recorder {
+		fn on_block_imported(&self, number: u64) {
+			self.imported.borrow_mut().push(number);
+		}
+
+		fn on_import_failed(&self, error: &ImportError<BE, EE>) {
+			self.failed.borrow_mut().push(error.to_string());
+		}
+
+		fn on_execution_time(&self, _duration: std::time::Duration) {
+			self.executions.set(self.executions.get() + 1);
+		}
+	}
+
+	#[test]
+	fn fires_metrics_callbacks() {
+		let chain = chain();
+		let genesis = chain.head();
+		let metrics = Recorder::default();
+		let mut state = chain.backend().state_at(&genesis.id()).unwrap();
+		let block = child(&genesis, &mut state, vec![Extrinsic::Add(1)]);
+		let import = |block| import_block_with_metrics(
+			chain.backend(), chain.import_lock(), chain.executor(), &metrics, block
+		);
+
+		import(block.clone()).unwrap();
+		assert_eq!(*metrics.imported.borrow(), vec![1]);
+		assert!(metrics.failed.borrow().is_empty());
+		assert_eq!(metrics.executions.get(), 1);
+
+		let mut state = chain.backend().state_at(&block.id()).unwrap();
+		let failed = failing(child(&block, &mut state, Vec::new()));
+		assert!(import(failed).is_err());
+		assert_eq!(*metrics.imported.borrow(), vec![1]);
+		assert_eq!(metrics.failed.borrow().len(), 1);
+		assert!(metrics.failed.borrow()[0].starts_with("Execution error"));
+		assert_eq!(metrics.executions.get(), 2);
+	}
+
+	#[test]
+	fn rejects_duplicate_import() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut state = chain.backend().state_at(&genesis.id()).unwrap();
+		let block = child(&genesis, &mut state, vec![Extrinsic::Add(1)]);
+
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		let before = chain.backend().state_at(&block.id()).unwrap();
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()),
+			Err(ImportError::AlreadyImported)
+		));
+
+		assert_eq!(chain.backend().state_at(&block.id()).unwrap().as_ref(), before.as_ref());
+		assert_eq!(chain.backend().children_at(&genesis.id()).unwrap(), vec![block.id()]);
+	}
+
+	#[test]
+	fn rejects_parentless_blocks() {
+		let chain = chain();
+		let genesis = chain.head();
+		assert!(genesis.is_genesis());
+
+		let mut state = chain.backend().state_at(&genesis.id()).unwrap();
+		let mut parentless = child(&genesis, &mut state, vec![Extrinsic::Add(1)]);
+		assert!(!parentless.is_genesis());
+		parentless.parent = None;
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), seal(parentless)),
+			Err(ImportError::Malformed)
+		));
+
+		let mut second_genesis = genesis.clone();
+		second_genesis.timestamp = 1;
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), second_genesis),
+			Err(ImportError::GenesisAlreadyExists)
+		));
+	}
+
+	#[test]
+	fn rejects_block_not_numbered_after_parent_without_executing() {
+		let chain = chain();
+		let genesis = chain.head();
+		let block = fork(&chain, &genesis, 1, 1).remove(0);
+
+		// Executing the misnumbered blocks would fail.
+		for number in [2, 5] {
+			let mut misnumbered = failing(block.clone());
+			misnumbered.number = number;
+			match import_block(chain.backend(), chain.import_lock(), chain.executor(), seal(misnumbered)) {
+				Err(err @ ImportError::Malformed) => {
+					assert_eq!(err.to_string(), "Block number does not follow its parent");
+				},
+				other => panic!("unexpected import result {:?}", other),
+			}
+		}
+
+		import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		assert_eq!(chain.backend().children_at(&genesis.id()).unwrap(), vec![block.id()]);
+	}
+
+	#[test]
+	fn exposes_execution_error_as_source() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut state = chain.backend().state_at(&genesis.id()).unwrap();
+		let block = failing(child(&genesis, &mut state, Vec::new()));
+
+		let err = import_block(chain.backend(), chain.import_lock(), chain.executor(), block).unwrap_err();
+		let source = stderror::Error::source(&err).expect("Execution errors have a source; qed");
+		assert_eq!(source.downcast_ref::<TestError>(), Some(&TestError::Invalid));
+		assert!(stderror::Error::source(&ImportError::<MemoryError, TestError>::BelowFinalized).is_none());
+	}
+
+	#[test]
+	fn rejects_block_below_finalized_height_without_executing() {
+		let chain = chain();
+		let genesis = chain.head();
+		let canonical = fork(&chain, &genesis, 3, 1);
+		for block in &canonical {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		chain.backend().clone().finalize(&canonical[1].id()).unwrap();
+
+		// Executing the stale block would fail.
+		let stale = failing(fork(&chain, &canonical[0], 1, 5).remove(0));
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), stale.clone()),
+			Err(ImportError::BelowFinalized)
+		));
+
+		// The height is checked before the parent is looked up.
+		let mut orphan = stale;
+		orphan.parent = Some(hash(b"unknown"));
+		assert!(matches!(
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), seal(orphan)),
+			Err(ImportError::BelowFinalized)
+		));
+	}
+
+	#[test]
+	fn concurrent_imports_on_same_head_succeed_once() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = [fork(&chain, &genesis, 1, 1).remove(0), fork(&chain, &genesis, 1, 2).remove(0)];
+		let barrier = Barrier::new(blocks.len());
+
+		let results = thread::scope(|scope| {
+			let handles = blocks.iter().map(|block| {
+				let (chain, genesis, barrier) = (&chain, &genesis, &barrier);
+				scope.spawn(move || {
+					barrier.wait();
+					import_if_best(chain.backend(), chain.import_lock(), chain.executor(), block.clone(), &genesis.id())
+				})
+			}).collect::<Vec<_>>();
+
+			handles.into_iter()
+				.map(|handle| handle.join().expect("Import thread panicked"))
+				.collect::<Vec<_>>()
+		});
+
+		assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+		assert_eq!(results.iter().filter(|result| matches!(result, Err(ImportError::StaleTip))).count(), 1);
+		let winner = results.iter().position(|result| result.is_ok()).unwrap();
+		assert_eq!(chain.head(), blocks[winner]);
+	}
+
+	/// Logger recording every message, shared by all tests of the process.
+	struct LogRecorder(std::sync::Mutex<Vec<(Level, String)>>);
+
+	impl Log for LogRecorder {
+		fn enabled(&self, _: &Metadata) -> bool { true }
+
+		fn log(&self, record: &Record) {
+			self.0.lock().expect("Lock is poisoned").push((record.level(), record.args().to_string()));
+		}
+
+		fn flush(&self) { }
+	}
+
+	static RECORDER: LogRecorder = LogRecorder(std::sync::Mutex::new(Vec::new()));
+
+	#[test]
+	fn logs_imported_block_number() {
+		// Fails if another test installed the logger first, which is fine
+		// as it is the same one.
+		let _ = set_logger(&RECORDER);
+		set_max_level(LevelFilter::Info);
+
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 2, 1);
+		for block in &blocks {
+			import_block(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		let expected = format!("Imported block #2 ({})", format_id_short(&blocks[1].id()));
+		let records = RECORDER.0.lock().expect("Lock is poisoned");
+		assert!(records.iter().any(|(level, message)| *level == Level::Info && *message == expected));
+	}
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock};
+use crate::import::{import_block, ImportError};
+
+/// Default maximum number of quarantined blocks.
+pub const DEFAULT_MAX_QUARANTINED: usize = 1024;
+
+struct Entry {
+	error: String,
+	used: u64,
+}
+
+struct Blocks<Id> {
+	entries: HashMap<Id, Entry>,
+	max_blocks: usize,
+	tick: u64,
+}
+
+impl<Id: Clone + Eq + std::hash::Hash> Blocks<Id> {
+	fn touch(&mut self, id: &Id) -> Option<&Entry> {
+		self.tick += 1;
+		let tick = self.tick;
+		self.entries.get_mut(id).map(|entry| {
+			entry.used = tick;
+			&*entry
+		})
+	}
+
+	fn evict_least_recently_used(&mut self) {
+		let oldest = self.entries.iter()
+			.min_by_key(|(_, entry)| entry.used)
+			.map(|(id, _)| id.clone());
+
+		if let Some(id) = oldest {
+			self.entries.remove(&id);
+		}
+	}
+}
+
+/// Blocks whose execution failed with a poisoning error, together with the
+/// rendered error. Quarantined blocks are refused without being executed
+/// again. At most `DEFAULT_MAX_QUARANTINED` blocks are held unless
+/// configured otherwise; when full, the least recently used block is
+/// released to make room.
+pub struct Quarantine<Id> {
+	blocks: Arc<Mutex<Blocks<Id>>>,
+}
+
+impl<Id> Clone for Quarantine<Id> {
+	fn clone(&self) -> Self {
+		Self { blocks: self.blocks.clone() }
+	}
+}
+
+impl<Id> Default for Quarantine<Id> {
+	fn default() -> Self {
+		Self {
+			blocks: Arc::new(Mutex::new(Blocks {
+				entries: HashMap::new(),
+				max_blocks: DEFAULT_MAX_QUARANTINED,
+				tick: 0,
+			})),
+		}
+	}
+}
+
+impl<Id: Clone + Eq + std::hash::Hash> Quarantine<Id> {
+	/// Create an empty quarantine.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Hold at most `max_blocks` quarantined blocks.
+	pub fn with_max_blocks(self, max_blocks: usize) -> Self {
+		self.blocks.lock().expect("Lock is poisoned").max_blocks = max_blocks;
+		self
+	}
+
+	/// Quarantine a block with the error it triggered.
+	pub fn insert(&self, id: Id, error: String) {
+		let mut blocks = self.blocks.lock().expect("Lock is poisoned");
+		if blocks.max_blocks == 0 {
+			return
+		}
+		if !blocks.entries.contains_key(&id) && blocks.entries.len() >= blocks.max_blocks {
+			blocks.evict_least_recently_used();
+		}
+
+		blocks.tick += 1;
+		let used = blocks.tick;
+		blocks.entries.insert(id, Entry { error, used });
+	}
+
+	/// Release a block from quarantine, returning the recorded error.
+	pub fn remove(&self, id: &Id) -> Option<String> {
+		self.blocks.lock().expect("Lock is poisoned").entries.remove(id)
+			.map(|entry| entry.error)
+	}
+
+	/// Error recorded for a quarantined block.
+	pub fn get(&self, id: &Id) -> Option<String> {
+		self.blocks.lock().expect("Lock is poisoned").touch(id)
+			.map(|entry| entry.error.clone())
+	}
+
+	/// Whether the block is quarantined.
+	pub fn contains(&self, id: &Id) -> bool {
+		self.blocks.lock().expect("Lock is poisoned").touch(id).is_some()
+	}
+
+	/// Number of quarantined blocks.
+	pub fn len(&self) -> usize {
+		self.blocks.lock().expect("Lock is poisoned").entries.len()
+	}
+
+	/// Whether no block is quarantined.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+/// Same as `import_block`, refusing quarantined blocks with `Quarantined`.
+/// A block whose execution fails with an error `is_poison` accepts is
+/// quarantined, so that it is executed at most once.
+pub fn import_block_with_quarantine<Ba, E, F>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	quarantine: &Quarantine<<Ba::Block as Block>::Identifier>,
+	is_poison: F,
+	block: Ba::Block,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	F: Fn(&E::Error) -> bool,
+{
+	quarantined(quarantine, is_poison, block.id(), || import_block(backend, import_lock, executor, block))
+}
+
+/// Run the import of the block unless it is quarantined, and quarantine it
+/// if it fails with a poisoning error.
+pub(crate) fn quarantined<Id, BE, EE, F, I>(
+	quarantine: &Quarantine<Id>,
+	is_poison: F,
+	id: Id,
+	import: I,
+) -> Result<(), ImportError<BE, EE>> where
+	Id: Clone + Eq + std::hash::Hash,
+	EE: std::fmt::Display,
+	F: Fn(&EE) -> bool,
+	I: FnOnce() -> Result<(), ImportError<BE, EE>>,
+{
+	if quarantine.contains(&id) {
+		return Err(ImportError::Quarantined)
+	}
+
+	match import() {
+		Err(ImportError::Execution(err)) if is_poison(&err) => {
+			quarantine.insert(id, err.to_string());
+			Err(ImportError::Execution(err))
+		},
+		result => result,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::runtime::{chain, fork, failing};
+
+	#[test]
+	fn refuses_block_after_poisoning_failure() {
+		let chain = chain();
+		let genesis = chain.head();
+		let block = failing(fork(&chain, &genesis, 1, 1).remove(0));
+		let quarantine = Quarantine::new();
+
+		match import_block_with_quarantine(
+			chain.backend(), chain.import_lock(), chain.executor(), &quarantine, |_| true, block.clone(),
+		) {
+			Err(ImportError::Execution(_)) => (),
+			other => panic!("unexpected import result {:?}", other),
+		}
+		assert_eq!(quarantine.get(&block.id()), Some("Invalid".to_string()));
+
+		match import_block_with_quarantine(
+			chain.backend(), chain.import_lock(), chain.executor(), &quarantine, |_| true, block,
+		) {
+			Err(ImportError::Quarantined) => (),
+			other => panic!("unexpected import result {:?}", other),
+		}
+	}
+
+	#[test]
+	fn does_not_quarantine_other_failures() {
+		let chain = chain();
+		let genesis = chain.head();
+		let block = failing(fork(&chain, &genesis, 1, 1).remove(0));
+		let quarantine = Quarantine::new();
+
+		assert!(import_block_with_quarantine(
+			chain.backend(), chain.import_lock(), chain.executor(), &quarantine, |_| false, block,
+		).is_err());
+		assert!(quarantine.is_empty());
+	}
+
+	#[test]
+	fn evicts_least_recently_used_when_full() {
+		let quarantine = Quarantine::new().with_max_blocks(2);
+		quarantine.insert(1, "first".to_string());
+		quarantine.insert(2, "second".to_string());
+		assert!(quarantine.contains(&1));
+
+		quarantine.insert(3, "third".to_string());
+		assert_eq!(quarantine.len(), 2);
+		assert!(quarantine.contains(&1));
+		assert!(!quarantine.contains(&2));
+		assert!(quarantine.contains(&3));
+
+		quarantine.insert(3, "again".to_string());
+		assert_eq!(quarantine.len(), 2);
+		assert_eq!(quarantine.remove(&3), Some("again".to_string()));
+	}
+}
@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock};
+use crate::import::{import_block, ImportError};
+
+struct Orphan<B> {
+	block: B,
+	inserted: Instant,
+	used: u64,
+}
+
+/// Import queue buffering orphan blocks, whose parent is not yet known.
+/// Orphans are imported once their parent is imported through the queue.
+/// When the queue is full, the least recently used orphan is evicted to
+/// make room, an orphan being used when it is received, again or not, and
+/// when a descendant of it is buffered. Orphans older than the time to live,
+/// if any, are evicted on the next import.
+pub struct ImportQueue<B: Block> {
+	orphans: HashMap<B::Identifier, Vec<Orphan<B>>>,
+	len: usize,
+	max_orphans: usize,
+	ttl: Option<Duration>,
+	clock: Box<dyn Fn() -> Instant + Send + Sync>,
+	tick: u64,
+}
+
+impl<B: Block> ImportQueue<B> {
+	/// Create a new queue holding at most `max_orphans` orphan blocks.
+	pub fn new(max_orphans: usize) -> Self {
+		Self {
+			orphans: HashMap::new(),
+			len: 0,
+			max_orphans,
+			ttl: None,
+			clock: Box::new(Instant::now),
+			tick: 0,
+		}
+	}
+
+	/// Evict orphans buffered for longer than `ttl`, whose parent is
+	/// unlikely to ever arrive.
+	pub fn with_ttl(mut self, ttl: Duration) -> Self {
+		self.ttl = Some(ttl);
+		self
+	}
+
+	/// Use the given clock instead of the system one.
+	pub fn with_clock<C: Fn() -> Instant + Send + Sync + 'static>(mut self, clock: C) -> Self {
+		self.clock = Box::new(clock);
+		self
+	}
+
+	/// Number of buffered orphan blocks.
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	/// Whether there are no buffered orphan blocks.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Import a block. If its parent is unknown, the block is buffered and
+	/// zero is returned, unless the queue cannot hold any orphan, in which
+	/// case the block is dropped with `UnknownParent`. After a successful
+	/// import, buffered descendants of the block are imported as well.
+	/// Returns the number of imported blocks.
+	pub fn import<Ba, E>(
+		&mut self,
+		backend: &Ba,
+		import_lock: &ImportLock,
+		executor: &E,
+		block: B,
+	) -> Result<usize, ImportError<Ba::Error, E::Error>> where
+		Ba: Store<Block=B> + ChainQuery + SharedCommittable<Operation=Operation<B, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+		Ba::State: AsExternalities<E::Externalities>,
+		E: BlockExecutor<Block=B>,
+		B::Identifier: AsRef<[u8]>,
+	{
+		self.evict_expired();
+
+		let id = block.id();
+		let parent_id = block.parent_id();
+
+		match import_block(backend, import_lock, executor, block.clone()) {
+			Ok(()) => (),
+			Err(ImportError::UnknownParent(unknown)) => {
+				if let Some(parent_id) = parent_id {
+					if self.insert(parent_id, block) {
+						return Ok(0)
+					}
+				}
+				return Err(ImportError::UnknownParent(unknown))
+			},
+			Err(err) => return Err(err),
+		}
+
+		let mut imported = 1;
+		let mut parents = vec![id];
+
+		while let Some(parent_id) = parents.pop() {
+			for child in self.take_children(&parent_id) {
+				let child_id = child.id();
+
+				match import_block(backend, import_lock, executor, child) {
+					Ok(()) => {
+						imported += 1;
+						parents.push(child_id);
+					},
+					Err(ImportError::AlreadyImported) => parents.push(child_id),
+					Err(_) => self.discard_descendants(&child_id),
+				}
+			}
+		}
+
+		Ok(imported)
+	}
+
+	/// Evict orphans buffered for longer than the time to live.
+	pub fn evict_expired(&mut self) {
+		let ttl = match self.ttl {
+			Some(ttl) => ttl,
+			None => return,
+		};
+
+		let now = (self.clock)();
+		let mut evicted = 0;
+		self.orphans.retain(|_, siblings| {
+			let before = siblings.len();
+			siblings.retain(|orphan| now.saturating_duration_since(orphan.inserted) < ttl);
+			evicted += before - siblings.len();
+			!siblings.is_empty()
+		});
+		self.len -= evicted;
+	}
+
+	fn insert(&mut self, parent_id: B::Identifier, block: B) -> bool {
+		if self.max_orphans == 0 {
+			return false
+		}
+
+		// A duplicate is used again, but keeps its original insertion time,
+		// so that resending an orphan cannot keep it from expiring.
+		let id = block.id();
+		if self.touch(&id).is_some() {
+			return true
+		}
+
+		// Buffered ancestors are used by the new orphan, and are kept over
+		// unrelated orphans.
+		let mut ancestor = Some(parent_id.clone());
+		while let Some(id) = ancestor {
+			ancestor = self.touch(&id);
+		}
+
+		if self.len >= self.max_orphans {
+			self.evict_least_recently_used();
+		}
+
+		self.tick += 1;
+		let (inserted, used) = ((self.clock)(), self.tick);
+		self.orphans.entry(parent_id).or_default().push(Orphan { block, inserted, used });
+		self.len += 1;
+		true
+	}
+
+	/// Mark the buffered orphan with the given id as used, returning its
+	/// parent id.
+	fn touch(&mut self, id: &B::Identifier) -> Option<B::Identifier> {
+		self.tick += 1;
+		let tick = self.tick;
+		self.orphans.iter_mut()
+			.find_map(|(parent_id, siblings)| {
+				siblings.iter_mut().find(|orphan| orphan.block.id() == *id).map(|orphan| {
+					orphan.used = tick;
+					parent_id.clone()
+				})
+			})
+	}
+
+	fn evict_least_recently_used(&mut self) {
+		let oldest = self.orphans.iter()
+			.flat_map(|(parent_id, siblings)| {
+				siblings.iter().enumerate().map(move |(index, orphan)| (orphan.used, parent_id, index))
+			})
+			.min_by_key(|(used, _, _)| *used)
+			.map(|(_, parent_id, index)| (parent_id.clone(), index));
+
+		if let Some((parent_id, index)) = oldest {
+			let siblings = self.orphans.get_mut(&parent_id)
+				.expect("Parent id was just found in the orphans; qed");
+			siblings.remove(index);
+			if siblings.is_empty() {
+				self.orphans.remove(&parent_id);
+			}
+			self.len -= 1;
+		}
+	}
+
+	fn take_children(&mut self, parent_id: &B::Identifier) -> Vec<B> {
+		let children = self.orphans.remove(parent_id).unwrap_or_default();
+		self.len -= children.len();
+		children.into_iter().map(|orphan| orphan.block).collect()
+	}
+
+	fn discard_descendants(&mut self, id: &B::Identifier) {
+		let mut discarding = vec![id.clone()];
+
+		while let Some(id) = discarding.pop() {
+			discarding.extend(self.take_children(&id).iter().map(|child| child.id()));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{Arc, Mutex};
+	use super::*;
+	use crate::testing::runtime::{chain, fork, TestBlock};
+
+	fn clock() -> (Arc<Mutex<Instant>>, impl Fn() -> Instant + Send + Sync + 'static) {
+		let now = Arc::new(Mutex::new(Instant::now()));
+		let shared = now.clone();
+		(now, move || *shared.lock().unwrap())
+	}
+
+	fn advance(now: &Mutex<Instant>, duration: Duration) {
+		let mut now = now.lock().unwrap();
+		*now += duration;
+	}
+
+	#[test]
+	fn imports_buffered_orphans_with_their_parent() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 3, 1);
+		let mut queue = ImportQueue::<TestBlock>::new(8);
+
+		assert_eq!(queue.import(chain.backend(), chain.import_lock(), chain.executor(), blocks[2].clone()).unwrap(), 0);
+		assert_eq!(queue.import(chain.backend(), chain.import_lock(), chain.executor(), blocks[1].clone()).unwrap(), 0);
+		assert_eq!(queue.len(), 2);
+		assert_eq!(queue.import(chain.backend(), chain.import_lock(), chain.executor(), blocks[0].clone()).unwrap(), 3);
+		assert!(queue.is_empty());
+		assert!(chain.backend().contains(&blocks[2].id()).unwrap());
+	}
+
+	#[test]
+	fn evicts_least_recently_used_when_full() {
+		let chain = chain();
+		let genesis = chain.head();
+		let (now, clock) = clock();
+		let mut queue = ImportQueue::<TestBlock>::new(2).with_clock(clock);
+		let orphans = (1..=3).map(|value| fork(&chain, &genesis, 2, value).pop().unwrap()).collect::<Vec<_>>();
+
+		for orphan in &orphans {
+			queue.import(chain.backend(), chain.import_lock(), chain.executor(), orphan.clone()).unwrap();
+			advance(&now, Duration::from_secs(1));
+		}
+
+		assert_eq!(queue.len(), 2);
+		assert!(queue.take_children(&orphans[0].parent_id().unwrap()).is_empty());
+		assert_eq!(queue.take_children(&orphans[2].parent_id().unwrap()), vec![orphans[2].clone()]);
+	}
+
+	#[test]
+	fn keeps_resent_and_extended_orphans_over_unused_ones() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut queue = ImportQueue::<TestBlock>::new(2);
+		let branches = (1..=3).map(|value| fork(&chain, &genesis, 3, value)).collect::<Vec<_>>();
+		let import = |queue: &mut ImportQueue<TestBlock>, block: &TestBlock| {
+			queue.import(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		};
+
+		// Resending the first orphan makes the second one least recently
+		// used.
+		import(&mut queue, &branches[0][1]);
+		import(&mut queue, &branches[1][1]);
+		import(&mut queue, &branches[0][1]);
+		import(&mut queue, &branches[2][1]);
+		assert_eq!(queue.len(), 2);
+		assert!(queue.take_children(&branches[1][0].id()).is_empty());
+
+		// Buffering a child of the first orphan uses it as well, so the
+		// third one is evicted instead.
+		import(&mut queue, &branches[0][2]);
+		assert_eq!(queue.len(), 2);
+		assert!(queue.take_children(&branches[2][0].id()).is_empty());
+		assert_eq!(queue.take_children(&branches[0][0].id()), vec![branches[0][1].clone()]);
+		assert_eq!(queue.take_children(&branches[0][1].id()), vec![branches[0][2].clone()]);
+	}
+
+	#[test]
+	fn evicts_expired_orphans() {
+		let chain = chain();
+		let genesis = chain.head();
+		let (now, clock) = clock();
+		let mut queue = ImportQueue::<TestBlock>::new(8)
+			.with_ttl(Duration::from_secs(10))
+			.with_clock(clock);
+		let old = fork(&chain, &genesis, 2, 1).pop().unwrap();
+		let young = fork(&chain, &genesis, 2, 2).pop().unwrap();
+
+		queue.import(chain.backend(), chain.import_lock(), chain.executor(), old).unwrap();
+		advance(&now, Duration::from_secs(6));
+		queue.import(chain.backend(), chain.import_lock(), chain.executor(), young.clone()).unwrap();
+		advance(&now, Duration::from_secs(6));
+		queue.evict_expired();
+
+		assert_eq!(queue.len(), 1);
+		assert_eq!(queue.take_children(&young.parent_id().unwrap()), vec![young]);
+	}
+
+	#[test]
+	fn duplicate_orphan_keeps_insertion_time() {
+		let chain = chain();
+		let genesis = chain.head();
+		let (now, clock) = clock();
+		let mut queue = ImportQueue::<TestBlock>::new(8)
+			.with_ttl(Duration::from_secs(10))
+			.with_clock(clock);
+		let orphan = fork(&chain, &genesis, 2, 1).pop().unwrap();
+
+		queue.import(chain.backend(), chain.import_lock(), chain.executor(), orphan.clone()).unwrap();
+		advance(&now, Duration::from_secs(6));
+		queue.import(chain.backend(), chain.import_lock(), chain.executor(), orphan).unwrap();
+		assert_eq!(queue.len(), 1);
+
+		advance(&now, Duration::from_secs(6));
+		queue.evict_expired();
+		assert!(queue.is_empty());
+	}
+
+	#[test]
+	fn drops_orphan_without_capacity() {
+		let chain = chain();
+		let genesis = chain.head();
+		let orphan = fork(&chain, &genesis, 2, 1).pop().unwrap();
+		let mut queue = ImportQueue::<TestBlock>::new(0);
+
+		match queue.import(chain.backend(), chain.import_lock(), chain.executor(), orphan) {
+			Err(ImportError::UnknownParent(_)) => (),
+			other => panic!("unexpected import result {:?}", other),
+		}
+	}
+
+	#[test]
+	fn imports_parent_then_child_without_buffering() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 2, 1);
+		let mut queue = ImportQueue::<TestBlock>::new(8);
+
+		assert_eq!(queue.import(chain.backend(), chain.import_lock(), chain.executor(), blocks[0].clone()).unwrap(), 1);
+		assert_eq!(queue.import(chain.backend(), chain.import_lock(), chain.executor(), blocks[1].clone()).unwrap(), 1);
+		assert!(queue.is_empty());
+		assert!(chain.backend().contains(&blocks[1].id()).unwrap());
+	}
+}
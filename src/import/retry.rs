@@ -0,0 +1,239 @@
+use std::thread;
+use std::time::Duration;
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportLock};
+use crate::import::{import_block, ImportError};
+
+/// Retry policy of imports failing with transient errors. The backoff starts
+/// at `initial_backoff` and doubles on each retry, up to `max_backoff`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+	/// Maximum number of attempts, including the first one.
+	pub max_attempts: u32,
+	/// Backoff before the first retry.
+	pub initial_backoff: Duration,
+	/// Maximum backoff between retries.
+	pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 3,
+			initial_backoff: Duration::from_millis(100),
+			max_backoff: Duration::from_secs(5),
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Policy that never retries.
+	pub fn none() -> Self {
+		Self { max_attempts: 1, ..Default::default() }
+	}
+
+	/// Backoff before the given retry, starting from zero.
+	pub fn backoff(&self, retry: u32) -> Duration {
+		self.initial_backoff.checked_mul(2u32.saturating_pow(retry))
+			.map(|backoff| core::cmp::min(backoff, self.max_backoff))
+			.unwrap_or(self.max_backoff)
+	}
+}
+
+/// Same as `import_block`, retrying according to the policy while execution
+/// fails with an error `is_transient` accepts. Any other error is returned
+/// immediately. A failed execution leaves the backend unchanged, so the
+/// block can be executed again.
+pub fn import_block_with_retry<Ba, E, F>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	policy: &RetryPolicy,
+	is_transient: F,
+	block: Ba::Block,
+) -> Result<(), ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	F: Fn(&E::Error) -> bool,
+{
+	let mut retry = 0;
+
+	loop {
+		match import_block(backend, import_lock, executor, block.clone()) {
+			Err(ImportError::Execution(err)) if is_transient(&err) && retry + 1 < policy.max_attempts => {
+				thread::sleep(policy.backoff(retry));
+				retry += 1;
+			},
+			result => return result,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{fmt, error as stderror};
+	use std::convert::Infallible;
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicU32, Ordering};
+	use crate::StorageExternalities;
+	use crate::backend::{KeyValueMemoryState, SharedMemoryBackend};
+	use crate::testing::runtime::{chain, failing, fork, Extrinsic, TestBlock};
+
+	#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+	enum StateError {
+		Unavailable,
+	}
+
+	#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+	enum Error {
+		State(StateError),
+		Invalid,
+	}
+
+	impl fmt::Display for Error {
+		fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+			write!(f, "{:?}", self)
+		}
+	}
+
+	impl stderror::Error for Error { }
+
+	/// State whose reads fail as unavailable while `failures` is non-zero,
+	/// each failed read using one of them up. Clones share the counters,
+	/// so they cover every state the backend hands out.
+	#[derive(Clone, Default)]
+	struct FlakyState {
+		inner: KeyValueMemoryState,
+		failures: Arc<AtomicU32>,
+		reads: Arc<AtomicU32>,
+	}
+
+	impl StorageExternalities<StateError> for FlakyState {
+		fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StateError> {
+			self.reads.fetch_add(1, Ordering::SeqCst);
+			let failing = self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |failures| {
+				failures.checked_sub(1)
+			});
+			if failing.is_ok() {
+				return Err(StateError::Unavailable)
+			}
+
+			StorageExternalities::<Infallible>::read_storage(&self.inner, key).map_err(|err| match err { })
+		}
+
+		fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+			StorageExternalities::<Infallible>::write_storage(&mut self.inner, key, value)
+		}
+
+		fn remove_storage(&mut self, key: &[u8]) {
+			StorageExternalities::<Infallible>::remove_storage(&mut self.inner, key)
+		}
+
+		fn for_each_storage(&self, f: &mut dyn FnMut(&[u8], &[u8])) {
+			StorageExternalities::<Infallible>::for_each_storage(&self.inner, f)
+		}
+	}
+
+	impl AsExternalities<dyn StorageExternalities<StateError>> for FlakyState {
+		fn as_externalities(&mut self) -> &mut (dyn StorageExternalities<StateError> + 'static) {
+			self
+		}
+	}
+
+	/// Executor adding the values of the block to a counter read from
+	/// state, with read errors surfacing as execution errors.
+	struct Counter;
+
+	impl BlockExecutor for Counter {
+		type Error = Error;
+		type Block = TestBlock;
+		type Externalities = dyn StorageExternalities<StateError> + 'static;
+		type Event = u64;
+
+		fn execute_block(
+			&self,
+			block: &TestBlock,
+			state: &mut Self::Externalities,
+		) -> Result<Vec<u64>, Error> {
+			let mut counter = match state.read_storage(b"counter").map_err(Error::State)? {
+				Some(value) => {
+					let mut bytes = [0u8; 8];
+					bytes.copy_from_slice(&value);
+					u64::from_be_bytes(bytes)
+				},
+				None => 0,
+			};
+			for extrinsic in &block.extrinsics {
+				match extrinsic {
+					Extrinsic::Add(value) => counter += value,
+					Extrinsic::Fail => return Err(Error::Invalid),
+				}
+			}
+
+			state.write_storage(b"counter".to_vec(), counter.to_be_bytes().to_vec());
+			Ok(vec![counter])
+		}
+	}
+
+	/// Backend with the genesis of the test chain, whose states fail the
+	/// given number of reads, and a child of genesis adding one.
+	fn flaky_chain(failures: u32) -> (SharedMemoryBackend<TestBlock, (), FlakyState>, FlakyState, TestBlock) {
+		let chain = chain();
+		let genesis = chain.head();
+		let block = fork(&chain, &genesis, 1, 1).remove(0);
+		let state = FlakyState { failures: Arc::new(AtomicU32::new(failures)), ..Default::default() };
+
+		(SharedMemoryBackend::new_with_genesis(genesis, state.clone()), state, block)
+	}
+
+	fn is_transient(err: &Error) -> bool {
+		*err == Error::State(StateError::Unavailable)
+	}
+
+	fn policy() -> RetryPolicy {
+		RetryPolicy { max_attempts: 3, initial_backoff: Duration::from_millis(0), ..Default::default() }
+	}
+
+	#[test]
+	fn retries_transient_state_errors() {
+		let (backend, state, block) = flaky_chain(2);
+
+		import_block_with_retry(&backend, &ImportLock::new(), &Counter, &policy(), is_transient, block.clone())
+			.unwrap();
+
+		assert_eq!(state.reads.load(Ordering::SeqCst), 3);
+		assert!(backend.contains(&block.id()).unwrap());
+		let imported = backend.state_at(&block.id()).unwrap();
+		assert_eq!(imported.read_storage(b"counter"), Ok(Some(1u64.to_be_bytes().to_vec())));
+	}
+
+	#[test]
+	fn gives_up_after_max_attempts() {
+		let (backend, state, block) = flaky_chain(5);
+
+		let result = import_block_with_retry(
+			&backend, &ImportLock::new(), &Counter, &policy(), is_transient, block.clone(),
+		);
+
+		assert!(matches!(result, Err(ImportError::Execution(Error::State(StateError::Unavailable)))));
+		assert_eq!(state.reads.load(Ordering::SeqCst), 3);
+		assert!(!backend.contains(&block.id()).unwrap());
+	}
+
+	#[test]
+	fn returns_deterministic_errors_at_once() {
+		let (backend, state, block) = flaky_chain(0);
+		let block = failing(block);
+
+		let result = import_block_with_retry(
+			&backend, &ImportLock::new(), &Counter, &policy(), is_transient, block.clone(),
+		);
+
+		assert!(matches!(result, Err(ImportError::Execution(Error::Invalid))));
+		assert_eq!(state.reads.load(Ordering::SeqCst), 1);
+		assert!(!backend.contains(&block.id()).unwrap());
+	}
+}
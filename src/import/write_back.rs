@@ -0,0 +1,374 @@
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, SharedCommittable, ChainQuery, Operation, ImportOperation, ImportLock};
+use crate::import::{ImportAction, ImportError, Metrics, NoopMetrics, Quarantine};
+use crate::import::pipeline::{check_block, execute_checked};
+use crate::import::quarantine::quarantined;
+
+/// When executed blocks are committed to the backend.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommitPolicy {
+	/// Commit every block as soon as it is executed.
+	#[default]
+	WriteThrough,
+	/// Keep executed blocks in memory, and commit them together once
+	/// `every` of them are pending.
+	WriteBack {
+		/// Number of pending blocks triggering a commit.
+		every: u32,
+	},
+}
+
+/// Durable record of the blocks executed but not yet committed, so that
+/// they can be executed again after a crash.
+pub trait BlockJournal<B: Block, Error> {
+	/// Record an executed block.
+	fn append(&mut self, block: &B) -> Result<(), Error>;
+	/// Forget committed blocks.
+	fn remove(&mut self, ids: &[B::Identifier]) -> Result<(), Error>;
+	/// Recorded blocks, in the order they were appended.
+	fn blocks(&self) -> Result<Vec<B>, Error>;
+}
+
+/// No journal, so pending blocks are lost on a crash.
+impl<B: Block, Error> BlockJournal<B, Error> for () {
+	fn append(&mut self, _block: &B) -> Result<(), Error> {
+		Ok(())
+	}
+
+	fn remove(&mut self, _ids: &[B::Identifier]) -> Result<(), Error> {
+		Ok(())
+	}
+
+	fn blocks(&self) -> Result<Vec<B>, Error> {
+		Ok(Vec::new())
+	}
+}
+
+/// Import buffer committing executed blocks according to a commit policy.
+/// Pending blocks are held in memory, and recorded in the journal, if any,
+/// from which `recover` executes them again after a crash. Blocks are
+/// checked as by `import_block`, pending ones standing in for their
+/// parents. Head is not changed.
+pub struct WriteBack<B, S, J = ()> {
+	policy: CommitPolicy,
+	pending: Vec<ImportOperation<B, S>>,
+	journal: J,
+}
+
+impl<B: Block, S: Clone> WriteBack<B, S> {
+	/// Create a new buffer with the given policy, and no journal.
+	pub fn new(policy: CommitPolicy) -> Self {
+		Self { policy, pending: Vec::new(), journal: () }
+	}
+}
+
+impl<B: Block, S: Clone, J> WriteBack<B, S, J> {
+	/// Record pending blocks in the given journal.
+	pub fn with_journal<J2>(self, journal: J2) -> WriteBack<B, S, J2> {
+		WriteBack { policy: self.policy, pending: self.pending, journal }
+	}
+
+	/// Number of executed blocks not yet committed.
+	pub fn len(&self) -> usize {
+		self.pending.len()
+	}
+
+	/// Whether all executed blocks are committed.
+	pub fn is_empty(&self) -> bool {
+		self.pending.is_empty()
+	}
+
+	/// Execute a block on top of its parent, which is either in the backend
+	/// or pending, and commit pending blocks if the policy asks for it.
+	pub fn import<Ba, E>(
+		&mut self,
+		backend: &Ba,
+		import_lock: &ImportLock,
+		executor: &E,
+		block: B,
+	) -> Result<(), ImportError<Ba::Error, E::Error>> where
+		Ba: Store<Block=B, State=S> + ChainQuery + SharedCommittable<Operation=Operation<B, S, <Ba as Store>::Auxiliary>>,
+		S: AsExternalities<E::Externalities>,
+		E: BlockExecutor<Block=B>,
+		B::Identifier: AsRef<[u8]>,
+		J: BlockJournal<B, Ba::Error>,
+	{
+		self.import_with_metrics(backend, import_lock, executor, &NoopMetrics, block)
+	}
+
+	/// Same as `import`, reporting progress to the given metrics.
+	pub fn import_with_metrics<Ba, E, M>(
+		&mut self,
+		backend: &Ba,
+		import_lock: &ImportLock,
+		executor: &E,
+		metrics: &M,
+		block: B,
+	) -> Result<(), ImportError<Ba::Error, E::Error>> where
+		Ba: Store<Block=B, State=S> + ChainQuery + SharedCommittable<Operation=Operation<B, S, <Ba as Store>::Auxiliary>>,
+		S: AsExternalities<E::Externalities>,
+		E: BlockExecutor<Block=B>,
+		B::Identifier: AsRef<[u8]>,
+		J: BlockJournal<B, Ba::Error>,
+		M: Metrics<Ba::Error, E::Error> + ?Sized,
+	{
+		let number = block.number();
+		match self.execute(backend, import_lock, executor, metrics, block, true) {
+			Ok(()) => {
+				metrics.on_block_imported(number);
+				Ok(())
+			},
+			Err(err) => {
+				metrics.on_import_failed(&err);
+				Err(err)
+			},
+		}
+	}
+
+	/// Same as `import`, refusing quarantined blocks as
+	/// `import_block_with_quarantine` does.
+	pub fn import_with_quarantine<Ba, E, F>(
+		&mut self,
+		backend: &Ba,
+		import_lock: &ImportLock,
+		executor: &E,
+		quarantine: &Quarantine<B::Identifier>,
+		is_poison: F,
+		block: B,
+	) -> Result<(), ImportError<Ba::Error, E::Error>> where
+		Ba: Store<Block=B, State=S> + ChainQuery + SharedCommittable<Operation=Operation<B, S, <Ba as Store>::Auxiliary>>,
+		S: AsExternalities<E::Externalities>,
+		E: BlockExecutor<Block=B>,
+		B::Identifier: AsRef<[u8]>,
+		J: BlockJournal<B, Ba::Error>,
+		F: Fn(&E::Error) -> bool,
+	{
+		quarantined(quarantine, is_poison, block.id(), || {
+			self.import(backend, import_lock, executor, block)
+		})
+	}
+
+	/// Execute again the blocks left in the journal by a crash, and return
+	/// how many of them are pending again. Blocks committed before the
+	/// crash are skipped.
+	pub fn recover<Ba, E>(
+		&mut self,
+		backend: &Ba,
+		import_lock: &ImportLock,
+		executor: &E,
+	) -> Result<usize, ImportError<Ba::Error, E::Error>> where
+		Ba: Store<Block=B, State=S> + ChainQuery + SharedCommittable<Operation=Operation<B, S, <Ba as Store>::Auxiliary>>,
+		S: AsExternalities<E::Externalities>,
+		E: BlockExecutor<Block=B>,
+		B::Identifier: AsRef<[u8]>,
+		J: BlockJournal<B, Ba::Error>,
+	{
+		let mut recovered = 0;
+		for block in self.journal.blocks().map_err(ImportError::Backend)? {
+			match self.execute(backend, import_lock, executor, &NoopMetrics, block, false) {
+				Ok(()) => recovered += 1,
+				Err(ImportError::AlreadyImported) => (),
+				Err(err) => return Err(err),
+			}
+		}
+
+		Ok(recovered)
+	}
+
+	/// Commit all pending blocks in one operation. Pending blocks are kept
+	/// if the commit fails.
+	pub fn flush<Ba>(
+		&mut self,
+		backend: &Ba,
+		import_lock: &ImportLock,
+	) -> Result<(), Ba::Error> where
+		Ba: Store<Block=B, State=S> + SharedCommittable<Operation=Operation<B, S, <Ba as Store>::Auxiliary>>,
+		J: BlockJournal<B, Ba::Error>,
+	{
+		self.commit(ImportAction::new(backend, import_lock.lock()))
+	}
+
+	fn execute<Ba, E, M>(
+		&mut self,
+		backend: &Ba,
+		import_lock: &ImportLock,
+		executor: &E,
+		metrics: &M,
+		block: B,
+		journal: bool,
+	) -> Result<(), ImportError<Ba::Error, E::Error>> where
+		Ba: Store<Block=B, State=S> + ChainQuery + SharedCommittable<Operation=Operation<B, S, <Ba as Store>::Auxiliary>>,
+		S: AsExternalities<E::Externalities>,
+		E: BlockExecutor<Block=B>,
+		B::Identifier: AsRef<[u8]>,
+		J: BlockJournal<B, Ba::Error>,
+		M: Metrics<Ba::Error, E::Error> + ?Sized,
+	{
+		let action = ImportAction::new(backend, import_lock.lock());
+
+		let context = check_block(backend, &block, &|id| self.pending(id).map(|op| &op.block))?;
+		let mut state = match self.pending(&context.parent_id) {
+			Some(parent) => parent.state.clone(),
+			None => backend.state_at(&context.parent_id).map_err(ImportError::Backend)?,
+		};
+		execute_checked(executor, metrics, &block, &context, state.as_externalities(), |block, context, state| {
+			executor.execute_block_with_context(block, context, state).map(|_| ())
+		})?;
+
+		if journal {
+			self.journal.append(&block).map_err(ImportError::Backend)?;
+		}
+		self.pending.push(ImportOperation { block, state });
+
+		let due = match self.policy {
+			CommitPolicy::WriteThrough => true,
+			CommitPolicy::WriteBack { every } => self.pending.len() >= every as usize,
+		};
+		if due {
+			self.commit(action).map_err(ImportError::Backend)?;
+		}
+
+		Ok(())
+	}
+
+	fn commit<Ba>(&mut self, mut action: ImportAction<Ba>) -> Result<(), Ba::Error> where
+		Ba: Store<Block=B, State=S> + SharedCommittable<Operation=Operation<B, S, <Ba as Store>::Auxiliary>>,
+		J: BlockJournal<B, Ba::Error>,
+	{
+		if self.pending.is_empty() {
+			return Ok(())
+		}
+
+		for op in self.pending.iter().cloned() {
+			action.import_raw(op);
+		}
+		action.commit()?;
+		let ids = self.pending.drain(..).map(|op| op.block.id()).collect::<Vec<_>>();
+		self.journal.remove(&ids)
+	}
+
+	fn pending(&self, id: &B::Identifier) -> Option<&ImportOperation<B, S>> {
+		self.pending.iter().find(|op| &op.block.id() == id)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::{Arc, Mutex};
+	use crate::backend::Finalizable;
+	use crate::import::import_if_deeper;
+	use crate::testing::runtime::{chain, fork, Id, TestBlock};
+
+	/// Journal shared between a write-back buffer and its replacement after
+	/// a crash.
+	#[derive(Clone, Default)]
+	struct SharedJournal(Arc<Mutex<Vec<TestBlock>>>);
+
+	impl<Error> BlockJournal<TestBlock, Error> for SharedJournal {
+		fn append(&mut self, block: &TestBlock) -> Result<(), Error> {
+			self.0.lock().expect("Lock is poisoned").push(block.clone());
+			Ok(())
+		}
+
+		fn remove(&mut self, ids: &[Id]) -> Result<(), Error> {
+			self.0.lock().expect("Lock is poisoned").retain(|block| !ids.contains(&block.id()));
+			Ok(())
+		}
+
+		fn blocks(&self) -> Result<Vec<TestBlock>, Error> {
+			Ok(self.0.lock().expect("Lock is poisoned").clone())
+		}
+	}
+
+	#[test]
+	fn write_back_commits_every_fifth_block() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 6, 1);
+		let mut write_back = WriteBack::new(CommitPolicy::WriteBack { every: 5 });
+
+		for (index, block) in blocks.iter().take(4).enumerate() {
+			write_back.import(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+			assert_eq!(write_back.len(), index + 1);
+			assert!(!chain.backend().contains(&block.id()).unwrap());
+		}
+
+		write_back.import(chain.backend(), chain.import_lock(), chain.executor(), blocks[4].clone()).unwrap();
+		assert!(write_back.is_empty());
+		for block in blocks.iter().take(5) {
+			assert!(chain.backend().contains(&block.id()).unwrap());
+		}
+
+		write_back.import(chain.backend(), chain.import_lock(), chain.executor(), blocks[5].clone()).unwrap();
+		assert!(!chain.backend().contains(&blocks[5].id()).unwrap());
+		write_back.flush(chain.backend(), chain.import_lock()).unwrap();
+		assert!(chain.backend().contains(&blocks[5].id()).unwrap());
+	}
+
+	#[test]
+	fn write_through_commits_every_block() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut write_back = WriteBack::new(CommitPolicy::WriteThrough);
+
+		for block in fork(&chain, &genesis, 2, 1) {
+			write_back.import(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+			assert!(write_back.is_empty());
+			assert!(chain.backend().contains(&block.id()).unwrap());
+		}
+	}
+
+	#[test]
+	fn recovers_pending_blocks_from_journal_after_crash() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 7, 1);
+		let journal = SharedJournal::default();
+		let policy = CommitPolicy::WriteBack { every: 5 };
+
+		let mut write_back = WriteBack::new(policy).with_journal(journal.clone());
+		for block in &blocks[..3] {
+			write_back.import(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+		drop(write_back);
+		assert!(!chain.backend().contains(&blocks[0].id()).unwrap());
+
+		let mut write_back = WriteBack::new(policy).with_journal(journal.clone());
+		assert_eq!(write_back.recover(chain.backend(), chain.import_lock(), chain.executor()).unwrap(), 3);
+		assert_eq!(write_back.len(), 3);
+		for block in &blocks[3..] {
+			write_back.import(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		assert_eq!(write_back.len(), 2);
+		assert!(chain.backend().contains(&blocks[4].id()).unwrap());
+		assert_eq!(journal.0.lock().unwrap().clone(), blocks[5..].to_vec());
+	}
+
+	#[test]
+	fn rejects_pending_parent_no_longer_descending_from_finalized() {
+		let chain = chain();
+		let genesis = chain.head();
+		let pending = fork(&chain, &genesis, 3, 1);
+		let mut write_back = WriteBack::new(CommitPolicy::WriteBack { every: 5 });
+		for block in &pending[..2] {
+			write_back.import(chain.backend(), chain.import_lock(), chain.executor(), block.clone()).unwrap();
+		}
+
+		for block in fork(&chain, &genesis, 2, 2) {
+			import_if_deeper(chain.backend(), chain.import_lock(), chain.executor(), block).unwrap();
+		}
+		let finalized = chain.backend().block_at(&chain.head().parent_id().unwrap()).unwrap();
+		chain.backend().clone().finalize(&finalized.id()).unwrap();
+
+		assert!(matches!(
+			write_back.import(chain.backend(), chain.import_lock(), chain.executor(), pending[2].clone()),
+			Err(ImportError::BelowFinalized)
+		));
+		assert!(matches!(
+			write_back.import(chain.backend(), chain.import_lock(), chain.executor(), pending[1].clone()),
+			Err(ImportError::AlreadyImported)
+		));
+	}
+}
@@ -0,0 +1,115 @@
+//! Inherent data assembled at block authoring time.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Identifier of an inherent value.
+pub type InherentIdentifier = [u8; 8];
+
+/// Inherent values keyed by their identifiers.
+pub type InherentData = BTreeMap<InherentIdentifier, Vec<u8>>;
+
+/// Provider of one inherent value.
+pub trait InherentDataProvider: Send + Sync {
+	/// Identifier of the provided value.
+	fn identifier(&self) -> InherentIdentifier;
+	/// Provide the encoded value.
+	fn provide(&self) -> Vec<u8>;
+}
+
+/// Registry of inherent data providers.
+#[derive(Default)]
+pub struct InherentDataProviders {
+	providers: BTreeMap<InherentIdentifier, Box<dyn InherentDataProvider>>,
+}
+
+impl InherentDataProviders {
+	/// Create an empty registry.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Register a provider, replacing any provider with the same identifier.
+	pub fn register<P: InherentDataProvider + 'static>(&mut self, provider: P) {
+		self.providers.insert(provider.identifier(), Box::new(provider));
+	}
+
+	/// Assemble the values of all registered providers.
+	pub fn create_inherent_data(&self) -> InherentData {
+		self.providers.iter()
+			.map(|(identifier, provider)| (*identifier, provider.provide()))
+			.collect()
+	}
+}
+
+/// Identifier of the timestamp inherent.
+pub const TIMESTAMP_INHERENT: InherentIdentifier = *b"timstap0";
+
+/// Provider of the current unix time in seconds, encoded as little endian
+/// `u64`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimestampProvider;
+
+impl TimestampProvider {
+	/// Current unix time in seconds.
+	pub fn now() -> u64 {
+		SystemTime::now().duration_since(UNIX_EPOCH)
+			.map(|duration| duration.as_secs())
+			.unwrap_or(0)
+	}
+
+	/// Decode the timestamp from inherent data.
+	pub fn timestamp(data: &InherentData) -> Option<u64> {
+		let value = data.get(&TIMESTAMP_INHERENT)?;
+		let mut bytes = [0; 8];
+		if value.len() != bytes.len() {
+			return None
+		}
+		bytes.copy_from_slice(value);
+		Some(u64::from_le_bytes(bytes))
+	}
+}
+
+impl InherentDataProvider for TimestampProvider {
+	fn identifier(&self) -> InherentIdentifier {
+		TIMESTAMP_INHERENT
+	}
+
+	fn provide(&self) -> Vec<u8> {
+		Self::now().to_le_bytes().to_vec()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct Fixed(InherentIdentifier, Vec<u8>);
+
+	impl InherentDataProvider for Fixed {
+		fn identifier(&self) -> InherentIdentifier {
+			self.0
+		}
+
+		fn provide(&self) -> Vec<u8> {
+			self.1.clone()
+		}
+	}
+
+	#[test]
+	fn assembles_decodable_timestamp() {
+		let mut providers = InherentDataProviders::new();
+		providers.register(TimestampProvider);
+		providers.register(Fixed(*b"fixed000", vec![1, 2, 3]));
+
+		let before = TimestampProvider::now();
+		let data = providers.create_inherent_data();
+		let timestamp = TimestampProvider::timestamp(&data).unwrap();
+
+		assert!(before <= timestamp && timestamp <= TimestampProvider::now());
+		assert_eq!(data[b"fixed000"], vec![1, 2, 3]);
+
+		providers.register(Fixed(TIMESTAMP_INHERENT, vec![1]));
+		assert_eq!(TimestampProvider::timestamp(&providers.create_inherent_data()), None);
+	}
+}
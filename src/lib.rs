@@ -5,3 +5,18 @@
 pub use blockchain_core::*;
 pub mod import;
 pub mod backend;
+pub mod consensus;
+pub mod chain;
+pub mod builder;
+pub mod author;
+pub mod inherent;
+pub mod proof;
+pub mod migration;
+pub mod format;
+pub mod testing;
+pub mod sync;
+pub mod pool;
+#[cfg(feature = "codec")]
+pub mod storage;
+#[cfg(feature = "rpc")]
+pub mod rpc;
@@ -0,0 +1,86 @@
+//! Storage migrations keyed on the storage version.
+
+use std::{fmt, error as stderror};
+use crate::StorageExternalities;
+
+/// Reserved key of the storage version, stored as little endian `u32`. A
+/// missing version is version zero.
+pub const VERSION_KEY: &[u8] = b":version";
+
+/// Migration of the storage layout to a new version.
+pub trait Migration<E> {
+	/// Error of the migration.
+	type Error;
+
+	/// Storage version after the migration.
+	fn version(&self) -> u32;
+	/// Migrate the storage from the previous version.
+	fn migrate(&self, state: &mut dyn StorageExternalities<E>) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug)]
+/// Errors of running migrations.
+pub enum MigrationError<E, ME> {
+	/// Storage error
+	Storage(E),
+	/// Storage version is not a valid version
+	CorruptedVersion,
+	/// Migration error
+	Migration(ME),
+}
+
+impl<E: fmt::Debug, ME: fmt::Debug> fmt::Display for MigrationError<E, ME> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl<E: fmt::Debug, ME: fmt::Debug> stderror::Error for MigrationError<E, ME> { }
+
+/// Get the storage version.
+pub fn storage_version<E>(
+	state: &dyn StorageExternalities<E>,
+) -> Result<u32, MigrationError<E, core::convert::Infallible>> {
+	match state.read_storage(VERSION_KEY).map_err(MigrationError::Storage)? {
+		Some(value) => {
+			let mut bytes = [0; 4];
+			if value.len() != bytes.len() {
+				return Err(MigrationError::CorruptedVersion)
+			}
+			bytes.copy_from_slice(&value);
+			Ok(u32::from_le_bytes(bytes))
+		},
+		None => Ok(0),
+	}
+}
+
+/// Set the storage version.
+pub fn set_storage_version<E>(state: &mut dyn StorageExternalities<E>, version: u32) {
+	state.write_storage(VERSION_KEY.to_vec(), version.to_le_bytes().to_vec());
+}
+
+/// Run the migrations to versions above the current storage version, in
+/// ascending order of versions, recording the version after each of them.
+/// Returns the resulting storage version.
+pub fn run_migrations<E, ME>(
+	state: &mut dyn StorageExternalities<E>,
+	migrations: &[&dyn Migration<E, Error=ME>],
+) -> Result<u32, MigrationError<E, ME>> {
+	let mut version = storage_version(state).map_err(|err| match err {
+		MigrationError::Storage(err) => MigrationError::Storage(err),
+		_ => MigrationError::CorruptedVersion,
+	})?;
+
+	let mut pending = migrations.iter()
+		.filter(|migration| migration.version() > version)
+		.collect::<Vec<_>>();
+	pending.sort_by_key(|migration| migration.version());
+
+	for migration in pending {
+		migration.migrate(state).map_err(MigrationError::Migration)?;
+		version = migration.version();
+		set_storage_version(state, version);
+	}
+
+	Ok(version)
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+/// Token bucket rate limiter, keyed by an opaque submitter id such as a peer
+/// or client address. Every submitter starts with a full bucket of `burst`
+/// tokens, refilled at `rate` tokens per second, and each submission takes
+/// one token.
+pub struct RateLimiter {
+	burst: f64,
+	rate: f64,
+	buckets: HashMap<Vec<u8>, Bucket>,
+	clock: Box<dyn Fn() -> Instant + Send + Sync>,
+}
+
+impl RateLimiter {
+	/// Allow bursts of `burst` submissions per submitter, and `rate`
+	/// submissions per second on average.
+	pub fn new(burst: u32, rate: f64) -> Self {
+		Self {
+			burst: f64::from(burst),
+			rate,
+			buckets: HashMap::new(),
+			clock: Box::new(Instant::now),
+		}
+	}
+
+	/// Use the given clock instead of the system one.
+	pub fn with_clock<C: Fn() -> Instant + Send + Sync + 'static>(mut self, clock: C) -> Self {
+		self.clock = Box::new(clock);
+		self
+	}
+
+	/// Take a token from the bucket of the submitter. Returns whether the
+	/// submission is within the limit.
+	pub fn acquire(&mut self, submitter: &[u8]) -> bool {
+		let now = (self.clock)();
+		let burst = self.burst;
+		let rate = self.rate;
+		let bucket = self.buckets.entry(submitter.to_vec())
+			.or_insert(Bucket { tokens: burst, updated: now });
+
+		let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+		bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+		bucket.updated = now;
+
+		if bucket.tokens < 1.0 {
+			return false
+		}
+
+		bucket.tokens -= 1.0;
+		true
+	}
+
+	/// Forget submitters whose bucket has refilled, which behave as new
+	/// ones. Keeps memory bounded when many submitters come and go.
+	pub fn prune(&mut self) {
+		let now = (self.clock)();
+		let burst = self.burst;
+		let rate = self.rate;
+		self.buckets.retain(|_, bucket| {
+			let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+			bucket.tokens + elapsed * rate < burst
+		});
+	}
+}
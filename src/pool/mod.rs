@@ -0,0 +1,250 @@
+//! Pool of pending extrinsics.
+
+use crate::{ExtrinsicBuilder, ExtrinsicFilter, AllowAll, DecodeExtrinsic, AsExternalities, TransactionalExternalities};
+use crate::builder::BlockBuilder;
+use core::cmp::Reverse;
+use std::{fmt, error as stderror};
+
+mod limiter;
+
+pub use self::limiter::RateLimiter;
+
+#[derive(Debug)]
+/// Errors of submitting an extrinsic to the pool.
+pub enum PoolError {
+	/// Submitter exceeded its submission rate
+	RateLimited,
+}
+
+impl fmt::Display for PoolError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for PoolError { }
+
+/// Transaction pool holding submitted extrinsics until they are included
+/// in a block.
+pub struct TransactionPool<E: ExtrinsicBuilder> {
+	executor: E,
+	filter: Box<dyn ExtrinsicFilter<E::Extrinsic> + Send + Sync>,
+	limiter: Option<RateLimiter>,
+	pending: Vec<E::Extrinsic>,
+}
+
+impl<E: ExtrinsicBuilder> TransactionPool<E> where
+	E::Extrinsic: Clone + PartialEq,
+{
+	/// Create a new empty pool, accepting all extrinsics.
+	pub fn new(executor: E) -> Self {
+		Self { executor, filter: Box::new(AllowAll), limiter: None, pending: Vec::new() }
+	}
+
+	/// Only accept extrinsics allowed by the filter.
+	pub fn with_filter<F>(mut self, filter: F) -> Self where
+		F: ExtrinsicFilter<E::Extrinsic> + Send + Sync + 'static,
+	{
+		self.filter = Box::new(filter);
+		self
+	}
+
+	/// Limit the submission rate of each submitter of `submit_from`.
+	pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+		self.limiter = Some(limiter);
+		self
+	}
+
+	/// Submit an extrinsic on behalf of an untrusted submitter, identified
+	/// by an opaque id. Submissions over the submitter's rate are rejected
+	/// before reaching the filter. Returns whether the filter accepted it.
+	pub fn submit_from(
+		&mut self,
+		submitter: &[u8],
+		extrinsic: E::Extrinsic,
+	) -> Result<bool, PoolError> {
+		if let Some(limiter) = self.limiter.as_mut() {
+			if !limiter.acquire(submitter) {
+				return Err(PoolError::RateLimited)
+			}
+		}
+
+		Ok(self.submit(extrinsic))
+	}
+
+	/// Submit an extrinsic to the pool, bypassing the rate limiter. Returns
+	/// whether the filter accepted it.
+	pub fn submit(&mut self, extrinsic: E::Extrinsic) -> bool {
+		if !self.filter.allow(&extrinsic) {
+			return false
+		}
+
+		self.pending.push(extrinsic);
+		true
+	}
+
+	/// Decode an extrinsic and submit it to the pool. Malformed extrinsics
+	/// are rejected. Returns whether the filter accepted it.
+	pub fn submit_encoded(&mut self, bytes: &[u8]) -> Result<bool, E::Error> where
+		E: DecodeExtrinsic,
+	{
+		let extrinsic = self.executor.decode_extrinsic(bytes)?;
+		Ok(self.submit(extrinsic))
+	}
+
+	/// Pending extrinsics, in submission order.
+	pub fn pending(&self) -> &[E::Extrinsic] {
+		&self.pending
+	}
+
+	/// Number of pending extrinsics.
+	pub fn len(&self) -> usize {
+		self.pending.len()
+	}
+
+	/// Whether the pool is empty.
+	pub fn is_empty(&self) -> bool {
+		self.pending.is_empty()
+	}
+
+	/// Get extrinsics that can be applied, in order, to a block built on
+	/// top of the parent block and state. Extrinsics are packed in
+	/// descending order of priority, and in submission order among equal
+	/// priorities. Extrinsics failing the dry run are dropped from the pool.
+	/// Extrinsics that do not fit in the block's extrinsic or weight limit
+	/// are kept for later blocks. Weight refunded by applied extrinsics is
+	/// available to the following ones.
+	pub fn ready<S>(
+		&mut self,
+		parent_block: &E::Block,
+		state: S,
+		inherent: E::Inherent,
+	) -> Vec<E::Extrinsic> where
+		S: AsExternalities<E::Externalities> + TransactionalExternalities,
+	{
+		let mut builder = match BlockBuilder::new(&self.executor, parent_block, state, inherent) {
+			Ok(builder) => builder,
+			Err(_) => return Vec::new(),
+		};
+
+		let executor = &self.executor;
+		self.pending.sort_by_key(|extrinsic| Reverse(executor.priority(extrinsic)));
+
+		let mut ready = Vec::new();
+		let mut remaining = Vec::new();
+
+		for extrinsic in self.pending.drain(..) {
+			let extrinsic_weight = self.executor.weight(&extrinsic);
+			let fits = ready.len() < self.executor.max_extrinsics() &&
+				builder.weight().saturating_add(extrinsic_weight) <= self.executor.max_weight();
+
+			if !fits {
+				remaining.push(extrinsic);
+				continue
+			}
+
+			if builder.push(extrinsic.clone()).is_ok() {
+				remaining.push(extrinsic.clone());
+				ready.push(extrinsic);
+			}
+		}
+
+		self.pending = remaining;
+		ready
+	}
+
+	/// Preview the state as it would be after the pending extrinsics are
+	/// applied to a block built on top of the parent block and state, in the
+	/// same order as `ready`. Extrinsics that fail to apply or do not fit
+	/// are skipped. The pool is left untouched, and nothing is committed;
+	/// the given state should be a copy of the parent state.
+	pub fn preview_state<S>(
+		&self,
+		parent_block: &E::Block,
+		state: S,
+		inherent: E::Inherent,
+	) -> Result<S, E::Error> where
+		S: AsExternalities<E::Externalities> + TransactionalExternalities,
+	{
+		let mut pending = self.pending.clone();
+		pending.sort_by_key(|extrinsic| Reverse(self.executor.priority(extrinsic)));
+
+		let mut builder = BlockBuilder::new(&self.executor, parent_block, state, inherent)?;
+		builder.push_many(pending);
+		let (_, state) = builder.build()?;
+		Ok(state)
+	}
+
+	/// Remove extrinsics that were included in a block.
+	pub fn prune(&mut self, applied: &[E::Extrinsic]) {
+		self.pending.retain(|extrinsic| !applied.contains(extrinsic));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::{Arc, Mutex};
+	use std::time::{Duration, Instant};
+	use crate::Block;
+	use crate::backend::ChainQuery;
+	use crate::testing::runtime::{chain, read_counter, Extrinsic, TestExecutor};
+
+	#[test]
+	fn drops_extrinsics_failing_dry_run() {
+		let chain = chain();
+		let genesis = chain.head();
+		let state = || chain.backend().state_at(&genesis.id()).unwrap();
+		let mut pool = TransactionPool::new(TestExecutor::default());
+
+		assert!(pool.submit(Extrinsic::Add(1)));
+		assert!(pool.submit(Extrinsic::Fail));
+		assert!(pool.submit(Extrinsic::Add(2)));
+
+		let ready = pool.ready(&genesis, state(), ());
+		assert_eq!(ready, vec![Extrinsic::Add(1), Extrinsic::Add(2)]);
+		assert_eq!(pool.pending(), &ready[..]);
+		assert_eq!(pool.ready(&genesis, state(), ()), ready);
+
+		pool.prune(&ready);
+		assert!(pool.is_empty());
+	}
+
+	#[test]
+	fn previews_pending_extrinsics_without_committing() {
+		let chain = chain();
+		let genesis = chain.head();
+		let state = || chain.backend().state_at(&genesis.id()).unwrap();
+		let mut pool = TransactionPool::new(TestExecutor::default());
+
+		assert!(pool.submit(Extrinsic::Add(1)));
+		assert!(pool.submit(Extrinsic::Fail));
+		assert!(pool.submit(Extrinsic::Add(2)));
+
+		let preview = pool.preview_state(&genesis, state(), ()).unwrap();
+		assert_eq!(read_counter(&preview), 3);
+		assert_eq!(read_counter(&state()), 0);
+		assert_eq!(pool.len(), 3);
+	}
+
+	#[test]
+	fn throttles_bursts_but_accepts_steady_rate() {
+		let start = Instant::now();
+		let elapsed = Arc::new(Mutex::new(Duration::from_secs(0)));
+		let clock = elapsed.clone();
+		let limiter = RateLimiter::new(3, 1.0)
+			.with_clock(move || start + *clock.lock().expect("Lock is poisoned"));
+		let mut pool = TransactionPool::new(TestExecutor::default()).with_rate_limiter(limiter);
+
+		let burst = (1..=5).map(|value| pool.submit_from(b"spammer", Extrinsic::Add(value))).collect::<Vec<_>>();
+		assert!(burst[..3].iter().all(|submitted| matches!(submitted, Ok(true))));
+		assert!(burst[3..].iter().all(|submitted| matches!(submitted, Err(PoolError::RateLimited))));
+		assert!(matches!(pool.submit_from(b"other", Extrinsic::Add(1)), Ok(true)));
+
+		for value in 1..=10 {
+			*elapsed.lock().expect("Lock is poisoned") += Duration::from_secs(1);
+			assert!(matches!(pool.submit_from(b"steady", Extrinsic::Add(value)), Ok(true)));
+		}
+		assert_eq!(pool.len(), 14);
+	}
+}
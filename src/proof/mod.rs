@@ -0,0 +1,260 @@
+//! Merkle storage roots and proofs of storage values against them.
+//!
+//! Leaves are the storage entries in ascending key order. Leaves and inner
+//! nodes are hashed with distinct prefixes, and the last node of a level
+//! with an odd number of nodes is carried up unchanged.
+
+use crate::StorageExternalities;
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Proof of a single storage entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofEntry<T> {
+	/// Index of the entry among all storage entries.
+	pub index: usize,
+	/// Key of the entry.
+	pub key: Vec<u8>,
+	/// Value of the entry.
+	pub value: Vec<u8>,
+	/// Sibling hashes from the leaf up to the root.
+	pub path: Vec<T>,
+}
+
+/// Proof of storage entries against a storage root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageProof<T> {
+	/// Number of entries in the storage.
+	pub leaf_count: usize,
+	/// Proven entries.
+	pub entries: Vec<ProofEntry<T>>,
+}
+
+fn leaf_hash<T, F: Fn(&[u8]) -> T>(key: &[u8], value: &[u8], hash: &F) -> T {
+	let mut data = Vec::with_capacity(1 + 4 + key.len() + value.len());
+	data.push(LEAF_PREFIX);
+	data.extend_from_slice(&(key.len() as u32).to_le_bytes());
+	data.extend_from_slice(key);
+	data.extend_from_slice(value);
+	hash(&data)
+}
+
+fn node_hash<T: AsRef<[u8]>, F: Fn(&[u8]) -> T>(left: &T, right: &T, hash: &F) -> T {
+	let mut data = Vec::with_capacity(1 + left.as_ref().len() + right.as_ref().len());
+	data.push(NODE_PREFIX);
+	data.extend_from_slice(left.as_ref());
+	data.extend_from_slice(right.as_ref());
+	hash(&data)
+}
+
+fn next_level<T: AsRef<[u8]> + Clone, F: Fn(&[u8]) -> T>(level: &[T], hash: &F) -> Vec<T> {
+	level.chunks(2)
+		.map(|pair| match pair {
+			[left, right] => node_hash(left, right, hash),
+			_ => pair[0].clone(),
+		})
+		.collect()
+}
+
+/// Merkle root of the storage. The root of empty storage is the hash of no
+/// data.
+pub fn storage_root<S, E, T, F>(state: &S, hash: F) -> T where
+	S: StorageExternalities<E> + ?Sized,
+	T: AsRef<[u8]> + Clone,
+	F: Fn(&[u8]) -> T,
+{
+	let mut level = Vec::new();
+	state.for_each_storage(&mut |key, value| {
+		level.push(leaf_hash(key, value, &hash));
+	});
+
+	if level.is_empty() {
+		return hash(&[])
+	}
+
+	while level.len() > 1 {
+		level = next_level(&level, &hash);
+	}
+	level.pop().expect("Level is checked to be non-empty; qed")
+}
+
+/// Generate a proof of the given keys. Keys that are not in the storage are
+/// left out of the proof.
+pub fn generate_proof<S, E, T, F>(state: &S, keys: &[&[u8]], hash: F) -> StorageProof<T> where
+	S: StorageExternalities<E> + ?Sized,
+	T: AsRef<[u8]> + Clone,
+	F: Fn(&[u8]) -> T,
+{
+	let mut storage = Vec::new();
+	let mut level = Vec::new();
+	state.for_each_storage(&mut |key, value| {
+		level.push(leaf_hash(key, value, &hash));
+		storage.push((key.to_vec(), value.to_vec()));
+	});
+	let leaf_count = level.len();
+
+	let mut entries = keys.iter()
+		.filter_map(|key| {
+			let index = storage.binary_search_by(|(k, _)| k.as_slice().cmp(key)).ok()?;
+			let (key, value) = storage[index].clone();
+			Some(ProofEntry { index, key, value, path: Vec::new() })
+		})
+		.collect::<Vec<_>>();
+
+	let mut indexes = entries.iter().map(|entry| entry.index).collect::<Vec<_>>();
+	while level.len() > 1 {
+		for (entry, index) in entries.iter_mut().zip(indexes.iter_mut()) {
+			if let Some(sibling) = level.get(*index ^ 1) {
+				entry.path.push(sibling.clone());
+			}
+			*index /= 2;
+		}
+		level = next_level(&level, &hash);
+	}
+
+	StorageProof { leaf_count, entries }
+}
+
+/// Verify the value of a key against the root. Returns `None` if the key is
+/// not in the proof or the proof does not match the root. Absence of a key
+/// is never proven.
+pub fn verify_proof<T, F>(root: &T, proof: &StorageProof<T>, key: &[u8], hash: F) -> Option<Vec<u8>> where
+	T: AsRef<[u8]> + Clone + PartialEq,
+	F: Fn(&[u8]) -> T,
+{
+	let entry = proof.entries.iter().find(|entry| entry.key == key)?;
+	if entry.index >= proof.leaf_count {
+		return None
+	}
+
+	let mut current = leaf_hash(&entry.key, &entry.value, &hash);
+	let mut index = entry.index;
+	let mut count = proof.leaf_count;
+	let mut path = entry.path.iter();
+
+	while count > 1 {
+		if index ^ 1 < count {
+			let sibling = path.next()?;
+			current = if index % 2 == 0 {
+				node_hash(&current, sibling, &hash)
+			} else {
+				node_hash(sibling, &current, &hash)
+			};
+		}
+		index /= 2;
+		count = count.div_ceil(2);
+	}
+
+	if path.next().is_some() || &current != root {
+		return None
+	}
+
+	Some(entry.value.clone())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::BTreeMap;
+	use core::convert::Infallible;
+	use crate::backend::KeyValueMemoryState;
+	use crate::testing::runtime::hash;
+
+	type Op = (Vec<u8>, Option<Vec<u8>>);
+
+	/// Xorshift generator, so that failing cases are reproducible from the
+	/// seed.
+	struct Rng(u64);
+
+	impl Rng {
+		fn next(&mut self) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0
+		}
+
+		fn below(&mut self, bound: usize) -> usize {
+			(self.next() % bound as u64) as usize
+		}
+
+		fn shuffle<T>(&mut self, items: &mut [T]) {
+			for i in (1..items.len()).rev() {
+				items.swap(i, self.below(i + 1));
+			}
+		}
+	}
+
+	fn random_ops(rng: &mut Rng) -> Vec<Op> {
+		(0..1 + rng.below(40))
+			.map(|_| {
+				let key = vec![rng.below(12) as u8; 1 + rng.below(3)];
+				let value = if rng.below(4) == 0 {
+					None
+				} else {
+					Some((0..rng.below(6)).map(|_| rng.next() as u8).collect())
+				};
+				(key, value)
+			})
+			.collect()
+	}
+
+	fn apply(ops: &[Op]) -> KeyValueMemoryState {
+		let mut state = KeyValueMemoryState::default();
+		for (key, value) in ops {
+			match value {
+				Some(value) => StorageExternalities::<Infallible>::write_storage(&mut state, key.clone(), value.clone()),
+				None => StorageExternalities::<Infallible>::remove_storage(&mut state, key),
+			}
+		}
+		state
+	}
+
+	fn root(state: &KeyValueMemoryState) -> [u8; 8] {
+		storage_root::<_, Infallible, _, _>(state, hash)
+	}
+
+	#[test]
+	fn storage_root_is_independent_of_write_order() {
+		for seed in 1..=512 {
+			let mut rng = Rng(seed);
+			let ops = random_ops(&mut rng);
+			let state = apply(&ops);
+
+			// Reorder freely, as long as the last operation on every key
+			// stays after all others on it, so the final state is the same.
+			let mut last = BTreeMap::new();
+			for (index, (key, _)) in ops.iter().enumerate() {
+				last.insert(key.clone(), index);
+			}
+			let (mut finals, mut earlier): (Vec<_>, Vec<_>) = ops.iter().cloned().enumerate()
+				.partition(|(index, (key, _))| last[key] == *index);
+			rng.shuffle(&mut earlier);
+			rng.shuffle(&mut finals);
+			let reordered = earlier.into_iter().chain(finals).map(|(_, op)| op).collect::<Vec<_>>();
+			let reordered_state = apply(&reordered);
+
+			let seeded_state = KeyValueMemoryState::new_with_storage(state.as_ref().clone());
+
+			assert_eq!(reordered_state.as_ref(), state.as_ref(), "seed {}", seed);
+			assert_eq!(root(&reordered_state), root(&state), "seed {}", seed);
+			assert_eq!(root(&seeded_state), root(&state), "seed {}", seed);
+		}
+	}
+
+	#[test]
+	fn verifies_counter_against_its_root_only() {
+		let state = apply(&[
+			(b"alice".to_vec(), Some(vec![1])),
+			(b"bob".to_vec(), Some(vec![2])),
+			(b"counter".to_vec(), Some(vec![42, 0, 0, 0])),
+			(b"zed".to_vec(), Some(vec![3])),
+		]);
+		let proof = generate_proof::<_, Infallible, _, _>(&state, &[b"counter"], hash);
+
+		assert_eq!(verify_proof(&root(&state), &proof, b"counter", hash), Some(vec![42, 0, 0, 0]));
+		assert_eq!(verify_proof(&[0; 8], &proof, b"counter", hash), None);
+		assert_eq!(verify_proof(&root(&state), &proof, b"alice", hash), None);
+	}
+}
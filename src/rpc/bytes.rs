@@ -0,0 +1,46 @@
+use std::fmt;
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de::Error as _};
+use crate::format::{encode_hex, decode_hex};
+
+/// Byte values, serialized as `0x` prefixed hex strings.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Bytes(pub Vec<u8>);
+
+impl From<Vec<u8>> for Bytes {
+	fn from(value: Vec<u8>) -> Self {
+		Self(value)
+	}
+}
+
+impl fmt::Display for Bytes {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&encode_hex(&self.0))
+	}
+}
+
+impl Serialize for Bytes {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&encode_hex(&self.0))
+	}
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		decode_hex(&value).map(Bytes).map_err(D::Error::custom)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn serializes_as_prefixed_hex() {
+		let bytes = Bytes(vec![0x01, 0xab]);
+		let json = serde_json::to_string(&bytes).unwrap();
+		assert_eq!(json, "\"0x01ab\"");
+		assert_eq!(serde_json::from_str::<Bytes>(&json).unwrap(), bytes);
+		assert!(serde_json::from_str::<Bytes>("\"0x1\"").is_err());
+	}
+}
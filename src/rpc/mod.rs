@@ -0,0 +1,7 @@
+//! JSON-RPC server exposing chain state over HTTP.
+
+mod bytes;
+mod server;
+
+pub use self::bytes::Bytes;
+pub use self::server::{start, RpcHandle};
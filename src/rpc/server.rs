@@ -0,0 +1,285 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use crate::{Block, ReadOnlyExternalities};
+use crate::backend::ChainQuery;
+use crate::rpc::Bytes;
+
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const BACKEND_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+	method: String,
+	#[serde(default)]
+	params: Value,
+	#[serde(default)]
+	id: Value,
+}
+
+struct RpcError {
+	code: i64,
+	message: String,
+}
+
+impl RpcError {
+	fn new<M: fmt::Display>(code: i64, message: M) -> Self {
+		Self { code, message: message.to_string() }
+	}
+}
+
+/// Handle of a running RPC server. The server is shut down when the handle
+/// is stopped or dropped.
+pub struct RpcHandle {
+	address: SocketAddr,
+	running: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl RpcHandle {
+	/// Address the server is listening on.
+	pub fn local_addr(&self) -> SocketAddr {
+		self.address
+	}
+
+	/// Stop the server and wait for it to exit.
+	pub fn stop(mut self) {
+		self.shutdown();
+	}
+
+	fn shutdown(&mut self) {
+		if let Some(thread) = self.thread.take() {
+			self.running.store(false, Ordering::SeqCst);
+			// Wake up the blocking accept so the server can observe the flag.
+			let _ = TcpStream::connect(self.address);
+			let _ = thread.join();
+		}
+	}
+}
+
+impl Drop for RpcHandle {
+	fn drop(&mut self) {
+		self.shutdown();
+	}
+}
+
+/// Start a JSON-RPC server over HTTP, serving `chain_getBlock`,
+/// `chain_getBestBlock`, `state_getStorage` and `state_getStorageAt` from
+/// the backend. `state_getStorage` reads from the state of the current head,
+/// and `state_getStorageAt` from the state of the given block.
+pub fn start<Ba, E, Ad>(address: Ad, backend: Ba) -> io::Result<RpcHandle> where
+	Ba: ChainQuery + Send + 'static,
+	Ba::Block: Serialize,
+	<Ba::Block as Block>::Identifier: Serialize + DeserializeOwned,
+	Ba::State: ReadOnlyExternalities<E>,
+	E: fmt::Display + 'static,
+	Ad: ToSocketAddrs,
+{
+	let listener = TcpListener::bind(address)?;
+	let address = listener.local_addr()?;
+	let running = Arc::new(AtomicBool::new(true));
+
+	let thread = {
+		let running = running.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				if !running.load(Ordering::SeqCst) {
+					break
+				}
+
+				if let Ok(stream) = stream {
+					let _ = handle_connection::<Ba, E>(stream, &backend);
+				}
+			}
+		})
+	};
+
+	Ok(RpcHandle { address, running, thread: Some(thread) })
+}
+
+fn handle_connection<Ba, E>(mut stream: TcpStream, backend: &Ba) -> io::Result<()> where
+	Ba: ChainQuery,
+	Ba::Block: Serialize,
+	<Ba::Block as Block>::Identifier: Serialize + DeserializeOwned,
+	Ba::State: ReadOnlyExternalities<E>,
+	E: fmt::Display,
+{
+	stream.set_read_timeout(Some(READ_TIMEOUT))?;
+	let mut reader = BufReader::new(stream.try_clone()?);
+
+	let mut line = String::new();
+	reader.read_line(&mut line)?;
+
+	let mut content_length = 0;
+	loop {
+		line.clear();
+		if reader.read_line(&mut line)? == 0 {
+			break
+		}
+
+		let header = line.trim_end();
+		if header.is_empty() {
+			break
+		}
+
+		if let Some((name, value)) = header.split_once(':') {
+			if name.trim().eq_ignore_ascii_case("content-length") {
+				content_length = value.trim().parse()
+					.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid content length"))?;
+			}
+		}
+	}
+
+	if content_length > MAX_BODY_SIZE {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"))
+	}
+
+	let mut body = vec![0; content_length];
+	reader.read_exact(&mut body)?;
+
+	let response = serde_json::to_vec(&handle_request::<Ba, E>(backend, &body))
+		.expect("JSON values always serialize; qed");
+
+	write!(
+		stream,
+		"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		response.len(),
+	)?;
+	stream.write_all(&response)?;
+	stream.flush()
+}
+
+fn handle_request<Ba, E>(backend: &Ba, body: &[u8]) -> Value where
+	Ba: ChainQuery,
+	Ba::Block: Serialize,
+	<Ba::Block as Block>::Identifier: Serialize + DeserializeOwned,
+	Ba::State: ReadOnlyExternalities<E>,
+	E: fmt::Display,
+{
+	let request: Request = match serde_json::from_slice(body) {
+		Ok(request) => request,
+		Err(err) => return error_response(Value::Null, RpcError::new(PARSE_ERROR, err)),
+	};
+
+	match call::<Ba, E>(backend, &request.method, &request.params) {
+		Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": request.id }),
+		Err(err) => error_response(request.id, err),
+	}
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+	json!({
+		"jsonrpc": "2.0",
+		"error": { "code": error.code, "message": error.message },
+		"id": id,
+	})
+}
+
+fn param<T: DeserializeOwned>(params: &Value, index: usize) -> Result<T, RpcError> {
+	let value = params.get(index).cloned()
+		.ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing parameter {}", index)))?;
+	serde_json::from_value(value)
+		.map_err(|err| RpcError::new(INVALID_PARAMS, err))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+	serde_json::to_value(value)
+		.map_err(|err| RpcError::new(BACKEND_ERROR, err))
+}
+
+fn call<Ba, E>(backend: &Ba, method: &str, params: &Value) -> Result<Value, RpcError> where
+	Ba: ChainQuery,
+	Ba::Block: Serialize,
+	<Ba::Block as Block>::Identifier: Serialize + DeserializeOwned,
+	Ba::State: ReadOnlyExternalities<E>,
+	E: fmt::Display,
+{
+	let backend_error = |err: Ba::Error| RpcError::new(BACKEND_ERROR, err);
+
+	match method {
+		"chain_getBlock" => {
+			let id: <Ba::Block as Block>::Identifier = param(params, 0)?;
+			if !backend.contains(&id).map_err(backend_error)? {
+				return Ok(Value::Null)
+			}
+			to_value(backend.block_at(&id).map_err(backend_error)?)
+		},
+		"chain_getBestBlock" => {
+			to_value(backend.block_at(&backend.head()).map_err(backend_error)?)
+		},
+		"state_getStorage" => {
+			let key: Bytes = param(params, 0)?;
+			let state = backend.state_at(&backend.head()).map_err(backend_error)?;
+			to_value(read_storage::<E>(&state, &key.0)?.map(Bytes))
+		},
+		"state_getStorageAt" => {
+			let key: Bytes = param(params, 0)?;
+			let id: <Ba::Block as Block>::Identifier = param(params, 1)?;
+			if !backend.contains(&id).map_err(backend_error)? {
+				return Ok(Value::Null)
+			}
+			let state = backend.state_at(&id).map_err(backend_error)?;
+			to_value(read_storage::<E>(&state, &key.0)?.map(Bytes))
+		},
+		_ => Err(RpcError::new(METHOD_NOT_FOUND, format!("method {} not found", method))),
+	}
+}
+
+fn read_storage<E: fmt::Display>(
+	state: &dyn ReadOnlyExternalities<E>,
+	key: &[u8],
+) -> Result<Option<Vec<u8>>, RpcError> {
+	state.read_storage(key)
+		.map_err(|err| RpcError::new(BACKEND_ERROR, err))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::convert::Infallible;
+	use crate::testing::runtime::{chain, Extrinsic};
+
+	fn request(address: SocketAddr, method: &str, params: Value) -> Value {
+		let body = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 }).to_string();
+		let mut stream = TcpStream::connect(address).unwrap();
+		write!(
+			stream,
+			"POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+			body.len(), body,
+		).unwrap();
+
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		let (_, body) = response.split_once("\r\n\r\n").unwrap();
+		serde_json::from_str(body).unwrap()
+	}
+
+	#[test]
+	fn serves_storage_of_head() {
+		let chain = chain();
+		chain.build_block((), vec![Extrinsic::Add(5)]).unwrap();
+		let server = start::<_, Infallible, _>("127.0.0.1:0", chain.backend().clone()).unwrap();
+
+		let response = request(server.local_addr(), "state_getStorage", json!([Bytes(b"counter".to_vec())]));
+		assert_eq!(response["result"], json!(Bytes(5u64.to_be_bytes().to_vec())));
+
+		let response = request(server.local_addr(), "state_getStorage", json!(["0x00"]));
+		assert_eq!(response["result"], Value::Null);
+
+		let response = request(server.local_addr(), "chain_unknown", json!([]));
+		assert_eq!(response["error"]["code"], json!(METHOD_NOT_FOUND));
+
+		server.stop();
+	}
+}
@@ -0,0 +1,138 @@
+//! Typed access to storage externalities, with values encoded in SCALE and
+//! keys namespaced by Sha3-256 hashes.
+
+use core::marker::PhantomData;
+use std::convert::Infallible;
+use std::{fmt, error as stderror};
+use parity_codec::{Encode, Decode};
+use sha3::{Digest, Sha3_256};
+use crate::StorageExternalities;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Value in storage does not decode, with its key.
+pub struct StateCorruption(pub Vec<u8>);
+
+impl fmt::Display for StateCorruption {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "State corruption at key {}", crate::format::encode_hex(&self.0))
+	}
+}
+
+impl stderror::Error for StateCorruption { }
+
+/// Decoding reads over storage externalities.
+pub trait ReadDecoded {
+	/// Read and decode the value at key. A value that fails to decode is
+	/// reported with its key.
+	fn read_decoded<T: Decode>(&self, key: &[u8]) -> Result<Option<T>, StateCorruption>;
+}
+
+impl<S: StorageExternalities<Infallible> + ?Sized> ReadDecoded for S {
+	fn read_decoded<T: Decode>(&self, key: &[u8]) -> Result<Option<T>, StateCorruption> {
+		let mut decoded = None;
+		self.read_storage_ref(key, &mut |value| {
+			decoded = value.map(|mut value| T::decode(&mut value));
+		}).expect("Error is infallible; qed");
+
+		match decoded {
+			Some(value) => Ok(Some(value.ok_or_else(|| StateCorruption(key.to_vec()))?)),
+			None => Ok(None),
+		}
+	}
+}
+
+/// Storage key of an item of a module, as `sha3(encoded module ++ encoded
+/// item)`, so that modules can use the same item names.
+pub fn storage_key(module: &[u8], item: &[u8]) -> Vec<u8> {
+	let mut hasher = Sha3_256::new();
+	hasher.input(module.encode());
+	hasher.input(item.encode());
+	hasher.result().to_vec()
+}
+
+/// Typed storage map, with keys namespaced under a prefix.
+pub struct StorageMap<K, V> {
+	prefix: &'static [u8],
+	_marker: PhantomData<(K, V)>,
+}
+
+impl<K: Encode, V: Encode + Decode> StorageMap<K, V> {
+	/// Create a map whose keys are namespaced under the prefix.
+	pub const fn new(prefix: &'static [u8]) -> Self {
+		Self { prefix, _marker: PhantomData }
+	}
+
+	/// Storage key of the given map key, as `sha3(prefix ++ encoded key)`.
+	pub fn storage_key(&self, key: &K) -> Vec<u8> {
+		let mut hasher = Sha3_256::new();
+		hasher.input(self.prefix);
+		hasher.input(key.encode());
+		hasher.result().to_vec()
+	}
+
+	/// Get the value of the key.
+	pub fn get<S>(&self, state: &S, key: &K) -> Result<Option<V>, StateCorruption> where
+		S: StorageExternalities<Infallible> + ?Sized,
+	{
+		state.read_decoded(&self.storage_key(key))
+	}
+
+	/// Set the value of the key.
+	pub fn insert<S>(&self, state: &mut S, key: &K, value: &V) where
+		S: StorageExternalities<Infallible> + ?Sized,
+	{
+		state.write_storage(self.storage_key(key), value.encode());
+	}
+
+	/// Remove the value of the key.
+	pub fn remove<S>(&self, state: &mut S, key: &K) where
+		S: StorageExternalities<Infallible> + ?Sized,
+	{
+		state.remove_storage(&self.storage_key(key));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::KeyValueMemoryState;
+
+	const VALUES: StorageMap<u32, u128> = StorageMap::new(b"Test:values");
+
+	#[test]
+	fn inserts_gets_and_removes_map_values() {
+		let mut state = KeyValueMemoryState::default();
+
+		assert_eq!(VALUES.get(&state, &1).unwrap(), None);
+		VALUES.insert(&mut state, &1, &10);
+		VALUES.insert(&mut state, &2, &20);
+		assert_eq!(VALUES.get(&state, &1).unwrap(), Some(10));
+		assert_eq!(VALUES.get(&state, &2).unwrap(), Some(20));
+
+		VALUES.insert(&mut state, &1, &11);
+		VALUES.remove(&mut state, &2);
+		assert_eq!(VALUES.get(&state, &1).unwrap(), Some(11));
+		assert_eq!(VALUES.get(&state, &2).unwrap(), None);
+	}
+
+	#[test]
+	fn reports_key_of_corrupted_map_value() {
+		let key = VALUES.storage_key(&1);
+		let state = KeyValueMemoryState::new_with_storage(vec![(key.clone(), vec![1, 2])]);
+
+		assert_eq!(VALUES.get(&state, &1), Err(StateCorruption(key)));
+		assert_eq!(state.read_decoded::<u64>(b"absent"), Ok(None));
+	}
+
+	#[test]
+	fn namespaces_keys() {
+		let other = StorageMap::<u32, u128>::new(b"Test:others");
+
+		assert_eq!(VALUES.storage_key(&1), VALUES.storage_key(&1));
+		assert_ne!(VALUES.storage_key(&1), VALUES.storage_key(&2));
+		assert_ne!(VALUES.storage_key(&1), other.storage_key(&1));
+		assert_ne!(storage_key(b"Counter", b"value"), storage_key(b"Balances", b"value"));
+		assert_ne!(storage_key(b"ab", b"c"), storage_key(b"a", b"bc"));
+		assert_eq!(storage_key(b"Counter", b"value").len(), 32);
+	}
+}
@@ -0,0 +1,63 @@
+use crate::{Block, BlockContext, BlockExecutor, HeaderVerifier, NullExternalities};
+use crate::backend::{Store, ChainQuery, SharedCommittable, Operation, ImportLock};
+use crate::import::ImportError;
+use crate::sync::apply_blocks;
+
+/// Executor of a header-only chain, whose blocks are headers checked by a
+/// header verifier. State is never touched, so a header backend stores
+/// `()` as the state of each header.
+pub struct HeaderExecutor<'a, V: ?Sized>(&'a V);
+
+impl<'a, V: HeaderVerifier + ?Sized> HeaderExecutor<'a, V> {
+	/// Create a header executor from its verifier.
+	pub fn new(verifier: &'a V) -> Self {
+		HeaderExecutor(verifier)
+	}
+}
+
+impl<'a, V: HeaderVerifier + ?Sized> BlockExecutor for HeaderExecutor<'a, V> {
+	type Error = V::Error;
+	type Block = V::Header;
+	type Externalities = dyn NullExternalities + 'static;
+	type Event = ();
+
+	fn execute_block(
+		&self,
+		header: &V::Header,
+		_state: &mut Self::Externalities,
+	) -> Result<Vec<()>, V::Error> {
+		self.0.verify_header(header)?;
+		Ok(Vec::new())
+	}
+
+	fn execute_block_with_context(
+		&self,
+		header: &V::Header,
+		context: &BlockContext<<V::Header as Block>::Identifier>,
+		_state: &mut Self::Externalities,
+	) -> Result<Vec<()>, V::Error> {
+		self.0.verify_header_with_context(header, context)?;
+		Ok(Vec::new())
+	}
+
+	fn verify_block(&self, header: &V::Header) -> Result<(), V::Error> {
+		self.0.verify_header(header)
+	}
+}
+
+/// Import headers in order into a header-only backend, verifying each one
+/// against its parent, as `apply_blocks` does for full blocks. Returns the
+/// number of imported headers.
+pub fn apply_headers<Ba, V, I>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	verifier: &V,
+	headers: I,
+) -> Result<usize, ImportError<Ba::Error, V::Error>> where
+	Ba: ChainQuery<State=()> + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	V: HeaderVerifier<Header=Ba::Block> + ?Sized,
+	I: IntoIterator<Item=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	apply_blocks(backend, import_lock, &HeaderExecutor::new(verifier), headers)
+}
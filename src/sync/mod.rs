@@ -0,0 +1,173 @@
+//! Block sync between backends.
+
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, ChainQuery, SharedCommittable, Operation, ImportLock};
+use crate::import::{import_if_deeper, ImportError};
+
+mod reputation;
+mod headers;
+mod protocol;
+
+pub use self::headers::{HeaderExecutor, apply_headers};
+pub use self::protocol::{
+	BlockRequest, BlockResponse, Direction, serve_request, handle_response,
+	MAX_BLOCKS_PER_REQUEST,
+};
+pub use self::reputation::{
+	PeerReputation, ReputationTracker, penalty,
+	INVALID_BLOCK_PENALTY, UNUSABLE_BLOCK_PENALTY, DEFAULT_DISCONNECT_THRESHOLD,
+};
+
+/// Get up to `count` canonical blocks following `from`, in order. Returns
+/// nothing if `from` is not canonical.
+pub fn request_blocks<Ba: ChainQuery>(
+	backend: &Ba,
+	from: &<Ba::Block as Block>::Identifier,
+	count: usize,
+) -> Result<Vec<Ba::Block>, Ba::Error> {
+	if !backend.is_canon(from)? {
+		return Ok(Vec::new())
+	}
+
+	let from_depth = backend.depth_at(from)?;
+	let mut blocks = Vec::new();
+
+	for depth in (from_depth + 1)..(from_depth + 1 + count) {
+		match backend.lookup_canon_depth(depth)? {
+			Some(id) => blocks.push(backend.block_at(&id)?),
+			None => break,
+		}
+	}
+
+	Ok(blocks)
+}
+
+/// Import blocks in order, stopping at the first failure. Blocks already in
+/// the backend are skipped. Head is moved to each imported block that is
+/// deeper than the current head. Returns the number of imported blocks.
+pub fn apply_blocks<Ba, E, I>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	blocks: I,
+) -> Result<usize, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	I: IntoIterator<Item=Ba::Block>,
+{
+	let mut imported = 0;
+
+	for block in blocks {
+		match import_if_deeper(backend, import_lock, executor, block) {
+			Ok(_) => imported += 1,
+			Err(ImportError::AlreadyImported) => continue,
+			Err(err) => return Err(err),
+		}
+	}
+
+	Ok(imported)
+}
+
+/// Same as `apply_blocks` for blocks received from `peer`, reporting a
+/// failure to the peer's reputation.
+pub fn apply_blocks_from<Ba, E, I, R, P>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	reputation: &mut R,
+	peer: &P,
+	blocks: I,
+) -> Result<usize, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+	I: IntoIterator<Item=Ba::Block>,
+	R: PeerReputation<P>,
+{
+	let result = apply_blocks(backend, import_lock, executor, blocks);
+	if let Err(err) = &result {
+		reputation.report(peer, err);
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use std::thread;
+	use super::*;
+	use crate::testing::runtime::{chain, failing, fork};
+
+	#[test]
+	fn moves_head_to_deeper_fork_only() {
+		let chain = chain();
+		let genesis = chain.head();
+		let short = fork(&chain, &genesis, 2, 1);
+		let long = fork(&chain, &genesis, 3, 2);
+
+		assert_eq!(apply_blocks(chain.backend(), chain.import_lock(), chain.executor(), short.clone()).unwrap(), 2);
+		assert_eq!(chain.head(), short[1]);
+		assert_eq!(apply_blocks(chain.backend(), chain.import_lock(), chain.executor(), long.clone()).unwrap(), 3);
+		assert_eq!(chain.head(), long[2]);
+
+		let shorter = fork(&chain, &short[1], 1, 3);
+		apply_blocks(chain.backend(), chain.import_lock(), chain.executor(), shorter).unwrap();
+		assert_eq!(chain.head(), long[2]);
+	}
+
+	#[test]
+	fn skips_already_imported_blocks() {
+		let chain = chain();
+		let genesis = chain.head();
+		let blocks = fork(&chain, &genesis, 3, 1);
+
+		apply_blocks(chain.backend(), chain.import_lock(), chain.executor(), blocks[..2].to_vec()).unwrap();
+		assert_eq!(apply_blocks(chain.backend(), chain.import_lock(), chain.executor(), blocks.clone()).unwrap(), 1);
+		assert_eq!(chain.head(), blocks[2]);
+	}
+
+	#[test]
+	fn concurrent_forks_leave_deepest_head() {
+		let chain = chain();
+		let genesis = chain.head();
+		let forks = (1..=4).map(|value| fork(&chain, &genesis, 4 + value as usize, value)).collect::<Vec<_>>();
+
+		thread::scope(|scope| {
+			for blocks in &forks {
+				let chain = &chain;
+				scope.spawn(move || {
+					for block in blocks {
+						apply_blocks(chain.backend(), chain.import_lock(), chain.executor(), vec![block.clone()]).unwrap();
+					}
+				});
+			}
+		});
+
+		assert_eq!(chain.head(), *forks[3].last().unwrap());
+	}
+
+	#[test]
+	fn disconnects_peer_after_three_invalid_blocks() {
+		let chain = chain();
+		let genesis = chain.head();
+		let mut reputation = ReputationTracker::default();
+
+		for value in 1..=3 {
+			assert!(!reputation.should_disconnect(&"bad"));
+			let invalid = failing(fork(&chain, &genesis, 1, value).remove(0));
+			assert!(apply_blocks_from(
+				chain.backend(), chain.import_lock(), chain.executor(), &mut reputation, &"bad", vec![invalid],
+			).is_err());
+		}
+		assert_eq!(reputation.score(&"bad"), -3 * INVALID_BLOCK_PENALTY);
+		assert!(reputation.should_disconnect(&"bad"));
+
+		let valid = fork(&chain, &genesis, 1, 1);
+		apply_blocks_from(chain.backend(), chain.import_lock(), chain.executor(), &mut reputation, &"good", valid).unwrap();
+		assert_eq!(reputation.score(&"good"), 0);
+		assert!(!reputation.should_disconnect(&"good"));
+	}
+}
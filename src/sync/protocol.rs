@@ -0,0 +1,195 @@
+use crate::{Block, BlockExecutor, AsExternalities};
+use crate::backend::{Store, ChainQuery, SharedCommittable, Operation, ImportLock};
+use crate::import::ImportError;
+use crate::sync::apply_blocks;
+
+/// Default maximum number of blocks served for a single request.
+pub const MAX_BLOCKS_PER_REQUEST: u32 = 128;
+
+/// Direction of a block range request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+	/// Canonical blocks from the start block towards head.
+	Ascending,
+	/// Ancestors from the start block towards genesis.
+	Descending,
+}
+
+/// Request for a range of blocks, starting at and including `start_id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockRequest<Id> {
+	/// Id of the first block of the range.
+	pub start_id: Id,
+	/// Maximum number of blocks in the range.
+	pub count: u32,
+	/// Direction of the range.
+	pub direction: Direction,
+}
+
+/// Response to a block range request, in the requested direction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockResponse<B> {
+	/// Blocks of the range.
+	pub blocks: Vec<B>,
+}
+
+/// Serve a block range request, returning at most `max_count` blocks
+/// whatever the requested count. Ascending ranges only follow the
+/// canonical chain, so they are empty if the start block is not canonical.
+/// Descending ranges follow parent ids, stopping at genesis. Unknown start
+/// blocks are served an empty range.
+pub fn serve_request<Ba: ChainQuery>(
+	backend: &Ba,
+	request: &BlockRequest<<Ba::Block as Block>::Identifier>,
+	max_count: u32,
+) -> Result<BlockResponse<Ba::Block>, Ba::Error> {
+	let count = core::cmp::min(request.count, max_count) as usize;
+	let mut blocks = Vec::new();
+
+	if count == 0 || !backend.contains(&request.start_id)? {
+		return Ok(BlockResponse { blocks })
+	}
+
+	match request.direction {
+		Direction::Ascending => {
+			if !backend.is_canon(&request.start_id)? {
+				return Ok(BlockResponse { blocks })
+			}
+
+			let start_depth = backend.depth_at(&request.start_id)?;
+			for depth in start_depth..(start_depth + count) {
+				match backend.lookup_canon_depth(depth)? {
+					Some(id) => blocks.push(backend.block_at(&id)?),
+					None => break,
+				}
+			}
+		},
+		Direction::Descending => {
+			let mut current = Some(request.start_id.clone());
+			while let Some(id) = current {
+				if blocks.len() >= count {
+					break
+				}
+
+				let block = backend.block_at(&id)?;
+				current = block.parent_id();
+				blocks.push(block);
+			}
+		},
+	}
+
+	Ok(BlockResponse { blocks })
+}
+
+/// Import the blocks of a response with `apply_blocks`. Blocks are
+/// imported in ascending order of number, whatever the direction they were
+/// served in. Returns the number of imported blocks.
+pub fn handle_response<Ba, E>(
+	backend: &Ba,
+	import_lock: &ImportLock,
+	executor: &E,
+	response: BlockResponse<Ba::Block>,
+) -> Result<usize, ImportError<Ba::Error, E::Error>> where
+	Ba: ChainQuery + SharedCommittable<Operation=Operation<<Ba as Store>::Block, <Ba as Store>::State, <Ba as Store>::Auxiliary>>,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: BlockExecutor<Block=Ba::Block>,
+	<Ba::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	let mut blocks = response.blocks;
+	blocks.sort_by_key(|block| block.number());
+
+	apply_blocks(backend, import_lock, executor, blocks)
+}
+
+#[cfg(feature = "codec")]
+mod codec {
+	use parity_codec::{Encode, Decode, Input, Output};
+	use super::{Direction, BlockRequest, BlockResponse};
+
+	impl Encode for Direction {
+		fn encode_to<T: Output>(&self, dest: &mut T) {
+			dest.push_byte(match self {
+				Direction::Ascending => 0,
+				Direction::Descending => 1,
+			})
+		}
+	}
+
+	impl Decode for Direction {
+		fn decode<I: Input>(input: &mut I) -> Option<Self> {
+			match input.read_byte()? {
+				0 => Some(Direction::Ascending),
+				1 => Some(Direction::Descending),
+				_ => None,
+			}
+		}
+	}
+
+	impl<Id: Encode> Encode for BlockRequest<Id> {
+		fn encode_to<T: Output>(&self, dest: &mut T) {
+			self.start_id.encode_to(dest);
+			self.count.encode_to(dest);
+			self.direction.encode_to(dest);
+		}
+	}
+
+	impl<Id: Decode> Decode for BlockRequest<Id> {
+		fn decode<I: Input>(input: &mut I) -> Option<Self> {
+			Some(BlockRequest {
+				start_id: Decode::decode(input)?,
+				count: Decode::decode(input)?,
+				direction: Decode::decode(input)?,
+			})
+		}
+	}
+
+	impl<B: Encode> Encode for BlockResponse<B> {
+		fn encode_to<T: Output>(&self, dest: &mut T) {
+			self.blocks.encode_to(dest);
+		}
+	}
+
+	impl<B: Decode> Decode for BlockResponse<B> {
+		fn decode<I: Input>(input: &mut I) -> Option<Self> {
+			Some(BlockResponse { blocks: Decode::decode(input)? })
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::import::import_if_deeper;
+	use crate::testing::runtime::{chain, fork};
+
+	#[test]
+	fn serves_descending_range_imported_by_fresh_backend() {
+		let server = chain();
+		let genesis = server.head();
+		let blocks = fork(&server, &genesis, 5, 1);
+		for block in &blocks {
+			import_if_deeper(server.backend(), server.import_lock(), server.executor(), block.clone()).unwrap();
+		}
+
+		let request = BlockRequest { start_id: blocks[4].id(), count: 10, direction: Direction::Descending };
+		let capped = serve_request(server.backend(), &request, 3).unwrap();
+		assert_eq!(capped.blocks, blocks[2..].iter().rev().cloned().collect::<Vec<_>>());
+
+		let response = serve_request(server.backend(), &request, MAX_BLOCKS_PER_REQUEST).unwrap();
+		assert_eq!(response.blocks.len(), 6);
+		assert_eq!(response.blocks.last(), Some(&genesis));
+
+		let client = chain();
+		assert_eq!(handle_response(client.backend(), client.import_lock(), client.executor(), response).unwrap(), 5);
+		assert_eq!(client.head(), blocks[4]);
+	}
+
+	#[cfg(feature = "codec")]
+	#[test]
+	fn request_round_trips_through_scale() {
+		use parity_codec::{Encode, Decode};
+
+		let request = BlockRequest { start_id: [7u8; 8], count: 3, direction: Direction::Descending };
+		assert_eq!(BlockRequest::decode(&mut &request.encode()[..]), Some(request));
+	}
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use crate::import::ImportError;
+
+/// Score lost by a peer for a block that can never be imported.
+pub const INVALID_BLOCK_PENALTY: i32 = 100;
+/// Score lost by a peer for a block that cannot be imported here, but that
+/// an honest peer on another fork may send.
+pub const UNUSABLE_BLOCK_PENALTY: i32 = 10;
+/// Default score at or below which peers should be disconnected.
+pub const DEFAULT_DISCONNECT_THRESHOLD: i32 = -3 * INVALID_BLOCK_PENALTY;
+
+/// Reputation of peers blocks are received from.
+pub trait PeerReputation<P> {
+	/// Report a block from the peer failing to import.
+	fn report<BE, EE>(&mut self, peer: &P, error: &ImportError<BE, EE>);
+	/// Whether the peer should be disconnected.
+	fn should_disconnect(&self, peer: &P) -> bool;
+}
+
+/// Penalty of a failed import. Errors of the local backend are not the
+/// peer's fault, and are not penalized.
+pub fn penalty<BE, EE>(error: &ImportError<BE, EE>) -> i32 {
+	match error {
+		ImportError::Backend(_) | ImportError::AlreadyImported |
+		ImportError::NotCanonical | ImportError::CyclicChain |
+		ImportError::AncestryTooDeep | ImportError::StaleTip => 0,
+		ImportError::UnknownParent(_) | ImportError::BelowFinalized |
+		ImportError::ReorgTooDeep => UNUSABLE_BLOCK_PENALTY,
+		ImportError::Malformed | ImportError::GenesisAlreadyExists |
+		ImportError::GenesisMismatch | ImportError::Quarantined |
+		ImportError::ParentHashMismatch | ImportError::StateRootMismatch(_) |
+		ImportError::Execution(_) => INVALID_BLOCK_PENALTY,
+	}
+}
+
+/// In-memory reputation tracker. Peers start at a score of zero, and lose
+/// the penalty of each reported error.
+pub struct ReputationTracker<P> {
+	scores: HashMap<P, i32>,
+	threshold: i32,
+}
+
+impl<P: Eq + Hash> Default for ReputationTracker<P> {
+	fn default() -> Self {
+		Self::new(DEFAULT_DISCONNECT_THRESHOLD)
+	}
+}
+
+impl<P: Eq + Hash> ReputationTracker<P> {
+	/// Create a new tracker disconnecting peers at or below `threshold`.
+	pub fn new(threshold: i32) -> Self {
+		Self { scores: HashMap::new(), threshold }
+	}
+
+	/// Current score of the peer.
+	pub fn score(&self, peer: &P) -> i32 {
+		self.scores.get(peer).cloned().unwrap_or(0)
+	}
+
+	/// Forget the peer, for example once it is disconnected.
+	pub fn remove(&mut self, peer: &P) {
+		self.scores.remove(peer);
+	}
+}
+
+impl<P: Clone + Eq + Hash> PeerReputation<P> for ReputationTracker<P> {
+	fn report<BE, EE>(&mut self, peer: &P, error: &ImportError<BE, EE>) {
+		let score = self.scores.entry(peer.clone()).or_insert(0);
+		*score = score.saturating_sub(penalty(error));
+	}
+
+	fn should_disconnect(&self, peer: &P) -> bool {
+		self.score(peer) <= self.threshold
+	}
+}
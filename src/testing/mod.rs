@@ -0,0 +1,109 @@
+//! Test harness for building chains on top of a memory backend.
+
+use crate::{Block, BuildGenesis, ExtrinsicBuilder, AsExternalities};
+use crate::backend::{SharedMemoryBackend, KeyValueMemoryState, MemoryError, ChainQuery, ImportLock};
+use crate::builder::BlockBuilder;
+use crate::import::{import_block, ImportAction, ImportError};
+
+#[cfg(test)]
+pub(crate) mod runtime;
+
+/// Chain of an executor over a memory backend, building blocks on the
+/// current head.
+pub struct TestChain<E: ExtrinsicBuilder, F> {
+	executor: E,
+	backend: SharedMemoryBackend<E::Block, (), KeyValueMemoryState>,
+	import_lock: ImportLock,
+	seal: F,
+}
+
+impl<E, F> TestChain<E, F> where
+	E: ExtrinsicBuilder + BuildGenesis,
+	KeyValueMemoryState: AsExternalities<E::Externalities>,
+	F: Fn(E::BuildBlock) -> E::Block,
+	<E::Block as Block>::Identifier: AsRef<[u8]>,
+{
+	/// Create a new chain from the executor's genesis. `seal` turns a built
+	/// block into a block ready for import.
+	pub fn new(executor: E, seal: F) -> Result<Self, E::Error> {
+		let mut genesis_state = KeyValueMemoryState::default();
+		let genesis_block = executor.build_genesis(genesis_state.as_externalities())?;
+		let backend = SharedMemoryBackend::new_with_genesis(genesis_block, genesis_state);
+
+		Ok(Self { executor, backend, import_lock: ImportLock::new(), seal })
+	}
+
+	/// Get the executor of the chain.
+	pub fn executor(&self) -> &E {
+		&self.executor
+	}
+
+	/// Get the backend of the chain.
+	pub fn backend(&self) -> &SharedMemoryBackend<E::Block, (), KeyValueMemoryState> {
+		&self.backend
+	}
+
+	/// Get the import lock of the chain.
+	pub fn import_lock(&self) -> &ImportLock {
+		&self.import_lock
+	}
+
+	/// Get the current head block.
+	pub fn head(&self) -> E::Block {
+		self.backend.block_at(&self.backend.head())
+			.expect("Head always exists in backend; qed")
+	}
+
+	/// Build a block on top of the head with the given inherent and
+	/// extrinsics, seal and import it, and set it as the new head.
+	pub fn build_block(
+		&self,
+		inherent: E::Inherent,
+		extrinsics: Vec<E::Extrinsic>,
+	) -> Result<E::Block, ImportError<MemoryError, E::Error>> {
+		let head = self.backend.head();
+		let parent_block = self.backend.block_at(&head).map_err(ImportError::Backend)?;
+		let parent_state = self.backend.state_at(&head).map_err(ImportError::Backend)?;
+
+		let mut builder = BlockBuilder::new(
+			&self.executor, &parent_block, parent_state, inherent
+		).map_err(ImportError::Execution)?;
+		for extrinsic in extrinsics {
+			builder.push(extrinsic).map_err(ImportError::Execution)?;
+		}
+		let (unsealed_block, _) = builder.build().map_err(ImportError::Execution)?;
+
+		let block = (self.seal)(unsealed_block);
+		let id = block.id();
+		import_block(&self.backend, &self.import_lock, &self.executor, block.clone())?;
+
+		let mut action = ImportAction::new(&self.backend, self.import_lock.lock());
+		action.set_head(id);
+		action.commit().map_err(ImportError::Backend)?;
+
+		Ok(block)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::runtime::{chain, read_counter, Extrinsic};
+	use crate::Block;
+	use crate::backend::ChainQuery;
+
+	#[test]
+	fn builds_and_imports_blocks_on_head() {
+		let chain = chain();
+		let genesis = chain.head();
+
+		let first = chain.build_block((), vec![Extrinsic::Add(2)]).unwrap();
+		let second = chain.build_block((), vec![Extrinsic::Add(3), Extrinsic::Add(4)]).unwrap();
+
+		assert_eq!(first.parent_id(), Some(genesis.id()));
+		assert_eq!(second.parent_id(), Some(first.id()));
+		assert_eq!(chain.head(), second);
+		assert_eq!(read_counter(&chain.backend().state_at(&second.id()).unwrap()), 9);
+		assert!(chain.build_block((), vec![Extrinsic::Fail]).is_err());
+		assert_eq!(chain.head(), second);
+	}
+}
@@ -0,0 +1,481 @@
+//! Minimal runtime for testing the framework itself. Blocks carry a list of
+//! additions to a single counter, and commit to the resulting state root.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::convert::Infallible;
+use std::{fmt, error as stderror};
+use crate::{
+	Block, HeaderBlock, BlockExecutor, ExtrinsicBuilder, BuildGenesis, DiffExecutor,
+	StorageExternalities, AsExternalities, ExtrinsicOutcome, StateDiff, BlockContext,
+};
+use crate::backend::{KeyValueMemoryState, ChainQuery};
+use crate::consensus::pow::{self, PowBlock};
+use crate::proof::storage_root;
+#[cfg(feature = "codec")]
+use parity_codec::Decode;
+use super::TestChain;
+
+/// Identifier of test blocks.
+pub type Id = [u8; 8];
+
+/// Hash data into an identifier.
+pub fn hash(data: &[u8]) -> Id {
+	let mut hasher = DefaultHasher::new();
+	hasher.write(data);
+	hasher.finish().to_be_bytes()
+}
+
+/// Extrinsic of the test runtime.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "rpc", derive(serde::Serialize))]
+pub enum Extrinsic {
+	/// Add to the counter.
+	Add(u64),
+	/// Bump the counter, then fail to apply with `Error::Invalid`.
+	Fail,
+}
+
+/// Block of the test runtime. Its header is the block itself without
+/// extrinsics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "rpc", derive(serde::Serialize))]
+pub struct TestBlock {
+	/// Parent block id.
+	pub parent: Option<Id>,
+	/// Block number.
+	pub number: u64,
+	/// Timestamp, the number of the block unless changed.
+	pub timestamp: u64,
+	/// Claimed difficulty.
+	pub difficulty: usize,
+	/// Root of the state after executing the block.
+	pub state_root: Id,
+	/// Hash of the extrinsics.
+	pub extrinsics_root: Id,
+	/// Extrinsics, empty in headers.
+	pub extrinsics: Vec<Extrinsic>,
+	/// Proof of work nonce.
+	pub nonce: u64,
+}
+
+impl TestBlock {
+	/// Recompute the extrinsics root from the extrinsics.
+	pub fn seal_extrinsics(&mut self) {
+		self.extrinsics_root = extrinsics_root(&self.extrinsics);
+	}
+}
+
+fn extrinsics_root(extrinsics: &[Extrinsic]) -> Id {
+	hash(format!("{:?}", extrinsics).as_bytes())
+}
+
+impl Block for TestBlock {
+	type Identifier = Id;
+
+	fn id(&self) -> Id {
+		Self::seal_hash(&self.pre_hash(), self.nonce)
+	}
+
+	fn parent_id(&self) -> Option<Id> {
+		self.parent
+	}
+
+	fn number(&self) -> u64 {
+		self.number
+	}
+}
+
+impl HeaderBlock for TestBlock {
+	type Header = TestBlock;
+
+	fn header(&self) -> TestBlock {
+		TestBlock { extrinsics: Vec::new(), ..self.clone() }
+	}
+}
+
+impl PowBlock for TestBlock {
+	fn nonce(&self) -> u64 {
+		self.nonce
+	}
+
+	fn set_nonce(&mut self, nonce: u64) {
+		self.nonce = nonce;
+	}
+
+	fn difficulty(&self) -> usize {
+		self.difficulty
+	}
+
+	fn timestamp(&self) -> u64 {
+		self.timestamp
+	}
+
+	fn pre_hash(&self) -> Id {
+		hash(format!(
+			"{:?}{}{}{}{:?}{:?}",
+			self.parent, self.number, self.timestamp, self.difficulty,
+			self.state_root, self.extrinsics_root,
+		).as_bytes())
+	}
+
+	fn seal_hash(pre_hash: &Id, nonce: u64) -> Id {
+		let mut data = pre_hash.to_vec();
+		data.extend_from_slice(&nonce.to_be_bytes());
+		hash(&data)
+	}
+}
+
+#[cfg(feature = "codec")]
+impl parity_codec::Encode for Extrinsic {
+	fn encode_to<T: parity_codec::Output>(&self, dest: &mut T) {
+		match self {
+			Extrinsic::Add(value) => { dest.push_byte(0); value.encode_to(dest) },
+			Extrinsic::Fail => dest.push_byte(1),
+		}
+	}
+}
+
+#[cfg(feature = "codec")]
+impl parity_codec::Decode for Extrinsic {
+	fn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {
+		match input.read_byte()? {
+			0 => Some(Extrinsic::Add(u64::decode(input)?)),
+			1 => Some(Extrinsic::Fail),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(feature = "codec")]
+impl parity_codec::Encode for TestBlock {
+	fn encode_to<T: parity_codec::Output>(&self, dest: &mut T) {
+		self.parent.encode_to(dest);
+		self.number.encode_to(dest);
+		self.timestamp.encode_to(dest);
+		(self.difficulty as u64).encode_to(dest);
+		self.state_root.encode_to(dest);
+		self.extrinsics_root.encode_to(dest);
+		self.extrinsics.encode_to(dest);
+		self.nonce.encode_to(dest);
+	}
+}
+
+#[cfg(feature = "codec")]
+impl parity_codec::Decode for TestBlock {
+	fn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {
+		Some(Self {
+			parent: Decode::decode(input)?,
+			number: Decode::decode(input)?,
+			timestamp: Decode::decode(input)?,
+			difficulty: u64::decode(input)? as usize,
+			state_root: Decode::decode(input)?,
+			extrinsics_root: Decode::decode(input)?,
+			extrinsics: Decode::decode(input)?,
+			nonce: Decode::decode(input)?,
+		})
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error of the test runtime.
+pub enum Error {
+	/// State root does not match.
+	StateRootMismatch,
+	/// Extrinsics root does not match.
+	ExtrinsicsRootMismatch,
+	/// Block is invalid.
+	Invalid,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for Error { }
+
+/// Externalities of the test runtime.
+pub trait TestExternalities: StorageExternalities<Infallible> { }
+
+impl<T: StorageExternalities<Infallible>> TestExternalities for T { }
+
+impl AsExternalities<dyn TestExternalities> for KeyValueMemoryState {
+	fn as_externalities(&mut self) -> &mut (dyn TestExternalities + 'static) {
+		self
+	}
+}
+
+const COUNTER_KEY: &[u8] = b"counter";
+
+/// Read the counter from state.
+pub fn read_counter(state: &dyn TestExternalities) -> u64 {
+	match state.read_storage(COUNTER_KEY) {
+		Ok(Some(value)) => {
+			let mut bytes = [0u8; 8];
+			bytes.copy_from_slice(&value);
+			u64::from_be_bytes(bytes)
+		},
+		Ok(None) => 0,
+		Err(err) => match err { },
+	}
+}
+
+fn write_counter(state: &mut dyn TestExternalities, value: u64) {
+	state.write_storage(COUNTER_KEY.to_vec(), value.to_be_bytes().to_vec());
+}
+
+fn state_root(state: &dyn TestExternalities) -> Id {
+	storage_root(state, hash)
+}
+
+/// Executor of the test runtime.
+#[derive(Clone, Debug, Default)]
+pub struct TestExecutor {
+	/// Difficulty new blocks are built with and checked against.
+	pub difficulty: usize,
+	/// Maximum total weight of a block, unlimited if `None`.
+	pub max_weight: Option<u64>,
+}
+
+impl TestExecutor {
+	fn execute(&self, block: &TestBlock, state: &mut dyn TestExternalities) -> Result<(Vec<u64>, u64), Error> {
+		let mut events = Vec::new();
+		let mut weight = 0u64;
+		for extrinsic in &block.extrinsics {
+			self.apply(extrinsic, state)?;
+			events.push(read_counter(state));
+			weight = weight.saturating_add(self.weight(extrinsic));
+		}
+
+		if state_root(state) != block.state_root {
+			return Err(Error::StateRootMismatch)
+		}
+
+		Ok((events, weight))
+	}
+
+	fn apply(&self, extrinsic: &Extrinsic, state: &mut dyn TestExternalities) -> Result<(), Error> {
+		match extrinsic {
+			Extrinsic::Add(value) => {
+				let counter = read_counter(state);
+				write_counter(state, counter.wrapping_add(*value));
+				Ok(())
+			},
+			Extrinsic::Fail => {
+				let counter = read_counter(state);
+				write_counter(state, counter.wrapping_add(1));
+				Err(Error::Invalid)
+			},
+		}
+	}
+}
+
+impl BlockExecutor for TestExecutor {
+	type Error = Error;
+	type Block = TestBlock;
+	type Externalities = dyn TestExternalities + 'static;
+	type Event = u64;
+
+	fn execute_block(
+		&self,
+		block: &TestBlock,
+		state: &mut Self::Externalities,
+	) -> Result<Vec<u64>, Error> {
+		self.execute(block, state).map(|(events, _)| events)
+	}
+
+	fn verify_block(&self, block: &TestBlock) -> Result<(), Error> {
+		if extrinsics_root(&block.extrinsics) != block.extrinsics_root {
+			return Err(Error::ExtrinsicsRootMismatch)
+		}
+
+		if !pow::meets_difficulty(&block.id(), block.difficulty) {
+			return Err(Error::Invalid)
+		}
+
+		Ok(())
+	}
+
+	fn is_state_root_mismatch(&self, error: &Error) -> bool {
+		*error == Error::StateRootMismatch
+	}
+}
+
+impl ExtrinsicBuilder for TestExecutor {
+	type BuildBlock = TestBlock;
+	type Inherent = ();
+	type Extrinsic = Extrinsic;
+
+	fn initialize_block(
+		&self,
+		parent_block: &TestBlock,
+		_state: &mut Self::Externalities,
+		_inherent: (),
+	) -> Result<TestBlock, Error> {
+		Ok(TestBlock {
+			parent: Some(parent_block.id()),
+			number: parent_block.number + 1,
+			timestamp: parent_block.number + 1,
+			difficulty: self.difficulty,
+			state_root: [0; 8],
+			extrinsics_root: [0; 8],
+			extrinsics: Vec::new(),
+			nonce: 0,
+		})
+	}
+
+	fn apply_extrinsic(
+		&self,
+		block: &mut TestBlock,
+		extrinsic: Extrinsic,
+		state: &mut Self::Externalities,
+	) -> Result<ExtrinsicOutcome<u64>, Error> {
+		self.apply(&extrinsic, state)?;
+		let weight = self.weight(&extrinsic);
+		block.extrinsics.push(extrinsic);
+
+		Ok(ExtrinsicOutcome { weight, events: vec![read_counter(state)] })
+	}
+
+	fn finalize_block(
+		&self,
+		block: &mut TestBlock,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		block.state_root = state_root(state);
+		block.seal_extrinsics();
+		Ok(())
+	}
+
+	fn weight(&self, extrinsic: &Extrinsic) -> u64 {
+		match extrinsic {
+			Extrinsic::Add(value) => *value,
+			Extrinsic::Fail => 0,
+		}
+	}
+
+	fn max_weight(&self) -> u64 {
+		self.max_weight.unwrap_or(u64::MAX)
+	}
+
+	fn block_weight(&self, block: &TestBlock) -> u64 {
+		block.extrinsics.iter().map(|extrinsic| self.weight(extrinsic)).sum()
+	}
+
+	fn execute_block_with_weight(
+		&self,
+		block: &TestBlock,
+		_context: &BlockContext<Id>,
+		state: &mut Self::Externalities,
+	) -> Result<(Vec<u64>, u64), Error> {
+		self.execute(block, state)
+	}
+}
+
+impl BuildGenesis for TestExecutor {
+	fn build_genesis(&self, state: &mut Self::Externalities) -> Result<TestBlock, Error> {
+		write_counter(state, 0);
+
+		let mut block = TestBlock {
+			parent: None,
+			number: 0,
+			timestamp: 0,
+			difficulty: 0,
+			state_root: state_root(state),
+			extrinsics_root: [0; 8],
+			extrinsics: Vec::new(),
+			nonce: 0,
+		};
+		block.seal_extrinsics();
+		Ok(block)
+	}
+
+	fn verify_genesis(&self, block: &TestBlock, state: &mut Self::Externalities) -> Result<(), Error> {
+		if state_root(state) != block.state_root {
+			return Err(Error::StateRootMismatch)
+		}
+
+		Ok(())
+	}
+}
+
+impl DiffExecutor for TestExecutor {
+	fn apply_diff(
+		&self,
+		block: &TestBlock,
+		diff: &StateDiff,
+		state: &mut Self::Externalities,
+	) -> Result<(), Error> {
+		for (key, value) in diff {
+			match value {
+				Some(value) => state.write_storage(key.clone(), value.clone()),
+				None => state.remove_storage(key),
+			}
+		}
+
+		if state_root(state) != block.state_root {
+			return Err(Error::StateRootMismatch)
+		}
+
+		Ok(())
+	}
+}
+
+/// Seal a built block at its claimed difficulty.
+pub fn seal(block: TestBlock) -> TestBlock {
+	let difficulty = block.difficulty;
+	pow::seal(block, difficulty)
+}
+
+/// Test chain of the test runtime.
+pub type Chain = TestChain<TestExecutor, fn(TestBlock) -> TestBlock>;
+
+/// Create a test chain with the default executor.
+pub fn chain() -> Chain {
+	TestChain::new(TestExecutor::default(), seal as fn(TestBlock) -> TestBlock)
+		.expect("Building test genesis never fails; qed")
+}
+
+/// Build a block adding the given values on top of the given parent state,
+/// without importing it.
+pub fn child(
+	parent: &TestBlock,
+	parent_state: &mut (dyn TestExternalities + 'static),
+	extrinsics: Vec<Extrinsic>,
+) -> TestBlock {
+	let executor = TestExecutor::default();
+	let mut block = executor.initialize_block(parent, parent_state, ())
+		.expect("Initializing test blocks never fails; qed");
+	for extrinsic in extrinsics {
+		executor.apply_extrinsic(&mut block, extrinsic, parent_state)
+			.expect("Extrinsics of test blocks are valid; qed");
+	}
+	executor.finalize_block(&mut block, parent_state)
+		.expect("Finalizing test blocks never fails; qed");
+	seal(block)
+}
+
+/// Build `count` blocks in a row on top of the given block of the chain,
+/// each adding `value`, without importing them.
+pub fn fork(chain: &Chain, parent: &TestBlock, count: usize, value: u64) -> Vec<TestBlock> {
+	let mut state = chain.backend().state_at(&parent.id())
+		.expect("Parent of a fork is in the chain; qed");
+	let mut blocks: Vec<TestBlock> = Vec::new();
+
+	for _ in 0..count {
+		let parent = blocks.last().unwrap_or(parent).clone();
+		blocks.push(child(&parent, &mut state, vec![Extrinsic::Add(value)]));
+	}
+
+	blocks
+}
+
+/// Turn the block into one failing execution, by appending an extrinsic
+/// that fails to apply.
+pub fn failing(mut block: TestBlock) -> TestBlock {
+	block.extrinsics.push(Extrinsic::Fail);
+	block.seal_extrinsics();
+	seal(block)
+}